@@ -88,24 +88,18 @@ impl EthereumBaseLayerContract {
 impl BaseLayerContract for EthereumBaseLayerContract {
     type Error = EthereumBaseLayerError;
 
-    async fn latest_proved_block(
+    async fn latest_l1_block_number(&self) -> Result<u64, Self::Error> {
+        Ok(self.contract.client().get_block_number().await?.as_u64())
+    }
+
+    async fn proved_block_at(
         &self,
-        min_confirmations: Option<u64>,
+        l1_block_number: u64,
     ) -> Result<Option<(BlockNumber, BlockHash)>, Self::Error> {
-        let ethereum_block_number = self
-            .contract
-            .client()
-            .get_block_number()
-            .await?
-            .checked_sub(min_confirmations.unwrap_or(0).into());
-        let Some(ethereum_block_number) = ethereum_block_number else {
-            return Ok(None);
-        };
-
         let call_state_block_number =
-            self.contract.method::<_, I256>("stateBlockNumber", ())?.block(ethereum_block_number);
+            self.contract.method::<_, I256>("stateBlockNumber", ())?.block(l1_block_number);
         let call_state_block_hash =
-            self.contract.method::<_, U256>("stateBlockHash", ())?.block(ethereum_block_number);
+            self.contract.method::<_, U256>("stateBlockHash", ())?.block(l1_block_number);
         let (state_block_number, state_block_hash) =
             tokio::try_join!(call_state_block_number.call(), call_state_block_hash.call())?;
 
@@ -114,4 +108,16 @@ impl BaseLayerContract for EthereumBaseLayerContract {
             BlockHash(StarkHash::try_from(state_block_hash.encode_hex().as_str())?),
         )))
     }
+
+    async fn latest_proved_block(
+        &self,
+        min_confirmations: Option<u64>,
+    ) -> Result<Option<(BlockNumber, BlockHash)>, Self::Error> {
+        let ethereum_block_number =
+            self.latest_l1_block_number().await?.checked_sub(min_confirmations.unwrap_or(0));
+        let Some(ethereum_block_number) = ethereum_block_number else {
+            return Ok(None);
+        };
+        self.proved_block_at(ethereum_block_number).await
+    }
 }
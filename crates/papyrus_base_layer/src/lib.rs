@@ -11,6 +11,16 @@ pub mod ethereum_base_layer_contract;
 pub trait BaseLayerContract {
     type Error;
 
+    /// Get the number of the latest block of the base layer.
+    async fn latest_l1_block_number(&self) -> Result<u64, Self::Error>;
+
+    /// Get the latest Starknet block that is proved on the base layer as of the given base layer
+    /// block.
+    async fn proved_block_at(
+        &self,
+        l1_block_number: u64,
+    ) -> Result<Option<(BlockNumber, BlockHash)>, Self::Error>;
+
     /// Get the latest Starknet block that is proved on the base layer.
     /// Optionally, require minimum confirmations.
     async fn latest_proved_block(
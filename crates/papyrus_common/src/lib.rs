@@ -6,6 +6,7 @@ pub mod metrics;
 pub mod patricia_hash_tree;
 pub mod pending_classes;
 pub mod state;
+pub mod sync_progress;
 pub mod transaction_hash;
 
 #[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
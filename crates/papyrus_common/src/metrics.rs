@@ -18,6 +18,30 @@ pub const PAPYRUS_COMPILED_CLASS_MARKER: &str = "papyrus_compiled_class_marker";
 /// finality.
 pub const PAPYRUS_BASE_LAYER_MARKER: &str = "papyrus_base_layer_marker";
 
+/// The number of blocks that the node has headers of and that weren't proved on the base layer as
+/// of the latest poll of the base layer. It counts Starknet blocks, so it grows with the synced
+/// headers even while the base layer source keeps up with L1.
+pub const PAPYRUS_UNPROVED_BLOCKS: &str = "papyrus_unproved_blocks";
+
+/// The number of base layer blocks between the latest one that the base layer source observed
+/// and the latest one that it read the proved Starknet block at. It grows while the base layer
+/// advances and the source fails to read the proved block.
+pub const PAPYRUS_BASE_LAYER_SOURCE_LAG: &str = "papyrus_base_layer_source_lag";
+
+/// The Unix timestamp, in seconds, of the latest successful poll of the base layer. It stops
+/// advancing while the polls fail.
+pub const PAPYRUS_BASE_LAYER_LAST_SUCCESSFUL_POLL: &str =
+    "papyrus_base_layer_last_successful_poll_timestamp";
+
 /// The latency, in seconds, between a block timestamp (as state in its header) and the time the
 /// node stores the header.
 pub const PAPYRUS_HEADER_LATENCY_SEC: &str = "papyrus_header_latency";
+
+/// The number of requests that were sent to the central source, labeled by their endpoint.
+pub const PAPYRUS_CENTRAL_REQUESTS: &str = "papyrus_central_requests";
+
+/// The latency, in seconds, of the requests to the central source, labeled by their endpoint.
+pub const PAPYRUS_CENTRAL_REQUEST_LATENCY_SECONDS: &str = "papyrus_central_request_latency_seconds";
+
+/// The label of the central source endpoint that a request was sent to.
+pub const PAPYRUS_CENTRAL_ENDPOINT_LABEL: &str = "endpoint";
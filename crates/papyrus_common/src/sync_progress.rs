@@ -0,0 +1,97 @@
+#[cfg(test)]
+#[path = "sync_progress_test.rs"]
+mod sync_progress_test;
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use starknet_api::block::{BlockHash, BlockNumber};
+
+// The number of latest committed blocks the sync rate is computed over.
+const SYNC_RATE_WINDOW_SIZE: usize = 100;
+
+/// An event published by the sync whenever it makes progress.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SyncProgressEvent {
+    pub block_number: BlockNumber,
+    pub timestamp: SystemTime,
+    pub kind: SyncProgressEventKind,
+}
+
+impl SyncProgressEvent {
+    /// Creates an event that happened now.
+    pub fn new(block_number: BlockNumber, kind: SyncProgressEventKind) -> Self {
+        Self { block_number, timestamp: SystemTime::now(), kind }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum SyncProgressEventKind {
+    /// The block was downloaded from the central source.
+    BlockDownloaded,
+    /// The header and body of the block were committed to the storage.
+    BlockCommitted,
+    /// Blocks were reverted, and the block number of the event is the first reverted block.
+    /// The new head hash is None if all the blocks were reverted.
+    ReorgDetected { old_head_hash: BlockHash, new_head_hash: Option<BlockHash> },
+    /// The block is the latest block of the central source.
+    CaughtUpToTip,
+}
+
+/// The sync progress, aggregated from the sync progress events.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SyncProgress {
+    /// The latest event that was received.
+    pub latest_event: Option<SyncProgressEvent>,
+    /// The latest reorg that was received.
+    pub latest_reorg: Option<SyncProgressEvent>,
+    /// The amount of blocks committed per second, computed over the latest committed blocks.
+    pub blocks_per_second: Option<f64>,
+    #[serde(skip)]
+    latest_commits: VecDeque<(BlockNumber, SystemTime)>,
+}
+
+impl SyncProgress {
+    pub fn update(&mut self, event: SyncProgressEvent) {
+        match event.kind {
+            SyncProgressEventKind::BlockCommitted => {
+                if self.latest_commits.len() == SYNC_RATE_WINDOW_SIZE {
+                    self.latest_commits.pop_front();
+                }
+                self.latest_commits.push_back((event.block_number, event.timestamp));
+                self.blocks_per_second = self.compute_blocks_per_second();
+            }
+            SyncProgressEventKind::ReorgDetected { .. } => {
+                // The reverted blocks shouldn't count towards the sync rate.
+                self.latest_commits.clear();
+                self.blocks_per_second = None;
+                self.latest_reorg = Some(event.clone());
+            }
+            SyncProgressEventKind::BlockDownloaded | SyncProgressEventKind::CaughtUpToTip => {}
+        }
+        self.latest_event = Some(event);
+    }
+
+    fn compute_blocks_per_second(&self) -> Option<f64> {
+        let (first_block_number, first_timestamp) = self.latest_commits.front()?;
+        let (last_block_number, last_timestamp) = self.latest_commits.back()?;
+        let elapsed = last_timestamp.duration_since(*first_timestamp).ok()?.as_secs_f64();
+        if elapsed == 0.0 {
+            return None;
+        }
+        Some(last_block_number.0.saturating_sub(first_block_number.0) as f64 / elapsed)
+    }
+}
+
+/// How far the sync is from the latest block of the central source.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SyncStatus {
+    /// Whether the stored blocks reached the latest block of the central source. Set once the
+    /// initial catch-up is done, and stays set even if the sync falls behind again afterwards.
+    pub initial_sync_complete: bool,
+    /// The number of blocks between the latest stored block and the latest block of the central
+    /// source, as of the time the latest block was stored. None until a block is stored while the
+    /// latest block of the central source is known.
+    pub blocks_behind: Option<u64>,
+}
@@ -0,0 +1,61 @@
+use std::time::{Duration, SystemTime};
+
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockHash, BlockNumber};
+
+use super::{SyncProgress, SyncProgressEvent, SyncProgressEventKind};
+
+fn event_at(
+    block_number: u64,
+    seconds_since_start: u64,
+    kind: SyncProgressEventKind,
+) -> SyncProgressEvent {
+    SyncProgressEvent {
+        block_number: BlockNumber(block_number),
+        timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(seconds_since_start),
+        kind,
+    }
+}
+
+#[test]
+fn blocks_per_second_is_computed_from_committed_blocks() {
+    let mut sync_progress = SyncProgress::default();
+
+    sync_progress.update(event_at(0, 0, SyncProgressEventKind::BlockCommitted));
+    assert_eq!(sync_progress.blocks_per_second, None);
+
+    sync_progress.update(event_at(1, 1, SyncProgressEventKind::BlockDownloaded));
+    assert_eq!(sync_progress.blocks_per_second, None);
+
+    sync_progress.update(event_at(4, 2, SyncProgressEventKind::BlockCommitted));
+    assert_eq!(sync_progress.blocks_per_second, Some(2.0));
+
+    let caught_up_event = event_at(4, 2, SyncProgressEventKind::CaughtUpToTip);
+    sync_progress.update(caught_up_event.clone());
+    assert_eq!(sync_progress.blocks_per_second, Some(2.0));
+    assert_eq!(sync_progress.latest_event, Some(caught_up_event));
+}
+
+#[test]
+fn reorg_resets_blocks_per_second() {
+    let mut sync_progress = SyncProgress::default();
+    sync_progress.update(event_at(0, 0, SyncProgressEventKind::BlockCommitted));
+    sync_progress.update(event_at(4, 2, SyncProgressEventKind::BlockCommitted));
+
+    let reorg_event = event_at(
+        3,
+        3,
+        SyncProgressEventKind::ReorgDetected {
+            old_head_hash: BlockHash::default(),
+            new_head_hash: None,
+        },
+    );
+    sync_progress.update(reorg_event.clone());
+    assert_eq!(sync_progress.blocks_per_second, None);
+    assert_eq!(sync_progress.latest_reorg, Some(reorg_event));
+
+    sync_progress.update(event_at(3, 4, SyncProgressEventKind::BlockCommitted));
+    assert_eq!(sync_progress.blocks_per_second, None);
+    sync_progress.update(event_at(4, 5, SyncProgressEventKind::BlockCommitted));
+    assert_eq!(sync_progress.blocks_per_second, Some(1.0));
+}
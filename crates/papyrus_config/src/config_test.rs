@@ -5,7 +5,7 @@ use std::path::PathBuf;
 use std::time::Duration;
 
 use assert_matches::assert_matches;
-use clap::Command;
+use clap::{Arg, ArgAction, Command};
 use itertools::chain;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
@@ -15,7 +15,12 @@ use test_utils::get_absolute_path;
 use validator::Validate;
 
 use crate::command::{get_command_matches, update_config_map_by_command_args};
-use crate::converters::deserialize_milliseconds_to_duration;
+use crate::converters::{
+    deserialize_milliseconds_to_duration,
+    deserialize_optional_seconds_to_duration,
+    deserialize_vec,
+    serialize_slice,
+};
 use crate::dumping::{
     append_sub_config_name,
     combine_config_map_and_pointers,
@@ -30,12 +35,13 @@ use crate::dumping::{
 use crate::loading::{
     load,
     load_and_process_config,
+    load_and_process_config_with_subcommand,
     split_pointers_map,
     split_values_and_types,
     update_config_map_by_pointers,
     update_optional_values,
 };
-use crate::presentation::get_config_presentation;
+use crate::presentation::{get_config_presentation, get_detailed_config_presentation};
 use crate::{
     ConfigError,
     ParamPath,
@@ -106,6 +112,64 @@ fn dump_and_load_config() {
     }
 }
 
+#[test]
+fn dump_and_load_vec_config() {
+    #[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq)]
+    struct VecConfig {
+        #[serde(deserialize_with = "deserialize_vec")]
+        values: Vec<u8>,
+    }
+    impl SerializeConfig for VecConfig {
+        fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+            BTreeMap::from_iter([ser_param(
+                "values",
+                &serialize_slice(&self.values),
+                "A vector.",
+                ParamPrivacyInput::Public,
+            )])
+        }
+    }
+
+    for vec_config in [VecConfig { values: vec![] }, VecConfig { values: vec![1, 2, 3] }] {
+        let (dumped, _) = split_values_and_types(vec_config.dump());
+        let loaded_config = load::<VecConfig>(&dumped).unwrap();
+        assert_eq!(loaded_config, vec_config);
+    }
+
+    let dumped = BTreeMap::from([("values".to_owned(), json!("1 a"))]);
+    assert_matches!(load::<VecConfig>(&dumped), Err(ConfigError::MissingParam(_)));
+}
+
+#[test]
+fn dump_and_load_optional_duration_config() {
+    #[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq)]
+    struct DurationConfig {
+        #[serde(deserialize_with = "deserialize_optional_seconds_to_duration")]
+        duration: Option<Duration>,
+    }
+    impl SerializeConfig for DurationConfig {
+        fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+            BTreeMap::from_iter(ser_optional_param(
+                &self.duration.map(|duration| duration.as_secs()),
+                1,
+                "duration",
+                "An optional duration in seconds.",
+                ParamPrivacyInput::Public,
+            ))
+        }
+    }
+
+    for duration_config in [
+        DurationConfig { duration: None },
+        DurationConfig { duration: Some(Duration::from_secs(5)) },
+    ] {
+        let (mut dumped, _) = split_values_and_types(duration_config.dump());
+        update_optional_values(&mut dumped);
+        let loaded_config = load::<DurationConfig>(&dumped).unwrap();
+        assert_eq!(loaded_config, duration_config);
+    }
+}
+
 #[test]
 fn test_validation() {
     let outer_config =
@@ -169,6 +233,48 @@ fn test_config_presentation() {
     assert_eq!(keys, vec!["a", "b"]);
 }
 
+#[test]
+fn test_detailed_config_presentation() {
+    let config = TypicalConfig { a: Duration::from_secs(1), b: "bbb".to_owned(), c: false };
+    let presentation = get_detailed_config_presentation(&config, true).unwrap();
+    assert_eq!(
+        presentation,
+        json!({
+            "a": {"description": "This is a as milliseconds.", "privacy": "Public", "value": 1000},
+            "b": {"description": "This is b.", "privacy": "Public", "value": "bbb"},
+            "c": {"description": "This is c.", "privacy": "Private", "value": false},
+        })
+    );
+
+    let public_presentation = get_detailed_config_presentation(&config, false).unwrap();
+    let keys: Vec<_> = public_presentation.as_object().unwrap().keys().collect();
+    assert_eq!(keys, vec!["a", "b"]);
+}
+
+#[test]
+fn test_detailed_config_presentation_of_optional_params() {
+    let config =
+        OuterConfig { opt_elem: None, opt_config: None, inner_config: InnerConfig { o: 5 } };
+    let presentation = get_detailed_config_presentation(&config, true).unwrap();
+    assert_eq!(
+        presentation,
+        json!({
+            "inner_config": {"o": {"description": "This is o.", "privacy": "Public", "value": 5}},
+            "opt_config": null,
+            "opt_elem": {"description": "This is elem.", "privacy": "Public", "value": null},
+        })
+    );
+
+    let config = OuterConfig {
+        opt_elem: Some(2),
+        opt_config: Some(InnerConfig { o: 3 }),
+        inner_config: InnerConfig { o: 4 },
+    };
+    let presentation = get_detailed_config_presentation(&config, true).unwrap();
+    assert_eq!(presentation["opt_elem"]["value"], json!(2));
+    assert_eq!(presentation["opt_config"]["o"]["value"], json!(3));
+}
+
 #[test]
 fn test_pointers_flow() {
     let config_map = BTreeMap::from([
@@ -310,6 +416,39 @@ fn test_load_many_custom_config_files() {
     assert_eq!(param_path, "custom value");
 }
 
+#[test]
+fn test_load_config_with_subcommand() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("config.json");
+    CustomConfig { param_path: "default value".to_owned(), seed: 5 }
+        .dump_to_file(&vec![], file_path.to_str().unwrap())
+        .unwrap();
+    let command = Command::new("Program").subcommand(
+        Command::new("sub").arg(Arg::new("flag").long("flag").action(ArgAction::SetTrue)),
+    );
+
+    let args = vec!["Testing", "--param_path", "command value", "sub", "--flag"];
+    let (config, subcommand) = load_and_process_config_with_subcommand::<CustomConfig>(
+        File::open(&file_path).unwrap(),
+        command.clone(),
+        args.into_iter().map(|s| s.to_owned()).collect(),
+    )
+    .unwrap();
+    assert_eq!(config.param_path, "command value");
+    let (subcommand_name, subcommand_matches) = subcommand.unwrap();
+    assert_eq!(subcommand_name, "sub");
+    assert!(subcommand_matches.get_flag("flag"));
+
+    let args = vec!["Testing"];
+    let (_, subcommand) = load_and_process_config_with_subcommand::<CustomConfig>(
+        File::open(&file_path).unwrap(),
+        command,
+        args.into_iter().map(|s| s.to_owned()).collect(),
+    )
+    .unwrap();
+    assert!(subcommand.is_none());
+}
+
 #[test]
 fn test_generated_type() {
     let args = vec!["Testing"];
@@ -25,6 +25,8 @@
 //! ```
 
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
 use std::time::Duration;
 
 use serde::de::Error;
@@ -48,6 +50,15 @@ where
     Ok(Duration::from_secs(secs))
 }
 
+/// Deserializes optional seconds to an optional duration object.
+pub fn deserialize_optional_seconds_to_duration<'de, D>(de: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let secs: Option<u64> = Deserialize::deserialize(de)?;
+    Ok(secs.map(Duration::from_secs))
+}
+
 /// Serializes a map to "k1:v1 k2:v2" string structure.
 pub fn serialize_optional_map(optional_map: &Option<HashMap<String, String>>) -> String {
     match optional_map {
@@ -78,3 +89,26 @@ where
     }
     Ok(Some(map))
 }
+
+/// Serializes a slice to "v1 v2 ..." string structure.
+pub fn serialize_slice<T: Display>(slice: &[T]) -> String {
+    slice.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+/// Deserializes a vector from "v1 v2 ..." string structure.
+pub fn deserialize_vec<'de, D, T>(de: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let raw_str: String = Deserialize::deserialize(de)?;
+    raw_str
+        .split_whitespace()
+        .map(|raw_value| {
+            raw_value.parse().map_err(|err| {
+                D::Error::custom(format!("value \"{raw_value}\" is not valid: {err}"))
+            })
+        })
+        .collect()
+}
@@ -10,7 +10,7 @@ use std::ops::IndexMut;
 use std::path::PathBuf;
 
 use clap::parser::Values;
-use clap::Command;
+use clap::{ArgMatches, Command};
 use command::{get_command_matches, update_config_map_by_command_args};
 use itertools::any;
 use serde::Deserialize;
@@ -51,6 +51,16 @@ pub fn load_and_process_config<T: for<'a> Deserialize<'a>>(
     command: Command,
     args: Vec<String>,
 ) -> Result<T, ConfigError> {
+    Ok(load_and_process_config_with_subcommand(default_config_file, command, args)?.0)
+}
+
+/// Same as [`load_and_process_config`], and also returns the name and the matched arguments of the
+/// subcommand of the given command, if one was given.
+pub fn load_and_process_config_with_subcommand<T: for<'a> Deserialize<'a>>(
+    default_config_file: File,
+    command: Command,
+    args: Vec<String>,
+) -> Result<(T, Option<(String, ArgMatches)>), ConfigError> {
     let deserialized_default_config: Map<String, Value> =
         serde_json::from_reader(default_config_file)?;
 
@@ -60,6 +70,7 @@ pub fn load_and_process_config<T: for<'a> Deserialize<'a>>(
     // Take param paths with corresponding descriptions, and get the matching arguments.
     let mut arg_matches = get_command_matches(&default_config_map, command, args)?;
     let (mut values_map, types_map) = split_values_and_types(default_config_map);
+    let subcommand = arg_matches.remove_subcommand();
     // If the config_file arg is given, updates the values map according to this files.
     if let Some(custom_config_paths) = arg_matches.remove_many::<PathBuf>("config_file") {
         update_config_map_by_custom_configs(&mut values_map, &types_map, custom_config_paths)?;
@@ -71,7 +82,7 @@ pub fn load_and_process_config<T: for<'a> Deserialize<'a>>(
     // Set values according to the is-none marks.
     update_optional_values(&mut values_map);
     // Build and return a Config object.
-    load(&values_map)
+    Ok((load(&values_map)?, subcommand))
 }
 
 // Separates a json map into config map of the raw values and pointers map.
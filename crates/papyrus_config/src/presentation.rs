@@ -1,12 +1,14 @@
 //! presentation of a configuration, with hiding or exposing private parameters.
 
+use std::collections::HashSet;
 use std::ops::IndexMut;
 
 use itertools::Itertools;
 use serde::Serialize;
+use serde_json::{json, Value};
 
 use crate::dumping::SerializeConfig;
-use crate::{ConfigError, ParamPrivacy};
+use crate::{ConfigError, ParamPrivacy, SerializedContent, IS_NONE_MARK};
 
 /// Returns presentation of the public parameters in the config.
 pub fn get_config_presentation<T: Serialize + SerializeConfig>(
@@ -41,3 +43,87 @@ pub fn get_config_presentation<T: Serialize + SerializeConfig>(
     }
     Ok(config_presentation)
 }
+
+/// Returns a presentation of the parameters in the config, nested by their param paths like in
+/// [`get_config_presentation`], where each parameter is presented with its value, description and
+/// privacy. For example, the param 'a.b' is presented as
+/// `{"a": {"b": {"description": ..., "privacy": "Public", "value": ...}}}`. The values are
+/// presented as in the config files, and optional params and sub configs that are None are
+/// presented as null. The private parameters are omitted unless `include_private_parameters` is
+/// set.
+pub fn get_detailed_config_presentation<T: Serialize + SerializeConfig>(
+    config: &T,
+    include_private_parameters: bool,
+) -> Result<Value, ConfigError> {
+    let config_value = serde_json::to_value(config)?;
+    let dumped_config = config.dump();
+    let none_param_paths: HashSet<_> = dumped_config
+        .iter()
+        .filter(|(_, serialized_param)| {
+            serialized_param.content == SerializedContent::DefaultValue(json!(true))
+        })
+        .filter_map(|(param_path, _)| param_path.strip_suffix(&format!(".{IS_NONE_MARK}")))
+        .map(|param_path| param_path.to_owned())
+        .collect();
+
+    let mut presentation = json!({});
+    for (param_path, serialized_param) in &dumped_config {
+        match serialized_param.privacy {
+            ParamPrivacy::Public => {}
+            ParamPrivacy::Private if include_private_parameters => {}
+            // The temporary values, e.g. the None marks of optional params, aren't a part of the
+            // config.
+            _ => continue,
+        }
+        // The params of a sub config that is None aren't a part of the config.
+        if none_param_paths
+            .iter()
+            .any(|none_param_path| param_path.starts_with(&format!("{none_param_path}.")))
+        {
+            continue;
+        }
+        let value = if none_param_paths.contains(param_path) {
+            Value::Null
+        } else {
+            match &serialized_param.content {
+                SerializedContent::DefaultValue(value) => value.clone(),
+                // Params without a value in the dump, e.g. generated params, are taken from the
+                // config itself.
+                SerializedContent::PointerTarget(_) | SerializedContent::ParamType(_) => param_path
+                    .split('.')
+                    .try_fold(&config_value, |value, config_name| value.get(config_name))
+                    .cloned()
+                    .unwrap_or(Value::Null),
+            }
+        };
+        *nested_entry(&mut presentation, param_path)? = json!({
+            "description": serialized_param.description,
+            "privacy": serialized_param.privacy,
+            "value": value,
+        });
+    }
+
+    // Sub configs that are None have no params of their own to present them.
+    for none_param_path in &none_param_paths {
+        let entry = nested_entry(&mut presentation, none_param_path)?;
+        if entry.as_object().is_some_and(|object| object.is_empty()) {
+            *entry = Value::Null;
+        }
+    }
+    Ok(presentation)
+}
+
+// Returns the entry of the given param path in a nested presentation, creating the entries on the
+// way if they don't exist.
+fn nested_entry<'a>(
+    presentation: &'a mut Value,
+    param_path: &str,
+) -> Result<&'a mut Value, ConfigError> {
+    param_path.split('.').try_fold(presentation, |entry, config_name| {
+        Ok(entry
+            .as_object_mut()
+            .ok_or_else(|| ConfigError::ParamNotFound { param_path: param_path.to_owned() })?
+            .entry(config_name)
+            .or_insert_with(|| json!({})))
+    })
+}
@@ -1,21 +1,38 @@
 use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
+use std::time::Duration;
 
+use assert_matches::assert_matches;
 use axum::body::Body;
+use axum::http::header::CONTENT_TYPE;
 use axum::http::{Request, StatusCode};
 use axum::response::Response;
 use axum::Router;
 use http_body::combinators::UnsyncBoxBody;
 use metrics::{absolute_counter, describe_counter, register_counter};
 use metrics_exporter_prometheus::PrometheusBuilder;
+use papyrus_common::sync_progress::{SyncProgress, SyncProgressEvent, SyncProgressEventKind};
+use papyrus_config::presentation::get_detailed_config_presentation;
 use papyrus_storage::{table_names, test_utils};
 use pretty_assertions::assert_eq;
 use serde_json::{json, Value};
+use starknet_api::block::BlockNumber;
 use starknet_client::reader::MockStarknetReader;
 use starknet_client::writer::MockStarknetWriter;
+use tokio::sync::{broadcast, RwLock};
 use tower::ServiceExt;
 
-use crate::{app, is_ready, MONITORING_PREFIX};
+use crate::{
+    app,
+    collect_sync_progress,
+    is_ready,
+    BindKind,
+    MonitoringGatewayConfig,
+    MonitoringServer,
+    MonitoringServerError,
+    MONITORING_PREFIX,
+};
 
 const TEST_CONFIG_PRESENTATION: &str = "full_general_config_presentation";
 const PUBLIC_TEST_CONFIG_PRESENTATION: &str = "public_general_config_presentation";
@@ -24,6 +41,10 @@ const TEST_VERSION: &str = "1.2.3-dev";
 
 // TODO(dan): consider using a proper fixture.
 fn setup_app() -> Router {
+    setup_app_with_sync_progress(Arc::default())
+}
+
+fn setup_app_with_sync_progress(sync_progress: Arc<RwLock<SyncProgress>>) -> Router {
     let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
     app(
         String::from("https://default_url"),
@@ -33,6 +54,8 @@ fn setup_app() -> Router {
         serde_json::to_value(PUBLIC_TEST_CONFIG_PRESENTATION).unwrap(),
         SECRET.to_string(),
         None,
+        sync_progress,
+        Arc::default(),
     )
 }
 
@@ -50,6 +73,21 @@ async fn request_app(
     .unwrap()
 }
 
+#[tokio::test]
+async fn db_env_stats() {
+    let app = setup_app();
+    let response = request_app(app, "dbStats").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    for field in ["map_size", "used_size", "last_txn_id"] {
+        assert!(!body[field].is_null(), "{field} is not found in returned DB statistics.");
+    }
+    assert_eq!(body["num_tables"], json!(table_names().len()));
+}
+
 #[tokio::test]
 async fn db_stats() {
     let app = setup_app();
@@ -60,6 +98,7 @@ async fn db_stats() {
     let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
     let body: Value = serde_json::from_slice(&body).unwrap();
     assert!(!body["db_stats"].is_null());
+    assert_eq!(body["in_flight_write_operations"], json!(0));
     for &name in table_names() {
         assert!(
             body["tables_stats"].get(name).is_some(),
@@ -68,6 +107,25 @@ async fn db_stats() {
     }
 }
 
+#[tokio::test]
+async fn db_tables_size() {
+    let app = setup_app();
+    let response = request_app(app, "dbTablesSize").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    for name in table_names() {
+        for field in ["branch_pages", "leaf_pages", "overflow_pages", "total_bytes"] {
+            assert!(
+                !body[name][field].is_null(),
+                "{field} of {name} is not found in returned DB tables size."
+            );
+        }
+    }
+}
+
 #[tokio::test]
 async fn version() {
     let app = setup_app();
@@ -79,6 +137,22 @@ async fn version() {
     assert_eq!(&body[..], TEST_VERSION.as_bytes());
 }
 
+#[tokio::test]
+async fn libmdbx_version() {
+    let app = setup_app();
+    let response = request_app(app, "libmdbxVersion").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    let version = body["version"].as_str().unwrap();
+    assert!(version.split('.').all(|part| part.parse::<u32>().is_ok()), "{version}");
+    for field in ["git_describe", "build_target", "build_options", "build_flags"] {
+        assert!(body[field].is_string(), "{field} is not found in returned libmdbx version.");
+    }
+}
+
 async fn validate_response(request: &str, expected_response: &str) {
     let app = setup_app();
     let response = request_app(app, request).await;
@@ -108,6 +182,45 @@ async fn node_config_invalid_secret() {
     assert_eq!(response.status(), StatusCode::FORBIDDEN);
 }
 
+#[tokio::test]
+async fn node_config_is_a_detailed_json_presentation() {
+    let config = MonitoringGatewayConfig {
+        present_full_config_secret: SECRET.to_owned(),
+        ..Default::default()
+    };
+    let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
+    let app = app(
+        String::from("https://default_url"),
+        storage_reader,
+        TEST_VERSION,
+        get_detailed_config_presentation(&config, true).unwrap(),
+        get_detailed_config_presentation(&config, false).unwrap(),
+        SECRET.to_string(),
+        None,
+        Arc::default(),
+        Arc::default(),
+    );
+
+    let response = request_app(app.clone(), "nodeConfig").await;
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let public_presentation: Value = serde_json::from_slice(&body).unwrap();
+    let server_address = &public_presentation["server_address"];
+    assert_eq!(server_address["value"], json!(config.server_address.to_string()));
+    assert_eq!(server_address["privacy"], json!("Public"));
+    assert!(server_address["description"].is_string());
+    // The secret is a private parameter.
+    assert!(public_presentation.get("present_full_config_secret").is_none());
+
+    let response = request_app(app, format!("nodeConfigFull/{SECRET}").as_str()).await;
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let full_presentation: Value = serde_json::from_slice(&body).unwrap();
+    let secret = &full_presentation["present_full_config_secret"];
+    assert_eq!(secret["value"], json!(SECRET));
+    assert_eq!(secret["privacy"], json!("Private"));
+}
+
 #[tokio::test]
 async fn alive() {
     let app = setup_app();
@@ -124,9 +237,25 @@ async fn ready() {
     gateway_client_mock.expect_is_alive().times(1).returning(|| true);
     feeder_gateway_client_mock.expect_is_alive().times(1).returning(|| true);
 
-    let response: String =
-        is_ready(Arc::new(gateway_client_mock), Arc::new(feeder_gateway_client_mock)).await;
-    assert_eq!(response, StatusCode::OK.to_string());
+    let response = is_ready(
+        Arc::new(AtomicBool::new(true)),
+        Arc::new(gateway_client_mock),
+        Arc::new(feeder_gateway_client_mock),
+    )
+    .await;
+    assert_eq!(response, Ok(StatusCode::OK.to_string()));
+}
+
+#[tokio::test]
+async fn not_ready_before_initial_sync_is_complete() {
+    // The gateways aren't checked before the initial sync is complete.
+    let response = is_ready(
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(MockStarknetWriter::new()),
+        Arc::new(MockStarknetReader::new()),
+    )
+    .await;
+    assert_eq!(response, Err(StatusCode::SERVICE_UNAVAILABLE));
 }
 
 #[tokio::test]
@@ -152,6 +281,8 @@ async fn with_metrics() {
         serde_json::Value::default(),
         String::new(),
         Some(prometheus_handle),
+        Arc::default(),
+        Arc::default(),
     );
 
     // Register a metric.
@@ -175,6 +306,30 @@ async fn with_metrics() {
     assert!(body_string.starts_with(&expected_prefix));
 }
 
+#[tokio::test]
+async fn sync_progress() {
+    let (sender, receiver) = broadcast::channel(10);
+    let sync_progress = Arc::new(RwLock::new(SyncProgress::default()));
+    let collector = tokio::spawn(collect_sync_progress(receiver, sync_progress.clone()));
+    let event = SyncProgressEvent::new(BlockNumber(1), SyncProgressEventKind::BlockCommitted);
+    sender.send(event).unwrap();
+    // The collector returns once the sender is dropped.
+    drop(sender);
+    collector.await.unwrap();
+
+    let app = setup_app_with_sync_progress(sync_progress);
+    let response = request_app(app, "syncProgress").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["latest_event"]["block_number"], json!(1));
+    assert_eq!(body["latest_event"]["kind"], json!("BlockCommitted"));
+    assert_eq!(body["latest_reorg"], Value::Null);
+    assert_eq!(body["blocks_per_second"], Value::Null);
+}
+
 #[tokio::test]
 async fn run_server() {
     let listener = TcpListener::bind("0.0.0.0:0".parse::<SocketAddr>().unwrap()).unwrap();
@@ -203,6 +358,100 @@ async fn run_server() {
     assert_eq!(response.status(), StatusCode::OK);
 }
 
+#[cfg(unix)]
+#[tokio::test]
+async fn run_server_on_unix_socket() {
+    let ((storage_reader, _), temp_dir) = test_utils::get_test_storage();
+    let socket_path = temp_dir.path().join("monitoring.sock");
+    let config = MonitoringGatewayConfig {
+        server_address: BindKind::Unix(socket_path.clone()),
+        ..Default::default()
+    };
+    let (_sync_progress_sender, sync_progress_receiver) = broadcast::channel(1);
+    MonitoringServer::new(
+        config,
+        serde_json::to_value(TEST_CONFIG_PRESENTATION).unwrap(),
+        serde_json::to_value(PUBLIC_TEST_CONFIG_PRESENTATION).unwrap(),
+        storage_reader,
+        TEST_VERSION,
+        sync_progress_receiver,
+    )
+    .unwrap()
+    .spawn_server()
+    .await;
+
+    // Wait for the server to bind the socket.
+    let stream = loop {
+        match tokio::net::UnixStream::connect(&socket_path).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let response = sender
+        .send_request(
+            Request::builder()
+                .uri(format!("/{MONITORING_PREFIX}/nodeVersion"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], TEST_VERSION.as_bytes());
+}
+
+// The socket can't be created in a directory that doesn't exist, even by a privileged user.
+#[cfg(unix)]
+#[tokio::test]
+async fn server_fails_on_an_unwritable_unix_socket_path() {
+    let ((storage_reader, _), temp_dir) = test_utils::get_test_storage();
+    let socket_path = temp_dir.path().join("missing_dir").join("monitoring.sock");
+    let config = MonitoringGatewayConfig {
+        server_address: BindKind::Unix(socket_path.clone()),
+        monitoring_required: true,
+        ..Default::default()
+    };
+    let (_sync_progress_sender, sync_progress_receiver) = broadcast::channel(1);
+    let server_handle = MonitoringServer::new(
+        config,
+        serde_json::to_value(TEST_CONFIG_PRESENTATION).unwrap(),
+        serde_json::to_value(PUBLIC_TEST_CONFIG_PRESENTATION).unwrap(),
+        storage_reader,
+        TEST_VERSION,
+        sync_progress_receiver,
+    )
+    .unwrap()
+    .spawn_server()
+    .await;
+
+    assert_matches!(
+        server_handle.await.unwrap(),
+        Err(MonitoringServerError::UnixSocketBind { path, .. }) if path == socket_path
+    );
+}
+
+#[test]
+fn server_address_is_a_tcp_address_or_a_unix_socket() {
+    let tcp_address = "127.0.0.1:8081";
+    assert_eq!(
+        tcp_address.parse::<BindKind>().unwrap(),
+        BindKind::Tcp(tcp_address.parse().unwrap())
+    );
+    assert_eq!(
+        "unix:/tmp/monitoring.sock".parse::<BindKind>().unwrap(),
+        BindKind::Unix("/tmp/monitoring.sock".into())
+    );
+    assert!("not an address".parse::<BindKind>().is_err());
+    for bind_kind in [BindKind::Tcp(tcp_address.parse().unwrap()), BindKind::Unix("a.sock".into())]
+    {
+        assert_eq!(bind_kind.to_string().parse::<BindKind>().unwrap(), bind_kind);
+    }
+}
+
 #[test]
 fn serialization_precision() {
     let input =
@@ -211,3 +460,37 @@ fn serialization_precision() {
     let deserialized = serde_json::to_string(&serialized).unwrap();
     assert_eq!(input, deserialized);
 }
+
+#[tokio::test]
+async fn server_failure_ends_the_task_only_if_monitoring_is_required() {
+    // Occupy the address of the server.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server_address = BindKind::Tcp(listener.local_addr().unwrap());
+    for monitoring_required in [true, false] {
+        let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
+        let config = MonitoringGatewayConfig {
+            server_address: server_address.clone(),
+            monitoring_required,
+            ..Default::default()
+        };
+        let (_sync_progress_sender, sync_progress_receiver) = broadcast::channel(1);
+        let server_handle = MonitoringServer::new(
+            config,
+            serde_json::to_value(TEST_CONFIG_PRESENTATION).unwrap(),
+            serde_json::to_value(PUBLIC_TEST_CONFIG_PRESENTATION).unwrap(),
+            storage_reader,
+            TEST_VERSION,
+            sync_progress_receiver,
+        )
+        .unwrap()
+        .spawn_server()
+        .await;
+
+        let res = tokio::time::timeout(Duration::from_millis(500), server_handle).await;
+        if monitoring_required {
+            assert!(res.unwrap().unwrap().is_err());
+        } else {
+            assert!(res.is_err());
+        }
+    }
+}
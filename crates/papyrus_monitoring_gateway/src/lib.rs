@@ -7,8 +7,13 @@ mod gateway_test;
 
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
-use std::net::SocketAddr;
+use std::future::pending;
+use std::net::{AddrParseError, SocketAddr};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use axum::extract::Path;
@@ -18,9 +23,11 @@ use axum::routing::get;
 use axum::{Json, Router};
 use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
 use metrics_process::Collector;
+use papyrus_common::sync_progress::{SyncProgress, SyncProgressEvent};
 use papyrus_config::converters::{deserialize_optional_map, serialize_optional_map};
 use papyrus_config::dumping::{ser_generated_param, ser_param, SerializeConfig};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializationType, SerializedParam};
+use papyrus_storage::db::db_stats::{self, DbEnvStats, DbTableSize, LibmdbxVersion};
 use papyrus_storage::{DbStats, StorageError, StorageReader};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
@@ -28,15 +35,18 @@ use serde::{Deserialize, Serialize};
 use starknet_client::reader::{StarknetFeederGatewayClient, StarknetReader};
 use starknet_client::writer::{StarknetGatewayClient, StarknetWriter};
 use starknet_client::RetryConfig;
-use tracing::{debug, info, instrument};
-use validator::Validate;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, RwLock};
+use tracing::{debug, error, info, instrument, warn};
+use validator::{Validate, ValidationError};
 
 const MONITORING_PREFIX: &str = "monitoring";
 const PROCESS_METRICS_PREFIX: &str = "papyrus_";
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Validate)]
 pub struct MonitoringGatewayConfig {
-    pub server_address: String,
+    #[validate(custom = "validate_server_address")]
+    pub server_address: BindKind,
     pub collect_metrics: bool,
     #[serde(deserialize_with = "deserialize_optional_map")]
     pub metric_labels: Option<HashMap<String, String>>,
@@ -44,6 +54,65 @@ pub struct MonitoringGatewayConfig {
     #[serde(default = "random_secret")]
     pub present_full_config_secret: String,
     pub starknet_url: String,
+    /// If false, a failure of the monitoring server is logged and the node keeps running without
+    /// it.
+    pub monitoring_required: bool,
+}
+
+/// The address the monitoring server listens on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BindKind {
+    /// A TCP socket address, e.g. 0.0.0.0:8081.
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket, written as unix:<path> in the config. Only supported on
+    /// Unix platforms.
+    Unix(PathBuf),
+}
+
+const UNIX_SOCKET_PREFIX: &str = "unix:";
+
+impl FromStr for BindKind {
+    type Err = AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix(UNIX_SOCKET_PREFIX) {
+            Some(path) => Ok(BindKind::Unix(PathBuf::from(path))),
+            None => Ok(BindKind::Tcp(SocketAddr::from_str(s)?)),
+        }
+    }
+}
+
+impl TryFrom<String> for BindKind {
+    type Error = AddrParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for BindKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindKind::Tcp(address) => write!(f, "{address}"),
+            BindKind::Unix(path) => write!(f, "{UNIX_SOCKET_PREFIX}{}", path.display()),
+        }
+    }
+}
+
+impl From<BindKind> for String {
+    fn from(bind_kind: BindKind) -> Self {
+        bind_kind.to_string()
+    }
+}
+
+fn validate_server_address(server_address: &BindKind) -> Result<(), ValidationError> {
+    if cfg!(not(unix)) && matches!(server_address, BindKind::Unix(_)) {
+        return Err(ValidationError::new(
+            "Binding to a Unix domain socket is only supported on Unix platforms",
+        ));
+    }
+    Ok(())
 }
 
 fn random_secret() -> String {
@@ -55,12 +124,13 @@ fn random_secret() -> String {
 impl Default for MonitoringGatewayConfig {
     fn default() -> Self {
         MonitoringGatewayConfig {
-            server_address: String::from("0.0.0.0:8081"),
+            server_address: BindKind::Tcp(SocketAddr::from(([0, 0, 0, 0], 8081))),
             collect_metrics: false,
             metric_labels: None,
             // A constant value for testing purposes.
             present_full_config_secret: String::from("qwerty"),
             starknet_url: String::from("https://alpha-mainnet.starknet.io/"),
+            monitoring_required: true,
         }
     }
 }
@@ -70,8 +140,9 @@ impl SerializeConfig for MonitoringGatewayConfig {
         BTreeMap::from_iter([
             ser_param(
                 "server_address",
-                &self.server_address,
-                "node's monitoring server.",
+                &self.server_address.to_string(),
+                "node's monitoring server. Either a TCP address or unix:<path> of a Unix domain \
+                 socket.",
                 ParamPrivacyInput::Public,
             ),
             ser_param(
@@ -98,6 +169,13 @@ impl SerializeConfig for MonitoringGatewayConfig {
                 "The URL of a centralized Starknet gateway.",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "monitoring_required",
+                &self.monitoring_required,
+                "If false, the node keeps running without the monitoring server when it fails to \
+                 start, e.g. when its address is taken.",
+                ParamPrivacyInput::Public,
+            ),
         ])
     }
 }
@@ -111,13 +189,20 @@ impl Display for MonitoringGatewayConfig {
 
 pub struct MonitoringServer {
     config: MonitoringGatewayConfig,
-    // Nested Json presentation of all the parameters in the node config.
+    // Nested Json presentation of all the parameters in the node config, with their values,
+    // descriptions and privacy.
     full_general_config_presentation: serde_json::Value,
-    // Nested Json presentation of the public parameters in the node config.
+    // Nested Json presentation of the public parameters in the node config, with their values,
+    // descriptions and privacy.
     public_general_config_presentation: serde_json::Value,
     storage_reader: StorageReader,
     version: &'static str,
     prometheus_handle: Option<PrometheusHandle>,
+    sync_progress: Arc<RwLock<SyncProgress>>,
+    // Taken when the server is spawned.
+    sync_progress_receiver: Option<broadcast::Receiver<SyncProgressEvent>>,
+    // The node isn't ready before the initial sync is complete.
+    initial_sync_complete: Arc<AtomicBool>,
 }
 
 impl MonitoringServer {
@@ -127,6 +212,7 @@ impl MonitoringServer {
         public_general_config_presentation: serde_json::Value,
         storage_reader: StorageReader,
         version: &'static str,
+        sync_progress_receiver: broadcast::Receiver<SyncProgressEvent>,
     ) -> Result<Self, BuildError> {
         let prometheus_handle = if config.collect_metrics {
             let mut builder = PrometheusBuilder::new();
@@ -146,12 +232,37 @@ impl MonitoringServer {
             public_general_config_presentation,
             version,
             prometheus_handle,
+            sync_progress: Arc::new(RwLock::new(SyncProgress::default())),
+            sync_progress_receiver: Some(sync_progress_receiver),
+            initial_sync_complete: Arc::new(AtomicBool::new(false)),
         })
     }
 
-    /// Spawns a monitoring server.
-    pub async fn spawn_server(self) -> tokio::task::JoinHandle<Result<(), hyper::Error>> {
-        tokio::spawn(async move { self.run_server().await })
+    /// Returns a flag to set once the initial sync is complete. Until it's set, the server reports
+    /// that the node isn't ready.
+    pub fn initial_sync_complete(&self) -> Arc<AtomicBool> {
+        self.initial_sync_complete.clone()
+    }
+
+    /// Spawns a monitoring server, along with a task that collects the sync progress events.
+    /// If the monitoring isn't required, the returned task doesn't end when the server fails, so
+    /// the failure doesn't stop the node.
+    pub async fn spawn_server(
+        mut self,
+    ) -> tokio::task::JoinHandle<Result<(), MonitoringServerError>> {
+        if let Some(sync_progress_receiver) = self.sync_progress_receiver.take() {
+            tokio::spawn(collect_sync_progress(sync_progress_receiver, self.sync_progress.clone()));
+        }
+        tokio::spawn(async move {
+            if self.config.monitoring_required {
+                return self.run_server().await;
+            }
+            match self.run_server().await {
+                Ok(()) => error!("Monitoring server stopped, running without it."),
+                Err(err) => error!("Monitoring server failed, running without it: {err}"),
+            }
+            pending().await
+        })
     }
 
     #[instrument(
@@ -163,9 +274,7 @@ impl MonitoringServer {
             public_general_config_presentation = %self.public_general_config_presentation,
             present_full_config_secret = %self.config.present_full_config_secret),
         level = "debug")]
-    async fn run_server(&self) -> std::result::Result<(), hyper::Error> {
-        let server_address = SocketAddr::from_str(&self.config.server_address)
-            .expect("Configuration value for monitor server address should be valid");
+    async fn run_server(&self) -> std::result::Result<(), MonitoringServerError> {
         let app = app(
             self.config.starknet_url.clone(),
             self.storage_reader.clone(),
@@ -174,12 +283,52 @@ impl MonitoringServer {
             self.public_general_config_presentation.clone(),
             self.config.present_full_config_secret.clone(),
             self.prometheus_handle.clone(),
+            self.sync_progress.clone(),
+            self.initial_sync_complete.clone(),
         );
         debug!("Starting monitoring gateway.");
-        axum::Server::bind(&server_address).serve(app.into_make_service()).await
+        match &self.config.server_address {
+            BindKind::Tcp(server_address) => {
+                Ok(axum::Server::try_bind(server_address)?.serve(app.into_make_service()).await?)
+            }
+            #[cfg(unix)]
+            BindKind::Unix(path) => serve_on_unix_socket(path, app).await,
+            #[cfg(not(unix))]
+            BindKind::Unix(_) => {
+                panic!("Binding to a Unix domain socket is only supported on Unix platforms.")
+            }
+        }
     }
 }
 
+/// An error that stopped the monitoring server.
+#[derive(thiserror::Error, Debug)]
+pub enum MonitoringServerError {
+    #[error(transparent)]
+    Hyper(#[from] hyper::Error),
+    #[error("Failed binding the monitoring gateway to {path:?}: {source}")]
+    UnixSocketBind { path: PathBuf, source: std::io::Error },
+}
+
+#[cfg(unix)]
+async fn serve_on_unix_socket(
+    path: &std::path::Path,
+    app: Router,
+) -> Result<(), MonitoringServerError> {
+    let bind_error =
+        |source| MonitoringServerError::UnixSocketBind { path: path.to_path_buf(), source };
+    // A socket that is left from a previous run would fail the bind.
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path).map_err(bind_error)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path).map_err(bind_error)?;
+    let incoming = hyper::server::accept::poll_fn(move |cx| {
+        listener.poll_accept(cx).map(|accepted| Some(accepted.map(|(stream, _address)| stream)))
+    });
+    Ok(axum::Server::builder(incoming).serve(app.into_make_service()).await?)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn app(
     starknet_url: String,
     storage_reader: StorageReader,
@@ -188,6 +337,8 @@ fn app(
     public_general_config_presentation: serde_json::Value,
     present_full_config_secret: String,
     prometheus_handle: Option<PrometheusHandle>,
+    sync_progress: Arc<RwLock<SyncProgress>>,
+    initial_sync_complete: Arc<AtomicBool>,
 ) -> Router {
     let is_ready_retry_config =
         RetryConfig { retry_base_millis: 50, retry_max_delay_millis: 1000, max_retries: 0 };
@@ -205,10 +356,20 @@ fn app(
             .expect("Failed creating Starknet client."),
     );
 
+    let db_tables_stats_storage_reader = storage_reader.clone();
+    let db_tables_size_storage_reader = storage_reader.clone();
     Router::new()
+        .route(
+            format!("/{MONITORING_PREFIX}/dbStats").as_str(),
+            get(move || db_stats(storage_reader)),
+        )
         .route(
             format!("/{MONITORING_PREFIX}/dbTablesStats").as_str(),
-            get(move || db_tables_stats(storage_reader)),
+            get(move || db_tables_stats(db_tables_stats_storage_reader)),
+        )
+        .route(
+            format!("/{MONITORING_PREFIX}/dbTablesSize").as_str(),
+            get(move || db_tables_size(db_tables_size_storage_reader)),
         )
         .route(
             format!("/{MONITORING_PREFIX}/nodeConfig").as_str(),
@@ -229,6 +390,7 @@ fn app(
             format!("/{MONITORING_PREFIX}/nodeVersion").as_str(),
             get(move || node_version(version)),
         )
+        .route(format!("/{MONITORING_PREFIX}/libmdbxVersion").as_str(), get(libmdbx_version))
         .route(
             format!("/{MONITORING_PREFIX}/alive").as_str(),
             get(move || async { StatusCode::OK.to_string() }),
@@ -237,23 +399,40 @@ fn app(
             format!("/{MONITORING_PREFIX}/metrics").as_str(),
             get(move || metrics(prometheus_handle)),
         )
+        .route(
+            format!("/{MONITORING_PREFIX}/syncProgress").as_str(),
+            get(move || sync_progress_summary(sync_progress)),
+        )
         .route(
             format!("/{MONITORING_PREFIX}/ready").as_str(),
-            get(move || is_ready(starknet_client, starknet_feeder_client)),
+            get(move || is_ready(initial_sync_complete, starknet_client, starknet_feeder_client)),
         )
 }
 
+// The node is ready once the initial sync is complete, and as long as the Starknet gateways are
+// alive.
 async fn is_ready<TStarknetWriter: StarknetWriter, TStarknetReader: StarknetReader>(
+    initial_sync_complete: Arc<AtomicBool>,
     starknet_client: Arc<TStarknetWriter>,
     starknet_feeder_client: Arc<TStarknetReader>,
-) -> String {
+) -> Result<String, StatusCode> {
+    if !initial_sync_complete.load(Ordering::Acquire) {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
     let response = starknet_feeder_client.is_alive().await;
     assert!(response);
 
     let response = starknet_client.is_alive().await;
     assert!(response);
 
-    StatusCode::OK.to_string()
+    Ok(StatusCode::OK.to_string())
+}
+
+/// Returns information about the DB environment.
+#[instrument(skip(storage_reader), level = "debug", ret)]
+async fn db_stats(storage_reader: StorageReader) -> Result<Json<DbEnvStats>, ServerError> {
+    Ok(storage_reader.db_stats()?.into())
 }
 
 /// Returns DB statistics.
@@ -262,6 +441,14 @@ async fn db_tables_stats(storage_reader: StorageReader) -> Result<Json<DbStats>,
     Ok(storage_reader.db_tables_stats()?.into())
 }
 
+/// Returns the size of each table in the DB.
+#[instrument(skip(storage_reader), level = "debug", ret)]
+async fn db_tables_size(
+    storage_reader: StorageReader,
+) -> Result<Json<BTreeMap<String, DbTableSize>>, ServerError> {
+    Ok(storage_reader.db_tables_size()?.into())
+}
+
 /// Returns the node config.
 #[instrument(level = "debug", ret)]
 async fn node_config(
@@ -304,6 +491,34 @@ async fn node_version(version: &'static str) -> String {
     version.to_string()
 }
 
+/// Returns the version of libmdbx the node was built with and its build options.
+#[instrument(level = "debug", ret)]
+async fn libmdbx_version() -> Json<LibmdbxVersion> {
+    db_stats::libmdbx_version().into()
+}
+
+/// Returns the sync progress.
+#[instrument(level = "debug", ret, skip(sync_progress))]
+async fn sync_progress_summary(sync_progress: Arc<RwLock<SyncProgress>>) -> Json<SyncProgress> {
+    sync_progress.read().await.clone().into()
+}
+
+// Aggregates the sync progress events until the sync stops publishing them.
+async fn collect_sync_progress(
+    mut sync_progress_receiver: broadcast::Receiver<SyncProgressEvent>,
+    sync_progress: Arc<RwLock<SyncProgress>>,
+) {
+    loop {
+        match sync_progress_receiver.recv().await {
+            Ok(event) => sync_progress.write().await.update(event),
+            Err(RecvError::Lagged(num_skipped_events)) => {
+                warn!("Skipped {num_skipped_events} sync progress events.");
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 enum ServerError {
     #[error(transparent)]
@@ -0,0 +1,147 @@
+//! A query for a range of consecutive blocks and the data that is sent in response to it, for
+//! using the streamed data protocol to sync blocks.
+//!
+//! On the wire, a [`BlockRangeQuery`] is a `BlockHeadersRequest` (or equivalently a
+//! `BlockBodiesRequest`) of the [`Starknet p2p specs`], and each [`BlockRangeData`] holds either a
+//! `BlockHeadersResponse` or a `BlockBodiesResponse`.
+//!
+//! [`Starknet p2p specs`]: https://github.com/starknet-io/starknet-p2p-specs/
+
+#[cfg(test)]
+#[path = "block_range_test.rs"]
+mod block_range_test;
+
+use prost::bytes::{Buf, BufMut};
+use prost::encoding::{DecodeContext, WireType};
+use prost::{DecodeError, Message};
+use starknet_api::block::BlockNumber;
+
+use crate::messages::protobuf;
+use crate::Direction;
+
+/// A query for `count` consecutive blocks, starting from the block `start` and going in the given
+/// direction.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BlockRangeQuery {
+    pub start: BlockNumber,
+    pub count: u32,
+    pub direction: Direction,
+}
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum BlockRangeQueryError {
+    #[error("The query asks for {count} blocks, which is more than the maximum of {max_count}.")]
+    CountTooLarge { count: u32, max_count: u32 },
+}
+
+impl BlockRangeQuery {
+    /// Checks that the query doesn't ask for more than `max_count` blocks, so that a peer can't
+    /// ask for the whole chain in a single query. Peers should validate each query they get before
+    /// answering it.
+    pub fn validate(&self, max_count: u32) -> Result<(), BlockRangeQueryError> {
+        if self.count > max_count {
+            return Err(BlockRangeQueryError::CountTooLarge { count: self.count, max_count });
+        }
+        Ok(())
+    }
+}
+
+impl From<BlockRangeQuery> for protobuf::BlockHeadersRequest {
+    fn from(query: BlockRangeQuery) -> Self {
+        let direction = match query.direction {
+            Direction::Forward => protobuf::iteration::Direction::Forward,
+            Direction::Backward => protobuf::iteration::Direction::Backward,
+        };
+        Self {
+            iteration: Some(protobuf::Iteration {
+                start: Some(protobuf::iteration::Start::BlockNumber(query.start.0)),
+                direction: direction.into(),
+                limit: query.count.into(),
+                step: 1,
+            }),
+        }
+    }
+}
+
+impl TryFrom<protobuf::BlockHeadersRequest> for BlockRangeQuery {
+    type Error = DecodeError;
+
+    fn try_from(request: protobuf::BlockHeadersRequest) -> Result<Self, Self::Error> {
+        let Some(iteration) = request.iteration else {
+            return Ok(Self::default());
+        };
+        let start = match iteration.start {
+            Some(protobuf::iteration::Start::BlockNumber(block_number)) => {
+                BlockNumber(block_number)
+            }
+            Some(protobuf::iteration::Start::Header(_)) => {
+                return Err(DecodeError::new(
+                    "Starting a block range from a hash isn't supported.",
+                ));
+            }
+            None => BlockNumber::default(),
+        };
+        let direction = match protobuf::iteration::Direction::try_from(iteration.direction) {
+            Ok(protobuf::iteration::Direction::Forward) => Direction::Forward,
+            Ok(protobuf::iteration::Direction::Backward) => Direction::Backward,
+            Err(_) => return Err(DecodeError::new("Unknown block range direction.")),
+        };
+        // A step of 0 is the default of a step that wasn't set.
+        if iteration.step > 1 {
+            return Err(DecodeError::new("Block ranges with a step aren't supported."));
+        }
+        let count = u32::try_from(iteration.limit)
+            .map_err(|_| DecodeError::new("The block range count doesn't fit in 32 bits."))?;
+        Ok(Self { start, count, direction })
+    }
+}
+
+// The query is encoded as a BlockHeadersRequest. A message may carry its fields in several parts,
+// so each part is merged into the encoding of the fields that were decoded so far.
+impl Message for BlockRangeQuery {
+    fn encode_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        protobuf::BlockHeadersRequest::from(*self).encode_raw(buf)
+    }
+
+    fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        let mut request = protobuf::BlockHeadersRequest::from(*self);
+        request.merge_field(tag, wire_type, buf, ctx)?;
+        *self = request.try_into()?;
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        protobuf::BlockHeadersRequest::from(*self).encoded_len()
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// A message that is sent in response to a [`BlockRangeQuery`].
+#[derive(Clone, PartialEq, Message)]
+pub struct BlockRangeData {
+    #[prost(oneof = "BlockRangeDataKind", tags = "1, 2")]
+    pub kind: Option<BlockRangeDataKind>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum BlockRangeDataKind {
+    #[prost(message, tag = "1")]
+    Headers(protobuf::BlockHeadersResponse),
+    #[prost(message, tag = "2")]
+    Bodies(protobuf::BlockBodiesResponse),
+}
@@ -0,0 +1,126 @@
+//! Serving the data of a [`BlockRangeQuery`] from the storage.
+//!
+//! Materializing the data of a whole block range before sending it takes memory that grows with
+//! the range. Instead, [`BlockRangeStorageStream`] reads the data of each block from the storage
+//! only once it's polled, and `send_data_from_stream` pulls data from it into an inbound session
+//! only while the session has fewer than a given number of messages that weren't written to the
+//! peer yet. So the data of at most that many blocks is buffered at any time, and a peer that reads
+//! slowly slows down the reading from the storage.
+
+#[cfg(test)]
+#[path = "block_range_stream_test.rs"]
+mod block_range_stream_test;
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{FutureExt, Stream, StreamExt};
+use papyrus_storage::db::RO;
+use papyrus_storage::{StorageError, StorageReader, StorageResult, StorageTxn};
+use starknet_api::block::BlockNumber;
+
+use crate::block_range::BlockRangeQuery;
+use crate::streamed_data_protocol::behaviour::{Behaviour, SendDataError, SessionIdNotFoundError};
+use crate::streamed_data_protocol::{DataBound, InboundSessionId, QueryBound};
+use crate::Direction;
+
+/// Reads the data of a block, or returns None if the storage doesn't have it.
+pub type ReadBlockData<Data> = fn(&StorageTxn<'_, RO>, BlockNumber) -> StorageResult<Option<Data>>;
+
+/// A stream of the data of the blocks of a [`BlockRangeQuery`], in the order of the query. The data
+/// of each block is read when the stream is polled for it, in its own read transaction, so that a
+/// peer that reads slowly doesn't keep a transaction open for the whole range. The stream ends at
+/// the first block that the storage doesn't have, or after the first error.
+pub struct BlockRangeStorageStream<Data> {
+    storage_reader: StorageReader,
+    read_block_data: ReadBlockData<Data>,
+    // None once the stream ended.
+    next_block_number: Option<BlockNumber>,
+    num_remaining_blocks: u32,
+    direction: Direction,
+}
+
+impl<Data> BlockRangeStorageStream<Data> {
+    pub fn new(
+        storage_reader: StorageReader,
+        query: BlockRangeQuery,
+        read_block_data: ReadBlockData<Data>,
+    ) -> Self {
+        Self {
+            storage_reader,
+            read_block_data,
+            next_block_number: Some(query.start),
+            num_remaining_blocks: query.count,
+            direction: query.direction,
+        }
+    }
+
+    fn read_next_block_data(&mut self) -> Option<StorageResult<Data>> {
+        let block_number = self.next_block_number.take()?;
+        if self.num_remaining_blocks == 0 {
+            return None;
+        }
+        let maybe_data = self
+            .storage_reader
+            .begin_ro_txn()
+            .and_then(|txn| (self.read_block_data)(&txn, block_number))
+            .transpose()?;
+        if maybe_data.is_ok() {
+            self.num_remaining_blocks -= 1;
+            self.next_block_number = match self.direction {
+                Direction::Forward => Some(block_number.next()),
+                Direction::Backward => block_number.prev(),
+            };
+        }
+        Some(maybe_data)
+    }
+}
+
+impl<Data> Stream for BlockRangeStorageStream<Data> {
+    type Item = StorageResult<Data>;
+
+    // The storage is read synchronously, so the stream is always ready.
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.read_next_block_data())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum SendDataFromStreamError {
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+    #[error(transparent)]
+    SendDataError(#[from] SendDataError),
+}
+
+/// Sends data from the stream to the given inbound session until the session has
+/// `max_unwritten_messages` messages that weren't written to the peer, or the stream has no data
+/// ready. Returns whether the stream ended, in which case the session can be closed. Otherwise, it
+/// should be called again once the swarm is polled, which passes the messages on.
+// The node doesn't serve queries yet.
+#[allow(dead_code)]
+pub(crate) fn send_data_from_stream<Query, Data, DataStream>(
+    behaviour: &mut Behaviour<Query, Data>,
+    inbound_session_id: InboundSessionId,
+    data_stream: &mut DataStream,
+    max_unwritten_messages: usize,
+) -> Result<bool, SendDataFromStreamError>
+where
+    Query: QueryBound,
+    Data: DataBound,
+    DataStream: Stream<Item = StorageResult<Data>> + Unpin,
+{
+    loop {
+        let num_unwritten_messages = behaviour
+            .num_unwritten_messages(inbound_session_id)
+            .ok_or(SendDataError::from(SessionIdNotFoundError))?;
+        if num_unwritten_messages >= max_unwritten_messages {
+            return Ok(false);
+        }
+        match data_stream.next().now_or_never() {
+            None => return Ok(false),
+            Some(None) => return Ok(true),
+            Some(Some(data)) => behaviour.send_data(data?, inbound_session_id)?,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::task::{Context, Poll};
+
+use assert_matches::assert_matches;
+use futures::task::noop_waker_ref;
+use futures::{FutureExt, StreamExt};
+use libp2p::core::{ConnectedPoint, Endpoint};
+use libp2p::swarm::behaviour::ConnectionEstablished;
+use libp2p::swarm::{ConnectionId, FromSwarm, NetworkBehaviour, ToSwarm};
+use libp2p::{Multiaddr, PeerId};
+use papyrus_storage::db::RO;
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::{StorageReader, StorageResult, StorageTxn};
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::hash::StarkFelt;
+
+use super::{send_data_from_stream, BlockRangeStorageStream};
+use crate::block_range::BlockRangeQuery;
+use crate::messages::protobuf;
+use crate::streamed_data_protocol::behaviour::{Behaviour, Event};
+use crate::streamed_data_protocol::handler::{RequestFromBehaviourEvent, ToBehaviourEvent};
+use crate::streamed_data_protocol::{Config, InboundSessionId};
+use crate::Direction;
+
+fn read_block_number(
+    txn: &StorageTxn<'_, RO>,
+    block_number: BlockNumber,
+) -> StorageResult<Option<protobuf::BasicMessage>> {
+    Ok(txn
+        .get_block_header(block_number)?
+        .map(|header| protobuf::BasicMessage { number: header.block_number.0 }))
+}
+
+fn get_storage_reader_with_blocks(num_blocks: u64) -> (StorageReader, tempfile::TempDir) {
+    let ((storage_reader, mut storage_writer), temp_dir) = get_test_storage();
+    let mut txn = storage_writer.begin_rw_txn().unwrap();
+    for i in 0..num_blocks {
+        let header = BlockHeader {
+            block_hash: BlockHash(StarkFelt::from(i)),
+            block_number: BlockNumber(i),
+            ..BlockHeader::default()
+        };
+        txn = txn.append_header(BlockNumber(i), &header).unwrap();
+    }
+    txn.commit().unwrap();
+    (storage_reader, temp_dir)
+}
+
+fn stream_block_numbers(storage_reader: StorageReader, query: BlockRangeQuery) -> Vec<u64> {
+    BlockRangeStorageStream::new(storage_reader, query, read_block_number)
+        .map(|data| data.unwrap().number)
+        .collect::<Vec<_>>()
+        .now_or_never()
+        .unwrap()
+}
+
+#[test]
+fn stream_ends_at_the_end_of_the_range_or_at_a_missing_block() {
+    let (storage_reader, _temp_dir) = get_storage_reader_with_blocks(5);
+
+    let query = BlockRangeQuery { start: BlockNumber(1), count: 3, direction: Direction::Forward };
+    assert_eq!(stream_block_numbers(storage_reader.clone(), query), vec![1, 2, 3]);
+
+    let query = BlockRangeQuery { start: BlockNumber(3), count: 10, direction: Direction::Forward };
+    assert_eq!(stream_block_numbers(storage_reader.clone(), query), vec![3, 4]);
+
+    let query =
+        BlockRangeQuery { start: BlockNumber(2), count: 10, direction: Direction::Backward };
+    assert_eq!(stream_block_numbers(storage_reader.clone(), query), vec![2, 1, 0]);
+
+    let query = BlockRangeQuery { start: BlockNumber(5), count: 10, direction: Direction::Forward };
+    assert_eq!(stream_block_numbers(storage_reader, query), Vec::<u64>::new());
+}
+
+#[test]
+fn block_range_is_served_with_bounded_buffering() {
+    const NUM_BLOCKS: u64 = 1000;
+    const MAX_UNWRITTEN_MESSAGES: usize = 10;
+    // The swarm passes on fewer messages each time it's polled than the session may buffer.
+    const NUM_MESSAGES_PASSED_PER_POLL: usize = 3;
+    let (storage_reader, _temp_dir) = get_storage_reader_with_blocks(NUM_BLOCKS);
+
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let peer_id = PeerId::random();
+    let connection_id = ConnectionId::new_unchecked(0);
+    let address = Multiaddr::empty();
+    let _handler = behaviour
+        .handle_established_outbound_connection(
+            connection_id,
+            peer_id,
+            &address,
+            Endpoint::Listener,
+        )
+        .unwrap();
+    behaviour.on_swarm_event(FromSwarm::ConnectionEstablished(ConnectionEstablished {
+        peer_id,
+        connection_id,
+        endpoint: &ConnectedPoint::Listener {
+            send_back_addr: address,
+            local_addr: Multiaddr::empty(),
+        },
+        failed_addresses: &[],
+        other_established: 0,
+    }));
+    let inbound_session_id = InboundSessionId::default();
+    behaviour.on_connection_handler_event(
+        peer_id,
+        connection_id,
+        ToBehaviourEvent::NewInboundSession {
+            query: protobuf::BasicMessage::default(),
+            inbound_session_id,
+            peer_id,
+            protocol_name: Config::get_test_config().protocol_names[0].clone(),
+        },
+    );
+    let mut cx = Context::from_waker(noop_waker_ref());
+    assert_matches!(
+        behaviour.poll(&mut cx),
+        Poll::Ready(ToSwarm::GenerateEvent(Event::NewInboundSession { .. }))
+    );
+
+    let query = BlockRangeQuery {
+        start: BlockNumber(0),
+        count: NUM_BLOCKS.try_into().unwrap(),
+        direction: Direction::Forward,
+    };
+    let mut data_stream = BlockRangeStorageStream::new(storage_reader, query, read_block_number);
+    let mut sent_block_numbers = Vec::new();
+    let mut max_num_unwritten_messages = 0;
+    loop {
+        let stream_ended = send_data_from_stream(
+            &mut behaviour,
+            inbound_session_id,
+            &mut data_stream,
+            MAX_UNWRITTEN_MESSAGES,
+        )
+        .unwrap();
+        let num_unwritten_messages = behaviour.num_unwritten_messages(inbound_session_id).unwrap();
+        max_num_unwritten_messages = max_num_unwritten_messages.max(num_unwritten_messages);
+        if stream_ended && num_unwritten_messages == 0 {
+            break;
+        }
+        for _ in 0..NUM_MESSAGES_PASSED_PER_POLL {
+            match behaviour.poll(&mut cx) {
+                Poll::Ready(ToSwarm::NotifyHandler {
+                    event: RequestFromBehaviourEvent::SendData { data, .. },
+                    ..
+                }) => sent_block_numbers.push(data.number),
+                Poll::Pending => break,
+                Poll::Ready(event) => panic!("Unexpected event {event:?}."),
+            }
+        }
+    }
+
+    assert_eq!(sent_block_numbers, (0..NUM_BLOCKS).collect::<Vec<_>>());
+    assert_eq!(max_num_unwritten_messages, MAX_UNWRITTEN_MESSAGES);
+}
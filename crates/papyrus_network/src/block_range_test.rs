@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use futures::StreamExt;
+use libp2p::swarm::SwarmEvent;
+use libp2p_swarm_test::SwarmExt;
+use pretty_assertions::assert_eq;
+use prost::Message;
+use starknet_api::block::BlockNumber;
+
+use super::{BlockRangeData, BlockRangeDataKind, BlockRangeQuery, BlockRangeQueryError};
+use crate::messages::protobuf;
+use crate::streamed_data_protocol::behaviour::{Behaviour, Event};
+use crate::streamed_data_protocol::{CloseReason, Config, SessionId};
+use crate::test_utils::create_swarm_with_config;
+use crate::Direction;
+
+const MAX_COUNT: u32 = 10;
+
+fn header_data(block_number: BlockNumber) -> BlockRangeData {
+    BlockRangeData {
+        kind: Some(BlockRangeDataKind::Headers(protobuf::BlockHeadersResponse {
+            part: vec![protobuf::BlockHeadersResponsePart {
+                header_message: Some(protobuf::block_headers_response_part::HeaderMessage::Header(
+                    protobuf::BlockHeader { number: block_number.0, ..Default::default() },
+                )),
+            }],
+        })),
+    }
+}
+
+#[test]
+fn query_round_trip() {
+    let query = BlockRangeQuery { start: BlockNumber(5), count: 3, direction: Direction::Backward };
+    let bytes = query.encode_to_vec();
+    assert_eq!(BlockRangeQuery::decode(bytes.as_slice()).unwrap(), query);
+
+    // The query is encoded as a request of the specs.
+    assert_eq!(
+        protobuf::BlockHeadersRequest::decode(bytes.as_slice()).unwrap(),
+        protobuf::BlockHeadersRequest {
+            iteration: Some(protobuf::Iteration {
+                start: Some(protobuf::iteration::Start::BlockNumber(5)),
+                direction: protobuf::iteration::Direction::Backward.into(),
+                limit: 3,
+                step: 1,
+            }),
+        }
+    );
+}
+
+#[test]
+fn query_with_unsupported_iteration_fails_to_decode() {
+    let request_starting_from_hash = protobuf::BlockHeadersRequest {
+        iteration: Some(protobuf::Iteration {
+            start: Some(protobuf::iteration::Start::Header(protobuf::Hash::default())),
+            ..Default::default()
+        }),
+    };
+    assert!(
+        BlockRangeQuery::decode(request_starting_from_hash.encode_to_vec().as_slice()).is_err()
+    );
+
+    let request_with_step = protobuf::BlockHeadersRequest {
+        iteration: Some(protobuf::Iteration { step: 2, ..Default::default() }),
+    };
+    assert!(BlockRangeQuery::decode(request_with_step.encode_to_vec().as_slice()).is_err());
+}
+
+#[test]
+fn validate_count() {
+    let query = BlockRangeQuery { count: MAX_COUNT, ..Default::default() };
+    assert_eq!(query.validate(MAX_COUNT), Ok(()));
+
+    let query = BlockRangeQuery { count: MAX_COUNT + 1, ..Default::default() };
+    assert_eq!(
+        query.validate(MAX_COUNT),
+        Err(BlockRangeQueryError::CountTooLarge { count: MAX_COUNT + 1, max_count: MAX_COUNT })
+    );
+}
+
+#[tokio::test]
+async fn peer_answers_block_range_query() {
+    let config = Config { substream_timeout: Duration::from_secs(60), ..Config::get_test_config() };
+    let mut outbound_swarm = create_swarm_with_config(
+        |peer_id| Behaviour::<BlockRangeQuery, BlockRangeData>::new(config.clone(), peer_id),
+        &config,
+    );
+    let mut inbound_swarm = create_swarm_with_config(
+        |peer_id| Behaviour::<BlockRangeQuery, BlockRangeData>::new(config.clone(), peer_id),
+        &config,
+    );
+    inbound_swarm.listen().with_memory_addr_external().await;
+    outbound_swarm.connect(&mut inbound_swarm).await;
+    let inbound_peer_id = *inbound_swarm.local_peer_id();
+
+    // The inbound peer answers each valid query with a header for each block in the range.
+    tokio::spawn(async move {
+        while let Some(event) = inbound_swarm.next().await {
+            let SwarmEvent::Behaviour(Event::NewInboundSession {
+                query, inbound_session_id, ..
+            }) = event
+            else {
+                continue;
+            };
+            let behaviour = inbound_swarm.behaviour_mut();
+            if query.validate(MAX_COUNT).is_ok() {
+                for i in 0..u64::from(query.count) {
+                    let block_number = match query.direction {
+                        Direction::Forward => BlockNumber(query.start.0 + i),
+                        Direction::Backward => BlockNumber(query.start.0 - i),
+                    };
+                    behaviour.send_data(header_data(block_number), inbound_session_id).unwrap();
+                }
+            }
+            behaviour.close_session(inbound_session_id.into()).unwrap();
+        }
+    });
+
+    let query = BlockRangeQuery { start: BlockNumber(7), count: 3, direction: Direction::Backward };
+    let outbound_session_id =
+        outbound_swarm.behaviour_mut().send_query(query, inbound_peer_id, None, None).unwrap();
+    let mut received_data = Vec::new();
+    loop {
+        match outbound_swarm.next().await.unwrap() {
+            SwarmEvent::Behaviour(Event::ReceivedData { outbound_session_id: id, data }) => {
+                assert_eq!(id, outbound_session_id);
+                received_data.push(data);
+            }
+            SwarmEvent::Behaviour(Event::SessionClosedByPeer { session_id, reason }) => {
+                assert_eq!(session_id, SessionId::OutboundSessionId(outbound_session_id));
+                assert_matches!(reason, CloseReason::Completed);
+                break;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(
+        received_data,
+        [7, 6, 5].map(|block_number| header_data(BlockNumber(block_number))).to_vec()
+    );
+
+    // A query for too many blocks isn't answered.
+    let query = BlockRangeQuery { count: MAX_COUNT + 1, ..Default::default() };
+    outbound_swarm.behaviour_mut().send_query(query, inbound_peer_id, None, None).unwrap();
+    loop {
+        match outbound_swarm.next().await.unwrap() {
+            SwarmEvent::Behaviour(Event::ReceivedData { .. }) => {
+                panic!("Got data for a query that is too large.")
+            }
+            SwarmEvent::Behaviour(Event::SessionClosedByPeer { .. }) => break,
+            _ => {}
+        }
+    }
+}
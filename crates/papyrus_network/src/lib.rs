@@ -2,6 +2,8 @@
 /// to the [`Starknet p2p specs`]
 ///
 /// [`Starknet p2p specs`]: https://github.com/starknet-io/starknet-p2p-specs/
+pub mod block_range;
+pub mod block_range_stream;
 pub mod messages;
 pub mod streamed_data_protocol;
 #[cfg(test)]
@@ -9,7 +11,9 @@ mod test_utils;
 
 use starknet_api::block::{BlockHash, BlockNumber};
 
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub enum Direction {
+    #[default]
     Forward,
     Backward,
 }
@@ -1,9 +1,21 @@
 use std::time::Duration;
 
+use assert_matches::assert_matches;
 use futures::AsyncWriteExt;
 use pretty_assertions::assert_eq;
 
-use super::{read_message, write_message};
+use super::{
+    read_frame,
+    read_message,
+    write_close,
+    write_correlation_id,
+    write_message,
+    write_ping,
+    write_pong,
+    write_usize,
+    Frame,
+    ReadMessageError,
+};
 use crate::messages::protobuf;
 use crate::test_utils::{dummy_data, get_connected_streams};
 
@@ -40,3 +52,49 @@ async fn read_message_is_pending_when_other_stream_didnt_send() {
         .is_err()
     );
 }
+
+#[tokio::test]
+async fn read_message_fails_on_undecodable_bytes() {
+    let (mut stream1, mut stream2, _) = get_connected_streams().await;
+    // A varint field tag without the field's value.
+    let undecodable_bytes = [0x08];
+    write_usize(&mut stream1, undecodable_bytes.len()).await.unwrap();
+    stream1.write_all(&undecodable_bytes).await.unwrap();
+    stream1.close().await.unwrap();
+
+    assert_matches!(
+        read_message::<protobuf::BasicMessage, _>(&mut stream2).await,
+        Err(ReadMessageError::DeserializationFailed(_))
+    );
+}
+
+#[tokio::test]
+async fn read_frame_distinguishes_control_frames_from_messages() {
+    let (mut stream1, mut stream2, _) = get_connected_streams().await;
+    let message = dummy_data()[0].clone();
+    write_correlation_id(&mut stream1, 7).await.unwrap();
+    write_ping(&mut stream1).await.unwrap();
+    write_message(message.clone(), &mut stream1).await.unwrap();
+    write_pong(&mut stream1).await.unwrap();
+    write_close(&mut stream1, 1).await.unwrap();
+    stream1.close().await.unwrap();
+
+    assert_eq!(
+        read_frame::<protobuf::BasicMessage, _>(&mut stream2).await.unwrap(),
+        Some(Frame::Correlation(7))
+    );
+    assert_eq!(
+        read_frame::<protobuf::BasicMessage, _>(&mut stream2).await.unwrap(),
+        Some(Frame::Ping)
+    );
+    assert_eq!(read_frame(&mut stream2).await.unwrap(), Some(Frame::Message(message)));
+    assert_eq!(
+        read_frame::<protobuf::BasicMessage, _>(&mut stream2).await.unwrap(),
+        Some(Frame::Pong)
+    );
+    assert_eq!(
+        read_frame::<protobuf::BasicMessage, _>(&mut stream2).await.unwrap(),
+        Some(Frame::Close(1))
+    );
+    assert_eq!(read_frame::<protobuf::BasicMessage, _>(&mut stream2).await.unwrap(), None);
+}
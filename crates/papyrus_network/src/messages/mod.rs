@@ -13,6 +13,45 @@ use unsigned_varint::encode::usize_buffer;
 
 pub const MAX_MESSAGE_SIZE: usize = 1 << 20;
 
+// Keep-alive frames, close trailers and correlation tags are marked by length prefixes that can't
+// belong to a valid message.
+const PING_MARKER: usize = MAX_MESSAGE_SIZE + 1;
+const PONG_MARKER: usize = MAX_MESSAGE_SIZE + 2;
+const CLOSE_MARKER: usize = MAX_MESSAGE_SIZE + 3;
+const CORRELATION_MARKER: usize = MAX_MESSAGE_SIZE + 4;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadMessageError {
+    #[error(transparent)]
+    IOError(#[from] io::Error),
+    /// The bytes of the message were read successfully but they don't form a valid message.
+    #[error(transparent)]
+    DeserializationFailed(#[from] prost::DecodeError),
+}
+
+impl From<ReadMessageError> for io::Error {
+    fn from(error: ReadMessageError) -> Self {
+        match error {
+            ReadMessageError::IOError(io_error) => io_error,
+            ReadMessageError::DeserializationFailed(decode_error) => decode_error.into(),
+        }
+    }
+}
+
+/// A frame read from a stream that may carry keep-alive frames in between the messages.
+#[derive(Debug, PartialEq)]
+pub enum Frame<T> {
+    Message(T),
+    Ping,
+    Pong,
+    /// A trailer that the sender writes right before closing the stream, carrying a code for the
+    /// reason it closed the stream.
+    Close(usize),
+    /// A tag that the sender writes before the messages of a session, carrying an id that
+    /// correlates the responses of the session with its query.
+    Correlation(usize),
+}
+
 pub async fn write_message<T: Message, Stream: AsyncWrite + Unpin>(
     message: T,
     mut io: Stream,
@@ -27,21 +66,82 @@ pub async fn write_message<T: Message, Stream: AsyncWrite + Unpin>(
 
 pub async fn read_message<T: Message + Default, Stream: AsyncRead + Unpin>(
     mut io: Stream,
-) -> Result<Option<T>, io::Error> {
+) -> Result<Option<T>, ReadMessageError> {
     // This code is based on read_length_prefixed from libp2p v0.52 which was erased in v0.53.
     let Some(message_len) = read_usize(&mut io).await? else { return Ok(None) };
+    Ok(Some(read_message_content(message_len, io).await?))
+}
+
+/// Read either a message or a keep-alive frame. Returns None if the stream was closed before
+/// starting to read.
+pub async fn read_frame<T: Message + Default, Stream: AsyncRead + Unpin>(
+    mut io: Stream,
+) -> Result<Option<Frame<T>>, ReadMessageError> {
+    let Some(frame_len) = read_usize(&mut io).await? else { return Ok(None) };
+    let frame = match frame_len {
+        PING_MARKER => Frame::Ping,
+        PONG_MARKER => Frame::Pong,
+        CLOSE_MARKER => {
+            let Some(reason_code) = read_usize(&mut io).await? else {
+                return Err(ReadMessageError::IOError(io::ErrorKind::UnexpectedEof.into()));
+            };
+            Frame::Close(reason_code)
+        }
+        CORRELATION_MARKER => {
+            let Some(correlation_id) = read_usize(&mut io).await? else {
+                return Err(ReadMessageError::IOError(io::ErrorKind::UnexpectedEof.into()));
+            };
+            Frame::Correlation(correlation_id)
+        }
+        message_len => Frame::Message(read_message_content(message_len, io).await?),
+    };
+    Ok(Some(frame))
+}
+
+pub async fn write_ping<Stream: AsyncWrite + Unpin>(mut io: Stream) -> Result<(), io::Error> {
+    write_usize(&mut io, PING_MARKER).await?;
+    io.flush().await
+}
+
+pub async fn write_pong<Stream: AsyncWrite + Unpin>(mut io: Stream) -> Result<(), io::Error> {
+    write_usize(&mut io, PONG_MARKER).await?;
+    io.flush().await
+}
+
+pub async fn write_close<Stream: AsyncWrite + Unpin>(
+    mut io: Stream,
+    reason_code: usize,
+) -> Result<(), io::Error> {
+    write_usize(&mut io, CLOSE_MARKER).await?;
+    write_usize(&mut io, reason_code).await?;
+    io.flush().await
+}
+
+pub async fn write_correlation_id<Stream: AsyncWrite + Unpin>(
+    mut io: Stream,
+    correlation_id: usize,
+) -> Result<(), io::Error> {
+    write_usize(&mut io, CORRELATION_MARKER).await?;
+    write_usize(&mut io, correlation_id).await?;
+    io.flush().await
+}
+
+async fn read_message_content<T: Message + Default, Stream: AsyncRead + Unpin>(
+    message_len: usize,
+    mut io: Stream,
+) -> Result<T, ReadMessageError> {
     if message_len > MAX_MESSAGE_SIZE {
-        return Err(io::Error::new(
+        return Err(ReadMessageError::IOError(io::Error::new(
             io::ErrorKind::InvalidData,
             format!(
                 "Received data size ({message_len} bytes) exceeds maximum ({MAX_MESSAGE_SIZE} \
                  bytes)"
             ),
-        ));
+        )));
     }
     let mut buf = vec![0; message_len];
     io.read_exact(&mut buf).await?;
-    Ok(Some(T::decode(buf.as_slice())?))
+    Ok(T::decode(buf.as_slice())?)
 }
 
 // This code is based on read_varint from libp2p v0.52 which was erased in v0.53. The difference
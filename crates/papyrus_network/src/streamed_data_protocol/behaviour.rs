@@ -2,7 +2,9 @@
 #[path = "behaviour_test.rs"]
 mod behaviour_test;
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::io;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
@@ -10,9 +12,13 @@ use std::task::{Context, Poll};
 use std::time::Duration;
 
 use defaultmap::DefaultHashMap;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
 use libp2p::core::Endpoint;
-use libp2p::swarm::behaviour::ConnectionEstablished;
+use libp2p::swarm::behaviour::{ConnectionEstablished, DialFailure};
 use libp2p::swarm::{
+    CloseConnection,
     ConnectionClosed,
     ConnectionDenied,
     ConnectionHandler,
@@ -24,18 +30,44 @@ use libp2p::swarm::{
     ToSwarm,
 };
 use libp2p::{Multiaddr, PeerId};
+use tokio::time::Instant;
+use tracing::{debug, debug_span};
 
-use super::handler::{Handler, RequestFromBehaviourEvent, SessionError as HandlerSessionError};
+use super::handler::{
+    Handler,
+    InboundSessionQueueDepths,
+    RequestFromBehaviourEvent,
+    SessionError as HandlerSessionError,
+};
+use super::pending_events::{PendingEvents, PendingEventsOwner};
 use super::{
+    session_label,
+    CloseReason,
     Config,
     DataBound,
     GenericEvent,
     InboundSessionId,
     OutboundSessionId,
+    PeerScore,
     QueryBound,
     SessionId,
 };
 
+/// The name of the histogram of the time from sending a query until its outbound session is
+/// established.
+pub const NETWORK_OUTBOUND_SESSION_HANDSHAKE_LATENCY_SECONDS: &str =
+    "network_outbound_session_handshake_latency_seconds";
+
+/// The name of the histogram of the time from establishing an outbound session until getting the
+/// first response on it.
+pub const NETWORK_OUTBOUND_SESSION_FIRST_BYTE_LATENCY_SECONDS: &str =
+    "network_outbound_session_first_byte_latency_seconds";
+
+/// The name of the histogram of the time from establishing an outbound session until the peer
+/// closed it.
+pub const NETWORK_OUTBOUND_SESSION_TOTAL_TRANSFER_LATENCY_SECONDS: &str =
+    "network_outbound_session_total_transfer_latency_seconds";
+
 #[derive(thiserror::Error, Debug)]
 // TODO(shahak) remove allow(dead_code).
 #[allow(dead_code)]
@@ -44,14 +76,24 @@ pub(crate) enum SessionError {
     Timeout { substream_timeout: Duration },
     #[error(transparent)]
     IOError(#[from] io::Error),
-    #[error("Remote peer doesn't support the {protocol_name} protocol.")]
-    RemoteDoesntSupportProtocol { protocol_name: StreamProtocol },
+    #[error("Remote peer doesn't support any of the protocols {protocol_names:?}.")]
+    RemoteDoesntSupportProtocol { protocol_names: Vec<StreamProtocol> },
     // If there's a connection with a single session and it was closed because of another reason,
     // we might get ConnectionClosed instead of that reason because the swarm automatically closes
-    // a connection that has no sessions. If this is a problem, set the swarm's
-    // idle_connection_timeout to a non-zero number.
+    // a connection that has no sessions. Config::idle_connection_timeout keeps the connection
+    // open long enough for the real reason to be reported.
     #[error("Connection to remote peer closed.")]
     ConnectionClosed,
+    #[error("Remote peer didn't respond to a keep-alive ping in time.")]
+    KeepAliveTimeout,
+    #[error("Remote peer sent a message that couldn't be deserialized: {0}")]
+    DeserializationFailed(prost::DecodeError),
+    #[error("Remote peer answered with correlation id {received} instead of {expected}.")]
+    CorrelationMismatch { expected: usize, received: usize },
+    #[error("Too many inbound sessions are waiting for an answer.")]
+    TooManySessions,
+    #[error("The query didn't complete within its deadline of {} ms.", deadline.as_millis())]
+    QueryDeadlineExceeded { deadline: Duration },
 }
 
 impl<Query: QueryBound, Data: DataBound> From<GenericEvent<Query, Data, HandlerSessionError>>
@@ -59,9 +101,12 @@ impl<Query: QueryBound, Data: DataBound> From<GenericEvent<Query, Data, HandlerS
 {
     fn from(event: GenericEvent<Query, Data, HandlerSessionError>) -> Self {
         match event {
-            GenericEvent::NewInboundSession { query, inbound_session_id, peer_id } => {
-                Self::NewInboundSession { query, inbound_session_id, peer_id }
-            }
+            GenericEvent::NewInboundSession {
+                query,
+                inbound_session_id,
+                peer_id,
+                protocol_name,
+            } => Self::NewInboundSession { query, inbound_session_id, peer_id, protocol_name },
             GenericEvent::ReceivedData { outbound_session_id, data } => {
                 Self::ReceivedData { outbound_session_id, data }
             }
@@ -78,17 +123,47 @@ impl<Query: QueryBound, Data: DataBound> From<GenericEvent<Query, Data, HandlerS
             } => Self::SessionFailed { session_id, error: SessionError::IOError(error) },
             GenericEvent::SessionFailed {
                 session_id,
-                error: HandlerSessionError::RemoteDoesntSupportProtocol { protocol_name },
+                error: HandlerSessionError::RemoteDoesntSupportProtocol { protocol_names },
             } => Self::SessionFailed {
                 session_id,
-                error: SessionError::RemoteDoesntSupportProtocol { protocol_name },
+                error: SessionError::RemoteDoesntSupportProtocol { protocol_names },
+            },
+            GenericEvent::SessionFailed {
+                session_id,
+                error: HandlerSessionError::KeepAliveTimeout,
+            } => Self::SessionFailed { session_id, error: SessionError::KeepAliveTimeout },
+            GenericEvent::SessionFailed {
+                session_id,
+                error: HandlerSessionError::DeserializationFailed(decode_error),
+            } => Self::SessionFailed {
+                session_id,
+                error: SessionError::DeserializationFailed(decode_error),
+            },
+            GenericEvent::SessionFailed {
+                session_id,
+                error: HandlerSessionError::CorrelationMismatch { expected, received },
+            } => Self::SessionFailed {
+                session_id,
+                error: SessionError::CorrelationMismatch { expected, received },
             },
             GenericEvent::SessionClosedByRequest { session_id } => {
                 Self::SessionClosedByRequest { session_id }
             }
-            GenericEvent::SessionClosedByPeer { session_id } => {
-                Self::SessionClosedByPeer { session_id }
+            GenericEvent::SessionClosedByPeer { session_id, reason } => {
+                Self::SessionClosedByPeer { session_id, reason }
+            }
+            GenericEvent::OutboundSessionNegotiated { outbound_session_id, protocol_name } => {
+                Self::OutboundSessionNegotiated { outbound_session_id, protocol_name }
             }
+            GenericEvent::OutboundSessionEstablished { outbound_session_id, peer_id } => {
+                Self::OutboundSessionEstablished { outbound_session_id, peer_id }
+            }
+            GenericEvent::OutboundSessionLatencyMeasured {
+                outbound_session_id,
+                peer_id,
+                latency,
+            } => Self::OutboundSessionLatencyMeasured { outbound_session_id, peer_id, latency },
+            GenericEvent::PeerDisconnected { peer_id } => Self::PeerDisconnected { peer_id },
         }
     }
 }
@@ -100,57 +175,306 @@ pub(crate) type Event<Query, Data> = GenericEvent<Query, Data, SessionError>;
 pub(crate) struct SessionIdNotFoundError;
 
 #[derive(thiserror::Error, Debug)]
-#[error("We are not connected to the given peer. Dial to the given peer and try again.")]
+#[error(
+    "We are not connected to the given peer and not dialing it. Dial to the given peer and try \
+     again."
+)]
 pub(crate) struct PeerNotConnected;
 
+#[derive(thiserror::Error, Debug)]
+#[error("The peer is banned.")]
+pub(crate) struct PeerBanned;
+
+#[derive(thiserror::Error, Debug)]
+#[error("The given peer is the local peer. Sessions can't be opened with the local peer.")]
+pub(crate) struct CannotDialSelf;
+
+#[derive(thiserror::Error, Debug)]
+#[error("The peer isn't allowed to connect, either since it's denied or since it isn't allowed.")]
+pub(crate) struct PeerNotAllowed;
+
+#[derive(thiserror::Error, Debug)]
+#[error("The behaviour is shutting down.")]
+pub(crate) struct ShuttingDown;
+
+#[derive(thiserror::Error, Debug)]
+#[error("The session is half closed.")]
+pub(crate) struct SessionHalfClosed;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum SendQueryError {
+    #[error(transparent)]
+    CannotDialSelf(#[from] CannotDialSelf),
+    #[error(transparent)]
+    PeerNotConnected(#[from] PeerNotConnected),
+    #[error(transparent)]
+    ShuttingDown(#[from] ShuttingDown),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum SendDataError {
+    #[error(transparent)]
+    SessionIdNotFound(#[from] SessionIdNotFoundError),
+    #[error(transparent)]
+    ShuttingDown(#[from] ShuttingDown),
+    #[error(transparent)]
+    SessionHalfClosed(#[from] SessionHalfClosed),
+}
+
+// How much a failed session lowers the score of the peer, according to how likely it is that the
+// failure is the peer's fault.
+fn session_failure_penalty(error: &SessionError) -> PeerScore {
+    match error {
+        SessionError::DeserializationFailed(_) | SessionError::CorrelationMismatch { .. } => 10,
+        SessionError::Timeout { .. } | SessionError::KeepAliveTimeout => 5,
+        SessionError::IOError(_) => 2,
+        SessionError::RemoteDoesntSupportProtocol { .. }
+        | SessionError::QueryDeadlineExceeded { .. } => 1,
+        SessionError::ConnectionClosed | SessionError::TooManySessions => 0,
+    }
+}
+
+// How much an outbound session that the peer completed raises its score. The score never rises
+// above its initial value of 0, so a peer recovers from occasional failures but can't build up
+// credit for future ones.
+const COMPLETED_SESSION_CREDIT: PeerScore = 1;
+
+/// The priority of a query. Queries that wait for a connection to their peer are sent by
+/// descending priority once the connection is established.
+pub(crate) type QueryPriority = u8;
+
+// A query that waits for a connection to its peer. Queries with the same priority are ordered by
+// the order they were sent in.
+#[derive(Clone)]
+struct PendingQuery<Query> {
+    priority: QueryPriority,
+    outbound_session_id: OutboundSessionId,
+    query: Query,
+    query_sent: Instant,
+}
+
+impl<Query> Ord for PendingQuery<Query> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| {
+            // Outbound session ids are increasing, so the smaller id was sent first.
+            other.outbound_session_id.value.cmp(&self.outbound_session_id.value)
+        })
+    }
+}
+
+impl<Query> PartialOrd for PendingQuery<Query> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<Query> PartialEq for PendingQuery<Query> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<Query> Eq for PendingQuery<Query> {}
+
+/// Aggregates of the durations of one stage of the outbound sessions with a peer.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LatencyStats {
+    /// The number of sessions the stage was measured for.
+    pub count: usize,
+    /// The sum of the measured durations.
+    pub total: Duration,
+    /// The longest measured duration.
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Returns the average measured duration, or None if nothing was measured.
+    pub fn mean(&self) -> Option<Duration> {
+        self.total.checked_div(u32::try_from(self.count).ok()?)
+    }
+
+    fn record(&mut self, duration: Duration) {
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+}
+
+/// The latencies of the outbound sessions with a peer, split by stage so that peers that are slow
+/// to connect can be told apart from peers that are slow to respond.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PeerLatencyStats {
+    /// From sending the query until the session was established. Includes waiting for the
+    /// connection, waiting for the session's turn to be established, the protocol negotiation and
+    /// the retries.
+    pub handshake: LatencyStats,
+    /// From establishing the session until getting the first response on it.
+    pub first_byte: LatencyStats,
+    /// From establishing the session until the peer closed it.
+    pub total_transfer: LatencyStats,
+}
+
+// The times of the stages an outbound session went through.
+struct OutboundSessionTimes {
+    query_sent: Instant,
+    established: Option<Instant>,
+    got_first_response: bool,
+}
+
 // TODO(shahak) remove allow dead code.
 #[allow(dead_code)]
 pub(crate) struct Behaviour<Query: QueryBound, Data: DataBound> {
     config: Config,
-    pending_events: VecDeque<ToSwarm<Event<Query, Data>, RequestFromBehaviourEvent<Query, Data>>>,
-    pending_queries: DefaultHashMap<PeerId, Vec<(Query, OutboundSessionId)>>,
+    local_peer_id: PeerId,
+    pending_events:
+        PendingEvents<ToSwarm<Event<Query, Data>, RequestFromBehaviourEvent<Query, Data>>>,
+    pending_queries: DefaultHashMap<PeerId, BinaryHeap<PendingQuery<Query>>>,
     connection_ids_map: DefaultHashMap<PeerId, HashSet<ConnectionId>>,
+    dialing_connection_ids_map: DefaultHashMap<PeerId, HashSet<ConnectionId>>,
     session_id_to_peer_id_and_connection_id: HashMap<SessionId, (PeerId, ConnectionId)>,
+    // The query and the number of retries done so far for each outbound session that can still be
+    // retried.
+    outbound_session_id_to_query_and_num_retries: HashMap<OutboundSessionId, (Query, usize)>,
+    pending_outbound_session_retries: FuturesUnordered<BoxFuture<'static, OutboundSessionId>>,
+    // Resolve when the deadlines of the outbound sessions that have one elapse, whether or not the
+    // sessions are still open.
+    pending_outbound_session_deadlines:
+        FuturesUnordered<BoxFuture<'static, (OutboundSessionId, Duration)>>,
+    // Outbound sessions that are being closed since their deadline elapsed, and their deadlines.
+    // Their closing is reported as a failure.
+    deadline_exceeded_outbound_session_id_to_deadline: HashMap<OutboundSessionId, Duration>,
+    // The times of the stages of each outbound session that has a connection, from the time its
+    // query was sent.
+    outbound_session_id_to_times: HashMap<OutboundSessionId, OutboundSessionTimes>,
+    // Kept after the peer disconnects, so that its history is available when it reconnects.
+    peer_id_to_latency_stats: DefaultHashMap<PeerId, PeerLatencyStats>,
+    peer_scores: DefaultHashMap<PeerId, PeerScore>,
+    // The time each banned peer is banned until. None means the peer is banned until it's
+    // unbanned manually.
+    banned_peers: HashMap<PeerId, Option<Instant>>,
+    // Inbound sessions for which no data was sent and that weren't closed yet.
+    unanswered_inbound_session_ids: HashSet<InboundSessionId>,
+    // Inbound sessions that were half closed and that the remote peer didn't close yet.
+    half_closed_inbound_session_ids: HashSet<InboundSessionId>,
+    // Inbound sessions that were rejected, either because there were too many unanswered inbound
+    // sessions or because of a shutdown, and the connection each of them is on. The handler's
+    // reports about them are ignored, since they were either already reported as failed or never
+    // reported at all.
+    rejected_inbound_session_id_to_connection_id: HashMap<InboundSessionId, ConnectionId>,
+    // Outbound sessions whose query was passed to their connection and that weren't negotiated
+    // and didn't end yet.
+    establishing_outbound_session_ids: HashSet<OutboundSessionId>,
+    // Outbound sessions whose query waits for fewer outbound sessions to be established, in the
+    // order they were started. Sessions that ended while waiting are skipped.
+    outbound_sessions_waiting_for_establishment: VecDeque<(OutboundSessionId, Query)>,
+    // The protocol that was last negotiated with each connected peer.
+    peer_id_to_protocol_name: HashMap<PeerId, StreamProtocol>,
+    is_shutting_down: bool,
     next_outbound_session_id: OutboundSessionId,
     next_inbound_session_id: Arc<AtomicUsize>,
+    // Updated by the handlers as the inbound sessions queue and write messages.
+    inbound_session_queue_depths: InboundSessionQueueDepths,
+    // The number of data messages of each inbound session that are in pending_events, which the
+    // queue depths of the handlers don't include yet.
+    inbound_session_id_to_num_pending_messages: HashMap<InboundSessionId, usize>,
 }
 
 // TODO(shahak) remove allow dead code.
 #[allow(dead_code)]
 impl<Query: QueryBound, Data: DataBound> Behaviour<Query, Data> {
-    pub fn new(config: Config) -> Self {
+    /// Creates a behaviour for the swarm of the given local peer. Queries to the local peer and
+    /// connections with it are rejected.
+    pub fn new(config: Config, local_peer_id: PeerId) -> Self {
+        let pending_events = PendingEvents::new(
+            PendingEventsOwner::Behaviour,
+            config.pending_events_warning_threshold,
+        );
         Self {
             config,
-            pending_events: Default::default(),
+            local_peer_id,
+            pending_events,
             pending_queries: Default::default(),
             connection_ids_map: Default::default(),
+            dialing_connection_ids_map: Default::default(),
             session_id_to_peer_id_and_connection_id: Default::default(),
+            outbound_session_id_to_query_and_num_retries: Default::default(),
+            pending_outbound_session_retries: Default::default(),
+            pending_outbound_session_deadlines: Default::default(),
+            deadline_exceeded_outbound_session_id_to_deadline: Default::default(),
+            outbound_session_id_to_times: Default::default(),
+            peer_id_to_latency_stats: Default::default(),
+            peer_scores: Default::default(),
+            banned_peers: Default::default(),
+            unanswered_inbound_session_ids: Default::default(),
+            half_closed_inbound_session_ids: Default::default(),
+            rejected_inbound_session_id_to_connection_id: Default::default(),
+            establishing_outbound_session_ids: Default::default(),
+            outbound_sessions_waiting_for_establishment: Default::default(),
+            peer_id_to_protocol_name: Default::default(),
+            is_shutting_down: false,
             next_outbound_session_id: Default::default(),
             next_inbound_session_id: Arc::new(Default::default()),
+            inbound_session_queue_depths: Default::default(),
+            inbound_session_id_to_num_pending_messages: Default::default(),
         }
     }
 
     /// Send query to the given peer and start a new outbound session with it. Return the id of the
     /// new session.
+    /// If the peer is being dialed, the query is sent once the connection is established. Queries
+    /// that wait for the same peer are sent by descending priority (the default priority is the
+    /// lowest), and queries with the same priority are sent in the order they were given.
+    /// Several queries can be sent to the same peer at once. Each of them gets its own substream
+    /// of the connection, and their data is received concurrently.
+    /// If a deadline is given and the session doesn't end within it, the session is closed and a
+    /// SessionFailed event with QueryDeadlineExceeded is reported. The deadline includes waiting
+    /// for the connection, unlike Config::substream_timeout.
     pub fn send_query(
         &mut self,
         query: Query,
         peer_id: PeerId,
-    ) -> Result<OutboundSessionId, PeerNotConnected> {
-        let connection_id =
-            *self.connection_ids_map.get(peer_id).iter().next().ok_or(PeerNotConnected)?;
+        priority: Option<QueryPriority>,
+        deadline: Option<Duration>,
+    ) -> Result<OutboundSessionId, SendQueryError> {
+        if self.is_shutting_down {
+            return Err(ShuttingDown.into());
+        }
+        if peer_id == self.local_peer_id {
+            return Err(CannotDialSelf.into());
+        }
+        let connection_id = self.connection_ids_map.get(peer_id).iter().next().copied();
+        if connection_id.is_none() && self.dialing_connection_ids_map.get(peer_id).is_empty() {
+            return Err(PeerNotConnected.into());
+        }
 
+        let query_sent = Instant::now();
         let outbound_session_id = self.next_outbound_session_id;
         self.next_outbound_session_id.value += 1;
+        if let Some(deadline) = deadline {
+            self.pending_outbound_session_deadlines.push(
+                async move {
+                    tokio::time::sleep(deadline).await;
+                    (outbound_session_id, deadline)
+                }
+                .boxed(),
+            );
+        }
 
-        self.session_id_to_peer_id_and_connection_id
-            .insert(outbound_session_id.into(), (peer_id, connection_id));
-
-        self.pending_events.push_back(ToSwarm::NotifyHandler {
-            peer_id,
-            handler: NotifyHandler::One(connection_id),
-            event: RequestFromBehaviourEvent::CreateOutboundSession { query, outbound_session_id },
-        });
+        match connection_id {
+            Some(connection_id) => self.start_outbound_session(
+                query,
+                outbound_session_id,
+                peer_id,
+                connection_id,
+                query_sent,
+            ),
+            None => self.pending_queries.get_mut(peer_id).push(PendingQuery {
+                priority: priority.unwrap_or_default(),
+                outbound_session_id,
+                query,
+                query_sent,
+            }),
+        }
 
         Ok(outbound_session_id)
     }
@@ -160,9 +484,18 @@ impl<Query: QueryBound, Data: DataBound> Behaviour<Query, Data> {
         &mut self,
         data: Data,
         inbound_session_id: InboundSessionId,
-    ) -> Result<(), SessionIdNotFoundError> {
+    ) -> Result<(), SendDataError> {
+        if self.is_shutting_down {
+            return Err(ShuttingDown.into());
+        }
         let (peer_id, connection_id) =
             self.get_peer_id_and_connection_id_from_session_id(inbound_session_id.into())?;
+        if self.half_closed_inbound_session_ids.contains(&inbound_session_id) {
+            return Err(SessionHalfClosed.into());
+        }
+        self.unanswered_inbound_session_ids.remove(&inbound_session_id);
+        *self.inbound_session_id_to_num_pending_messages.entry(inbound_session_id).or_default() +=
+            1;
         self.pending_events.push_back(ToSwarm::NotifyHandler {
             peer_id,
             handler: NotifyHandler::One(connection_id),
@@ -171,11 +504,42 @@ impl<Query: QueryBound, Data: DataBound> Behaviour<Query, Data> {
         Ok(())
     }
 
+    /// Instruct behaviour to finish sending the data that was given to an inbound session and let
+    /// the remote peer know that no more data will be sent. A SessionClosedByRequest event will be
+    /// reported once the remote peer closes the session as well. Data can't be sent on the session
+    /// afterwards.
+    pub fn half_close_inbound_session(
+        &mut self,
+        inbound_session_id: InboundSessionId,
+    ) -> Result<(), SessionIdNotFoundError> {
+        let (peer_id, connection_id) =
+            self.get_peer_id_and_connection_id_from_session_id(inbound_session_id.into())?;
+        self.unanswered_inbound_session_ids.remove(&inbound_session_id);
+        self.half_closed_inbound_session_ids.insert(inbound_session_id);
+        self.pending_events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: RequestFromBehaviourEvent::HalfCloseInboundSession { inbound_session_id },
+        });
+        Ok(())
+    }
+
     /// Instruct behaviour to close session. A corresponding SessionClosedByRequest event will be
     /// reported when the session is closed.
     pub fn close_session(&mut self, session_id: SessionId) -> Result<(), SessionIdNotFoundError> {
+        if let SessionId::OutboundSessionId(outbound_session_id) = session_id {
+            if self.remove_pending_query(outbound_session_id) {
+                self.pending_events.push_back(ToSwarm::GenerateEvent(
+                    Event::SessionClosedByRequest { session_id },
+                ));
+                return Ok(());
+            }
+        }
         let (peer_id, connection_id) =
             self.get_peer_id_and_connection_id_from_session_id(session_id)?;
+        if let SessionId::InboundSessionId(inbound_session_id) = session_id {
+            self.unanswered_inbound_session_ids.remove(&inbound_session_id);
+        }
         self.pending_events.push_back(ToSwarm::NotifyHandler {
             peer_id,
             handler: NotifyHandler::One(connection_id),
@@ -184,6 +548,466 @@ impl<Query: QueryBound, Data: DataBound> Behaviour<Query, Data> {
         Ok(())
     }
 
+    /// Instruct behaviour to close an outbound session once the data that was already received on
+    /// it is reported, without waiting for more data. ReceivedData events are reported for the
+    /// remaining data, followed by a SessionClosedByRequest event.
+    pub fn drain_and_close_outbound_session(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+    ) -> Result<(), SessionIdNotFoundError> {
+        if self.remove_pending_query(outbound_session_id) {
+            self.pending_events.push_back(ToSwarm::GenerateEvent(Event::SessionClosedByRequest {
+                session_id: outbound_session_id.into(),
+            }));
+            return Ok(());
+        }
+        let (peer_id, connection_id) =
+            self.get_peer_id_and_connection_id_from_session_id(outbound_session_id.into())?;
+        self.pending_events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: RequestFromBehaviourEvent::DrainAndCloseOutboundSession { outbound_session_id },
+        });
+        Ok(())
+    }
+
+    /// Instruct behaviour to close an inbound session before all the data for its query was sent,
+    /// letting the remote peer know that the session was aborted. A corresponding
+    /// SessionClosedByRequest event will be reported when the session is closed.
+    pub fn abort_inbound_session(
+        &mut self,
+        inbound_session_id: InboundSessionId,
+    ) -> Result<(), SessionIdNotFoundError> {
+        let (peer_id, connection_id) =
+            self.get_peer_id_and_connection_id_from_session_id(inbound_session_id.into())?;
+        self.unanswered_inbound_session_ids.remove(&inbound_session_id);
+        self.pending_events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: RequestFromBehaviourEvent::AbortInboundSession { inbound_session_id },
+        });
+        Ok(())
+    }
+
+    /// Returns the number of inbound sessions for which no data was sent and that weren't closed
+    /// yet.
+    pub fn num_unanswered_inbound_sessions(&self) -> usize {
+        self.unanswered_inbound_session_ids.len()
+    }
+
+    /// Close all the sessions, including the ones of queries that wait for a connection. A
+    /// corresponding SessionClosedByRequest event will be reported for each session when it's
+    /// closed. From now on, new inbound sessions are closed as soon as they're opened and queries
+    /// and data can't be sent.
+    pub fn shutdown(&mut self) {
+        if self.is_shutting_down {
+            return;
+        }
+        self.is_shutting_down = true;
+        for (session_id, (peer_id, connection_id)) in &self.session_id_to_peer_id_and_connection_id
+        {
+            self.pending_events.push_back(ToSwarm::NotifyHandler {
+                peer_id: *peer_id,
+                handler: NotifyHandler::One(*connection_id),
+                event: RequestFromBehaviourEvent::CloseSession { session_id: *session_id },
+            });
+        }
+        self.unanswered_inbound_session_ids.clear();
+        // The waiting outbound sessions are closed along with the rest of the sessions.
+        self.outbound_sessions_waiting_for_establishment.clear();
+        for pending_queries in self.pending_queries.values_mut() {
+            for pending_query in pending_queries.drain() {
+                self.pending_events.push_back(ToSwarm::GenerateEvent(
+                    Event::SessionClosedByRequest {
+                        session_id: pending_query.outbound_session_id.into(),
+                    },
+                ));
+            }
+        }
+    }
+
+    /// Returns whether shutdown was called, all the sessions were closed and all the events that
+    /// close them were passed to the swarm.
+    pub fn is_shutdown_complete(&self) -> bool {
+        self.is_shutting_down
+            && self.session_id_to_peer_id_and_connection_id.is_empty()
+            && self.pending_events.is_empty()
+    }
+
+    /// Returns the currently open sessions and the peers they're with.
+    pub fn active_sessions(&self) -> Vec<(SessionId, PeerId)> {
+        self.session_id_to_peer_id_and_connection_id
+            .iter()
+            .map(|(session_id, (peer_id, _connection_id))| (*session_id, *peer_id))
+            .collect()
+    }
+
+    /// Returns the currently open sessions with the given peer.
+    pub fn sessions_for_peer(&self, peer_id: PeerId) -> Vec<SessionId> {
+        self.session_id_to_peer_id_and_connection_id
+            .iter()
+            .filter(|(_session_id, (session_peer_id, _connection_id))| *session_peer_id == peer_id)
+            .map(|(session_id, _)| *session_id)
+            .collect()
+    }
+
+    /// Returns whether there's an established connection with the given peer, so that a query
+    /// sent to it won't fail with `PeerNotConnected`.
+    pub fn is_connected(&self, peer_id: &PeerId) -> bool {
+        !self.connection_ids_map.get(peer_id).is_empty()
+    }
+
+    /// Returns the peers that there's an established connection with.
+    pub fn connected_peers(&self) -> Vec<PeerId> {
+        self.connection_ids_map
+            .iter()
+            .filter(|(_peer_id, connection_ids)| !connection_ids.is_empty())
+            .map(|(peer_id, _connection_ids)| *peer_id)
+            .collect()
+    }
+
+    /// Returns the number of messages of the given inbound session that its connection received
+    /// and didn't write to the stream yet, or None if the session isn't open. A queue that keeps
+    /// growing means the remote peer reads slower than data is sent to it.
+    pub fn inbound_session_queue_depth(
+        &self,
+        inbound_session_id: InboundSessionId,
+    ) -> Option<usize> {
+        self.inbound_session_queue_depths
+            .lock()
+            .expect("Lock should not be poisoned")
+            .get(&inbound_session_id)
+            .copied()
+    }
+
+    /// Returns the number of data messages that were sent to the given inbound session and weren't
+    /// written to the stream yet, whether they were passed to the session's connection or not, or
+    /// None if the session isn't open. Unlike [`Behaviour::inbound_session_queue_depth`], this
+    /// counts a message as soon as it's sent, so it can bound how much data is sent ahead.
+    pub fn num_unwritten_messages(&self, inbound_session_id: InboundSessionId) -> Option<usize> {
+        if !self.session_id_to_peer_id_and_connection_id.contains_key(&inbound_session_id.into()) {
+            return None;
+        }
+        let num_pending_messages = self
+            .inbound_session_id_to_num_pending_messages
+            .get(&inbound_session_id)
+            .copied()
+            .unwrap_or_default();
+        Some(
+            num_pending_messages
+                + self.inbound_session_queue_depth(inbound_session_id).unwrap_or_default(),
+        )
+    }
+
+    /// Returns the protocol that was last negotiated with the given peer, or None if no session
+    /// was opened with it since it connected.
+    pub fn peer_protocol(&self, peer_id: PeerId) -> Option<StreamProtocol> {
+        self.peer_id_to_protocol_name.get(&peer_id).cloned()
+    }
+
+    /// Returns a human-readable label of the given open session, of the form
+    /// `<peer_id_short>/<direction>/<n>`. The label is attached to the log spans of the session.
+    pub fn session_label(&self, session_id: SessionId) -> Option<String> {
+        self.session_id_to_peer_id_and_connection_id
+            .get(&session_id)
+            .map(|(peer_id, _connection_id)| session_label(*peer_id, session_id))
+    }
+
+    /// Ban the given peer for the given duration. Connections to the peer are closed and new
+    /// connections with it are denied until the ban ends.
+    pub fn ban_peer(&mut self, peer_id: PeerId, duration: Duration) {
+        self.peer_scores.remove(&peer_id);
+        self.banned_peers.insert(peer_id, Instant::now().checked_add(duration));
+        self.pending_events
+            .push_back(ToSwarm::CloseConnection { peer_id, connection: CloseConnection::All });
+    }
+
+    /// Lift the ban of the given peer, if it's banned.
+    pub fn unban_peer(&mut self, peer_id: PeerId) {
+        self.banned_peers.remove(&peer_id);
+    }
+
+    /// Returns the latencies of the outbound sessions with the given peer so far, including
+    /// sessions from before it last disconnected.
+    pub fn peer_latency_stats(&self, peer_id: PeerId) -> PeerLatencyStats {
+        *self.peer_id_to_latency_stats.get(peer_id)
+    }
+
+    /// Returns the number of events that are waiting to be passed on to the swarm. A queue that
+    /// keeps growing means the swarm isn't polled fast enough.
+    pub fn num_pending_events(&self) -> usize {
+        self.pending_events.len()
+    }
+
+    // Unlike a ban, which depends on the behaviour of the peer, whether a peer is allowed is
+    // fixed by the config.
+    fn is_peer_allowed(&self, peer_id: PeerId) -> bool {
+        !self.config.denied_peers.contains(&peer_id)
+            && self.config.allowed_peers.as_ref().is_none_or(|allowed| allowed.contains(&peer_id))
+    }
+
+    fn is_peer_banned(&mut self, peer_id: PeerId) -> bool {
+        match self.banned_peers.get(&peer_id) {
+            Some(Some(banned_until)) if *banned_until <= Instant::now() => {
+                self.banned_peers.remove(&peer_id);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    fn penalize_peer(&mut self, peer_id: PeerId, penalty: PeerScore) {
+        let score = self.peer_scores.get_mut(peer_id);
+        *score = score.saturating_sub(penalty);
+        if *score < self.config.peer_score_threshold {
+            self.ban_peer(peer_id, self.config.peer_ban_cooldown);
+        }
+    }
+
+    fn credit_peer(&mut self, peer_id: PeerId) {
+        if *self.peer_scores.get(peer_id) < 0 {
+            let score = self.peer_scores.get_mut(peer_id);
+            *score = score.saturating_add(COMPLETED_SESSION_CREDIT).min(0);
+        }
+    }
+
+    fn start_outbound_session(
+        &mut self,
+        query: Query,
+        outbound_session_id: OutboundSessionId,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        query_sent: Instant,
+    ) {
+        self.session_id_to_peer_id_and_connection_id
+            .insert(outbound_session_id.into(), (peer_id, connection_id));
+        self.outbound_session_id_to_times.insert(
+            outbound_session_id,
+            OutboundSessionTimes { query_sent, established: None, got_first_response: false },
+        );
+        if self.config.max_outbound_retries > 0 {
+            self.outbound_session_id_to_query_and_num_retries
+                .insert(outbound_session_id, (query.clone(), 0));
+        }
+
+        if self.config.max_pending_outbound_sessions.is_some_and(|max_sessions| {
+            self.establishing_outbound_session_ids.len() >= max_sessions
+        }) {
+            self.outbound_sessions_waiting_for_establishment
+                .push_back((outbound_session_id, query));
+            return;
+        }
+        self.establish_outbound_session(query, outbound_session_id, peer_id, connection_id);
+    }
+
+    fn establish_outbound_session(
+        &mut self,
+        query: Query,
+        outbound_session_id: OutboundSessionId,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+    ) {
+        self.establishing_outbound_session_ids.insert(outbound_session_id);
+        self.pending_events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: RequestFromBehaviourEvent::CreateOutboundSession { query, outbound_session_id },
+        });
+    }
+
+    // Establishes the outbound sessions that wait for their turn, as long as there's room for
+    // them.
+    fn establish_waiting_outbound_sessions(&mut self) {
+        while !self.config.max_pending_outbound_sessions.is_some_and(|max_sessions| {
+            self.establishing_outbound_session_ids.len() >= max_sessions
+        }) {
+            let Some((outbound_session_id, query)) =
+                self.outbound_sessions_waiting_for_establishment.pop_front()
+            else {
+                return;
+            };
+            let Some((peer_id, connection_id)) = self
+                .session_id_to_peer_id_and_connection_id
+                .get(&outbound_session_id.into())
+                .copied()
+            else {
+                continue;
+            };
+            self.establish_outbound_session(query, outbound_session_id, peer_id, connection_id);
+        }
+    }
+
+    fn finish_establishing_outbound_session(&mut self, outbound_session_id: OutboundSessionId) {
+        if self.establishing_outbound_session_ids.remove(&outbound_session_id) {
+            self.establish_waiting_outbound_sessions();
+        }
+    }
+
+    // Returns whether the given outbound session was waiting for a connection.
+    fn remove_pending_query(&mut self, outbound_session_id: OutboundSessionId) -> bool {
+        let mut removed = false;
+        for pending_queries in self.pending_queries.values_mut() {
+            pending_queries.retain(|pending_query| {
+                let should_remove = pending_query.outbound_session_id == outbound_session_id;
+                removed |= should_remove;
+                !should_remove
+            });
+        }
+        removed
+    }
+
+    // Schedules another attempt to create the given outbound session. Returns false if the session
+    // has no retries left.
+    fn schedule_outbound_session_retry(&mut self, outbound_session_id: OutboundSessionId) -> bool {
+        let Some((_, num_retries)) =
+            self.outbound_session_id_to_query_and_num_retries.get_mut(&outbound_session_id)
+        else {
+            return false;
+        };
+        if *num_retries >= self.config.max_outbound_retries {
+            return false;
+        }
+        *num_retries += 1;
+        let retry_backoff = self.config.retry_backoff;
+        self.pending_outbound_session_retries.push(
+            async move {
+                tokio::time::sleep(retry_backoff).await;
+                outbound_session_id
+            }
+            .boxed(),
+        );
+        true
+    }
+
+    // Closes the given outbound session since its deadline elapsed, unless it already ended.
+    fn close_outbound_session_past_deadline(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+        deadline: Duration,
+    ) {
+        // The sessions are closed anyway during the shutdown.
+        if self.is_shutting_down {
+            return;
+        }
+        if self.remove_pending_query(outbound_session_id) {
+            self.pending_events.push_back(ToSwarm::GenerateEvent(Event::SessionFailed {
+                session_id: outbound_session_id.into(),
+                error: SessionError::QueryDeadlineExceeded { deadline },
+            }));
+            return;
+        }
+        let Some((peer_id, connection_id)) =
+            self.session_id_to_peer_id_and_connection_id.get(&outbound_session_id.into()).copied()
+        else {
+            return;
+        };
+        // The session isn't retried once its deadline elapsed.
+        self.outbound_session_id_to_query_and_num_retries.remove(&outbound_session_id);
+        self.deadline_exceeded_outbound_session_id_to_deadline
+            .insert(outbound_session_id, deadline);
+        self.pending_events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: RequestFromBehaviourEvent::CloseSession {
+                session_id: outbound_session_id.into(),
+            },
+        });
+    }
+
+    fn remove_session(&mut self, session_id: SessionId) {
+        self.session_id_to_peer_id_and_connection_id.remove(&session_id);
+        match session_id {
+            SessionId::OutboundSessionId(outbound_session_id) => {
+                self.outbound_session_id_to_query_and_num_retries.remove(&outbound_session_id);
+                self.outbound_session_id_to_times.remove(&outbound_session_id);
+                self.deadline_exceeded_outbound_session_id_to_deadline.remove(&outbound_session_id);
+                self.finish_establishing_outbound_session(outbound_session_id);
+            }
+            SessionId::InboundSessionId(inbound_session_id) => {
+                self.unanswered_inbound_session_ids.remove(&inbound_session_id);
+                self.half_closed_inbound_session_ids.remove(&inbound_session_id);
+            }
+        }
+    }
+
+    fn record_outbound_session_established(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+        peer_id: PeerId,
+    ) {
+        let Some(times) = self.outbound_session_id_to_times.get_mut(&outbound_session_id) else {
+            return;
+        };
+        if times.established.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        let handshake_latency = now - times.query_sent;
+        times.established = Some(now);
+        metrics::histogram!(
+            NETWORK_OUTBOUND_SESSION_HANDSHAKE_LATENCY_SECONDS,
+            handshake_latency.as_secs_f64()
+        );
+        self.peer_id_to_latency_stats.get_mut(peer_id).handshake.record(handshake_latency);
+    }
+
+    // Reports the latency of the given outbound session and records its first byte latency if this
+    // is its first response.
+    fn record_outbound_session_response(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+        peer_id: PeerId,
+    ) {
+        let Some(times) = self.outbound_session_id_to_times.get_mut(&outbound_session_id) else {
+            return;
+        };
+        if times.got_first_response {
+            return;
+        }
+        times.got_first_response = true;
+        self.pending_events.push_back(ToSwarm::GenerateEvent(
+            Event::OutboundSessionLatencyMeasured {
+                outbound_session_id,
+                peer_id,
+                latency: times.query_sent.elapsed(),
+            },
+        ));
+        let Some(established) = times.established else {
+            return;
+        };
+        let first_byte_latency = established.elapsed();
+        metrics::histogram!(
+            NETWORK_OUTBOUND_SESSION_FIRST_BYTE_LATENCY_SECONDS,
+            first_byte_latency.as_secs_f64()
+        );
+        self.peer_id_to_latency_stats.get_mut(peer_id).first_byte.record(first_byte_latency);
+    }
+
+    fn record_outbound_session_closed_by_peer(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+        peer_id: PeerId,
+    ) {
+        self.record_outbound_session_response(outbound_session_id, peer_id);
+        let Some(established) = self
+            .outbound_session_id_to_times
+            .get(&outbound_session_id)
+            .and_then(|times| times.established)
+        else {
+            return;
+        };
+        let total_transfer_latency = established.elapsed();
+        metrics::histogram!(
+            NETWORK_OUTBOUND_SESSION_TOTAL_TRANSFER_LATENCY_SECONDS,
+            total_transfer_latency.as_secs_f64()
+        );
+        self.peer_id_to_latency_stats
+            .get_mut(peer_id)
+            .total_transfer
+            .record(total_transfer_latency);
+    }
+
     fn get_peer_id_and_connection_id_from_session_id(
         &self,
         session_id: SessionId,
@@ -206,7 +1030,21 @@ impl<Query: QueryBound, Data: DataBound> NetworkBehaviour for Behaviour<Query, D
         _local_addr: &Multiaddr,
         _remote_addr: &Multiaddr,
     ) -> Result<Self::ConnectionHandler, ConnectionDenied> {
-        Ok(Handler::new(self.config.clone(), self.next_inbound_session_id.clone(), peer_id))
+        if peer_id == self.local_peer_id {
+            return Err(ConnectionDenied::new(CannotDialSelf));
+        }
+        if !self.is_peer_allowed(peer_id) {
+            return Err(ConnectionDenied::new(PeerNotAllowed));
+        }
+        if self.is_peer_banned(peer_id) {
+            return Err(ConnectionDenied::new(PeerBanned));
+        }
+        Ok(Handler::new(
+            self.config.clone(),
+            self.next_inbound_session_id.clone(),
+            self.inbound_session_queue_depths.clone(),
+            peer_id,
+        ))
     }
 
     fn handle_established_outbound_connection(
@@ -216,7 +1054,40 @@ impl<Query: QueryBound, Data: DataBound> NetworkBehaviour for Behaviour<Query, D
         _addr: &Multiaddr,
         _role_override: Endpoint,
     ) -> Result<Self::ConnectionHandler, ConnectionDenied> {
-        Ok(Handler::new(self.config.clone(), self.next_inbound_session_id.clone(), peer_id))
+        if peer_id == self.local_peer_id {
+            return Err(ConnectionDenied::new(CannotDialSelf));
+        }
+        if !self.is_peer_allowed(peer_id) {
+            return Err(ConnectionDenied::new(PeerNotAllowed));
+        }
+        if self.is_peer_banned(peer_id) {
+            return Err(ConnectionDenied::new(PeerBanned));
+        }
+        Ok(Handler::new(
+            self.config.clone(),
+            self.next_inbound_session_id.clone(),
+            self.inbound_session_queue_depths.clone(),
+            peer_id,
+        ))
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        _addresses: &[Multiaddr],
+        _effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        if maybe_peer == Some(self.local_peer_id) {
+            return Err(ConnectionDenied::new(CannotDialSelf));
+        }
+        if let Some(peer_id) = maybe_peer {
+            if !self.is_peer_allowed(peer_id) {
+                return Err(ConnectionDenied::new(PeerNotAllowed));
+            }
+            self.dialing_connection_ids_map.get_mut(peer_id).insert(connection_id);
+        }
+        Ok(vec![])
     }
 
     fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
@@ -227,11 +1098,70 @@ impl<Query: QueryBound, Data: DataBound> NetworkBehaviour for Behaviour<Query, D
                 ..
             }) => {
                 self.connection_ids_map.get_mut(peer_id).insert(connection_id);
+                self.dialing_connection_ids_map.get_mut(peer_id).remove(&connection_id);
+                let mut pending_queries = self.pending_queries.remove(&peer_id).unwrap_or_default();
+                while let Some(PendingQuery { query, outbound_session_id, query_sent, .. }) =
+                    pending_queries.pop()
+                {
+                    self.start_outbound_session(
+                        query,
+                        outbound_session_id,
+                        peer_id,
+                        connection_id,
+                        query_sent,
+                    );
+                }
             }
-            FromSwarm::ConnectionClosed(ConnectionClosed { peer_id, connection_id, .. }) => {
+            FromSwarm::DialFailure(DialFailure {
+                peer_id: Some(peer_id), connection_id, ..
+            }) => {
+                self.dialing_connection_ids_map.get_mut(peer_id).remove(&connection_id);
+                if self.dialing_connection_ids_map.get(peer_id).is_empty()
+                    && self.connection_ids_map.get(peer_id).is_empty()
+                {
+                    // There's no connection left that the pending queries can be sent on.
+                    for pending_query in self.pending_queries.remove(&peer_id).unwrap_or_default() {
+                        self.pending_events.push_back(ToSwarm::GenerateEvent(
+                            Event::SessionFailed {
+                                session_id: pending_query.outbound_session_id.into(),
+                                error: SessionError::ConnectionClosed,
+                            },
+                        ));
+                    }
+                }
+            }
+            FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                connection_id,
+                remaining_established,
+                ..
+            }) => {
+                let connection_ids = self.connection_ids_map.get_mut(peer_id);
+                connection_ids.remove(&connection_id);
+                if connection_ids.is_empty() {
+                    self.connection_ids_map.remove(&peer_id);
+                }
+                if remaining_established == 0 {
+                    self.peer_id_to_protocol_name.remove(&peer_id);
+                }
                 self.session_id_to_peer_id_and_connection_id.retain(
                     |session_id, (session_peer_id, session_connection_id)| {
                         if peer_id == *session_peer_id && connection_id == *session_connection_id {
+                            match session_id {
+                                SessionId::OutboundSessionId(outbound_session_id) => {
+                                    self.outbound_session_id_to_query_and_num_retries
+                                        .remove(outbound_session_id);
+                                    self.outbound_session_id_to_times.remove(outbound_session_id);
+                                    self.deadline_exceeded_outbound_session_id_to_deadline
+                                        .remove(outbound_session_id);
+                                }
+                                SessionId::InboundSessionId(inbound_session_id) => {
+                                    self.unanswered_inbound_session_ids.remove(inbound_session_id);
+                                    self.half_closed_inbound_session_ids.remove(inbound_session_id);
+                                    self.inbound_session_id_to_num_pending_messages
+                                        .remove(inbound_session_id);
+                                }
+                            }
                             self.pending_events.push_back(ToSwarm::GenerateEvent(
                                 Event::SessionFailed {
                                     session_id: *session_id,
@@ -244,6 +1174,20 @@ impl<Query: QueryBound, Data: DataBound> NetworkBehaviour for Behaviour<Query, D
                         }
                     },
                 );
+                self.rejected_inbound_session_id_to_connection_id.retain(
+                    |_inbound_session_id, session_connection_id| {
+                        connection_id != *session_connection_id
+                    },
+                );
+                self.establishing_outbound_session_ids.retain(|outbound_session_id| {
+                    self.session_id_to_peer_id_and_connection_id
+                        .contains_key(&(*outbound_session_id).into())
+                });
+                self.establish_waiting_outbound_sessions();
+                if remaining_established == 0 {
+                    self.pending_events
+                        .push_back(ToSwarm::GenerateEvent(Event::PeerDisconnected { peer_id }));
+                }
             }
             _ => {}
         }
@@ -255,30 +1199,190 @@ impl<Query: QueryBound, Data: DataBound> NetworkBehaviour for Behaviour<Query, D
         connection_id: ConnectionId,
         event: <Self::ConnectionHandler as ConnectionHandler>::ToBehaviour,
     ) {
+        let session_id =
+            event.session_id().expect("The handler should only report events about sessions.");
+        let _span = debug_span!("session", label = %session_label(peer_id, session_id)).entered();
+        // An outbound session that the behaviour already ended, e.g. since its deadline elapsed,
+        // isn't reported again.
+        if matches!(session_id, SessionId::OutboundSessionId(_))
+            && !self.session_id_to_peer_id_and_connection_id.contains_key(&session_id)
+        {
+            debug!("Ignoring an event of an outbound session that already ended.");
+            return;
+        }
         let converted_event = event.into();
+        if let Event::NewInboundSession { protocol_name, .. }
+        | Event::OutboundSessionNegotiated { protocol_name, .. } = &converted_event
+        {
+            self.peer_id_to_protocol_name.insert(peer_id, protocol_name.clone());
+        }
+        let mut penalty = None;
         match converted_event {
+            // The application isn't told about inbound sessions that were opened during the
+            // shutdown, since they're closed right away.
+            Event::NewInboundSession { inbound_session_id, .. } if self.is_shutting_down => {
+                self.rejected_inbound_session_id_to_connection_id
+                    .insert(inbound_session_id, connection_id);
+                self.pending_events.push_back(ToSwarm::NotifyHandler {
+                    peer_id,
+                    handler: NotifyHandler::One(connection_id),
+                    event: RequestFromBehaviourEvent::CloseSession {
+                        session_id: inbound_session_id.into(),
+                    },
+                });
+                return;
+            }
+            Event::NewInboundSession { inbound_session_id, .. }
+                if self.config.max_unanswered_inbound_sessions.is_some_and(|max_sessions| {
+                    self.unanswered_inbound_session_ids.len() >= max_sessions
+                }) =>
+            {
+                self.rejected_inbound_session_id_to_connection_id
+                    .insert(inbound_session_id, connection_id);
+                self.pending_events.push_back(ToSwarm::NotifyHandler {
+                    peer_id,
+                    handler: NotifyHandler::One(connection_id),
+                    event: RequestFromBehaviourEvent::AbortInboundSession { inbound_session_id },
+                });
+                self.pending_events.push_back(ToSwarm::GenerateEvent(Event::SessionFailed {
+                    session_id: inbound_session_id.into(),
+                    error: SessionError::TooManySessions,
+                }));
+                return;
+            }
             Event::NewInboundSession { inbound_session_id, .. } => {
                 self.session_id_to_peer_id_and_connection_id
                     .insert(inbound_session_id.into(), (peer_id, connection_id));
+                self.unanswered_inbound_session_ids.insert(inbound_session_id);
             }
-            Event::SessionFailed { session_id, .. }
-            | Event::SessionClosedByRequest { session_id, .. } => {
-                self.session_id_to_peer_id_and_connection_id.remove(&session_id);
+            Event::SessionFailed {
+                session_id: SessionId::InboundSessionId(inbound_session_id),
+                ..
             }
-            Event::SessionClosedByPeer { session_id } => {
-                self.session_id_to_peer_id_and_connection_id.remove(&session_id);
+            | Event::SessionClosedByRequest {
+                session_id: SessionId::InboundSessionId(inbound_session_id),
+            } if self
+                .rejected_inbound_session_id_to_connection_id
+                .remove(&inbound_session_id)
+                .is_some() =>
+            {
+                return;
             }
-            _ => {}
+            // The remote peer might not advertise the protocol yet if it has just connected, so
+            // the session is retried instead of being reported as failed.
+            Event::SessionFailed {
+                session_id: SessionId::OutboundSessionId(outbound_session_id),
+                error: SessionError::RemoteDoesntSupportProtocol { .. },
+            } if self.schedule_outbound_session_retry(outbound_session_id) => {
+                return;
+            }
+            Event::ReceivedData { outbound_session_id, .. } => {
+                // The session was created successfully, so it won't need to be retried.
+                self.outbound_session_id_to_query_and_num_retries.remove(&outbound_session_id);
+                self.record_outbound_session_response(outbound_session_id, peer_id);
+            }
+            Event::SessionFailed { session_id, ref error } => {
+                self.remove_session(session_id);
+                penalty = Some(session_failure_penalty(error));
+            }
+            Event::SessionClosedByPeer { session_id, reason } => {
+                if let SessionId::OutboundSessionId(outbound_session_id) = session_id {
+                    self.record_outbound_session_closed_by_peer(outbound_session_id, peer_id);
+                    if reason == CloseReason::Completed {
+                        self.credit_peer(peer_id);
+                    }
+                }
+                self.remove_session(session_id);
+            }
+            // The session was closed by the behaviour since its deadline elapsed.
+            Event::SessionClosedByRequest {
+                session_id: SessionId::OutboundSessionId(outbound_session_id),
+            } if self
+                .deadline_exceeded_outbound_session_id_to_deadline
+                .contains_key(&outbound_session_id) =>
+            {
+                let deadline =
+                    self.deadline_exceeded_outbound_session_id_to_deadline[&outbound_session_id];
+                self.remove_session(outbound_session_id.into());
+                let error = SessionError::QueryDeadlineExceeded { deadline };
+                let penalty = session_failure_penalty(&error);
+                self.pending_events.push_back(ToSwarm::GenerateEvent(Event::SessionFailed {
+                    session_id: outbound_session_id.into(),
+                    error,
+                }));
+                self.penalize_peer(peer_id, penalty);
+                return;
+            }
+            Event::SessionClosedByRequest { session_id } => {
+                self.remove_session(session_id);
+            }
+            // The negotiated protocol was already recorded, and the user is only told that the
+            // session was established.
+            Event::OutboundSessionNegotiated { outbound_session_id, .. } => {
+                self.record_outbound_session_established(outbound_session_id, peer_id);
+                self.pending_events.push_back(ToSwarm::GenerateEvent(
+                    Event::OutboundSessionEstablished { outbound_session_id, peer_id },
+                ));
+                self.finish_establishing_outbound_session(outbound_session_id);
+                return;
+            }
+            // The handler doesn't establish sessions, measure latencies or track peers.
+            Event::OutboundSessionEstablished { .. }
+            | Event::OutboundSessionLatencyMeasured { .. }
+            | Event::PeerDisconnected { .. } => {}
         }
         self.pending_events.push_back(ToSwarm::GenerateEvent(converted_event));
+        // Penalizing only after reporting the failure, so that the failure is reported before the
+        // connection is closed if the peer gets banned.
+        if let Some(penalty) = penalty {
+            self.penalize_peer(peer_id, penalty);
+        }
     }
 
     fn poll(
         &mut self,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> Poll<ToSwarm<Self::ToSwarm, <Self::ConnectionHandler as ConnectionHandler>::FromBehaviour>>
     {
+        while let Poll::Ready(Some(outbound_session_id)) =
+            self.pending_outbound_session_retries.poll_next_unpin(cx)
+        {
+            // The session might have been closed while waiting for the retry.
+            let (Some((query, _)), Some((peer_id, connection_id))) = (
+                self.outbound_session_id_to_query_and_num_retries.get(&outbound_session_id),
+                self.session_id_to_peer_id_and_connection_id.get(&outbound_session_id.into()),
+            ) else {
+                continue;
+            };
+            self.pending_events.push_back(ToSwarm::NotifyHandler {
+                peer_id: *peer_id,
+                handler: NotifyHandler::One(*connection_id),
+                event: RequestFromBehaviourEvent::CreateOutboundSession {
+                    query: query.clone(),
+                    outbound_session_id,
+                },
+            });
+        }
+        while let Poll::Ready(Some((outbound_session_id, deadline))) =
+            self.pending_outbound_session_deadlines.poll_next_unpin(cx)
+        {
+            self.close_outbound_session_past_deadline(outbound_session_id, deadline);
+        }
         if let Some(event) = self.pending_events.pop_front() {
+            if let ToSwarm::NotifyHandler {
+                event: RequestFromBehaviourEvent::SendData { inbound_session_id, .. },
+                ..
+            } = &event
+            {
+                if let Entry::Occupied(mut entry) =
+                    self.inbound_session_id_to_num_pending_messages.entry(*inbound_session_id)
+                {
+                    *entry.get_mut() -= 1;
+                    if *entry.get() == 0 {
+                        entry.remove();
+                    }
+                }
+            }
             return Poll::Ready(event);
         }
         Poll::Pending
@@ -1,16 +1,49 @@
+use std::collections::HashSet;
+use std::io;
 use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use assert_matches::assert_matches;
 use futures::{FutureExt, Stream, StreamExt};
 use libp2p::core::{ConnectedPoint, Endpoint};
-use libp2p::swarm::behaviour::ConnectionEstablished;
-use libp2p::swarm::{ConnectionClosed, ConnectionId, FromSwarm, NetworkBehaviour, ToSwarm};
+use libp2p::swarm::behaviour::{ConnectionEstablished, DialFailure};
+use libp2p::swarm::{
+    ConnectionClosed,
+    ConnectionId,
+    DialError,
+    FromSwarm,
+    NetworkBehaviour,
+    StreamProtocol,
+    ToSwarm,
+};
 use libp2p::{Multiaddr, PeerId};
 
-use super::super::handler::{RequestFromBehaviourEvent, ToBehaviourEvent};
-use super::super::{Config, DataBound, InboundSessionId, OutboundSessionId, QueryBound, SessionId};
-use super::{Behaviour, Event, SessionError};
+use super::super::handler::{
+    RequestFromBehaviourEvent,
+    SessionError as HandlerSessionError,
+    ToBehaviourEvent,
+};
+use super::super::{
+    CloseReason,
+    Config,
+    DataBound,
+    InboundSessionId,
+    OutboundSessionId,
+    QueryBound,
+    SessionId,
+};
+use super::{
+    Behaviour,
+    Event,
+    LatencyStats,
+    PeerLatencyStats,
+    SendDataError,
+    SendQueryError,
+    SessionError,
+};
 use crate::messages::protobuf;
 use crate::test_utils::dummy_data;
 
@@ -46,6 +79,30 @@ fn simulate_connection_established<Query: QueryBound, Data: DataBound>(
     }));
 }
 
+fn simulate_dialing<Query: QueryBound, Data: DataBound>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: PeerId,
+) {
+    // This is the same connection_id from simulate_connection_established
+    let connection_id = ConnectionId::new_unchecked(0);
+    behaviour
+        .handle_pending_outbound_connection(connection_id, Some(peer_id), &[], Endpoint::Dialer)
+        .unwrap();
+}
+
+fn simulate_dial_failure<Query: QueryBound, Data: DataBound>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: PeerId,
+) {
+    // This is the same connection_id from simulate_dialing
+    let connection_id = ConnectionId::new_unchecked(0);
+    behaviour.on_swarm_event(FromSwarm::DialFailure(DialFailure {
+        peer_id: Some(peer_id),
+        error: &DialError::Aborted,
+        connection_id,
+    }));
+}
+
 fn simulate_listener_connection<Query: QueryBound, Data: DataBound>(
     behaviour: &mut Behaviour<Query, Data>,
     peer_id: PeerId,
@@ -71,11 +128,25 @@ fn simulate_new_inbound_session<Query: QueryBound, Data: DataBound>(
     peer_id: PeerId,
     inbound_session_id: InboundSessionId,
     query: Query,
+) {
+    let protocol_name = behaviour.config.protocol_names[0].clone();
+    behaviour.on_connection_handler_event(
+        peer_id,
+        ConnectionId::new_unchecked(0),
+        ToBehaviourEvent::NewInboundSession { query, inbound_session_id, peer_id, protocol_name },
+    );
+}
+
+fn simulate_outbound_session_negotiated<Query: QueryBound, Data: DataBound>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: PeerId,
+    outbound_session_id: OutboundSessionId,
+    protocol_name: StreamProtocol,
 ) {
     behaviour.on_connection_handler_event(
         peer_id,
         ConnectionId::new_unchecked(0),
-        ToBehaviourEvent::NewInboundSession { query, inbound_session_id, peer_id },
+        ToBehaviourEvent::OutboundSessionNegotiated { outbound_session_id, protocol_name },
     );
 }
 
@@ -108,11 +179,41 @@ fn simulate_session_closed_by_peer<Query: QueryBound, Data: DataBound>(
     behaviour: &mut Behaviour<Query, Data>,
     peer_id: PeerId,
     session_id: SessionId,
+    reason: CloseReason,
+) {
+    behaviour.on_connection_handler_event(
+        peer_id,
+        ConnectionId::new_unchecked(0),
+        ToBehaviourEvent::SessionClosedByPeer { session_id, reason },
+    );
+}
+
+fn simulate_remote_doesnt_support_protocol<Query: QueryBound, Data: DataBound>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: PeerId,
+    outbound_session_id: OutboundSessionId,
+) {
+    let protocol_names = behaviour.config.protocol_names.clone();
+    behaviour.on_connection_handler_event(
+        peer_id,
+        ConnectionId::new_unchecked(0),
+        ToBehaviourEvent::SessionFailed {
+            session_id: outbound_session_id.into(),
+            error: HandlerSessionError::RemoteDoesntSupportProtocol { protocol_names },
+        },
+    );
+}
+
+fn simulate_session_failed<Query: QueryBound, Data: DataBound>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: PeerId,
+    session_id: SessionId,
+    error: HandlerSessionError,
 ) {
     behaviour.on_connection_handler_event(
         peer_id,
         ConnectionId::new_unchecked(0),
-        ToBehaviourEvent::SessionClosedByPeer { session_id },
+        ToBehaviourEvent::SessionFailed { session_id, error },
     );
 }
 
@@ -125,8 +226,7 @@ fn simulate_connection_closed<Query: QueryBound, Data: DataBound>(
     behaviour.on_swarm_event(FromSwarm::ConnectionClosed(ConnectionClosed {
         peer_id,
         connection_id,
-        // Filling these fields with arbitrary values since the behaviour doesn't look at these
-        // fields.
+        // Filling this field with an arbitrary value since the behaviour doesn't look at it.
         endpoint: &ConnectedPoint::Dialer {
             address: Multiaddr::empty(),
             role_override: Endpoint::Dialer,
@@ -167,9 +267,11 @@ async fn validate_new_inbound_session_event<Query: QueryBound + PartialEq, Data:
             query: event_query,
             inbound_session_id: event_inbound_session_id,
             peer_id: event_peer_id,
+            protocol_name: event_protocol_name,
         }) if event_query == *query
             && event_inbound_session_id == inbound_session_id
             && event_peer_id == *peer_id
+            && event_protocol_name == behaviour.config.protocol_names[0]
     );
 }
 
@@ -187,6 +289,40 @@ async fn validate_received_data_event<Query: QueryBound, Data: DataBound + Parti
     );
 }
 
+async fn validate_outbound_session_established_event<Query: QueryBound, Data: DataBound>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: &PeerId,
+    outbound_session_id: OutboundSessionId,
+) {
+    let event = behaviour.next().await.unwrap();
+    assert_matches!(
+        event,
+        ToSwarm::GenerateEvent(Event::OutboundSessionEstablished {
+            outbound_session_id: event_outbound_session_id,
+            peer_id: event_peer_id,
+        }) if event_outbound_session_id == outbound_session_id && event_peer_id == *peer_id
+    );
+}
+
+async fn validate_outbound_session_latency_event<Query: QueryBound, Data: DataBound>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: &PeerId,
+    outbound_session_id: OutboundSessionId,
+) -> Duration {
+    let event = behaviour.next().await.unwrap();
+    let ToSwarm::GenerateEvent(Event::OutboundSessionLatencyMeasured {
+        outbound_session_id: event_outbound_session_id,
+        peer_id: event_peer_id,
+        latency,
+    }) = event
+    else {
+        panic!("Got unexpected event {:?} when expecting OutboundSessionLatencyMeasured", event);
+    };
+    assert_eq!(event_outbound_session_id, outbound_session_id);
+    assert_eq!(event_peer_id, *peer_id);
+    latency
+}
+
 async fn validate_request_send_data_event<Query: QueryBound, Data: DataBound + PartialEq>(
     behaviour: &mut Behaviour<Query, Data>,
     peer_id: &PeerId,
@@ -225,6 +361,28 @@ async fn validate_request_close_session_event<Query: QueryBound, Data: DataBound
     );
 }
 
+async fn validate_request_abort_inbound_session_event<
+    Query: QueryBound,
+    Data: DataBound + PartialEq,
+>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: &PeerId,
+    inbound_session_id: InboundSessionId,
+) {
+    let event = behaviour.next().await.unwrap();
+    assert_matches!(
+        event,
+        ToSwarm::NotifyHandler {
+            peer_id: event_peer_id,
+            event: RequestFromBehaviourEvent::AbortInboundSession {
+                inbound_session_id: event_inbound_session_id
+            },
+            ..
+        } if *peer_id == event_peer_id
+            && inbound_session_id == event_inbound_session_id
+    );
+}
+
 async fn validate_session_closed_by_request_event<
     Query: QueryBound,
     Data: DataBound + PartialEq,
@@ -244,13 +402,15 @@ async fn validate_session_closed_by_request_event<
 async fn validate_session_closed_by_peer_event<Query: QueryBound, Data: DataBound + PartialEq>(
     behaviour: &mut Behaviour<Query, Data>,
     session_id: SessionId,
+    reason: CloseReason,
 ) {
     let event = behaviour.next().await.unwrap();
     assert_matches!(
         event,
         ToSwarm::GenerateEvent(Event::SessionClosedByPeer {
-            session_id: event_session_id
-        }) if event_session_id == session_id
+            session_id: event_session_id,
+            reason: event_reason,
+        }) if event_session_id == session_id && event_reason == reason
     );
 }
 
@@ -261,8 +421,10 @@ fn validate_no_events<Query: QueryBound, Data: DataBound>(behaviour: &mut Behavi
 
 #[tokio::test]
 async fn process_inbound_session() {
-    let mut behaviour =
-        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(Config::get_test_config());
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
 
     let query = protobuf::BasicMessage::default();
     let peer_id = PeerId::random();
@@ -294,16 +456,82 @@ async fn process_inbound_session() {
     validate_no_events(&mut behaviour);
 }
 
+#[tokio::test]
+async fn abort_inbound_session() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+    let inbound_session_id = InboundSessionId::default();
+
+    simulate_listener_connection(&mut behaviour, peer_id);
+    simulate_new_inbound_session(&mut behaviour, peer_id, inbound_session_id, query.clone());
+    validate_new_inbound_session_event(&mut behaviour, &peer_id, inbound_session_id, &query).await;
+
+    behaviour.abort_inbound_session(inbound_session_id).unwrap();
+    validate_request_abort_inbound_session_event(&mut behaviour, &peer_id, inbound_session_id)
+        .await;
+    validate_no_events(&mut behaviour);
+
+    simulate_session_closed_by_request(&mut behaviour, peer_id, inbound_session_id.into());
+    validate_session_closed_by_request_event(&mut behaviour, inbound_session_id.into()).await;
+    validate_no_events(&mut behaviour);
+}
+
+#[tokio::test]
+async fn half_closed_inbound_session_rejects_data_until_closed() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+    let inbound_session_id = InboundSessionId::default();
+
+    simulate_listener_connection(&mut behaviour, peer_id);
+    simulate_new_inbound_session(&mut behaviour, peer_id, inbound_session_id, query.clone());
+    validate_new_inbound_session_event(&mut behaviour, &peer_id, inbound_session_id, &query).await;
+
+    behaviour.half_close_inbound_session(inbound_session_id).unwrap();
+    let event = behaviour.next().await.unwrap();
+    assert_matches!(
+        event,
+        ToSwarm::NotifyHandler {
+            peer_id: event_peer_id,
+            event: RequestFromBehaviourEvent::HalfCloseInboundSession {
+                inbound_session_id: event_inbound_session_id
+            },
+            ..
+        } if peer_id == event_peer_id && inbound_session_id == event_inbound_session_id
+    );
+    validate_no_events(&mut behaviour);
+    assert_eq!(behaviour.num_unanswered_inbound_sessions(), 0);
+    assert_matches!(
+        behaviour.send_data(dummy_data()[0].clone(), inbound_session_id),
+        Err(SendDataError::SessionHalfClosed(_))
+    );
+
+    simulate_session_closed_by_request(&mut behaviour, peer_id, inbound_session_id.into());
+    validate_session_closed_by_request_event(&mut behaviour, inbound_session_id.into()).await;
+    validate_no_events(&mut behaviour);
+}
+
 #[tokio::test]
 async fn create_and_process_outbound_session() {
-    let mut behaviour =
-        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(Config::get_test_config());
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
 
     let query = protobuf::BasicMessage::default();
     let peer_id = PeerId::random();
 
     simulate_connection_established(&mut behaviour, peer_id);
-    let outbound_session_id = behaviour.send_query(query.clone(), peer_id).unwrap();
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
 
     validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
         .await;
@@ -314,6 +542,7 @@ async fn create_and_process_outbound_session() {
         simulate_received_data(&mut behaviour, peer_id, data.clone(), outbound_session_id);
     }
 
+    validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id).await;
     for data in &dummy_data_vec {
         validate_received_data_event(&mut behaviour, data, outbound_session_id).await;
     }
@@ -331,36 +560,51 @@ async fn create_and_process_outbound_session() {
 
 #[tokio::test]
 async fn outbound_session_closed_by_peer() {
-    let mut behaviour =
-        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(Config::get_test_config());
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
 
     let query = protobuf::BasicMessage::default();
     let peer_id = PeerId::random();
 
     simulate_connection_established(&mut behaviour, peer_id);
-    let outbound_session_id = behaviour.send_query(query.clone(), peer_id).unwrap();
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
 
     // Consume the event to create an outbound session.
     behaviour.next().await.unwrap();
 
-    simulate_session_closed_by_peer(&mut behaviour, peer_id, outbound_session_id.into());
+    simulate_session_closed_by_peer(
+        &mut behaviour,
+        peer_id,
+        outbound_session_id.into(),
+        CloseReason::Aborted,
+    );
 
-    validate_session_closed_by_peer_event(&mut behaviour, outbound_session_id.into()).await;
+    validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id).await;
+    validate_session_closed_by_peer_event(
+        &mut behaviour,
+        outbound_session_id.into(),
+        CloseReason::Aborted,
+    )
+    .await;
     validate_no_events(&mut behaviour);
 }
 
 // TODO(shahak): Test the other variants of SessionError.
 #[tokio::test]
 async fn connection_closed() {
-    let mut behaviour =
-        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(Config::get_test_config());
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
 
     let peer_id = PeerId::random();
 
     simulate_connection_established(&mut behaviour, peer_id);
 
     let query = protobuf::BasicMessage::default();
-    let outbound_session_id = behaviour.send_query(query.clone(), peer_id).unwrap();
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
 
     // Consume the event to create an outbound session.
     behaviour.next().await.unwrap();
@@ -399,32 +643,1195 @@ async fn connection_closed() {
     );
 }
 
-#[test]
-fn close_non_existing_session_fails() {
-    let mut behaviour =
-        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(Config::get_test_config());
-    behaviour.close_session(SessionId::InboundSessionId(InboundSessionId::default())).unwrap_err();
-    behaviour
-        .close_session(SessionId::OutboundSessionId(OutboundSessionId::default()))
-        .unwrap_err();
+#[tokio::test]
+async fn unwritten_messages_of_closed_connection_are_forgotten() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id);
+    let inbound_session_id = InboundSessionId::default();
+    simulate_new_inbound_session(
+        &mut behaviour,
+        peer_id,
+        inbound_session_id,
+        protobuf::BasicMessage::default(),
+    );
+    behaviour.next().await.unwrap();
+
+    // The messages are still waiting to be passed to the connection when it closes.
+    for _ in 0..2 {
+        behaviour.send_data(protobuf::BasicMessage::default(), inbound_session_id).unwrap();
+    }
+    assert_eq!(behaviour.num_unwritten_messages(inbound_session_id), Some(2));
+    simulate_connection_closed(&mut behaviour, peer_id);
+    assert!(behaviour.inbound_session_id_to_num_pending_messages.is_empty());
+    assert_eq!(behaviour.num_unwritten_messages(inbound_session_id), None);
 }
 
-#[test]
-fn send_data_non_existing_session_fails() {
-    let mut behaviour =
-        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(Config::get_test_config());
-    for data in dummy_data() {
-        behaviour.send_data(data, InboundSessionId::default()).unwrap_err();
+#[tokio::test]
+async fn peer_disconnected_is_reported_once_after_its_sessions_fail() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+    let inbound_session_id = InboundSessionId::default();
+    simulate_new_inbound_session(&mut behaviour, peer_id, inbound_session_id, query.clone());
+    validate_new_inbound_session_event(&mut behaviour, &peer_id, inbound_session_id, &query).await;
+
+    // The peer is still connected after one of its connections is closed.
+    behaviour.on_swarm_event(FromSwarm::ConnectionClosed(ConnectionClosed {
+        peer_id,
+        connection_id: ConnectionId::new_unchecked(1),
+        endpoint: &ConnectedPoint::Dialer {
+            address: Multiaddr::empty(),
+            role_override: Endpoint::Dialer,
+        },
+        remaining_established: 1,
+    }));
+    validate_no_events(&mut behaviour);
+
+    simulate_connection_closed(&mut behaviour, peer_id);
+    let mut failed_session_ids = HashSet::new();
+    for _ in 0..2 {
+        assert_matches!(
+            behaviour.next().await.unwrap(),
+            ToSwarm::GenerateEvent(Event::SessionFailed {
+                session_id,
+                error: SessionError::ConnectionClosed,
+            }) => failed_session_ids.insert(session_id)
+        );
     }
+    assert_eq!(
+        failed_session_ids,
+        HashSet::from([outbound_session_id.into(), inbound_session_id.into()])
+    );
+    assert_matches!(
+        behaviour.next().await.unwrap(),
+        ToSwarm::GenerateEvent(Event::PeerDisconnected { peer_id: disconnected_peer_id })
+            if disconnected_peer_id == peer_id
+    );
+    validate_no_events(&mut behaviour);
 }
 
-#[test]
-fn send_query_peer_not_connected_fails() {
-    let mut behaviour =
-        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(Config::get_test_config());
+#[tokio::test]
+async fn active_sessions_are_grouped_by_peer() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
+    let peer_id1 = PeerId::random();
+    let peer_id2 = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id1);
+    simulate_connection_established(&mut behaviour, peer_id2);
+
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_id1 = behaviour.send_query(query.clone(), peer_id1, None, None).unwrap();
+    let outbound_session_id2 = behaviour.send_query(query.clone(), peer_id2, None, None).unwrap();
+    let inbound_session_id1 = InboundSessionId { value: 1 };
+    let inbound_session_id2 = InboundSessionId { value: 2 };
+    simulate_new_inbound_session(&mut behaviour, peer_id1, inbound_session_id1, query.clone());
+    simulate_new_inbound_session(&mut behaviour, peer_id2, inbound_session_id2, query.clone());
+
+    let peer1_sessions: HashSet<SessionId> =
+        HashSet::from([outbound_session_id1.into(), inbound_session_id1.into()]);
+    let peer2_sessions: HashSet<SessionId> =
+        HashSet::from([outbound_session_id2.into(), inbound_session_id2.into()]);
+    assert_eq!(
+        behaviour.active_sessions().into_iter().collect::<HashSet<_>>(),
+        peer1_sessions
+            .iter()
+            .map(|session_id| (*session_id, peer_id1))
+            .chain(peer2_sessions.iter().map(|session_id| (*session_id, peer_id2)))
+            .collect::<HashSet<_>>()
+    );
+    assert_eq!(
+        behaviour.sessions_for_peer(peer_id1).into_iter().collect::<HashSet<_>>(),
+        peer1_sessions
+    );
+    assert_eq!(
+        behaviour.sessions_for_peer(peer_id2).into_iter().collect::<HashSet<_>>(),
+        peer2_sessions
+    );
+    assert!(behaviour.sessions_for_peer(PeerId::random()).is_empty());
+
+    // Closed sessions are no longer reported.
+    simulate_session_closed_by_peer(
+        &mut behaviour,
+        peer_id1,
+        outbound_session_id1.into(),
+        CloseReason::Completed,
+    );
+    assert_eq!(behaviour.sessions_for_peer(peer_id1), vec![inbound_session_id1.into()]);
+}
+
+#[tokio::test]
+async fn negotiated_protocol_is_kept_until_peer_disconnects() {
+    let protocol_names =
+        vec![StreamProtocol::new("/test/2.0.0"), StreamProtocol::new("/test/1.0.0")];
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { protocol_names: protocol_names.clone(), ..Config::get_test_config() },
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id);
+    assert_eq!(behaviour.peer_protocol(peer_id), None);
 
     let query = protobuf::BasicMessage::default();
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+
+    // The peer supports only the older version.
+    simulate_outbound_session_negotiated(
+        &mut behaviour,
+        peer_id,
+        outbound_session_id,
+        protocol_names[1].clone(),
+    );
+    // Only the establishment of the session is reported to the user.
+    validate_outbound_session_established_event(&mut behaviour, &peer_id, outbound_session_id)
+        .await;
+    validate_no_events(&mut behaviour);
+    assert_eq!(behaviour.peer_protocol(peer_id), Some(protocol_names[1].clone()));
+    assert_eq!(behaviour.peer_protocol(PeerId::random()), None);
+
+    simulate_connection_closed(&mut behaviour, peer_id);
+    assert_eq!(behaviour.peer_protocol(peer_id), None);
+}
+
+#[test]
+fn connected_peers_are_tracked_until_they_disconnect() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let peer_id1 = PeerId::random();
+    let peer_id2 = PeerId::random();
+    assert!(behaviour.connected_peers().is_empty());
+
+    simulate_connection_established(&mut behaviour, peer_id1);
+    simulate_connection_established(&mut behaviour, peer_id2);
+    assert!(behaviour.is_connected(&peer_id1));
+    assert_eq!(
+        behaviour.connected_peers().into_iter().collect::<HashSet<_>>(),
+        HashSet::from([peer_id1, peer_id2])
+    );
+
+    simulate_connection_closed(&mut behaviour, peer_id1);
+    assert!(!behaviour.is_connected(&peer_id1));
+    assert!(behaviour.is_connected(&peer_id2));
+    assert_eq!(behaviour.connected_peers(), vec![peer_id2]);
+    assert_matches!(
+        behaviour.send_query(protobuf::BasicMessage::default(), peer_id1, None, None),
+        Err(SendQueryError::PeerNotConnected(_))
+    );
+}
+
+#[tokio::test]
+async fn outbound_session_established_is_reported_once() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
     let peer_id = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+    validate_no_events(&mut behaviour);
+
+    let protocol_name = behaviour.config.protocol_names[0].clone();
+    simulate_outbound_session_negotiated(
+        &mut behaviour,
+        peer_id,
+        outbound_session_id,
+        protocol_name,
+    );
+    validate_outbound_session_established_event(&mut behaviour, &peer_id, outbound_session_id)
+        .await;
+    validate_no_events(&mut behaviour);
 
-    behaviour.send_query(query.clone(), peer_id).unwrap_err();
+    let dummy_data_vec = dummy_data();
+    for data in &dummy_data_vec {
+        simulate_received_data(&mut behaviour, peer_id, data.clone(), outbound_session_id);
+    }
+    validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id).await;
+    for data in &dummy_data_vec {
+        validate_received_data_event(&mut behaviour, data, outbound_session_id).await;
+    }
+    validate_no_events(&mut behaviour);
+}
+
+#[tokio::test]
+async fn outbound_sessions_past_pending_limit_wait_for_their_turn() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { max_pending_outbound_sessions: Some(2), ..Config::get_test_config() },
+        PeerId::random(),
+    );
+
+    let peer_ids = [PeerId::random(), PeerId::random(), PeerId::random(), PeerId::random()];
+    for peer_id in peer_ids {
+        simulate_connection_established(&mut behaviour, peer_id);
+    }
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_ids = peer_ids
+        .iter()
+        .map(|peer_id| behaviour.send_query(query.clone(), *peer_id, None, None).unwrap())
+        .collect::<Vec<_>>();
+
+    // Only the first two sessions are started.
+    for (peer_id, outbound_session_id) in peer_ids.iter().zip(&outbound_session_ids).take(2) {
+        validate_create_outbound_session_event(
+            &mut behaviour,
+            peer_id,
+            &query,
+            outbound_session_id,
+        )
+        .await;
+    }
+    validate_no_events(&mut behaviour);
+
+    // The third session starts once the first one is established.
+    let protocol_name = behaviour.config.protocol_names[0].clone();
+    simulate_outbound_session_negotiated(
+        &mut behaviour,
+        peer_ids[0],
+        outbound_session_ids[0],
+        protocol_name,
+    );
+    validate_outbound_session_established_event(
+        &mut behaviour,
+        &peer_ids[0],
+        outbound_session_ids[0],
+    )
+    .await;
+    validate_create_outbound_session_event(
+        &mut behaviour,
+        &peer_ids[2],
+        &query,
+        &outbound_session_ids[2],
+    )
+    .await;
+    validate_no_events(&mut behaviour);
+
+    // The fourth session starts once the second one fails.
+    simulate_session_failed(
+        &mut behaviour,
+        peer_ids[1],
+        outbound_session_ids[1].into(),
+        HandlerSessionError::IOError(io::ErrorKind::BrokenPipe.into()),
+    );
+    validate_create_outbound_session_event(
+        &mut behaviour,
+        &peer_ids[3],
+        &query,
+        &outbound_session_ids[3],
+    )
+    .await;
+    let event = behaviour.next().await.unwrap();
+    assert_matches!(
+        event,
+        ToSwarm::GenerateEvent(Event::SessionFailed { session_id, .. })
+            if session_id == outbound_session_ids[1].into()
+    );
+    validate_no_events(&mut behaviour);
+}
+
+#[test]
+fn session_label_format() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let peer_id = PeerId::from_str("12D3KooWD3eckifWpRn9wQpMG9R9hX3sD158z7EqHWmweQAJU5SA").unwrap();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    let outbound_session_id =
+        behaviour.send_query(Default::default(), peer_id, None, None).unwrap();
+    assert_eq!(
+        behaviour.session_label(outbound_session_id.into()),
+        Some("eQAJU5SA/out/0".to_owned())
+    );
+
+    let inbound_session_id = InboundSessionId { value: 5 };
+    simulate_new_inbound_session(&mut behaviour, peer_id, inbound_session_id, Default::default());
+    assert_eq!(
+        behaviour.session_label(inbound_session_id.into()),
+        Some("eQAJU5SA/in/5".to_owned())
+    );
+
+    // Sessions that aren't open have no label.
+    assert_eq!(behaviour.session_label(InboundSessionId { value: 6 }.into()), None);
+}
+
+#[tokio::test]
+async fn outbound_session_is_retried_when_remote_doesnt_support_protocol() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config {
+            max_outbound_retries: 1,
+            retry_backoff: Duration::from_millis(10),
+            ..Config::get_test_config()
+        },
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+
+    simulate_remote_doesnt_support_protocol(&mut behaviour, peer_id, outbound_session_id);
+    // The failure isn't reported and the session is created again with the original query.
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+    validate_no_events(&mut behaviour);
+
+    let dummy_data_vec = dummy_data();
+    for data in &dummy_data_vec {
+        simulate_received_data(&mut behaviour, peer_id, data.clone(), outbound_session_id);
+    }
+    validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id).await;
+    for data in &dummy_data_vec {
+        validate_received_data_event(&mut behaviour, data, outbound_session_id).await;
+    }
+    validate_no_events(&mut behaviour);
+}
+
+#[tokio::test]
+async fn outbound_session_fails_when_retries_are_exhausted() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config {
+            max_outbound_retries: 1,
+            retry_backoff: Duration::from_millis(10),
+            ..Config::get_test_config()
+        },
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+
+    simulate_remote_doesnt_support_protocol(&mut behaviour, peer_id, outbound_session_id);
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+
+    simulate_remote_doesnt_support_protocol(&mut behaviour, peer_id, outbound_session_id);
+    let event = behaviour.next().await.unwrap();
+    assert_matches!(
+        event,
+        ToSwarm::GenerateEvent(Event::SessionFailed {
+            session_id,
+            error: SessionError::RemoteDoesntSupportProtocol { protocol_names },
+        }) if session_id == outbound_session_id.into()
+            && protocol_names == behaviour.config.protocol_names
+    );
+    validate_no_events(&mut behaviour);
+}
+
+#[tokio::test]
+async fn queries_waiting_for_connection_are_sent_by_priority() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_dialing(&mut behaviour, peer_id);
+
+    let queries_and_priorities = [(1, None), (2, Some(1)), (3, Some(2)), (4, Some(1)), (5, None)];
+    let mut outbound_session_ids = Vec::new();
+    for (number, priority) in queries_and_priorities {
+        let query = protobuf::BasicMessage { number };
+        outbound_session_ids.push(behaviour.send_query(query, peer_id, priority, None).unwrap());
+    }
+    // The queries wait for the connection.
+    validate_no_events(&mut behaviour);
+
+    simulate_connection_established(&mut behaviour, peer_id);
+    for index in [2, 1, 3, 0, 4] {
+        let (number, _) = queries_and_priorities[index];
+        validate_create_outbound_session_event(
+            &mut behaviour,
+            &peer_id,
+            &protobuf::BasicMessage { number },
+            &outbound_session_ids[index],
+        )
+        .await;
+    }
+    validate_no_events(&mut behaviour);
+}
+
+fn is_connection_allowed<Query: QueryBound, Data: DataBound>(
+    behaviour: &mut Behaviour<Query, Data>,
+    peer_id: PeerId,
+) -> bool {
+    behaviour
+        .handle_established_inbound_connection(
+            ConnectionId::new_unchecked(1),
+            peer_id,
+            &Multiaddr::empty(),
+            &Multiaddr::empty(),
+        )
+        .is_ok()
+}
+
+#[test]
+fn queries_and_connections_to_the_local_peer_are_rejected() {
+    let local_peer_id = PeerId::random();
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        local_peer_id,
+    );
+
+    assert_matches!(
+        behaviour.send_query(protobuf::BasicMessage::default(), local_peer_id, None, None),
+        Err(SendQueryError::CannotDialSelf(_))
+    );
+    assert!(
+        behaviour
+            .handle_pending_outbound_connection(
+                ConnectionId::new_unchecked(0),
+                Some(local_peer_id),
+                &[],
+                Endpoint::Dialer,
+            )
+            .is_err()
+    );
+    assert!(!is_connection_allowed(&mut behaviour, local_peer_id));
+    assert!(is_connection_allowed(&mut behaviour, PeerId::random()));
+    validate_no_events(&mut behaviour);
+}
+
+#[test]
+fn connections_with_peers_that_are_not_allowed_are_refused() {
+    let allowed_peer_id = PeerId::random();
+    let denied_peer_id = PeerId::random();
+
+    // Without an allowlist, any peer that isn't denied can connect.
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { denied_peers: HashSet::from([denied_peer_id]), ..Config::get_test_config() },
+        PeerId::random(),
+    );
+    assert!(!is_connection_allowed(&mut behaviour, denied_peer_id));
+    assert!(is_connection_allowed(&mut behaviour, allowed_peer_id));
+    assert!(
+        behaviour
+            .handle_established_outbound_connection(
+                ConnectionId::new_unchecked(0),
+                denied_peer_id,
+                &Multiaddr::empty(),
+                Endpoint::Dialer,
+            )
+            .is_err()
+    );
+    assert!(
+        behaviour
+            .handle_pending_outbound_connection(
+                ConnectionId::new_unchecked(0),
+                Some(denied_peer_id),
+                &[],
+                Endpoint::Dialer,
+            )
+            .is_err()
+    );
+
+    // With an allowlist, only the allowed peers can connect, unless they're also denied.
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config {
+            allowed_peers: Some(HashSet::from([allowed_peer_id, denied_peer_id])),
+            denied_peers: HashSet::from([denied_peer_id]),
+            ..Config::get_test_config()
+        },
+        PeerId::random(),
+    );
+    assert!(is_connection_allowed(&mut behaviour, allowed_peer_id));
+    assert!(!is_connection_allowed(&mut behaviour, denied_peer_id));
+    assert!(!is_connection_allowed(&mut behaviour, PeerId::random()));
+    validate_no_events(&mut behaviour);
+}
+
+#[tokio::test(start_paused = true)]
+async fn peer_is_banned_after_repeated_failures_until_cooldown_passes() {
+    let peer_ban_cooldown = Duration::from_secs(60);
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { peer_score_threshold: -20, peer_ban_cooldown, ..Config::get_test_config() },
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    // Each deserialization failure lowers the score by 10, so the third one crosses the
+    // threshold.
+    for _ in 0..3 {
+        assert!(is_connection_allowed(&mut behaviour, peer_id));
+        let query = protobuf::BasicMessage::default();
+        let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+        validate_create_outbound_session_event(
+            &mut behaviour,
+            &peer_id,
+            &query,
+            &outbound_session_id,
+        )
+        .await;
+        simulate_session_failed(
+            &mut behaviour,
+            peer_id,
+            outbound_session_id.into(),
+            HandlerSessionError::DeserializationFailed(prost::DecodeError::new("garbage")),
+        );
+        assert_matches!(
+            behaviour.next().await.unwrap(),
+            ToSwarm::GenerateEvent(Event::SessionFailed {
+                error: SessionError::DeserializationFailed(_),
+                ..
+            })
+        );
+    }
+
+    assert_matches!(
+        behaviour.next().await.unwrap(),
+        ToSwarm::CloseConnection { peer_id: event_peer_id, .. } if event_peer_id == peer_id
+    );
+    assert!(!is_connection_allowed(&mut behaviour, peer_id));
+    // Other peers aren't affected.
+    assert!(is_connection_allowed(&mut behaviour, PeerId::random()));
+
+    tokio::time::advance(peer_ban_cooldown).await;
+    assert!(is_connection_allowed(&mut behaviour, peer_id));
+}
+
+#[tokio::test(start_paused = true)]
+async fn completed_sessions_restore_the_score_of_a_peer() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { peer_score_threshold: -20, ..Config::get_test_config() },
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    // Two deserialization failures bring the score to the threshold, ten completed sessions raise
+    // it by 10 and then a third failure doesn't cross the threshold.
+    let failures_and_completions =
+        [false, false].into_iter().chain([true; 10]).chain([false]).collect::<Vec<_>>();
+    for completed in failures_and_completions {
+        let query = protobuf::BasicMessage::default();
+        let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+        validate_create_outbound_session_event(
+            &mut behaviour,
+            &peer_id,
+            &query,
+            &outbound_session_id,
+        )
+        .await;
+        if completed {
+            simulate_session_closed_by_peer(
+                &mut behaviour,
+                peer_id,
+                outbound_session_id.into(),
+                CloseReason::Completed,
+            );
+            validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id)
+                .await;
+            validate_session_closed_by_peer_event(
+                &mut behaviour,
+                outbound_session_id.into(),
+                CloseReason::Completed,
+            )
+            .await;
+        } else {
+            simulate_session_failed(
+                &mut behaviour,
+                peer_id,
+                outbound_session_id.into(),
+                HandlerSessionError::DeserializationFailed(prost::DecodeError::new("garbage")),
+            );
+            assert_matches!(
+                behaviour.next().await.unwrap(),
+                ToSwarm::GenerateEvent(Event::SessionFailed {
+                    error: SessionError::DeserializationFailed(_),
+                    ..
+                })
+            );
+        }
+    }
+
+    validate_no_events(&mut behaviour);
+    assert!(is_connection_allowed(&mut behaviour, peer_id));
+}
+
+#[tokio::test]
+async fn pending_queries_fail_when_the_last_dial_fails() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
+    let peer_id = PeerId::random();
+    simulate_dialing(&mut behaviour, peer_id);
+    let outbound_session_ids = (0..3)
+        .map(|_| {
+            behaviour.send_query(protobuf::BasicMessage::default(), peer_id, None, None).unwrap()
+        })
+        .collect::<HashSet<_>>();
+    validate_no_events(&mut behaviour);
+
+    simulate_dial_failure(&mut behaviour, peer_id);
+    let mut failed_outbound_session_ids = HashSet::new();
+    for _ in 0..outbound_session_ids.len() {
+        assert_matches!(
+            behaviour.next().await.unwrap(),
+            ToSwarm::GenerateEvent(Event::SessionFailed {
+                session_id: SessionId::OutboundSessionId(outbound_session_id),
+                error: SessionError::ConnectionClosed,
+            }) if failed_outbound_session_ids.insert(outbound_session_id)
+        );
+    }
+    assert_eq!(failed_outbound_session_ids, outbound_session_ids);
+    validate_no_events(&mut behaviour);
+
+    // The peer is no longer being dialed, so new queries to it are rejected.
+    assert_matches!(
+        behaviour.send_query(protobuf::BasicMessage::default(), peer_id, None, None),
+        Err(SendQueryError::PeerNotConnected(_))
+    );
+}
+
+#[test]
+fn manually_banned_peer_is_denied_until_unbanned() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let peer_id = PeerId::random();
+
+    behaviour.ban_peer(peer_id, Duration::MAX);
+    assert!(!is_connection_allowed(&mut behaviour, peer_id));
+
+    behaviour.unban_peer(peer_id);
+    assert!(is_connection_allowed(&mut behaviour, peer_id));
+}
+
+// Collects the logs that are written to it.
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn pending_events_past_the_threshold_are_warned_about_once() {
+    const WARNING_THRESHOLD: usize = 5;
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config {
+            pending_events_warning_threshold: Some(WARNING_THRESHOLD),
+            ..Config::get_test_config()
+        },
+        PeerId::random(),
+    );
+    let peer_id = PeerId::random();
+    let inbound_session_id = InboundSessionId::default();
+    simulate_listener_connection(&mut behaviour, peer_id);
+    simulate_new_inbound_session(
+        &mut behaviour,
+        peer_id,
+        inbound_session_id,
+        protobuf::BasicMessage::default(),
+    );
+    validate_new_inbound_session_event(
+        &mut behaviour,
+        &peer_id,
+        inbound_session_id,
+        &protobuf::BasicMessage::default(),
+    )
+    .await;
+
+    let logs = LogBuffer::default();
+    let log_writer = logs.clone();
+    let subscriber =
+        tracing_subscriber::fmt().with_writer(move || log_writer.clone()).with_ansi(false).finish();
+    tracing::subscriber::with_default(subscriber, || {
+        // Flooding the behaviour with events without polling it.
+        for _ in 0..2 * WARNING_THRESHOLD {
+            behaviour.send_data(protobuf::BasicMessage::default(), inbound_session_id).unwrap();
+        }
+    });
+    assert_eq!(behaviour.num_pending_events(), 2 * WARNING_THRESHOLD);
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let warnings = logs.lines().filter(|line| line.contains("WARN")).collect::<Vec<_>>();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains(&format!(
+        "more than {WARNING_THRESHOLD} events waiting to be passed on by the behaviour"
+    )));
+
+    for _ in 0..2 * WARNING_THRESHOLD {
+        behaviour.next().now_or_never().unwrap();
+    }
+    assert_eq!(behaviour.num_pending_events(), 0);
+}
+
+#[test]
+fn close_non_existing_session_fails() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    behaviour.close_session(SessionId::InboundSessionId(InboundSessionId::default())).unwrap_err();
+    behaviour
+        .close_session(SessionId::OutboundSessionId(OutboundSessionId::default()))
+        .unwrap_err();
+}
+
+#[test]
+fn send_data_non_existing_session_fails() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    for data in dummy_data() {
+        behaviour.send_data(data, InboundSessionId::default()).unwrap_err();
+    }
+}
+
+#[test]
+fn send_query_peer_not_connected_fails() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+
+    behaviour.send_query(query.clone(), peer_id, None, None).unwrap_err();
+}
+
+#[tokio::test]
+async fn inbound_sessions_past_unanswered_limit_are_rejected() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { max_unanswered_inbound_sessions: Some(1), ..Config::get_test_config() },
+        PeerId::random(),
+    );
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+    let inbound_session_id1 = InboundSessionId { value: 1 };
+    let inbound_session_id2 = InboundSessionId { value: 2 };
+    let inbound_session_id3 = InboundSessionId { value: 3 };
+
+    simulate_listener_connection(&mut behaviour, peer_id);
+
+    simulate_new_inbound_session(&mut behaviour, peer_id, inbound_session_id1, query.clone());
+    validate_new_inbound_session_event(&mut behaviour, &peer_id, inbound_session_id1, &query).await;
+    assert_eq!(behaviour.num_unanswered_inbound_sessions(), 1);
+
+    simulate_new_inbound_session(&mut behaviour, peer_id, inbound_session_id2, query.clone());
+    validate_request_abort_inbound_session_event(&mut behaviour, &peer_id, inbound_session_id2)
+        .await;
+    assert_matches!(
+        behaviour.next().await.unwrap(),
+        ToSwarm::GenerateEvent(Event::SessionFailed {
+            session_id,
+            error: SessionError::TooManySessions,
+        }) if session_id == inbound_session_id2.into()
+    );
+    assert_eq!(behaviour.num_unanswered_inbound_sessions(), 1);
+
+    // The rejected session was already reported, so its closing isn't reported.
+    simulate_session_closed_by_request(&mut behaviour, peer_id, inbound_session_id2.into());
+    validate_no_events(&mut behaviour);
+
+    let data = dummy_data()[0].clone();
+    behaviour.send_data(data.clone(), inbound_session_id1).unwrap();
+    validate_request_send_data_event(&mut behaviour, &peer_id, &data, inbound_session_id1).await;
+    assert_eq!(behaviour.num_unanswered_inbound_sessions(), 0);
+
+    simulate_new_inbound_session(&mut behaviour, peer_id, inbound_session_id3, query.clone());
+    validate_new_inbound_session_event(&mut behaviour, &peer_id, inbound_session_id3, &query).await;
+    assert_eq!(behaviour.num_unanswered_inbound_sessions(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn outbound_session_latency_is_measured_until_first_response() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let response_delay = Duration::from_millis(300);
+    let tolerance = Duration::from_millis(10);
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+
+    simulate_connection_established(&mut behaviour, peer_id);
+    let outbound_session_id1 = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+    let outbound_session_id2 = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id1)
+        .await;
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id2)
+        .await;
+
+    // Simulating a peer that responds to the first query after a delay.
+    tokio::time::advance(response_delay).await;
+    let data = dummy_data()[0].clone();
+    simulate_received_data(&mut behaviour, peer_id, data.clone(), outbound_session_id1);
+    let latency =
+        validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id1)
+            .await;
+    assert!(latency >= response_delay && latency - response_delay <= tolerance);
+    validate_received_data_event(&mut behaviour, &data, outbound_session_id1).await;
+
+    // Only the first response of a session is measured.
+    simulate_received_data(&mut behaviour, peer_id, data.clone(), outbound_session_id1);
+    validate_received_data_event(&mut behaviour, &data, outbound_session_id1).await;
+    validate_no_events(&mut behaviour);
+
+    // A session that is closed without sending any data is measured until it's closed.
+    tokio::time::advance(response_delay).await;
+    simulate_session_closed_by_peer(
+        &mut behaviour,
+        peer_id,
+        outbound_session_id2.into(),
+        CloseReason::Completed,
+    );
+    let latency =
+        validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id2)
+            .await;
+    assert!(latency >= 2 * response_delay && latency - 2 * response_delay <= tolerance);
+    validate_session_closed_by_peer_event(
+        &mut behaviour,
+        outbound_session_id2.into(),
+        CloseReason::Completed,
+    )
+    .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn outbound_session_latencies_are_attributed_to_their_stage() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let handshake_delay = Duration::from_millis(100);
+    let first_byte_delay = Duration::from_millis(200);
+    let transfer_delay = Duration::from_millis(400);
+
+    let query = protobuf::BasicMessage::default();
+    let data = dummy_data()[0].clone();
+    let peer_id = PeerId::random();
+    let protocol_name = behaviour.config.protocol_names[0].clone();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    // Simulating a peer that is slower at each stage on the second session.
+    for i in 1..=2 {
+        let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+        validate_create_outbound_session_event(
+            &mut behaviour,
+            &peer_id,
+            &query,
+            &outbound_session_id,
+        )
+        .await;
+
+        tokio::time::advance(i * handshake_delay).await;
+        simulate_outbound_session_negotiated(
+            &mut behaviour,
+            peer_id,
+            outbound_session_id,
+            protocol_name.clone(),
+        );
+        validate_outbound_session_established_event(&mut behaviour, &peer_id, outbound_session_id)
+            .await;
+
+        tokio::time::advance(i * first_byte_delay).await;
+        simulate_received_data(&mut behaviour, peer_id, data.clone(), outbound_session_id);
+        validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id)
+            .await;
+        validate_received_data_event(&mut behaviour, &data, outbound_session_id).await;
+
+        tokio::time::advance(i * transfer_delay).await;
+        simulate_session_closed_by_peer(
+            &mut behaviour,
+            peer_id,
+            outbound_session_id.into(),
+            CloseReason::Completed,
+        );
+        validate_session_closed_by_peer_event(
+            &mut behaviour,
+            outbound_session_id.into(),
+            CloseReason::Completed,
+        )
+        .await;
+    }
+
+    let stats = behaviour.peer_latency_stats(peer_id);
+    assert_eq!(
+        stats.handshake,
+        LatencyStats { count: 2, total: 3 * handshake_delay, max: 2 * handshake_delay }
+    );
+    assert_eq!(
+        stats.first_byte,
+        LatencyStats { count: 2, total: 3 * first_byte_delay, max: 2 * first_byte_delay }
+    );
+    assert_eq!(
+        stats.total_transfer,
+        LatencyStats {
+            count: 2,
+            total: 3 * (first_byte_delay + transfer_delay),
+            max: 2 * (first_byte_delay + transfer_delay),
+        }
+    );
+    assert_eq!(stats.handshake.mean(), Some(handshake_delay * 3 / 2));
+
+    // Nothing was measured for other peers.
+    let other_stats = behaviour.peer_latency_stats(PeerId::random());
+    assert_eq!(other_stats, PeerLatencyStats::default());
+    assert_eq!(other_stats.handshake.mean(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn outbound_session_latencies_include_waiting_for_the_connection() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let dial_delay = Duration::from_millis(500);
+    let handshake_delay = Duration::from_millis(100);
+    let first_byte_delay = Duration::from_millis(200);
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+    let protocol_name = behaviour.config.protocol_names[0].clone();
+    simulate_dialing(&mut behaviour, peer_id);
+    let outbound_session_id = behaviour.send_query(query.clone(), peer_id, None, None).unwrap();
+
+    tokio::time::advance(dial_delay).await;
+    simulate_connection_established(&mut behaviour, peer_id);
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+    tokio::time::advance(handshake_delay).await;
+    simulate_outbound_session_negotiated(
+        &mut behaviour,
+        peer_id,
+        outbound_session_id,
+        protocol_name,
+    );
+    validate_outbound_session_established_event(&mut behaviour, &peer_id, outbound_session_id)
+        .await;
+
+    tokio::time::advance(first_byte_delay).await;
+    let data = dummy_data()[0].clone();
+    simulate_received_data(&mut behaviour, peer_id, data.clone(), outbound_session_id);
+    let latency =
+        validate_outbound_session_latency_event(&mut behaviour, &peer_id, outbound_session_id)
+            .await;
+    assert_eq!(latency, dial_delay + handshake_delay + first_byte_delay);
+    validate_received_data_event(&mut behaviour, &data, outbound_session_id).await;
+
+    let stats = behaviour.peer_latency_stats(peer_id);
+    assert_eq!(stats.handshake.total, dial_delay + handshake_delay);
+    assert_eq!(stats.first_byte.total, first_byte_delay);
+}
+
+#[tokio::test(start_paused = true)]
+async fn outbound_session_is_closed_when_its_deadline_elapses() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let deadline = Duration::from_millis(100);
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+    let protocol_name = behaviour.config.protocol_names[0].clone();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    // A session that ends before its deadline isn't affected by it.
+    let fast_session_id =
+        behaviour.send_query(query.clone(), peer_id, None, Some(deadline)).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &fast_session_id)
+        .await;
+    simulate_session_closed_by_peer(
+        &mut behaviour,
+        peer_id,
+        fast_session_id.into(),
+        CloseReason::Completed,
+    );
+    validate_outbound_session_latency_event(&mut behaviour, &peer_id, fast_session_id).await;
+    validate_session_closed_by_peer_event(
+        &mut behaviour,
+        fast_session_id.into(),
+        CloseReason::Completed,
+    )
+    .await;
+
+    // A slow peer that doesn't send any data.
+    let slow_session_id =
+        behaviour.send_query(query.clone(), peer_id, None, Some(deadline)).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &slow_session_id)
+        .await;
+    simulate_outbound_session_negotiated(&mut behaviour, peer_id, slow_session_id, protocol_name);
+    validate_outbound_session_established_event(&mut behaviour, &peer_id, slow_session_id).await;
+
+    // Waiting for the next event lets the deadline elapse.
+    validate_request_close_session_event(&mut behaviour, &peer_id, slow_session_id.into()).await;
+    simulate_session_closed_by_request(&mut behaviour, peer_id, slow_session_id.into());
+    let event = behaviour.next().await.unwrap();
+    assert_matches!(
+        event,
+        ToSwarm::GenerateEvent(Event::SessionFailed {
+            session_id,
+            error: SessionError::QueryDeadlineExceeded { deadline: event_deadline },
+        }) if session_id == slow_session_id.into() && event_deadline == deadline
+    );
+    assert!(behaviour.active_sessions().is_empty());
+
+    // A query that waits for its peer to be dialed fails without being sent.
+    let dialed_peer_id = PeerId::random();
+    simulate_dialing(&mut behaviour, dialed_peer_id);
+    let waiting_session_id =
+        behaviour.send_query(query, dialed_peer_id, None, Some(deadline)).unwrap();
+    let event = behaviour.next().await.unwrap();
+    assert_matches!(
+        event,
+        ToSwarm::GenerateEvent(Event::SessionFailed {
+            session_id,
+            error: SessionError::QueryDeadlineExceeded { .. },
+        }) if session_id == waiting_session_id.into()
+    );
+    validate_no_events(&mut behaviour);
+}
+
+#[tokio::test(start_paused = true)]
+async fn session_past_its_deadline_before_negotiation_reports_nothing_after_failing() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let deadline = Duration::from_millis(100);
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+    let protocol_name = behaviour.config.protocol_names[0].clone();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    let outbound_session_id =
+        behaviour.send_query(query.clone(), peer_id, None, Some(deadline)).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+
+    // The deadline elapses while the substream is being negotiated.
+    validate_request_close_session_event(&mut behaviour, &peer_id, outbound_session_id.into())
+        .await;
+    simulate_session_closed_by_request(&mut behaviour, peer_id, outbound_session_id.into());
+    assert_matches!(
+        behaviour.next().await.unwrap(),
+        ToSwarm::GenerateEvent(Event::SessionFailed {
+            session_id,
+            error: SessionError::QueryDeadlineExceeded { .. },
+        }) if session_id == outbound_session_id.into()
+    );
+
+    // Events the handler reports about the session afterwards are ignored.
+    simulate_outbound_session_negotiated(
+        &mut behaviour,
+        peer_id,
+        outbound_session_id,
+        protocol_name,
+    );
+    simulate_received_data(
+        &mut behaviour,
+        peer_id,
+        protobuf::BasicMessage::default(),
+        outbound_session_id,
+    );
+    simulate_session_closed_by_peer(
+        &mut behaviour,
+        peer_id,
+        outbound_session_id.into(),
+        CloseReason::Completed,
+    );
+    validate_no_events(&mut behaviour);
+    assert!(behaviour.active_sessions().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn closing_connection_forgets_the_sessions_past_their_deadline() {
+    let mut behaviour = Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        PeerId::random(),
+    );
+    let deadline = Duration::from_millis(100);
+
+    let query = protobuf::BasicMessage::default();
+    let peer_id = PeerId::random();
+    let protocol_name = behaviour.config.protocol_names[0].clone();
+    simulate_connection_established(&mut behaviour, peer_id);
+
+    let outbound_session_id =
+        behaviour.send_query(query.clone(), peer_id, None, Some(deadline)).unwrap();
+    validate_create_outbound_session_event(&mut behaviour, &peer_id, &query, &outbound_session_id)
+        .await;
+    simulate_outbound_session_negotiated(
+        &mut behaviour,
+        peer_id,
+        outbound_session_id,
+        protocol_name,
+    );
+    validate_outbound_session_established_event(&mut behaviour, &peer_id, outbound_session_id)
+        .await;
+
+    // The connection is dropped while the session is being closed since its deadline elapsed.
+    validate_request_close_session_event(&mut behaviour, &peer_id, outbound_session_id.into())
+        .await;
+    simulate_connection_closed(&mut behaviour, peer_id);
+    let event = behaviour.next().await.unwrap();
+    assert_matches!(
+        event,
+        ToSwarm::GenerateEvent(Event::SessionFailed {
+            session_id,
+            error: SessionError::ConnectionClosed,
+        }) if session_id == outbound_session_id.into()
+    );
+    assert_matches!(
+        behaviour.next().await.unwrap(),
+        ToSwarm::GenerateEvent(Event::PeerDisconnected { peer_id: disconnected_peer_id })
+            if disconnected_peer_id == peer_id
+    );
+    assert!(behaviour.deadline_exceeded_outbound_session_id_to_deadline.is_empty());
+    assert!(behaviour.active_sessions().is_empty());
+    validate_no_events(&mut behaviour);
 }
@@ -1,21 +1,29 @@
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
+use assert_matches::assert_matches;
 use defaultmap::DefaultHashMap;
 use futures::StreamExt;
 use libp2p::swarm::{NetworkBehaviour, StreamProtocol, SwarmEvent};
-use libp2p::{PeerId, Swarm};
+use libp2p::{Multiaddr, PeerId, Swarm};
 
-use super::behaviour::{Behaviour, Event};
-use super::{InboundSessionId, OutboundSessionId, SessionId};
+use super::behaviour::{Behaviour, Event, SessionError};
+use super::test_harness::{ConnectedBehaviours, Side};
+use super::{CloseReason, InboundSessionId, OutboundSessionId, SessionId};
 use crate::messages::protobuf;
 use crate::streamed_data_protocol::Config;
-use crate::test_utils::{create_fully_connected_swarms_stream, StreamHashMap};
+use crate::test_utils::{
+    create_fully_connected_swarms_stream,
+    create_swarm_with_config,
+    StreamHashMap,
+};
 
 const NUM_PEERS: usize = 3;
 const NUM_MESSAGES_PER_SESSION: usize = 5;
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
 
 type SwarmEventAlias<BehaviourTrait> = SwarmEvent<<BehaviourTrait as NetworkBehaviour>::ToSwarm>;
 
@@ -70,6 +78,8 @@ fn send_query_and_update_map(
                 number: get_number_for_query(outbound_peer_id, inbound_peer_id),
             },
             inbound_peer_id,
+            None,
+            None,
         )
         .unwrap();
     outbound_session_id_to_peer_id.insert((outbound_peer_id, outbound_session_id), inbound_peer_id);
@@ -115,7 +125,12 @@ fn check_new_inbound_session_event_and_return_id(
     let SwarmEvent::Behaviour(event) = swarm_event else {
         return None;
     };
-    let Event::NewInboundSession { query, inbound_session_id, peer_id: outbound_peer_id } = event
+    // The outbound sessions of the peer are established meanwhile.
+    if let Event::OutboundSessionEstablished { .. } = event {
+        return None;
+    }
+    let Event::NewInboundSession { query, inbound_session_id, peer_id: outbound_peer_id, .. } =
+        event
     else {
         panic!("Got unexpected event {:?} when expecting NewInboundSession", event);
     };
@@ -132,6 +147,11 @@ fn check_received_data_event(
     let SwarmEvent::Behaviour(event) = swarm_event else {
         return None;
     };
+    if let Event::OutboundSessionEstablished { .. } | Event::OutboundSessionLatencyMeasured { .. } =
+        event
+    {
+        return None;
+    }
     let Event::ReceivedData { outbound_session_id, data } = event else {
         panic!("Got unexpected event {:?} when expecting ReceivedData", event);
     };
@@ -149,7 +169,7 @@ fn check_outbound_session_closed_by_peer_event(
 ) -> Option<(PeerId, ())> {
     let SwarmEvent::Behaviour(Event::SessionClosedByPeer {
         session_id: SessionId::OutboundSessionId(outbound_session_id),
-        ..
+        reason: CloseReason::Completed,
     }) = swarm_event
     else {
         return None;
@@ -174,11 +194,11 @@ fn get_number_for_data(peer_id1: PeerId, peer_id2: PeerId, message_index: usize)
 
 #[tokio::test]
 async fn everyone_sends_to_everyone() {
-    let mut swarms_stream = create_fully_connected_swarms_stream(NUM_PEERS, || {
-        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(Config {
-            substream_timeout: Duration::from_secs(60),
-            protocol_name: StreamProtocol::new("/"),
-        })
+    let mut swarms_stream = create_fully_connected_swarms_stream(NUM_PEERS, |peer_id| {
+        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+            Config { substream_timeout: Duration::from_secs(60), ..Config::get_test_config() },
+            peer_id,
+        )
     })
     .await;
 
@@ -256,3 +276,257 @@ async fn everyone_sends_to_everyone() {
     )
     .await;
 }
+
+#[tokio::test]
+async fn peers_negotiate_highest_common_protocol_version() {
+    let protocol_names_per_peer = [
+        vec![
+            StreamProtocol::new("/test/3.0.0"),
+            StreamProtocol::new("/test/2.0.0"),
+            StreamProtocol::new("/test/1.0.0"),
+        ],
+        vec![StreamProtocol::new("/test/2.0.0"), StreamProtocol::new("/test/1.0.0")],
+    ];
+    let expected_protocol_name = StreamProtocol::new("/test/2.0.0");
+
+    let next_peer_index = AtomicUsize::new(0);
+    let mut swarms_stream = create_fully_connected_swarms_stream(2, |peer_id| {
+        let peer_index = next_peer_index.fetch_add(1, Ordering::Relaxed);
+        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+            Config {
+                substream_timeout: Duration::from_secs(60),
+                protocol_names: protocol_names_per_peer[peer_index].clone(),
+                protocol_extensions: HashMap::new(),
+                ..Config::get_test_config()
+            },
+            peer_id,
+        )
+    })
+    .await;
+
+    let peer_ids = swarms_stream.keys().copied().collect::<Vec<_>>();
+    perform_action_on_swarms(&mut swarms_stream, &peer_ids, &mut |outbound_swarm, peer_id| {
+        outbound_swarm.behaviour_mut().send_query(Default::default(), peer_id, None, None).unwrap();
+    });
+
+    // Each peer sent a query to the other one, so we expect one new inbound session on each
+    // side, and both should use the highest version both peers support.
+    let mut num_new_inbound_sessions = 0;
+    while num_new_inbound_sessions < 2 {
+        let (_, event) = swarms_stream.next().await.unwrap();
+        let SwarmEvent::Behaviour(event) = event else {
+            continue;
+        };
+        if let Event::OutboundSessionEstablished { .. } = event {
+            continue;
+        }
+        let Event::NewInboundSession { protocol_name, .. } = event else {
+            panic!("Got unexpected event {:?} when expecting NewInboundSession", event);
+        };
+        assert_eq!(protocol_name, expected_protocol_name);
+        num_new_inbound_sessions += 1;
+    }
+    perform_action_on_swarms(&mut swarms_stream, &peer_ids, &mut |swarm, peer_id| {
+        assert_eq!(swarm.behaviour().peer_protocol(peer_id), Some(expected_protocol_name.clone()));
+    });
+}
+
+#[tokio::test]
+async fn shutdown_closes_all_sessions() {
+    let mut swarms_stream = create_fully_connected_swarms_stream(2, |peer_id| {
+        Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+            Config { substream_timeout: Duration::from_secs(60), ..Config::get_test_config() },
+            peer_id,
+        )
+    })
+    .await;
+
+    let peer_ids = swarms_stream.keys().copied().collect::<Vec<_>>();
+    perform_action_on_swarms(&mut swarms_stream, &peer_ids, &mut |outbound_swarm, peer_id| {
+        outbound_swarm.behaviour_mut().send_query(Default::default(), peer_id, None, None).unwrap();
+    });
+
+    // Wait until each peer has both an outbound session and an inbound session.
+    let mut inbound_session_ids = HashMap::<PeerId, InboundSessionId>::new();
+    while inbound_session_ids.len() < 2 {
+        let (peer_id, event) = swarms_stream.next().await.unwrap();
+        let SwarmEvent::Behaviour(event) = event else {
+            continue;
+        };
+        if let Event::OutboundSessionEstablished { .. } = event {
+            continue;
+        }
+        let Event::NewInboundSession { inbound_session_id, .. } = event else {
+            panic!("Got unexpected event {:?} when expecting NewInboundSession", event);
+        };
+        inbound_session_ids.insert(peer_id, inbound_session_id);
+    }
+
+    for swarm in swarms_stream.values_mut() {
+        let peer_id = *swarm.local_peer_id();
+        let other_peer_id =
+            *peer_ids.iter().find(|other_peer_id| **other_peer_id != peer_id).unwrap();
+        swarm.behaviour_mut().shutdown();
+        swarm
+            .behaviour_mut()
+            .send_query(Default::default(), other_peer_id, None, None)
+            .unwrap_err();
+        swarm
+            .behaviour_mut()
+            .send_data(Default::default(), inbound_session_ids[&peer_id])
+            .unwrap_err();
+    }
+
+    // The remote peer may close a session before it's closed locally, so other events are
+    // possible as well.
+    let mut num_sessions_closed_by_request = DefaultHashMap::<PeerId, usize>::new(0);
+    while num_sessions_closed_by_request.values().sum::<usize>() < 4 {
+        let (peer_id, event) = swarms_stream.next().await.unwrap();
+        if let SwarmEvent::Behaviour(Event::SessionClosedByRequest { .. }) = event {
+            *num_sessions_closed_by_request.get_mut(peer_id) += 1;
+        }
+    }
+    for peer_id in &peer_ids {
+        assert_eq!(*num_sessions_closed_by_request.get(*peer_id), 2);
+    }
+    for swarm in swarms_stream.values_mut() {
+        assert!(swarm.behaviour().is_shutdown_complete());
+    }
+}
+
+#[tokio::test]
+async fn failed_session_reports_its_reason_instead_of_connection_closed() {
+    let outbound_config = Config {
+        substream_timeout: Duration::from_secs(60),
+        protocol_names: vec![StreamProtocol::new("/a")],
+        ..Config::get_test_config()
+    };
+    let inbound_config =
+        Config { protocol_names: vec![StreamProtocol::new("/b")], ..outbound_config.clone() };
+    let mut behaviours = ConnectedBehaviours::new(outbound_config, inbound_config).await;
+    let inbound_peer_id = behaviours.inbound_peer_id();
+
+    // The only session of the connection fails because the peers have no common protocol. The
+    // connection stays open after it, so the session reports that reason.
+    let outbound_session_id = behaviours
+        .outbound_behaviour()
+        .send_query(Default::default(), inbound_peer_id, None, None)
+        .unwrap();
+    let (session_id, error) = behaviours
+        .step_until(STEP_TIMEOUT, |side, event| match (side, event) {
+            (Side::Outbound, Event::SessionFailed { session_id, error }) => {
+                Some((session_id, error))
+            }
+            _ => None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(session_id, SessionId::OutboundSessionId(outbound_session_id));
+    assert_matches!(error, SessionError::RemoteDoesntSupportProtocol { .. });
+}
+
+#[tokio::test]
+async fn query_is_answered_with_the_data_of_the_inbound_session() {
+    let config = Config { substream_timeout: Duration::from_secs(60), ..Config::get_test_config() };
+    let mut behaviours = ConnectedBehaviours::new(config.clone(), config).await;
+    let data = (0..NUM_MESSAGES_PER_SESSION)
+        .map(|number| protobuf::BasicMessage { number: number.try_into().unwrap() })
+        .collect::<Vec<_>>();
+
+    let received_data =
+        behaviours.exchange(protobuf::BasicMessage { number: 1 }, data.clone()).await;
+    assert_eq!(received_data, data);
+}
+
+#[tokio::test]
+async fn swarm_reports_the_advertised_addresses_instead_of_the_listen_address() {
+    let advertised_addresses: Vec<Multiaddr> = vec![
+        "/ip4/203.0.113.7/tcp/10000".parse().unwrap(),
+        "/dns4/node.example.com/tcp/10000".parse().unwrap(),
+    ];
+    let config =
+        Config { advertised_addresses: advertised_addresses.clone(), ..Config::get_test_config() };
+    let mut swarm = create_swarm_with_config(
+        |peer_id| {
+            Behaviour::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+                config.clone(),
+                peer_id,
+            )
+        },
+        &config,
+    );
+    swarm.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+    let listen_address = loop {
+        if let SwarmEvent::NewListenAddr { address, .. } = swarm.next().await.unwrap() {
+            break address;
+        }
+    };
+
+    // The swarm doesn't keep the order of its external addresses.
+    assert_eq!(
+        swarm.external_addresses().cloned().collect::<HashSet<_>>(),
+        advertised_addresses.into_iter().collect::<HashSet<_>>()
+    );
+    assert!(!swarm.external_addresses().any(|address| *address == listen_address));
+}
+
+#[tokio::test]
+async fn half_closed_inbound_session_is_closed_once_the_outbound_peer_got_all_data() {
+    let config = Config { substream_timeout: Duration::from_secs(60), ..Config::get_test_config() };
+    let mut behaviours = ConnectedBehaviours::new(config.clone(), config).await;
+    let inbound_peer_id = behaviours.inbound_peer_id();
+    let data = (0..NUM_MESSAGES_PER_SESSION)
+        .map(|number| protobuf::BasicMessage { number: number.try_into().unwrap() })
+        .collect::<Vec<_>>();
+
+    let outbound_session_id = behaviours
+        .outbound_behaviour()
+        .send_query(protobuf::BasicMessage { number: 1 }, inbound_peer_id, None, None)
+        .unwrap();
+    let inbound_session_id = behaviours
+        .step_until(STEP_TIMEOUT, |side, event| match (side, event) {
+            (Side::Inbound, Event::NewInboundSession { inbound_session_id, .. }) => {
+                Some(inbound_session_id)
+            }
+            _ => None,
+        })
+        .await
+        .unwrap();
+    for message in data.clone() {
+        behaviours.inbound_behaviour().send_data(message, inbound_session_id).unwrap();
+    }
+    behaviours.inbound_behaviour().half_close_inbound_session(inbound_session_id).unwrap();
+
+    // The outbound session ends once it got all the data, and ending it acknowledges the half close
+    // to the inbound session, which is then closed as well.
+    let mut received_data = Vec::new();
+    let mut is_outbound_session_closed = false;
+    let mut is_inbound_session_closed = false;
+    behaviours
+        .step_until(STEP_TIMEOUT, |side, event| {
+            match (side, event) {
+                (Side::Outbound, Event::ReceivedData { outbound_session_id: session_id, data })
+                    if session_id == outbound_session_id =>
+                {
+                    received_data.push(data);
+                }
+                (Side::Outbound, Event::SessionClosedByPeer { session_id, reason }) => {
+                    assert_eq!(session_id, SessionId::OutboundSessionId(outbound_session_id));
+                    assert_eq!(reason, CloseReason::Completed);
+                    is_outbound_session_closed = true;
+                }
+                (Side::Inbound, Event::SessionClosedByRequest { session_id }) => {
+                    assert_eq!(session_id, SessionId::InboundSessionId(inbound_session_id));
+                    is_inbound_session_closed = true;
+                }
+                (_, Event::SessionFailed { session_id, error }) => {
+                    panic!("Session {session_id:?} failed with {error:?}.");
+                }
+                _ => {}
+            }
+            (is_outbound_session_closed && is_inbound_session_closed).then_some(())
+        })
+        .await
+        .unwrap();
+    assert_eq!(received_data, data);
+}
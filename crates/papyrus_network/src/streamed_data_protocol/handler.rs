@@ -3,16 +3,18 @@
 mod handler_test;
 mod session;
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, HashSet};
 use std::io;
+use std::num::NonZeroUsize;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
 use async_stream::stream;
+use futures::io::BufReader;
 use futures::stream::BoxStream;
-use futures::{FutureExt, StreamExt};
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt, FutureExt, StreamExt};
 use libp2p::swarm::handler::{
     ConnectionEvent,
     DialUpgradeError,
@@ -27,20 +29,25 @@ use libp2p::swarm::{
     SubstreamProtocol,
 };
 use libp2p::PeerId;
-use tracing::debug;
+use tracing::{debug, debug_span, Span};
 
+pub(crate) use self::session::InboundSessionQueueDepths;
 use self::session::{FinishReason, InboundSession};
+use super::pending_events::{PendingEvents, PendingEventsOwner};
 use super::protocol::{InboundProtocol, OutboundProtocol};
 use super::{
+    session_label,
+    CloseReason,
     Config,
     DataBound,
     GenericEvent,
     InboundSessionId,
     OutboundSessionId,
+    ProtocolExtension,
     QueryBound,
     SessionId,
 };
-use crate::messages::read_message;
+use crate::messages::{read_frame, write_pong, Frame, ReadMessageError};
 
 #[derive(Debug)]
 // TODO(shahak) remove allow(dead_code).
@@ -49,6 +56,24 @@ pub(crate) enum RequestFromBehaviourEvent<Query, Data> {
     CreateOutboundSession { query: Query, outbound_session_id: OutboundSessionId },
     SendData { data: Data, inbound_session_id: InboundSessionId },
     CloseSession { session_id: SessionId },
+    AbortInboundSession { inbound_session_id: InboundSessionId },
+    HalfCloseInboundSession { inbound_session_id: InboundSessionId },
+    DrainAndCloseOutboundSession { outbound_session_id: OutboundSessionId },
+}
+
+impl<Query, Data> RequestFromBehaviourEvent<Query, Data> {
+    fn session_id(&self) -> SessionId {
+        match self {
+            Self::CreateOutboundSession { outbound_session_id, .. }
+            | Self::DrainAndCloseOutboundSession { outbound_session_id } => {
+                (*outbound_session_id).into()
+            }
+            Self::SendData { inbound_session_id, .. }
+            | Self::AbortInboundSession { inbound_session_id }
+            | Self::HalfCloseInboundSession { inbound_session_id } => (*inbound_session_id).into(),
+            Self::CloseSession { session_id } => *session_id,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -59,12 +84,37 @@ pub(crate) enum SessionError {
     Timeout { substream_timeout: Duration },
     #[error(transparent)]
     IOError(#[from] io::Error),
-    #[error("Remote peer doesn't support the {protocol_name} protocol.")]
-    RemoteDoesntSupportProtocol { protocol_name: StreamProtocol },
+    #[error("Remote peer doesn't support any of the protocols {protocol_names:?}.")]
+    RemoteDoesntSupportProtocol { protocol_names: Vec<StreamProtocol> },
+    #[error("Remote peer didn't respond to a keep-alive ping in time.")]
+    KeepAliveTimeout,
+    #[error("Remote peer sent a message that couldn't be deserialized: {0}")]
+    DeserializationFailed(prost::DecodeError),
+    #[error("Remote peer answered with correlation id {received} instead of {expected}.")]
+    CorrelationMismatch { expected: usize, received: usize },
+}
+
+impl From<ReadMessageError> for SessionError {
+    fn from(error: ReadMessageError) -> Self {
+        match error {
+            ReadMessageError::IOError(io_error) => Self::IOError(io_error),
+            ReadMessageError::DeserializationFailed(decode_error) => {
+                Self::DeserializationFailed(decode_error)
+            }
+        }
+    }
 }
 
 pub(crate) type ToBehaviourEvent<Query, Data> = GenericEvent<Query, Data, SessionError>;
 
+// An item read from an outbound session. The session ends after a Closed item.
+enum OutboundSessionItem<Data> {
+    Data(Data),
+    Closed(CloseReason),
+}
+
+type OutboundSession<Data> = BoxStream<'static, Result<OutboundSessionItem<Data>, SessionError>>;
+
 type HandlerEvent<H> = ConnectionHandlerEvent<
     <H as ConnectionHandler>::OutboundProtocol,
     <H as ConnectionHandler>::OutboundOpenInfo,
@@ -75,51 +125,186 @@ pub(crate) struct Handler<Query: QueryBound, Data: DataBound> {
     // TODO(shahak): Consider changing to Arc<Config> if the config becomes heavy to clone.
     config: Config,
     next_inbound_session_id: Arc<AtomicUsize>,
+    inbound_session_queue_depths: InboundSessionQueueDepths,
     peer_id: PeerId,
-    id_to_inbound_session: HashMap<InboundSessionId, InboundSession<Data>>,
-    id_to_outbound_session: HashMap<OutboundSessionId, BoxStream<'static, Result<Data, io::Error>>>,
-    pending_events: VecDeque<HandlerEvent<Self>>,
-    inbound_sessions_marked_to_end: HashSet<InboundSessionId>,
+    id_to_inbound_session: HashMap<InboundSessionId, InboundSession<Query, Data>>,
+    // Each outbound session is stored alongside the protocol name that was negotiated for it.
+    id_to_outbound_session: HashMap<OutboundSessionId, (StreamProtocol, OutboundSession<Data>)>,
+    pending_events: PendingEvents<HandlerEvent<Self>>,
+    // The reason for closing each inbound session is sent to the remote peer once the session has
+    // sent all its pending data.
+    inbound_sessions_marked_to_end: HashMap<InboundSessionId, CloseReason>,
+    // Like inbound_sessions_marked_to_end, for the inbound sessions that should only stop sending.
+    inbound_sessions_marked_to_half_close: HashSet<InboundSessionId>,
+    // The size of the data in the ReceivedData events that are in pending_events, in total and
+    // per outbound session. The data leaves pending_events one event per poll, when it's passed to
+    // the behaviour, and the connection passes the events on only as fast as the swarm polls it.
+    // So when the total reaches the configured budget, the outbound sessions aren't polled until
+    // enough events were passed on. The data stops being counted once it's passed to the
+    // behaviour, whether or not the behaviour or the application consumed it. Since pending_events
+    // isn't empty while the budget is exhausted, poll always returns an event in that case and
+    // will be called again, so no wake up is needed for resuming.
+    queued_data_bytes: usize,
+    outbound_session_id_to_queued_data_bytes: HashMap<OutboundSessionId, usize>,
+    // Outbound sessions that are closed once they have no more data ready to be read. Until then,
+    // their data is read regardless of the queued data budget, since it was already received.
+    outbound_sessions_marked_to_drain: HashSet<OutboundSessionId>,
+    // Outbound sessions whose substream was requested and wasn't negotiated yet.
+    negotiating_outbound_session_ids: HashSet<OutboundSessionId>,
+    // Negotiating outbound sessions that were already closed. Their substream is dropped once
+    // it's negotiated, without reporting anything about it.
+    outbound_sessions_closed_before_negotiation: HashSet<OutboundSessionId>,
 }
 
 impl<Query: QueryBound, Data: DataBound> Handler<Query, Data> {
     // TODO(shahak) If we'll add more parameters, consider creating a HandlerConfig struct.
     // TODO(shahak) remove allow(dead_code).
     #[allow(dead_code)]
-    pub fn new(config: Config, next_inbound_session_id: Arc<AtomicUsize>, peer_id: PeerId) -> Self {
+    pub fn new(
+        config: Config,
+        next_inbound_session_id: Arc<AtomicUsize>,
+        inbound_session_queue_depths: InboundSessionQueueDepths,
+        peer_id: PeerId,
+    ) -> Self {
+        let pending_events = PendingEvents::new(
+            PendingEventsOwner::Handler,
+            config.pending_events_warning_threshold,
+        );
         Self {
             config,
             next_inbound_session_id,
+            inbound_session_queue_depths,
             peer_id,
             id_to_inbound_session: Default::default(),
             id_to_outbound_session: Default::default(),
-            pending_events: Default::default(),
+            pending_events,
             inbound_sessions_marked_to_end: Default::default(),
+            inbound_sessions_marked_to_half_close: Default::default(),
+            queued_data_bytes: 0,
+            outbound_session_id_to_queued_data_bytes: Default::default(),
+            outbound_sessions_marked_to_drain: Default::default(),
+            negotiating_outbound_session_ids: Default::default(),
+            outbound_sessions_closed_before_negotiation: Default::default(),
         }
     }
 
+    // A span for processing the events of the given session, labeled for correlating its logs.
+    fn session_span(&self, session_id: SessionId) -> Span {
+        debug_span!("session", label = %session_label(self.peer_id, session_id))
+    }
+
+    fn release_queued_data(&mut self, outbound_session_id: OutboundSessionId, num_bytes: usize) {
+        self.queued_data_bytes -= num_bytes;
+        if let Some(session_queued_data_bytes) =
+            self.outbound_session_id_to_queued_data_bytes.get_mut(&outbound_session_id)
+        {
+            *session_queued_data_bytes -= num_bytes;
+            if *session_queued_data_bytes == 0 {
+                self.outbound_session_id_to_queued_data_bytes.remove(&outbound_session_id);
+            }
+        }
+    }
+
+    fn mark_inbound_session_to_end(
+        &mut self,
+        inbound_session_id: InboundSessionId,
+        reason: CloseReason,
+    ) {
+        self.inbound_sessions_marked_to_end.insert(inbound_session_id, reason);
+        self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
+            ToBehaviourEvent::SessionClosedByRequest { session_id: inbound_session_id.into() },
+        ));
+    }
+
     /// Poll an inbound session, inserting any events needed to pending_events, and return whether
     /// the inbound session has finished.
     fn poll_inbound_session(
-        inbound_session: &mut InboundSession<Data>,
+        inbound_session: &mut InboundSession<Query, Data>,
         inbound_session_id: InboundSessionId,
-        pending_events: &mut VecDeque<HandlerEvent<Self>>,
+        pending_events: &mut PendingEvents<HandlerEvent<Self>>,
         cx: &mut Context<'_>,
     ) -> bool {
         let Poll::Ready(finish_reason) = inbound_session.poll_unpin(cx) else {
             let is_session_alive = false;
             return is_session_alive;
         };
-        if let FinishReason::Error(io_error) = finish_reason {
-            pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
-                ToBehaviourEvent::SessionFailed {
-                    session_id: inbound_session_id.into(),
-                    error: SessionError::IOError(io_error),
+        let error = match finish_reason {
+            FinishReason::Error(io_error) => SessionError::IOError(io_error),
+            FinishReason::KeepAliveTimeout => SessionError::KeepAliveTimeout,
+            // A half closed session is reported as closed only once the remote peer closed it as
+            // well.
+            FinishReason::Closed if inbound_session.is_half_closed() => {
+                pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                    ToBehaviourEvent::SessionClosedByRequest {
+                        session_id: inbound_session_id.into(),
+                    },
+                ));
+                return true;
+            }
+            FinishReason::Closed => return true,
+        };
+        pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
+            ToBehaviourEvent::SessionFailed { session_id: inbound_session_id.into(), error },
+        ));
+        true
+    }
+}
+
+// Reads the items of an outbound session from its stream through a buffer of the given size,
+// answering the pings of the remote peer and checking that it echoed the given correlation id, if
+// one was sent.
+fn read_outbound_session<Data: DataBound, Stream>(
+    stream: Stream,
+    read_buffer_size: NonZeroUsize,
+    correlation_id: Option<usize>,
+) -> OutboundSession<Data>
+where
+    Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut stream = BufReader::with_capacity(read_buffer_size.get(), stream);
+    stream! {
+        loop {
+            let result_opt = read_frame::<Data, _>(&mut stream).await;
+            let result = match result_opt {
+                Ok(Some(Frame::Message(data))) => Ok(OutboundSessionItem::Data(data)),
+                Ok(Some(Frame::Ping)) => match write_pong(&mut stream).await {
+                    Ok(()) => continue,
+                    Err(error) => Err(SessionError::IOError(error)),
+                },
+                Ok(Some(Frame::Pong)) => Err(SessionError::IOError(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Received a keep-alive pong without sending a ping.",
+                ))),
+                Ok(Some(Frame::Close(reason_code))) => {
+                    // Closing our side as well lets a remote peer that half closed the session
+                    // finish it cleanly, instead of having the stream reset when it's dropped.
+                    let _ = stream.close().await;
+                    yield Ok(OutboundSessionItem::Closed(reason_code.into()));
+                    break;
+                }
+                Ok(Some(Frame::Correlation(received))) => match correlation_id {
+                    Some(expected) if received == expected => continue,
+                    Some(expected) => Err(SessionError::CorrelationMismatch { expected, received }),
+                    None => Err(SessionError::IOError(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Received a correlation id without sending one.",
+                    ))),
                 },
-            ));
+                Ok(None) => break,
+                Err(error) => Err(error.into()),
+            };
+            let is_err = result.is_err();
+            yield result;
+            if is_err {
+                break;
+            }
         }
-        true
     }
+    .boxed()
+}
+
+fn is_over_budget(num_bytes: usize, budget: Option<usize>) -> bool {
+    budget.is_some_and(|budget| num_bytes >= budget)
 }
 
 impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Data> {
@@ -132,7 +317,7 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
         SubstreamProtocol::new(
-            InboundProtocol::new(self.config.protocol_name.clone()),
+            InboundProtocol::new(self.config.protocol_names.clone()),
             InboundSessionId { value: self.next_inbound_session_id.fetch_add(1, Ordering::AcqRel) },
         )
         .with_timeout(self.config.substream_timeout)
@@ -146,6 +331,14 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
     > {
         // Handle inbound sessions.
         self.id_to_inbound_session.retain(|inbound_session_id, inbound_session| {
+            // A half closed session already finished sending, so closing it only stops reading
+            // from it. Its closing was already reported.
+            if inbound_session.is_half_closed()
+                && self.inbound_sessions_marked_to_end.contains_key(inbound_session_id)
+            {
+                let is_session_alive = false;
+                return is_session_alive;
+            }
             if Self::poll_inbound_session(
                 inbound_session,
                 *inbound_session_id,
@@ -155,10 +348,23 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
                 let is_session_alive = false;
                 return is_session_alive;
             }
-            if self.inbound_sessions_marked_to_end.contains(inbound_session_id)
+            if let Some(reason) = self.inbound_sessions_marked_to_end.get(inbound_session_id) {
+                if inbound_session.is_waiting() {
+                    inbound_session.start_closing(*reason);
+                    if Self::poll_inbound_session(
+                        inbound_session,
+                        *inbound_session_id,
+                        &mut self.pending_events,
+                        cx,
+                    ) {
+                        let is_session_alive = false;
+                        return is_session_alive;
+                    }
+                }
+            } else if self.inbound_sessions_marked_to_half_close.contains(inbound_session_id)
                 && inbound_session.is_waiting()
             {
-                inbound_session.start_closing();
+                inbound_session.start_half_closing();
                 if Self::poll_inbound_session(
                     inbound_session,
                     *inbound_session_id,
@@ -172,10 +378,37 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
             true
         });
 
-        // Handle outbound sessions.
-        self.id_to_outbound_session.retain(|outbound_session_id, outbound_session| {
-            match outbound_session.poll_next_unpin(cx) {
-                Poll::Ready(Some(Ok(data))) => {
+        // Handle outbound sessions. Each session is polled for at most one item in each call, so
+        // the sessions of the connection advance together and a session with a lot of data ready
+        // doesn't hold back the others.
+        self.id_to_outbound_session.retain(|outbound_session_id, (_, outbound_session)| {
+            let is_draining = self.outbound_sessions_marked_to_drain.contains(outbound_session_id);
+            let session_queued_data_bytes = self
+                .outbound_session_id_to_queued_data_bytes
+                .get(outbound_session_id)
+                .copied()
+                .unwrap_or_default();
+            if !is_draining
+                && (is_over_budget(self.queued_data_bytes, self.config.max_queued_data_bytes)
+                    || is_over_budget(
+                        session_queued_data_bytes,
+                        self.config.max_queued_data_bytes_per_session,
+                    ))
+            {
+                return true;
+            }
+            let poll_result = outbound_session.poll_next_unpin(cx);
+            if is_draining && !matches!(poll_result, Poll::Ready(Some(Ok(_)))) {
+                self.outbound_sessions_marked_to_drain.remove(outbound_session_id);
+            }
+            match poll_result {
+                Poll::Ready(Some(Ok(OutboundSessionItem::Data(data)))) => {
+                    let num_bytes = data.encoded_len();
+                    self.queued_data_bytes += num_bytes;
+                    *self
+                        .outbound_session_id_to_queued_data_bytes
+                        .entry(*outbound_session_id)
+                        .or_default() += num_bytes;
                     self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
                         ToBehaviourEvent::ReceivedData {
                             outbound_session_id: *outbound_session_id,
@@ -184,19 +417,39 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
                     ));
                     true
                 }
-                Poll::Ready(Some(Err(io_error))) => {
+                Poll::Ready(Some(Err(error))) => {
                     self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
                         ToBehaviourEvent::SessionFailed {
                             session_id: SessionId::OutboundSessionId(*outbound_session_id),
-                            error: SessionError::IOError(io_error),
+                            error,
+                        },
+                    ));
+                    false
+                }
+                Poll::Ready(Some(Ok(OutboundSessionItem::Closed(reason)))) => {
+                    self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                        ToBehaviourEvent::SessionClosedByPeer {
+                            session_id: SessionId::OutboundSessionId(*outbound_session_id),
+                            reason,
                         },
                     ));
                     false
                 }
+                // The remote peer closed the session without sending a close trailer.
                 Poll::Ready(None) => {
                     self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
                         ToBehaviourEvent::SessionClosedByPeer {
                             session_id: SessionId::OutboundSessionId(*outbound_session_id),
+                            reason: CloseReason::Unspecified,
+                        },
+                    ));
+                    false
+                }
+                // A drained session has no more data that was already received.
+                Poll::Pending if is_draining => {
+                    self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                        ToBehaviourEvent::SessionClosedByRequest {
+                            session_id: SessionId::OutboundSessionId(*outbound_session_id),
                         },
                     ));
                     false
@@ -207,21 +460,41 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
 
         // Handling pending_events at the end of the function to avoid starvation.
         if let Some(event) = self.pending_events.pop_front() {
+            if let ConnectionHandlerEvent::NotifyBehaviour(ToBehaviourEvent::ReceivedData {
+                outbound_session_id,
+                data,
+            }) = &event
+            {
+                self.release_queued_data(*outbound_session_id, data.encoded_len());
+            }
             return Poll::Ready(event);
         }
         Poll::Pending
     }
 
     fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
+        let _span = self.session_span(event.session_id()).entered();
         match event {
             RequestFromBehaviourEvent::CreateOutboundSession { query, outbound_session_id } => {
+                self.negotiating_outbound_session_ids.insert(outbound_session_id);
                 // TODO(shahak) Consider extracting to a utility function to prevent forgetfulness
                 // of the timeout.
                 self.pending_events.push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
                     protocol: SubstreamProtocol::new(
                         OutboundProtocol {
                             query,
-                            protocol_name: self.config.protocol_name.clone(),
+                            correlation_id: outbound_session_id.value,
+                            correlated_protocol_names: self
+                                .config
+                                .protocol_names
+                                .iter()
+                                .filter(|protocol_name| {
+                                    self.config
+                                        .supports(protocol_name, ProtocolExtension::CorrelationId)
+                                })
+                                .cloned()
+                                .collect(),
+                            protocol_names: self.config.protocol_names.clone(),
                         },
                         outbound_session_id,
                     )
@@ -232,7 +505,9 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
                 if let Some(inbound_session) =
                     self.id_to_inbound_session.get_mut(&inbound_session_id)
                 {
-                    if self.inbound_sessions_marked_to_end.contains(&inbound_session_id) {
+                    if self.inbound_sessions_marked_to_end.contains_key(&inbound_session_id)
+                        || self.inbound_sessions_marked_to_half_close.contains(&inbound_session_id)
+                    {
                         // TODO(shahak): Consider handling this in a different way than just
                         // logging.
                         debug!(
@@ -253,17 +528,30 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
             RequestFromBehaviourEvent::CloseSession {
                 session_id: SessionId::InboundSessionId(inbound_session_id),
             } => {
-                self.inbound_sessions_marked_to_end.insert(inbound_session_id);
-                self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
-                    ToBehaviourEvent::SessionClosedByRequest {
-                        session_id: inbound_session_id.into(),
-                    },
-                ));
+                self.mark_inbound_session_to_end(inbound_session_id, CloseReason::Completed);
             }
+            RequestFromBehaviourEvent::AbortInboundSession { inbound_session_id } => {
+                self.mark_inbound_session_to_end(inbound_session_id, CloseReason::Aborted);
+            }
+            RequestFromBehaviourEvent::HalfCloseInboundSession { inbound_session_id } => {
+                self.inbound_sessions_marked_to_half_close.insert(inbound_session_id);
+            }
+            RequestFromBehaviourEvent::DrainAndCloseOutboundSession { outbound_session_id }
+                if self.id_to_outbound_session.contains_key(&outbound_session_id) =>
+            {
+                self.outbound_sessions_marked_to_drain.insert(outbound_session_id);
+            }
+            // An outbound session that wasn't negotiated yet has no data to drain, so it's closed
+            // right away.
             RequestFromBehaviourEvent::CloseSession {
                 session_id: SessionId::OutboundSessionId(outbound_session_id),
-            } => {
+            }
+            | RequestFromBehaviourEvent::DrainAndCloseOutboundSession { outbound_session_id } => {
                 self.id_to_outbound_session.remove(&outbound_session_id);
+                if self.negotiating_outbound_session_ids.contains(&outbound_session_id) {
+                    self.outbound_sessions_closed_before_negotiation.insert(outbound_session_id);
+                }
+                self.outbound_sessions_marked_to_drain.remove(&outbound_session_id);
                 self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
                     ToBehaviourEvent::SessionClosedByRequest {
                         session_id: outbound_session_id.into(),
@@ -285,46 +573,75 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
     ) {
         match event {
             ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
-                protocol: mut stream,
+                protocol: (stream, protocol_name),
                 info: outbound_session_id,
             }) => {
-                self.id_to_outbound_session.insert(
-                    outbound_session_id,
-                    stream! {
-                        loop {
-                            let result_opt = read_message::<Data, _>(&mut stream).await;
-                            let result = match result_opt {
-                                Ok(Some(data)) => Ok(data),
-                                Ok(None) => break,
-                                Err(error) => Err(error),
-                            };
-                            let is_err = result.is_err();
-                            yield result;
-                            if is_err {
-                                break;
-                            }
-                        }
-                    }
-                    .boxed(),
-                );
+                let _span = self.session_span(outbound_session_id.into()).entered();
+                self.negotiating_outbound_session_ids.remove(&outbound_session_id);
+                if self.outbound_sessions_closed_before_negotiation.remove(&outbound_session_id) {
+                    debug!("Dropping the substream of an outbound session that was closed.");
+                    return;
+                }
+                self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                    ToBehaviourEvent::OutboundSessionNegotiated {
+                        outbound_session_id,
+                        protocol_name: protocol_name.clone(),
+                    },
+                ));
+                // The correlation id was sent only if the negotiated protocol supports it.
+                let correlation_id = self
+                    .config
+                    .supports(&protocol_name, ProtocolExtension::CorrelationId)
+                    .then_some(outbound_session_id.value);
+                let data_stream =
+                    read_outbound_session(stream, self.config.read_buffer_size, correlation_id);
+                self.id_to_outbound_session
+                    .insert(outbound_session_id, (protocol_name, data_stream));
             }
             ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound {
-                protocol: (query, stream),
+                protocol: (query, correlation_id, stream, protocol_name),
                 info: inbound_session_id,
             }) => {
+                let _span = self.session_span(inbound_session_id.into()).entered();
                 self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
                     ToBehaviourEvent::NewInboundSession {
                         query,
                         inbound_session_id,
                         peer_id: self.peer_id,
+                        protocol_name: protocol_name.clone(),
                     },
                 ));
-                self.id_to_inbound_session.insert(inbound_session_id, InboundSession::new(stream));
+                // Peers with a protocol that doesn't support an extension fail on its frames.
+                let keep_alive_interval = self
+                    .config
+                    .keep_alive_interval
+                    .filter(|_| self.config.supports(&protocol_name, ProtocolExtension::KeepAlive));
+                let sends_close_trailer =
+                    self.config.supports(&protocol_name, ProtocolExtension::CloseTrailer);
+                self.id_to_inbound_session.insert(
+                    inbound_session_id,
+                    InboundSession::new(
+                        stream,
+                        inbound_session_id,
+                        correlation_id,
+                        self.inbound_session_queue_depths.clone(),
+                        protocol_name,
+                        sends_close_trailer,
+                        keep_alive_interval,
+                        self.config.substream_timeout,
+                    ),
+                );
             }
             ConnectionEvent::DialUpgradeError(DialUpgradeError {
                 info: outbound_session_id,
                 error: upgrade_error,
             }) => {
+                let _span = self.session_span(outbound_session_id.into()).entered();
+                self.negotiating_outbound_session_ids.remove(&outbound_session_id);
+                // The closing of the session was already reported.
+                if self.outbound_sessions_closed_before_negotiation.remove(&outbound_session_id) {
+                    return;
+                }
                 let session_error = match upgrade_error {
                     StreamUpgradeError::Timeout => {
                         SessionError::Timeout { substream_timeout: self.config.substream_timeout }
@@ -334,7 +651,7 @@ impl<Query: QueryBound, Data: DataBound> ConnectionHandler for Handler<Query, Da
                     }
                     StreamUpgradeError::NegotiationFailed => {
                         SessionError::RemoteDoesntSupportProtocol {
-                            protocol_name: self.config.protocol_name.clone(),
+                            protocol_names: self.config.protocol_names.clone(),
                         }
                     }
                     StreamUpgradeError::Io(error) => SessionError::IOError(error),
@@ -1,47 +1,156 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
+use async_stream::stream;
 use futures::future::BoxFuture;
-use futures::{AsyncWriteExt, FutureExt};
-use libp2p::swarm::Stream;
+use futures::io::WriteHalf;
+use futures::stream::{BoxStream, Fuse, FusedStream};
+use futures::{AsyncReadExt, AsyncWriteExt, FutureExt, StreamExt};
+use libp2p::swarm::{Stream, StreamProtocol};
 use replace_with::replace_with_or_abort;
+use tokio::time::{sleep, Instant, Sleep};
 
-use super::super::DataBound;
-use crate::messages::write_message;
+use super::super::{CloseReason, DataBound, InboundSessionId, QueryBound};
+use crate::messages::{
+    read_frame,
+    write_close,
+    write_correlation_id,
+    write_message,
+    write_ping,
+    Frame,
+    ReadMessageError,
+};
 
-pub(super) struct InboundSession<Data: DataBound> {
+/// The number of messages of each open inbound session that are waiting to be written to the
+/// stream. It's shared between the behaviour and the handlers of its connections.
+pub(crate) type InboundSessionQueueDepths = Arc<Mutex<HashMap<InboundSessionId, usize>>>;
+
+pub(super) struct InboundSession<Query: QueryBound, Data: DataBound> {
     pending_messages: VecDeque<Data>,
+    // The length of pending_messages is reported under this id whenever it changes, and the report
+    // is removed when the session is dropped.
+    inbound_session_id: InboundSessionId,
+    queue_depths: InboundSessionQueueDepths,
     current_task: WriteMessageTask,
+    protocol_name: StreamProtocol,
+    // Whether the reason for closing the session is sent to the remote peer before closing it.
+    sends_close_trailer: bool,
+    keep_alive: Option<KeepAlive>,
+    // The frames that the remote peer sends. These are only read while waiting for keep-alive
+    // pongs or after the session was half closed, until the remote peer closes its side.
+    incoming_frames: Fuse<BoxStream<'static, Result<Frame<Query>, ReadMessageError>>>,
+    is_half_closed: bool,
 }
 
 pub(super) enum FinishReason {
     Error(io::Error),
     Closed,
+    KeepAliveTimeout,
 }
 
 enum WriteMessageTask {
-    Waiting(Stream),
-    Running(BoxFuture<'static, Result<Stream, io::Error>>),
+    Waiting(WriteHalf<Stream>),
+    Running(BoxFuture<'static, Result<WriteHalf<Stream>, io::Error>>),
     Closing(BoxFuture<'static, Result<(), io::Error>>),
+    // The write side of a half closed session was closed.
+    Closed,
+}
+
+// Pings the remote peer whenever the session was idle for a whole interval, and expects a pong
+// for each ping within the pong timeout. Pongs arrive in the order of the pings, so the deadlines
+// are kept in a queue.
+struct KeepAlive {
+    interval: Duration,
+    pong_timeout: Duration,
+    next_ping: Pin<Box<Sleep>>,
+    pong_deadlines: VecDeque<Pin<Box<Sleep>>>,
+}
+
+impl KeepAlive {
+    fn new(interval: Duration, pong_timeout: Duration) -> Self {
+        Self {
+            interval,
+            pong_timeout,
+            next_ping: Box::pin(sleep(interval)),
+            pong_deadlines: Default::default(),
+        }
+    }
+
+    fn postpone_ping(&mut self) {
+        self.next_ping.as_mut().reset(Instant::now() + self.interval);
+    }
 }
 
-impl<Data: DataBound> InboundSession<Data> {
+impl<Query: QueryBound, Data: DataBound> InboundSession<Query, Data> {
     #[allow(dead_code)]
     // TODO(shahak) remove allow dead code.
-    pub fn new(stream: Stream) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        stream: Stream,
+        inbound_session_id: InboundSessionId,
+        correlation_id: Option<usize>,
+        queue_depths: InboundSessionQueueDepths,
+        protocol_name: StreamProtocol,
+        sends_close_trailer: bool,
+        keep_alive_interval: Option<Duration>,
+        pong_timeout: Duration,
+    ) -> Self {
+        let (mut read_half, mut write_half) = stream.split();
+        let incoming_frames = stream! {
+            loop {
+                match read_frame::<Query, _>(&mut read_half).await {
+                    Ok(Some(frame)) => yield Ok(frame),
+                    Ok(None) => break,
+                    Err(error) => {
+                        yield Err(error);
+                        break;
+                    }
+                }
+            }
+        }
+        .boxed()
+        .fuse();
+        queue_depths.lock().expect("Lock should not be poisoned").insert(inbound_session_id, 0);
+        // The correlation id of the query is echoed before any data is sent.
+        let current_task = match correlation_id {
+            Some(correlation_id) => WriteMessageTask::Running(
+                async move {
+                    write_correlation_id(&mut write_half, correlation_id).await?;
+                    Ok(write_half)
+                }
+                .boxed(),
+            ),
+            None => WriteMessageTask::Waiting(write_half),
+        };
         Self {
             pending_messages: Default::default(),
-            current_task: WriteMessageTask::Waiting(stream),
+            inbound_session_id,
+            queue_depths,
+            current_task,
+            protocol_name,
+            sends_close_trailer,
+            keep_alive: keep_alive_interval.map(|interval| KeepAlive::new(interval, pong_timeout)),
+            incoming_frames,
+            is_half_closed: false,
         }
     }
 
+    /// The protocol name that was negotiated for this session.
+    #[allow(dead_code)]
+    pub fn protocol_name(&self) -> &StreamProtocol {
+        &self.protocol_name
+    }
+
     #[allow(dead_code)]
     // TODO(shahak) remove allow dead code.
     pub fn add_message_to_queue(&mut self, data: Data) {
         self.pending_messages.push_back(data);
+        self.report_queue_depth();
     }
 
     #[allow(dead_code)]
@@ -51,17 +160,125 @@ impl<Data: DataBound> InboundSession<Data> {
             && self.pending_messages.is_empty()
     }
 
-    pub fn start_closing(&mut self) {
+    /// Let the remote peer know why the session is closed, if its protocol supports that, and close
+    /// it.
+    pub fn start_closing(&mut self, reason: CloseReason) {
+        let sends_close_trailer = self.sends_close_trailer;
         replace_with_or_abort(&mut self.current_task, |current_task| {
             let WriteMessageTask::Waiting(mut stream) = current_task else {
                 panic!("Called start_closing while not waiting.");
             };
-            WriteMessageTask::Closing(async move { stream.close().await }.boxed())
+            WriteMessageTask::Closing(
+                async move {
+                    if sends_close_trailer {
+                        write_close(&mut stream, reason.into()).await?;
+                    }
+                    stream.close().await
+                }
+                .boxed(),
+            )
         })
     }
 
+    /// Let the remote peer know that no more data will be sent and close the write side of the
+    /// session. The session is finished once the remote peer closes its side as well.
+    pub fn start_half_closing(&mut self) {
+        self.is_half_closed = true;
+        self.start_closing(CloseReason::Completed);
+    }
+
+    pub fn is_half_closed(&self) -> bool {
+        self.is_half_closed
+    }
+
+    fn report_queue_depth(&self) {
+        self.queue_depths
+            .lock()
+            .expect("Lock should not be poisoned")
+            .insert(self.inbound_session_id, self.pending_messages.len());
+    }
+
+    fn is_closing(&self) -> bool {
+        matches!(self.current_task, WriteMessageTask::Closing(_) | WriteMessageTask::Closed)
+    }
+
+    fn handle_incoming_frames(&mut self, cx: &mut Context<'_>) -> Option<FinishReason> {
+        let is_reading = self.is_half_closed || (self.keep_alive.is_some() && !self.is_closing());
+        if !is_reading {
+            return None;
+        }
+        // Once the remote peer finished sending, a session that waits for a pong fails when the
+        // pong deadline passes, and a half closed session is finished.
+        while let Poll::Ready(Some(frame_result)) = self.incoming_frames.poll_next_unpin(cx) {
+            match (frame_result, &mut self.keep_alive) {
+                (Ok(Frame::Pong), Some(keep_alive)) => {
+                    keep_alive.pong_deadlines.pop_front();
+                }
+                (Ok(_), _) => {
+                    return Some(FinishReason::Error(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Got an unexpected frame from the remote peer.",
+                    )));
+                }
+                (Err(error), _) => return Some(FinishReason::Error(error.into())),
+            }
+        }
+        None
+    }
+
+    fn handle_keep_alive(&mut self, cx: &mut Context<'_>) -> Option<FinishReason> {
+        let is_closing = self.is_closing();
+        let Some(keep_alive) = &mut self.keep_alive else {
+            return None;
+        };
+        if is_closing {
+            return None;
+        }
+        if let Some(pong_deadline) = keep_alive.pong_deadlines.front_mut() {
+            if pong_deadline.poll_unpin(cx).is_ready() {
+                return Some(FinishReason::KeepAliveTimeout);
+            }
+        }
+        if keep_alive.next_ping.poll_unpin(cx).is_pending() {
+            return None;
+        }
+        keep_alive.postpone_ping();
+        // Polling the timer again in order to be woken up when the next ping is due.
+        let _ = keep_alive.next_ping.poll_unpin(cx);
+        let is_idle = matches!(self.current_task, WriteMessageTask::Waiting(_))
+            && self.pending_messages.is_empty();
+        if !is_idle {
+            return None;
+        }
+        replace_with_or_abort(&mut self.current_task, |current_task| {
+            let WriteMessageTask::Waiting(mut stream) = current_task else {
+                panic!("Started a keep-alive ping while not waiting.");
+            };
+            WriteMessageTask::Running(
+                async move {
+                    write_ping(&mut stream).await?;
+                    Ok(stream)
+                }
+                .boxed(),
+            )
+        });
+        let mut pong_deadline = Box::pin(sleep(keep_alive.pong_timeout));
+        if keep_alive.pong_deadlines.is_empty() {
+            // Polling the deadline in order to be woken up when it passes.
+            let _ = pong_deadline.poll_unpin(cx);
+        }
+        keep_alive.pong_deadlines.push_back(pong_deadline);
+        None
+    }
+
     fn handle_waiting(&mut self, cx: &mut Context<'_>) -> Option<FinishReason> {
         if let Some(data) = self.pending_messages.pop_front() {
+            self.report_queue_depth();
+            // Sending data keeps the connection alive, so there's no need to ping while it's
+            // being sent.
+            if let Some(keep_alive) = &mut self.keep_alive {
+                keep_alive.postpone_ping();
+            }
             replace_with_or_abort(&mut self.current_task, |current_task| {
                 let WriteMessageTask::Waiting(mut stream) = current_task else {
                     panic!("Called handle_waiting while not waiting.");
@@ -99,21 +316,46 @@ impl<Data: DataBound> InboundSession<Data> {
         };
         match fut.poll_unpin(cx) {
             Poll::Pending => None,
+            Poll::Ready(Ok(())) if self.is_half_closed => {
+                self.current_task = WriteMessageTask::Closed;
+                self.handle_closed()
+            }
             Poll::Ready(Ok(())) => Some(FinishReason::Closed),
             Poll::Ready(Err(io_error)) => Some(FinishReason::Error(io_error)),
         }
     }
+
+    // A half closed session is finished once the remote peer closes its side as well.
+    fn handle_closed(&mut self) -> Option<FinishReason> {
+        self.incoming_frames.is_terminated().then_some(FinishReason::Closed)
+    }
+}
+
+impl<Query: QueryBound, Data: DataBound> Drop for InboundSession<Query, Data> {
+    fn drop(&mut self) {
+        self.queue_depths
+            .lock()
+            .expect("Lock should not be poisoned")
+            .remove(&self.inbound_session_id);
+    }
 }
 
-impl<Data: DataBound> Future for InboundSession<Data> {
+impl<Query: QueryBound, Data: DataBound> Future for InboundSession<Query, Data> {
     type Output = FinishReason;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let unpinned_self = Pin::into_inner(self);
+        if let Some(finish_reason) = unpinned_self.handle_incoming_frames(cx) {
+            return Poll::Ready(finish_reason);
+        }
+        if let Some(finish_reason) = unpinned_self.handle_keep_alive(cx) {
+            return Poll::Ready(finish_reason);
+        }
         let result = match &mut unpinned_self.current_task {
             WriteMessageTask::Running(_) => unpinned_self.handle_running(cx),
             WriteMessageTask::Waiting(_) => unpinned_self.handle_waiting(cx),
             WriteMessageTask::Closing(_) => unpinned_self.handle_closing(cx),
+            WriteMessageTask::Closed => unpinned_self.handle_closed(),
         };
         match result {
             Some(finish_reason) => Poll::Ready(finish_reason),
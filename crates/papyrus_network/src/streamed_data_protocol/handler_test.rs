@@ -1,12 +1,23 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io;
+use std::num::NonZeroUsize;
 use std::pin::Pin;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use assert_matches::assert_matches;
+use futures::io::Cursor;
 use futures::task::{Context, Poll};
-use futures::{select, AsyncWriteExt, FutureExt, Stream as StreamTrait, StreamExt};
+use futures::{
+    select,
+    AsyncRead,
+    AsyncWrite,
+    AsyncWriteExt,
+    FutureExt,
+    Stream as StreamTrait,
+    StreamExt,
+};
 use libp2p::swarm::handler::{
     ConnectionEvent,
     DialUpgradeError,
@@ -15,10 +26,39 @@ use libp2p::swarm::handler::{
 };
 use libp2p::swarm::{ConnectionHandler, ConnectionHandlerEvent, Stream, StreamUpgradeError};
 use libp2p::PeerId;
+use prost::Message;
+use tokio::time::Instant;
 
-use super::super::{Config, DataBound, InboundSessionId, OutboundSessionId, QueryBound, SessionId};
-use super::{Handler, HandlerEvent, RequestFromBehaviourEvent, SessionError, ToBehaviourEvent};
-use crate::messages::{protobuf, read_message, write_message};
+use super::super::{
+    CloseReason,
+    Config,
+    DataBound,
+    InboundSessionId,
+    OutboundSessionId,
+    QueryBound,
+    SessionId,
+};
+use super::{
+    read_outbound_session,
+    Handler,
+    HandlerEvent,
+    InboundSessionQueueDepths,
+    OutboundSessionItem,
+    RequestFromBehaviourEvent,
+    SessionError,
+    ToBehaviourEvent,
+};
+use crate::messages::{
+    protobuf,
+    read_frame,
+    read_message,
+    write_correlation_id,
+    write_message,
+    write_ping,
+    write_pong,
+    write_usize,
+    Frame,
+};
 use crate::test_utils::{dummy_data, get_connected_streams};
 
 impl<Query: QueryBound, Data: DataBound> Unpin for Handler<Query, Data> {}
@@ -34,6 +74,41 @@ impl<Query: QueryBound, Data: DataBound> StreamTrait for Handler<Query, Data> {
     }
 }
 
+// An in-memory stream that counts the reads from it.
+struct ReadCountingStream {
+    inner: Cursor<Vec<u8>>,
+    num_reads: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for ReadCountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.num_reads.fetch_add(1, Ordering::Relaxed);
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ReadCountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
 fn simulate_request_to_send_data_from_swarm<Query: QueryBound, Data: DataBound>(
     handler: &mut Handler<Query, Data>,
     data: Data,
@@ -66,8 +141,9 @@ fn simulate_negotiated_inbound_session_from_swarm<Query: QueryBound, Data: DataB
     inbound_stream: Stream,
     inbound_session_id: InboundSessionId,
 ) {
+    let protocol_name = handler.config.protocol_names[0].clone();
     handler.on_connection_event(ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound {
-        protocol: (query, inbound_stream),
+        protocol: (query, None, inbound_stream, protocol_name),
         info: inbound_session_id,
     }));
 }
@@ -77,11 +153,33 @@ fn simulate_negotiated_outbound_session_from_swarm<Query: QueryBound, Data: Data
     outbound_stream: Stream,
     outbound_session_id: OutboundSessionId,
 ) {
+    let protocol_name = handler.config.protocol_names[0].clone();
     handler.on_connection_event(ConnectionEvent::FullyNegotiatedOutbound(
-        FullyNegotiatedOutbound { protocol: outbound_stream, info: outbound_session_id },
+        FullyNegotiatedOutbound {
+            protocol: (outbound_stream, protocol_name),
+            info: outbound_session_id,
+        },
     ));
 }
 
+// Simulates an outbound session whose peer sends the given data endlessly, so the session always
+// has data ready to be read.
+fn simulate_outbound_session_with_endless_data<Query: QueryBound, Data: DataBound + Clone>(
+    handler: &mut Handler<Query, Data>,
+    outbound_session_id: OutboundSessionId,
+    data: Data,
+) {
+    let protocol_name = handler.config.protocol_names[0].clone();
+    handler.id_to_outbound_session.insert(
+        outbound_session_id,
+        (
+            protocol_name,
+            futures::stream::repeat_with(move || Ok(OutboundSessionItem::Data(data.clone())))
+                .boxed(),
+        ),
+    );
+}
+
 fn simulate_outbound_negotiation_failed<Query: QueryBound + PartialEq, Data: DataBound>(
     handler: &mut Handler<Query, Data>,
     outbound_session_id: OutboundSessionId,
@@ -105,9 +203,26 @@ async fn validate_new_inbound_session_event<Query: QueryBound + PartialEq, Data:
             query: event_query,
             inbound_session_id: event_inbound_session_id,
             peer_id: event_peer_id,
+            protocol_name: event_protocol_name,
         }) if event_query == *query
             && event_inbound_session_id == inbound_session_id
-            && event_peer_id == handler.peer_id => {}
+            && event_peer_id == handler.peer_id
+            && event_protocol_name == handler.config.protocol_names[0] => {}
+    );
+}
+
+async fn validate_outbound_session_negotiated_event<Query: QueryBound, Data: DataBound>(
+    handler: &mut Handler<Query, Data>,
+    outbound_session_id: OutboundSessionId,
+) {
+    let event = handler.next().await.unwrap();
+    assert_matches!(
+        event,
+        ConnectionHandlerEvent::NotifyBehaviour(ToBehaviourEvent::OutboundSessionNegotiated {
+            outbound_session_id: event_outbound_session_id,
+            protocol_name: event_protocol_name,
+        }) if event_outbound_session_id == outbound_session_id
+            && event_protocol_name == handler.config.protocol_names[0]
     );
 }
 
@@ -144,13 +259,15 @@ async fn validate_session_closed_by_request_event<
 async fn validate_session_closed_by_peer_event<Query: QueryBound, Data: DataBound + PartialEq>(
     handler: &mut Handler<Query, Data>,
     session_id: SessionId,
+    reason: CloseReason,
 ) {
     let event = handler.next().await.unwrap();
     assert_matches!(
         event,
         ConnectionHandlerEvent::NotifyBehaviour(ToBehaviourEvent::SessionClosedByPeer {
-            session_id: event_session_id
-        }) if event_session_id == session_id
+            session_id: event_session_id,
+            reason: event_reason,
+        }) if event_session_id == session_id && event_reason == reason
     );
 }
 
@@ -200,9 +317,10 @@ async fn read_messages<Query: QueryBound, Data: DataBound>(
     ) -> Vec<protobuf::BasicMessage> {
         let mut result = Vec::new();
         for _ in 0..num_messages {
-            match read_message::<protobuf::BasicMessage, _>(&mut *stream).await.unwrap() {
-                Some(message) => result.push(message),
-                None => return result,
+            match read_frame::<protobuf::BasicMessage, _>(&mut *stream).await.unwrap() {
+                Some(Frame::Message(message)) => result.push(message),
+                Some(Frame::Close(_)) | None => return result,
+                Some(frame) => panic!("Got unexpected frame {:?}", frame),
             }
         }
         result
@@ -220,12 +338,46 @@ async fn process_inbound_session() {
     let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
         Config::get_test_config(),
         Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (inbound_stream, mut outbound_stream, _) = get_connected_streams().await;
+    let query = protobuf::BasicMessage::default();
+    let inbound_session_id = InboundSessionId { value: 1 };
+
+    simulate_negotiated_inbound_session_from_swarm(
+        &mut handler,
+        query.clone(),
+        inbound_stream,
+        inbound_session_id,
+    );
+    validate_new_inbound_session_event(&mut handler, &query, inbound_session_id).await;
+    let dummy_data_vec = dummy_data();
+    for data in &dummy_data_vec {
+        simulate_request_to_send_data_from_swarm(&mut handler, data.clone(), inbound_session_id);
+    }
+
+    let data_received = read_messages(handler, &mut outbound_stream, dummy_data_vec.len()).await;
+    assert_eq!(dummy_data_vec, data_received);
+}
+
+#[tokio::test]
+async fn inbound_session_queue_depth_counts_unsent_messages() {
+    let queue_depths = InboundSessionQueueDepths::default();
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        queue_depths.clone(),
         PeerId::random(),
     );
+    let queue_depth =
+        |inbound_session_id| queue_depths.lock().unwrap().get(&inbound_session_id).copied();
 
     let (inbound_stream, mut outbound_stream, _) = get_connected_streams().await;
     let query = protobuf::BasicMessage::default();
     let inbound_session_id = InboundSessionId { value: 1 };
+    assert_eq!(queue_depth(inbound_session_id), None);
 
     simulate_negotiated_inbound_session_from_swarm(
         &mut handler,
@@ -234,13 +386,22 @@ async fn process_inbound_session() {
         inbound_session_id,
     );
     validate_new_inbound_session_event(&mut handler, &query, inbound_session_id).await;
+    assert_eq!(queue_depth(inbound_session_id), Some(0));
+
+    // The messages wait in the queue until the handler is polled.
     let dummy_data_vec = dummy_data();
     for data in &dummy_data_vec {
         simulate_request_to_send_data_from_swarm(&mut handler, data.clone(), inbound_session_id);
     }
+    assert_eq!(queue_depth(inbound_session_id), Some(dummy_data_vec.len()));
 
+    validate_no_events(&mut handler);
+    assert_eq!(queue_depth(inbound_session_id), Some(0));
     let data_received = read_messages(handler, &mut outbound_stream, dummy_data_vec.len()).await;
     assert_eq!(dummy_data_vec, data_received);
+
+    // The depth of a session is removed along with the session.
+    assert_eq!(queue_depth(inbound_session_id), None);
 }
 
 #[tokio::test]
@@ -248,6 +409,7 @@ async fn closed_inbound_session_ignores_behaviour_request_to_send_data() {
     let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
         Config::get_test_config(),
         Arc::new(Default::default()),
+        Default::default(),
         PeerId::random(),
     );
 
@@ -287,6 +449,7 @@ fn listen_protocol_across_multiple_handlers() {
             let handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
                 Config::get_test_config(),
                 next_inbound_session_id,
+                Default::default(),
                 PeerId::random(),
             );
             (0..NUM_PROTOCOLS_PER_HANDLER)
@@ -307,6 +470,7 @@ async fn process_outbound_session() {
     let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
         Config::get_test_config(),
         Arc::new(Default::default()),
+        Default::default(),
         PeerId::random(),
     );
 
@@ -327,6 +491,7 @@ async fn process_outbound_session() {
         outbound_stream,
         outbound_session_id,
     );
+    validate_outbound_session_negotiated_event(&mut handler, outbound_session_id).await;
 
     let dummy_data_vec = dummy_data();
     for data in dummy_data_vec.clone() {
@@ -339,8 +504,125 @@ async fn process_outbound_session() {
 
     validate_no_events(&mut handler);
 
+    // Closing the stream without a close trailer, as peers with an older version do.
     inbound_stream.close().await.unwrap();
-    validate_session_closed_by_peer_event(&mut handler, outbound_session_id.into()).await;
+    validate_session_closed_by_peer_event(
+        &mut handler,
+        outbound_session_id.into(),
+        CloseReason::Unspecified,
+    )
+    .await;
+}
+
+// Extracting to a function in order to run it for each way of closing an inbound session.
+async fn test_close_reason_is_passed_to_peer(
+    close_request: RequestFromBehaviourEvent<protobuf::BasicMessage, protobuf::BasicMessage>,
+    inbound_session_id: InboundSessionId,
+    expected_reason: CloseReason,
+) {
+    let mut inbound_handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+    let mut outbound_handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (inbound_stream, outbound_stream, _) = get_connected_streams().await;
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_id = OutboundSessionId { value: 1 };
+
+    simulate_negotiated_inbound_session_from_swarm(
+        &mut inbound_handler,
+        query.clone(),
+        inbound_stream,
+        inbound_session_id,
+    );
+    validate_new_inbound_session_event(&mut inbound_handler, &query, inbound_session_id).await;
+    simulate_negotiated_outbound_session_from_swarm(
+        &mut outbound_handler,
+        outbound_stream,
+        outbound_session_id,
+    );
+    validate_outbound_session_negotiated_event(&mut outbound_handler, outbound_session_id).await;
+
+    let data = dummy_data()[0].clone();
+    simulate_request_to_send_data_from_swarm(
+        &mut inbound_handler,
+        data.clone(),
+        inbound_session_id,
+    );
+    inbound_handler.on_behaviour_event(close_request);
+    validate_session_closed_by_request_event(&mut inbound_handler, inbound_session_id.into()).await;
+    let _inbound_handler_task =
+        tokio::spawn(async move { while inbound_handler.next().await.is_some() {} });
+
+    validate_received_data_event(&mut outbound_handler, &data, outbound_session_id).await;
+    validate_session_closed_by_peer_event(
+        &mut outbound_handler,
+        outbound_session_id.into(),
+        expected_reason,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn inbound_session_closes_without_trailer_when_protocol_doesnt_support_it() {
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { protocol_extensions: HashMap::new(), ..Config::get_test_config() },
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (inbound_stream, mut outbound_stream, _) = get_connected_streams().await;
+    let query = protobuf::BasicMessage::default();
+    let inbound_session_id = InboundSessionId { value: 1 };
+    simulate_negotiated_inbound_session_from_swarm(
+        &mut handler,
+        query.clone(),
+        inbound_stream,
+        inbound_session_id,
+    );
+    validate_new_inbound_session_event(&mut handler, &query, inbound_session_id).await;
+
+    let data = dummy_data()[0].clone();
+    simulate_request_to_send_data_from_swarm(&mut handler, data.clone(), inbound_session_id);
+    simulate_request_to_close_session(&mut handler, inbound_session_id.into());
+    validate_session_closed_by_request_event(&mut handler, inbound_session_id.into()).await;
+    let _handler_task = tokio::spawn(async move { while handler.next().await.is_some() {} });
+
+    // A peer that doesn't know close trailers reads them as oversized messages.
+    assert_eq!(
+        read_message::<protobuf::BasicMessage, _>(&mut outbound_stream).await.unwrap(),
+        Some(data)
+    );
+    assert_eq!(
+        read_message::<protobuf::BasicMessage, _>(&mut outbound_stream).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn inbound_session_close_reason_is_passed_to_peer() {
+    let inbound_session_id = InboundSessionId { value: 1 };
+    test_close_reason_is_passed_to_peer(
+        RequestFromBehaviourEvent::CloseSession { session_id: inbound_session_id.into() },
+        inbound_session_id,
+        CloseReason::Completed,
+    )
+    .await;
+    test_close_reason_is_passed_to_peer(
+        RequestFromBehaviourEvent::AbortInboundSession { inbound_session_id },
+        inbound_session_id,
+        CloseReason::Aborted,
+    )
+    .await;
 }
 
 // Extracting to a function because two closures have different types.
@@ -353,6 +635,7 @@ async fn test_outbound_session_negotiation_failure(
     let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
         config,
         Arc::new(Default::default()),
+        Default::default(),
         PeerId::random(),
     );
     simulate_outbound_negotiation_failed(&mut handler, outbound_session_id, upgrade_error);
@@ -395,8 +678,8 @@ async fn outbound_session_negotiation_failure() {
         |session_error| {
             matches!(
                 session_error,
-                SessionError::RemoteDoesntSupportProtocol { protocol_name }
-                if *protocol_name == config.protocol_name
+                SessionError::RemoteDoesntSupportProtocol { protocol_names }
+                if *protocol_names == config.protocol_names
             )
         },
         config.clone(),
@@ -421,6 +704,7 @@ async fn closed_outbound_session_doesnt_emit_events_when_data_is_sent() {
     let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
         Config::get_test_config(),
         Arc::new(Default::default()),
+        Default::default(),
         PeerId::random(),
     );
 
@@ -432,6 +716,7 @@ async fn closed_outbound_session_doesnt_emit_events_when_data_is_sent() {
         outbound_stream,
         outbound_session_id,
     );
+    validate_outbound_session_negotiated_event(&mut handler, outbound_session_id).await;
 
     simulate_request_to_close_session(&mut handler, outbound_session_id.into());
     validate_session_closed_by_request_event(&mut handler, outbound_session_id.into()).await;
@@ -443,3 +728,549 @@ async fn closed_outbound_session_doesnt_emit_events_when_data_is_sent() {
 
     validate_no_events(&mut handler);
 }
+
+#[tokio::test]
+async fn outbound_session_closed_before_negotiation_drops_its_substream() {
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_id = OutboundSessionId { value: 1 };
+    simulate_request_to_send_query_from_swarm(&mut handler, query.clone(), outbound_session_id);
+    validate_request_to_swarm_new_outbound_session_to_swarm_event(
+        &mut handler,
+        &query,
+        outbound_session_id,
+    )
+    .await;
+
+    simulate_request_to_close_session(&mut handler, outbound_session_id.into());
+    validate_session_closed_by_request_event(&mut handler, outbound_session_id.into()).await;
+
+    // The substream finishes negotiating after the session was closed.
+    let (mut inbound_stream, outbound_stream, _) = get_connected_streams().await;
+    simulate_negotiated_outbound_session_from_swarm(
+        &mut handler,
+        outbound_stream,
+        outbound_session_id,
+    );
+    for data in dummy_data() {
+        // The handler dropped outbound_stream, so we don't unwrap the result.
+        let _ = write_message(data, &mut inbound_stream).await;
+    }
+    validate_no_events(&mut handler);
+    assert!(handler.id_to_outbound_session.is_empty());
+    assert!(handler.outbound_sessions_closed_before_negotiation.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn inbound_session_pings_at_configured_interval() {
+    const NUM_PINGS: u32 = 3;
+    let keep_alive_interval = Duration::from_secs(10);
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { keep_alive_interval: Some(keep_alive_interval), ..Config::get_test_config() },
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (inbound_stream, mut outbound_stream, _) = get_connected_streams().await;
+    let inbound_session_id = InboundSessionId { value: 1 };
+    simulate_negotiated_inbound_session_from_swarm(
+        &mut handler,
+        protobuf::BasicMessage::default(),
+        inbound_stream,
+        inbound_session_id,
+    );
+    // consume the new inbound session event without reading it.
+    handler.next().await;
+
+    // The streams are driven outside of the tokio runtime, so while the test waits for them the
+    // paused clock would auto-advance to the next timer. Keeping the runtime busy makes the clock
+    // advance only when the test advances it.
+    let busy_task = tokio::spawn(async {
+        loop {
+            tokio::task::yield_now().await;
+        }
+    });
+    let answer_pings = async {
+        for _ in 0..NUM_PINGS {
+            tokio::time::advance(keep_alive_interval - Duration::from_millis(1)).await;
+            assert!(
+                read_frame::<protobuf::BasicMessage, _>(&mut outbound_stream)
+                    .now_or_never()
+                    .is_none()
+            );
+            tokio::time::advance(Duration::from_millis(1)).await;
+            let frame =
+                read_frame::<protobuf::BasicMessage, _>(&mut outbound_stream).await.unwrap();
+            assert_eq!(frame, Some(Frame::Ping));
+            write_pong(&mut outbound_stream).await.unwrap();
+        }
+    };
+    select! {
+        _ = answer_pings.fuse() => {},
+        event = handler.next().fuse() => panic!("Got unexpected event {:?}", event),
+    };
+    busy_task.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn inbound_session_fails_when_pong_doesnt_arrive() {
+    let keep_alive_interval = Duration::from_secs(10);
+    let substream_timeout = Duration::from_secs(5);
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config {
+            substream_timeout,
+            keep_alive_interval: Some(keep_alive_interval),
+            ..Config::get_test_config()
+        },
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    // Keeping the outbound stream so the session won't be closed by the remote peer.
+    let (inbound_stream, _outbound_stream, _) = get_connected_streams().await;
+    let inbound_session_id = InboundSessionId { value: 1 };
+    simulate_negotiated_inbound_session_from_swarm(
+        &mut handler,
+        protobuf::BasicMessage::default(),
+        inbound_stream,
+        inbound_session_id,
+    );
+    // consume the new inbound session event without reading it.
+    handler.next().await;
+
+    let start_time = Instant::now();
+    validate_session_failed_event(&mut handler, inbound_session_id.into(), |session_error| {
+        matches!(session_error, SessionError::KeepAliveTimeout)
+    })
+    .await;
+    // The ping is sent after one interval and the pong is expected within the substream timeout.
+    assert_eq!(start_time.elapsed(), keep_alive_interval + substream_timeout);
+}
+
+#[tokio::test(start_paused = true)]
+async fn inbound_session_doesnt_ping_when_protocol_doesnt_support_keep_alive() {
+    let keep_alive_interval = Duration::from_secs(10);
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config {
+            keep_alive_interval: Some(keep_alive_interval),
+            protocol_extensions: HashMap::new(),
+            ..Config::get_test_config()
+        },
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (inbound_stream, mut outbound_stream, _) = get_connected_streams().await;
+    let inbound_session_id = InboundSessionId { value: 1 };
+    simulate_negotiated_inbound_session_from_swarm(
+        &mut handler,
+        protobuf::BasicMessage::default(),
+        inbound_stream,
+        inbound_session_id,
+    );
+    // consume the new inbound session event without reading it.
+    handler.next().await;
+
+    // Polling the idle session after it would have pinged.
+    tokio::time::advance(keep_alive_interval * 3).await;
+    validate_no_events(&mut handler);
+    let data = dummy_data()[0].clone();
+    simulate_request_to_send_data_from_swarm(&mut handler, data.clone(), inbound_session_id);
+
+    // A peer that doesn't know keep-alive frames reads them as oversized messages.
+    let message = select! {
+        message = read_message::<protobuf::BasicMessage, _>(&mut outbound_stream).fuse() => message,
+        event = handler.next().fuse() => panic!("Got unexpected event {:?}", event),
+    };
+    assert_eq!(message.unwrap(), Some(data));
+}
+
+#[tokio::test]
+async fn outbound_session_answers_pings() {
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (mut inbound_stream, outbound_stream, _) = get_connected_streams().await;
+    let outbound_session_id = OutboundSessionId { value: 1 };
+    simulate_negotiated_outbound_session_from_swarm(
+        &mut handler,
+        outbound_stream,
+        outbound_session_id,
+    );
+    validate_outbound_session_negotiated_event(&mut handler, outbound_session_id).await;
+
+    write_ping(&mut inbound_stream).await.unwrap();
+    let frame = select! {
+        frame = read_frame::<protobuf::BasicMessage, _>(&mut inbound_stream).fuse() => frame,
+        event = handler.next().fuse() => panic!("Got unexpected event {:?}", event),
+    };
+    assert_eq!(frame.unwrap(), Some(Frame::Pong));
+}
+
+#[tokio::test]
+async fn outbound_session_fails_on_undecodable_message() {
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (mut inbound_stream, outbound_stream, _) = get_connected_streams().await;
+    let outbound_session_id = OutboundSessionId { value: 1 };
+    simulate_negotiated_outbound_session_from_swarm(
+        &mut handler,
+        outbound_stream,
+        outbound_session_id,
+    );
+    validate_outbound_session_negotiated_event(&mut handler, outbound_session_id).await;
+
+    // A varint field tag without the field's value.
+    let undecodable_bytes = [0x08];
+    write_usize(&mut inbound_stream, undecodable_bytes.len()).await.unwrap();
+    inbound_stream.write_all(&undecodable_bytes).await.unwrap();
+    inbound_stream.flush().await.unwrap();
+
+    validate_session_failed_event(&mut handler, outbound_session_id.into(), |session_error| {
+        matches!(session_error, SessionError::DeserializationFailed(_))
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn outbound_session_fails_on_mismatched_correlation_id() {
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (mut inbound_stream, outbound_stream, _) = get_connected_streams().await;
+    let outbound_session_id = OutboundSessionId { value: 1 };
+    simulate_negotiated_outbound_session_from_swarm(
+        &mut handler,
+        outbound_stream,
+        outbound_session_id,
+    );
+    validate_outbound_session_negotiated_event(&mut handler, outbound_session_id).await;
+
+    write_correlation_id(&mut inbound_stream, 2).await.unwrap();
+    write_message(protobuf::BasicMessage { number: 1 }, &mut inbound_stream).await.unwrap();
+
+    validate_session_failed_event(&mut handler, outbound_session_id.into(), |session_error| {
+        matches!(session_error, SessionError::CorrelationMismatch { expected: 1, received: 2 })
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn outbound_session_fails_on_correlation_id_when_protocol_doesnt_support_it() {
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { protocol_extensions: HashMap::new(), ..Config::get_test_config() },
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (mut inbound_stream, outbound_stream, _) = get_connected_streams().await;
+    let outbound_session_id = OutboundSessionId { value: 1 };
+    simulate_negotiated_outbound_session_from_swarm(
+        &mut handler,
+        outbound_stream,
+        outbound_session_id,
+    );
+    validate_outbound_session_negotiated_event(&mut handler, outbound_session_id).await;
+
+    write_correlation_id(&mut inbound_stream, 1).await.unwrap();
+
+    validate_session_failed_event(&mut handler, outbound_session_id.into(), |session_error| {
+        matches!(session_error, SessionError::IOError(error) if error.kind() == io::ErrorKind::InvalidData)
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn outbound_sessions_pause_while_queued_data_exceeds_budget() {
+    let data = protobuf::BasicMessage { number: 1 };
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config { max_queued_data_bytes: Some(data.encoded_len()), ..Config::get_test_config() },
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    simulate_outbound_session_with_endless_data(
+        &mut handler,
+        OutboundSessionId { value: 1 },
+        data.clone(),
+    );
+    simulate_outbound_session_with_endless_data(
+        &mut handler,
+        OutboundSessionId { value: 2 },
+        data.clone(),
+    );
+
+    for _ in 0..5 {
+        let event = handler.next().await.unwrap();
+        assert_matches!(
+            event,
+            ConnectionHandlerEvent::NotifyBehaviour(ToBehaviourEvent::ReceivedData {
+                data: event_data,
+                ..
+            }) if event_data == data
+        );
+        // Once one session was read the budget was exhausted, so the other session wasn't read.
+        assert!(handler.pending_events.is_empty());
+        assert_eq!(handler.queued_data_bytes, 0);
+    }
+}
+
+#[tokio::test]
+async fn outbound_session_pauses_while_its_queued_data_exceeds_budget() {
+    let data = protobuf::BasicMessage { number: 1 };
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config {
+            max_queued_data_bytes_per_session: Some(data.encoded_len()),
+            ..Config::get_test_config()
+        },
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+    let outbound_session_id = OutboundSessionId { value: 1 };
+
+    // Queue an event so that the session's data stays in the handler for one more poll.
+    let query = protobuf::BasicMessage::default();
+    simulate_request_to_send_query_from_swarm(
+        &mut handler,
+        query.clone(),
+        OutboundSessionId { value: 2 },
+    );
+    simulate_outbound_session_with_endless_data(&mut handler, outbound_session_id, data.clone());
+
+    validate_request_to_swarm_new_outbound_session_to_swarm_event(
+        &mut handler,
+        &query,
+        OutboundSessionId { value: 2 },
+    )
+    .await;
+    assert_eq!(handler.pending_events.len(), 1);
+
+    // The session wasn't read while its data was waiting to be passed to the behaviour.
+    validate_received_data_event(&mut handler, &data, outbound_session_id).await;
+    assert!(handler.pending_events.is_empty());
+    assert!(handler.outbound_session_id_to_queued_data_bytes.is_empty());
+
+    validate_received_data_event(&mut handler, &data, outbound_session_id).await;
+}
+
+// Reads the given bytes as an outbound session with the given read buffer size, and returns the
+// number of messages read and the number of reads from the stream.
+async fn read_outbound_session_and_count_reads(
+    bytes: Vec<u8>,
+    read_buffer_size: NonZeroUsize,
+) -> (usize, usize) {
+    let num_reads = Arc::new(AtomicUsize::new(0));
+    let stream = ReadCountingStream { inner: Cursor::new(bytes), num_reads: num_reads.clone() };
+    let items = read_outbound_session::<protobuf::BasicMessage, _>(stream, read_buffer_size, None)
+        .collect::<Vec<_>>()
+        .await;
+    assert!(items.iter().all(|item| matches!(item, Ok(OutboundSessionItem::Data(_)))));
+    (items.len(), num_reads.load(Ordering::Relaxed))
+}
+
+#[tokio::test]
+async fn larger_read_buffer_takes_fewer_reads_from_stream() {
+    const NUM_MESSAGES: usize = 10000;
+    let mut bytes = Vec::new();
+    for number in 1..=NUM_MESSAGES {
+        write_message(protobuf::BasicMessage { number: number as u64 }, &mut bytes).await.unwrap();
+    }
+
+    let (num_messages, unbuffered_num_reads) =
+        read_outbound_session_and_count_reads(bytes.clone(), NonZeroUsize::MIN).await;
+    assert_eq!(num_messages, NUM_MESSAGES);
+    // Without a buffer, each message takes a read for its length and another one for its content.
+    assert!(unbuffered_num_reads >= 2 * NUM_MESSAGES);
+
+    let (num_messages, buffered_num_reads) =
+        read_outbound_session_and_count_reads(bytes, NonZeroUsize::new(1 << 16).unwrap()).await;
+    assert_eq!(num_messages, NUM_MESSAGES);
+    assert!(
+        buffered_num_reads * 100 < unbuffered_num_reads,
+        "Reading with a large buffer took {buffered_num_reads} reads, while reading without a \
+         buffer took {unbuffered_num_reads} reads."
+    );
+}
+
+#[tokio::test]
+async fn half_closed_inbound_session_is_closed_once_peer_closes() {
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    let (inbound_stream, mut outbound_stream, _) = get_connected_streams().await;
+    let query = protobuf::BasicMessage::default();
+    let inbound_session_id = InboundSessionId { value: 1 };
+
+    simulate_negotiated_inbound_session_from_swarm(
+        &mut handler,
+        query.clone(),
+        inbound_stream,
+        inbound_session_id,
+    );
+    validate_new_inbound_session_event(&mut handler, &query, inbound_session_id).await;
+    let dummy_data_vec = dummy_data();
+    for data in &dummy_data_vec {
+        simulate_request_to_send_data_from_swarm(&mut handler, data.clone(), inbound_session_id);
+    }
+    handler.on_behaviour_event(RequestFromBehaviourEvent::HalfCloseInboundSession {
+        inbound_session_id,
+    });
+    // Data that was requested after the half close isn't sent.
+    simulate_request_to_send_data_from_swarm(
+        &mut handler,
+        dummy_data_vec[0].clone(),
+        inbound_session_id,
+    );
+
+    let peer_task = tokio::spawn(async move {
+        let mut data_received = Vec::new();
+        loop {
+            match read_frame::<protobuf::BasicMessage, _>(&mut outbound_stream).await.unwrap() {
+                Some(Frame::Message(message)) => data_received.push(message),
+                Some(Frame::Close(reason)) => {
+                    assert_eq!(CloseReason::from(reason), CloseReason::Completed);
+                    break;
+                }
+                frame => panic!("Got unexpected frame {:?}", frame),
+            }
+        }
+        outbound_stream.close().await.unwrap();
+        data_received
+    });
+
+    validate_session_closed_by_request_event(&mut handler, inbound_session_id.into()).await;
+    assert_eq!(peer_task.await.unwrap(), dummy_data_vec);
+}
+
+#[tokio::test]
+async fn concurrent_outbound_sessions_are_polled_fairly() {
+    const NUM_SESSIONS: usize = 3;
+    const NUM_MESSAGES_PER_SESSION: usize = 5;
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+
+    // Each query gets its own substream, without waiting for the previous ones to negotiate.
+    let query = protobuf::BasicMessage::default();
+    let outbound_session_ids =
+        (0..NUM_SESSIONS).map(|value| OutboundSessionId { value }).collect::<Vec<_>>();
+    for outbound_session_id in &outbound_session_ids {
+        simulate_request_to_send_query_from_swarm(
+            &mut handler,
+            query.clone(),
+            *outbound_session_id,
+        );
+    }
+    for outbound_session_id in &outbound_session_ids {
+        validate_request_to_swarm_new_outbound_session_to_swarm_event(
+            &mut handler,
+            &query,
+            *outbound_session_id,
+        )
+        .await;
+    }
+
+    // All the data of every session is ready to be read at once.
+    for outbound_session_id in &outbound_session_ids {
+        let mut bytes = Vec::new();
+        for message_index in 0..NUM_MESSAGES_PER_SESSION {
+            let number = outbound_session_id.value * NUM_MESSAGES_PER_SESSION + message_index;
+            write_message(protobuf::BasicMessage { number: number as u64 }, &mut bytes)
+                .await
+                .unwrap();
+        }
+        let protocol_name = handler.config.protocol_names[0].clone();
+        handler.id_to_outbound_session.insert(
+            *outbound_session_id,
+            (protocol_name, read_outbound_session(Cursor::new(bytes), NonZeroUsize::MIN, None)),
+        );
+    }
+
+    // The sessions take turns, so every round of events has data from each of them.
+    let mut received_numbers = HashSet::new();
+    for _ in 0..NUM_MESSAGES_PER_SESSION {
+        let mut round_session_ids = HashSet::new();
+        for _ in 0..NUM_SESSIONS {
+            let event = handler.next().await.unwrap();
+            let ConnectionHandlerEvent::NotifyBehaviour(ToBehaviourEvent::ReceivedData {
+                outbound_session_id,
+                data,
+            }) = event
+            else {
+                panic!("Got unexpected event {:?} when expecting ReceivedData", event);
+            };
+            assert_eq!(data.number as usize / NUM_MESSAGES_PER_SESSION, outbound_session_id.value);
+            assert!(round_session_ids.insert(outbound_session_id));
+            assert!(received_numbers.insert(data.number));
+        }
+    }
+    assert_eq!(received_numbers.len(), NUM_SESSIONS * NUM_MESSAGES_PER_SESSION);
+}
+
+#[tokio::test]
+async fn drained_outbound_session_reports_received_data_before_closing() {
+    let mut handler = Handler::<protobuf::BasicMessage, protobuf::BasicMessage>::new(
+        Config::get_test_config(),
+        Arc::new(Default::default()),
+        Default::default(),
+        PeerId::random(),
+    );
+    let outbound_session_id = OutboundSessionId { value: 1 };
+
+    // The data was already received, and the remote peer didn't send anything else yet.
+    let dummy_data_vec = dummy_data();
+    let protocol_name = handler.config.protocol_names[0].clone();
+    handler.id_to_outbound_session.insert(
+        outbound_session_id,
+        (
+            protocol_name,
+            futures::stream::iter(
+                dummy_data_vec.clone().into_iter().map(|data| Ok(OutboundSessionItem::Data(data))),
+            )
+            .chain(futures::stream::pending())
+            .boxed(),
+        ),
+    );
+
+    handler.on_behaviour_event(RequestFromBehaviourEvent::DrainAndCloseOutboundSession {
+        outbound_session_id,
+    });
+    for data in &dummy_data_vec {
+        validate_received_data_event(&mut handler, data, outbound_session_id).await;
+    }
+    validate_session_closed_by_request_event(&mut handler, outbound_session_id.into()).await;
+    validate_no_events(&mut handler);
+    assert!(handler.id_to_outbound_session.is_empty());
+}
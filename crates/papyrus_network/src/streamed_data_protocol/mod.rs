@@ -1,15 +1,20 @@
 pub mod behaviour;
 pub mod handler;
+pub mod pending_events;
 pub mod protocol;
 
 #[cfg(test)]
 mod flow_test;
+#[cfg(test)]
+pub(crate) mod test_harness;
 
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
 use std::time::Duration;
 
 use derive_more::Display;
-use libp2p::swarm::StreamProtocol;
-use libp2p::PeerId;
+use libp2p::swarm::{self, NetworkBehaviour, StreamProtocol, Swarm};
+use libp2p::{Multiaddr, PeerId};
 use prost::Message;
 
 #[derive(Clone, Copy, Debug, Default, Display, Eq, Hash, PartialEq)]
@@ -40,25 +45,254 @@ impl From<InboundSessionId> for SessionId {
     }
 }
 
+// The number of characters from the end of the peer id that are shown in session labels. The
+// beginning of a peer id is mostly the same for all the peers, since it encodes the key type.
+const SESSION_LABEL_PEER_ID_LEN: usize = 8;
+
+/// Returns a human-readable label for the given session with the given peer, for correlating the
+/// logs of the session. The label is of the form `<peer_id_short>/<direction>/<n>`, where n is the
+/// value of the session id.
+pub(crate) fn session_label(peer_id: PeerId, session_id: SessionId) -> String {
+    let peer_id = peer_id.to_base58();
+    let peer_id_short = &peer_id[peer_id.len().saturating_sub(SESSION_LABEL_PEER_ID_LEN)..];
+    let (direction, n) = match session_id {
+        SessionId::OutboundSessionId(OutboundSessionId { value }) => ("out", value),
+        SessionId::InboundSessionId(InboundSessionId { value }) => ("in", value),
+    };
+    format!("{peer_id_short}/{direction}/{n}")
+}
+
 // This is a workaround for the unstable feature trait aliases
 // https://doc.rust-lang.org/beta/unstable-book/language-features/trait-alias.html
-pub(crate) trait QueryBound: Message + 'static + Default + Clone {}
-impl<T> QueryBound for T where T: Message + 'static + Default + Clone {}
+pub(crate) trait QueryBound: Message + 'static + Unpin + Default + Clone {}
+impl<T> QueryBound for T where T: Message + 'static + Unpin + Default + Clone {}
 
 pub(crate) trait DataBound: Message + 'static + Unpin + Default {}
 impl<T> DataBound for T where T: Message + 'static + Unpin + Default {}
 
 #[derive(Debug)]
 pub(crate) enum GenericEvent<Query: QueryBound, Data: DataBound, SessionError> {
-    NewInboundSession { query: Query, inbound_session_id: InboundSessionId, peer_id: PeerId },
-    ReceivedData { outbound_session_id: OutboundSessionId, data: Data },
-    SessionFailed { session_id: SessionId, error: SessionError },
-    SessionClosedByRequest { session_id: SessionId },
-    SessionClosedByPeer { session_id: SessionId },
+    NewInboundSession {
+        query: Query,
+        inbound_session_id: InboundSessionId,
+        peer_id: PeerId,
+        protocol_name: StreamProtocol,
+    },
+    ReceivedData {
+        outbound_session_id: OutboundSessionId,
+        data: Data,
+    },
+    SessionFailed {
+        session_id: SessionId,
+        error: SessionError,
+    },
+    SessionClosedByRequest {
+        session_id: SessionId,
+    },
+    SessionClosedByPeer {
+        session_id: SessionId,
+        reason: CloseReason,
+    },
+    // Reported only by the handler, once the protocol of an outbound session was negotiated. The
+    // behaviour records the protocol and doesn't pass the event on.
+    OutboundSessionNegotiated {
+        outbound_session_id: OutboundSessionId,
+        protocol_name: StreamProtocol,
+    },
+    // Reported only by the behaviour, once the protocol of an outbound session was negotiated and
+    // the session is ready to receive data. Reported at most once per outbound session.
+    OutboundSessionEstablished {
+        outbound_session_id: OutboundSessionId,
+        peer_id: PeerId,
+    },
+    // Reported only by the behaviour, right before the first ReceivedData or SessionClosedByPeer
+    // event of an outbound session. The latency is the time from sending the query until getting
+    // the first response, including the time the query waited for a connection.
+    OutboundSessionLatencyMeasured {
+        outbound_session_id: OutboundSessionId,
+        peer_id: PeerId,
+        latency: Duration,
+    },
+    // Reported only by the behaviour, once the last connection to a peer was closed. Reported
+    // after the failures of the sessions that were open on that connection.
+    PeerDisconnected {
+        peer_id: PeerId,
+    },
+}
+
+impl<Query: QueryBound, Data: DataBound, SessionError> GenericEvent<Query, Data, SessionError> {
+    /// Returns the session that the event is about, or None if the event is about a peer.
+    pub(crate) fn session_id(&self) -> Option<SessionId> {
+        match self {
+            Self::NewInboundSession { inbound_session_id, .. } => {
+                Some((*inbound_session_id).into())
+            }
+            Self::ReceivedData { outbound_session_id, .. }
+            | Self::OutboundSessionNegotiated { outbound_session_id, .. }
+            | Self::OutboundSessionEstablished { outbound_session_id, .. }
+            | Self::OutboundSessionLatencyMeasured { outbound_session_id, .. } => {
+                Some((*outbound_session_id).into())
+            }
+            Self::SessionFailed { session_id, .. }
+            | Self::SessionClosedByRequest { session_id }
+            | Self::SessionClosedByPeer { session_id, .. } => Some(*session_id),
+            Self::PeerDisconnected { .. } => None,
+        }
+    }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+/// The reason the remote peer gave for closing a session.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum CloseReason {
+    /// The peer closed the session without giving a reason. This is the case for peers that don't
+    /// send a close trailer.
+    Unspecified,
+    /// The peer sent all the data for the query.
+    Completed,
+    /// The peer stopped sending data before sending all the data for the query.
+    Aborted,
+}
+
+impl From<usize> for CloseReason {
+    fn from(reason_code: usize) -> Self {
+        match reason_code {
+            1 => Self::Completed,
+            2 => Self::Aborted,
+            // Unknown codes may come from peers with a newer version of the protocol.
+            _ => Self::Unspecified,
+        }
+    }
+}
+
+impl From<CloseReason> for usize {
+    fn from(reason: CloseReason) -> Self {
+        match reason {
+            CloseReason::Unspecified => 0,
+            CloseReason::Completed => 1,
+            CloseReason::Aborted => 2,
+        }
+    }
+}
+
+/// An extension of the wire format that only some versions of the protocol support. Peers with a
+/// version that doesn't support an extension fail when they get its frames, so an extension is
+/// used in a session only if the negotiated protocol supports it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum ProtocolExtension {
+    /// Keep-alive pings and pongs in between the messages of a session.
+    KeepAlive,
+    /// A trailer with the reason for closing a session, sent right before the session is closed.
+    CloseTrailer,
+    /// An id sent before the query of a session, which the remote peer echoes before the data.
+    CorrelationId,
+}
+
+/// The default for [`Config::idle_connection_timeout`]. It's long enough for the failure of the
+/// last session of a connection to be reported before the connection is closed.
+// The swarm is only built by the tests until the node runs the network.
+#[allow(dead_code)]
+pub(crate) const DEFAULT_IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A score that is lowered whenever a session with the peer fails. Peers whose score drops below
+/// the configured threshold are banned.
+pub(crate) type PeerScore = i32;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct Config {
     pub substream_timeout: Duration,
-    pub protocol_name: StreamProtocol,
+    /// The supported protocol names, ordered from the most preferred to the least preferred.
+    pub protocol_names: Vec<StreamProtocol>,
+    /// The extensions that each of the protocol names supports. A protocol that isn't in the map
+    /// supports none of them.
+    pub protocol_extensions: HashMap<StreamProtocol, HashSet<ProtocolExtension>>,
+    /// If set, an inbound session that didn't send anything for this long pings the remote peer
+    /// and fails if it doesn't get a pong within `substream_timeout`. This keeps idle connections
+    /// from being dropped by middleboxes. Only sessions whose protocol supports
+    /// [`ProtocolExtension::KeepAlive`] are pinged.
+    pub keep_alive_interval: Option<Duration>,
+    /// How many times to retry creating an outbound session when the remote peer doesn't support
+    /// the protocol. Zero disables retries.
+    pub max_outbound_retries: usize,
+    /// How long to wait before each retry of creating an outbound session.
+    pub retry_backoff: Duration,
+    /// Each failed session lowers the score of its peer by a penalty that depends on the failure,
+    /// and each outbound session the peer completes raises it back towards 0. A peer whose score
+    /// drops below this threshold is banned for `peer_ban_cooldown`.
+    pub peer_score_threshold: PeerScore,
+    pub peer_ban_cooldown: Duration,
+    /// If set, a connection handler stops reading from its outbound sessions once the data it
+    /// read from them and didn't pass to the behaviour yet reaches this many bytes. Reading
+    /// resumes as the queued data is passed on, so a swarm that isn't polled slows down the peers
+    /// through the transport's flow control instead of filling up the handler's queue.
+    /// It bounds only the handler's queue. Data that was passed to the behaviour isn't counted
+    /// anymore, so the queues of the behaviour and of the application aren't bounded by it.
+    pub max_queued_data_bytes: Option<usize>,
+    /// Like `max_queued_data_bytes`, but for the data of each outbound session separately.
+    pub max_queued_data_bytes_per_session: Option<usize>,
+    /// If set, new inbound sessions are rejected while this many inbound sessions didn't get any
+    /// data and weren't closed yet.
+    pub max_unanswered_inbound_sessions: Option<usize>,
+    /// The size of the buffer that outbound sessions read their streams through. A bigger buffer
+    /// takes fewer reads from the stream when the data arrives in many small messages. A size of 1
+    /// reads directly from the stream.
+    pub read_buffer_size: NonZeroUsize,
+    /// If set, at most this many outbound sessions are being established at once. A session is
+    /// being established from when its query is passed to the connection until its protocol is
+    /// negotiated or it ends. Other queries wait for their turn in the order they were sent.
+    pub max_pending_outbound_sessions: Option<usize>,
+    /// How long the swarm keeps a connection open after its last session ended. If it's zero,
+    /// the swarm may close a connection whose only session failed before the failure is reported,
+    /// and the session is reported to have failed with `ConnectionClosed` instead of the real
+    /// reason. A longer timeout keeps idle connections, and the resources they hold, open for
+    /// longer. The swarm is in charge of closing connections, so this is applied to it with
+    /// [`Config::apply_to_swarm_config`] when it's built.
+    pub idle_connection_timeout: Duration,
+    /// If set, connections are established only with these peers. Otherwise, connections are
+    /// established with any peer that isn't denied.
+    pub allowed_peers: Option<HashSet<PeerId>>,
+    /// Connections with these peers are never established, even if they're allowed.
+    pub denied_peers: HashSet<PeerId>,
+    /// If set, a warning is logged when more than this many events are waiting to be passed on by
+    /// the behaviour or by a connection handler, which means the swarm isn't polled fast enough.
+    /// The events are still queued.
+    pub pending_events_warning_threshold: Option<usize>,
+    /// The addresses that the node advertises to other peers as the ones it's reachable at, for
+    /// example a public address behind a NAT. They're distinct from the listen addresses, which
+    /// are only bound locally. They're added to the swarm as its external addresses with
+    /// [`Config::apply_to_swarm`], which is where the identify protocol takes the addresses it
+    /// reports from.
+    pub advertised_addresses: Vec<Multiaddr>,
+}
+
+impl Config {
+    /// Returns whether the given protocol supports the given extension.
+    pub(crate) fn supports(
+        &self,
+        protocol_name: &StreamProtocol,
+        extension: ProtocolExtension,
+    ) -> bool {
+        self.protocol_extensions
+            .get(protocol_name)
+            .is_some_and(|extensions| extensions.contains(&extension))
+    }
+
+    /// Returns the given swarm config with the settings of this config that the swarm is in charge
+    /// of.
+    // The swarm is only built by the tests until the node runs the network.
+    #[allow(dead_code)]
+    pub(crate) fn apply_to_swarm_config(&self, swarm_config: swarm::Config) -> swarm::Config {
+        swarm_config.with_idle_connection_timeout(self.idle_connection_timeout)
+    }
+
+    /// Applies the settings of this config that the swarm is in charge of and that can only be
+    /// applied once the swarm is built.
+    #[allow(dead_code)]
+    pub(crate) fn apply_to_swarm<TBehaviour: NetworkBehaviour>(
+        &self,
+        swarm: &mut Swarm<TBehaviour>,
+    ) {
+        for address in &self.advertised_addresses {
+            swarm.add_external_address(address.clone());
+        }
+    }
 }
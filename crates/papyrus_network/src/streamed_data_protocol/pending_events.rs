@@ -0,0 +1,93 @@
+//! The queue of the events that the behaviour or a connection handler passes on to the swarm.
+//!
+//! The events are passed on only as fast as the swarm is polled, so if it isn't polled fast enough
+//! while the sessions generate many events, the queue keeps growing. The depth of all the queues of
+//! each kind is kept in a gauge, and a warning is logged when a queue grows past the configured
+//! threshold, so that such a backlog can be detected.
+
+use std::collections::VecDeque;
+
+use derive_more::Display;
+use tracing::warn;
+
+/// The name of the gauge that holds the number of events that are waiting to be passed from the
+/// behaviour to the swarm.
+pub const NETWORK_BEHAVIOUR_PENDING_EVENTS: &str = "network_behaviour_pending_events";
+
+/// The name of the gauge that holds the number of events that are waiting to be passed from the
+/// connection handlers to the behaviour, in all the connections.
+pub const NETWORK_HANDLER_PENDING_EVENTS: &str = "network_handler_pending_events";
+
+/// The component whose events are queued.
+#[derive(Clone, Copy, Debug, Display)]
+pub(crate) enum PendingEventsOwner {
+    #[display(fmt = "the behaviour")]
+    Behaviour,
+    #[display(fmt = "a connection handler")]
+    Handler,
+}
+
+impl PendingEventsOwner {
+    fn gauge_name(&self) -> &'static str {
+        match self {
+            Self::Behaviour => NETWORK_BEHAVIOUR_PENDING_EVENTS,
+            Self::Handler => NETWORK_HANDLER_PENDING_EVENTS,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PendingEvents<T> {
+    events: VecDeque<T>,
+    owner: PendingEventsOwner,
+    warning_threshold: Option<usize>,
+    // Whether the queue grew past the warning threshold and didn't drop back to it since, so that
+    // each backlog is warned about once.
+    is_past_warning_threshold: bool,
+}
+
+impl<T> PendingEvents<T> {
+    pub fn new(owner: PendingEventsOwner, warning_threshold: Option<usize>) -> Self {
+        Self { events: VecDeque::new(), owner, warning_threshold, is_past_warning_threshold: false }
+    }
+
+    pub fn push_back(&mut self, event: T) {
+        self.events.push_back(event);
+        metrics::increment_gauge!(self.owner.gauge_name(), 1.0);
+        let Some(warning_threshold) = self.warning_threshold else {
+            return;
+        };
+        if !self.is_past_warning_threshold && self.events.len() > warning_threshold {
+            warn!(
+                "There are more than {warning_threshold} events waiting to be passed on by {}. \
+                 The swarm might not be polled fast enough.",
+                self.owner
+            );
+            self.is_past_warning_threshold = true;
+        }
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let event = self.events.pop_front()?;
+        metrics::decrement_gauge!(self.owner.gauge_name(), 1.0);
+        if self.warning_threshold.is_some_and(|threshold| self.events.len() <= threshold) {
+            self.is_past_warning_threshold = false;
+        }
+        Some(event)
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl<T> Drop for PendingEvents<T> {
+    fn drop(&mut self) {
+        // The events of a closed connection are never passed on.
+        metrics::decrement_gauge!(self.owner.gauge_name(), self.events.len() as f64);
+    }
+}
@@ -2,8 +2,9 @@
 #[path = "protocol_test.rs"]
 mod protocol_test;
 
+use std::collections::HashSet;
 use std::marker::PhantomData;
-use std::{io, iter};
+use std::{io, vec};
 
 use futures::future::BoxFuture;
 use futures::{AsyncRead, AsyncWrite, FutureExt};
@@ -11,28 +12,29 @@ use libp2p::core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
 use libp2p::swarm::StreamProtocol;
 use prost::Message;
 
-use crate::messages::{read_message, write_message};
+use crate::messages::{read_frame, write_correlation_id, write_message, Frame};
 
 /// Substream upgrade protocol for sending data on blocks.
 ///
-/// Receives a request to get a range of blocks and sends a stream of data on the blocks.
+/// Receives a request to get a range of blocks and sends a stream of data on the blocks. The
+/// request may be preceded by a correlation id, which should be echoed back before the data.
 pub struct InboundProtocol<Query: Message + Default> {
     phantom: PhantomData<Query>,
-    protocol_name: StreamProtocol,
+    protocol_names: Vec<StreamProtocol>,
 }
 
 impl<Query: Message + Default> InboundProtocol<Query> {
-    pub fn new(protocol_name: StreamProtocol) -> Self {
-        Self { protocol_name, phantom: PhantomData }
+    pub fn new(protocol_names: Vec<StreamProtocol>) -> Self {
+        Self { protocol_names, phantom: PhantomData }
     }
 }
 
 impl<Query: Message + Default> UpgradeInfo for InboundProtocol<Query> {
     type Info = StreamProtocol;
-    type InfoIter = iter::Once<Self::Info>;
+    type InfoIter = vec::IntoIter<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(self.protocol_name.clone())
+        self.protocol_names.clone().into_iter()
     }
 }
 
@@ -41,16 +43,32 @@ where
     Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     Query: Message + Default,
 {
-    type Output = (Query, Stream);
+    type Output = (Query, Option<usize>, Stream, StreamProtocol);
     type Error = io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
-    fn upgrade_inbound(self, mut stream: Stream, _: Self::Info) -> Self::Future {
+    fn upgrade_inbound(self, mut stream: Stream, protocol_name: Self::Info) -> Self::Future {
         async move {
-            let request = read_message::<Query, _>(&mut stream)
-                .await?
-                .ok_or::<io::Error>(io::ErrorKind::UnexpectedEof.into())?;
-            Ok((request, stream))
+            let mut correlation_id = None;
+            loop {
+                let frame = read_frame::<Query, _>(&mut stream)
+                    .await?
+                    .ok_or::<io::Error>(io::ErrorKind::UnexpectedEof.into())?;
+                match frame {
+                    Frame::Correlation(id) if correlation_id.is_none() => {
+                        correlation_id = Some(id);
+                    }
+                    Frame::Message(request) => {
+                        return Ok((request, correlation_id, stream, protocol_name));
+                    }
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Expected a request from the remote peer.",
+                        ));
+                    }
+                }
+            }
         }
         .boxed()
     }
@@ -62,17 +80,25 @@ where
 #[derive(Debug)]
 pub struct OutboundProtocol<Query: Message + Default> {
     pub query: Query,
+    /// Sent before the query for the remote peer to echo back before the data, if the negotiated
+    /// protocol is one of `correlated_protocol_names`.
+    pub correlation_id: usize,
+    /// The protocol names that support correlation ids. Peers that only support other protocol
+    /// names fail on getting a correlation id.
+    pub correlated_protocol_names: HashSet<StreamProtocol>,
     // TODO(shahak): Think of a way to allow multiple protocols with different Query type for
     // each.
-    pub protocol_name: StreamProtocol,
+    /// The protocol names to propose to the remote peer, ordered from the most preferred to the
+    /// least preferred.
+    pub protocol_names: Vec<StreamProtocol>,
 }
 
 impl<Query: Message + Default> UpgradeInfo for OutboundProtocol<Query> {
     type Info = StreamProtocol;
-    type InfoIter = iter::Once<Self::Info>;
+    type InfoIter = vec::IntoIter<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(self.protocol_name.clone())
+        self.protocol_names.clone().into_iter()
     }
 }
 
@@ -80,14 +106,17 @@ impl<Stream, Query: Message + Default + 'static> OutboundUpgrade<Stream> for Out
 where
     Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    type Output = Stream;
+    type Output = (Stream, StreamProtocol);
     type Error = io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
-    fn upgrade_outbound(self, mut stream: Stream, _: Self::Info) -> Self::Future {
+    fn upgrade_outbound(self, mut stream: Stream, protocol_name: Self::Info) -> Self::Future {
         async move {
+            if self.correlated_protocol_names.contains(&protocol_name) {
+                write_correlation_id(&mut stream, self.correlation_id).await?;
+            }
             write_message(self.query, &mut stream).await?;
-            Ok(stream)
+            Ok((stream, protocol_name))
         }
         .boxed()
     }
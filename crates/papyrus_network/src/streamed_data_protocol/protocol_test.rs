@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::io::ErrorKind;
 
 use assert_matches::assert_matches;
@@ -12,20 +13,30 @@ use crate::messages::{protobuf, read_message, write_message, write_usize};
 use crate::test_utils::{dummy_data, get_connected_streams};
 
 pub const PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/get_blocks/1.0.0");
+pub const OLD_PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/get_blocks/0.1.0");
 
 #[test]
 fn outbound_protocol_info() {
     let outbound_protocol = OutboundProtocol::<protobuf::BasicMessage> {
         query: Default::default(),
-        protocol_name: PROTOCOL_NAME,
+        correlation_id: 0,
+        correlated_protocol_names: HashSet::new(),
+        protocol_names: vec![PROTOCOL_NAME, OLD_PROTOCOL_NAME],
     };
-    assert_eq!(outbound_protocol.protocol_info().collect::<Vec<_>>(), vec![PROTOCOL_NAME]);
+    assert_eq!(
+        outbound_protocol.protocol_info().collect::<Vec<_>>(),
+        vec![PROTOCOL_NAME, OLD_PROTOCOL_NAME]
+    );
 }
 
 #[test]
 fn inbound_protocol_info() {
-    let inbound_protocol = InboundProtocol::<protobuf::BasicMessage>::new(PROTOCOL_NAME);
-    assert_eq!(inbound_protocol.protocol_info().collect::<Vec<_>>(), vec![PROTOCOL_NAME]);
+    let inbound_protocol =
+        InboundProtocol::<protobuf::BasicMessage>::new(vec![PROTOCOL_NAME, OLD_PROTOCOL_NAME]);
+    assert_eq!(
+        inbound_protocol.protocol_info().collect::<Vec<_>>(),
+        vec![PROTOCOL_NAME, OLD_PROTOCOL_NAME]
+    );
 }
 
 #[tokio::test]
@@ -33,21 +44,29 @@ async fn positive_flow() {
     let (inbound_stream, outbound_stream, _) = get_connected_streams().await;
 
     let query = protobuf::BasicMessage::default();
-    let outbound_protocol = OutboundProtocol { query: query.clone(), protocol_name: PROTOCOL_NAME };
-    let inbound_protocol = InboundProtocol::<protobuf::BasicMessage>::new(PROTOCOL_NAME);
+    let outbound_protocol = OutboundProtocol {
+        query: query.clone(),
+        correlation_id: 0,
+        correlated_protocol_names: HashSet::new(),
+        protocol_names: vec![PROTOCOL_NAME],
+    };
+    let inbound_protocol = InboundProtocol::<protobuf::BasicMessage>::new(vec![PROTOCOL_NAME]);
 
     tokio::join!(
         async move {
-            let (received_query, mut stream) =
+            let (received_query, correlation_id, mut stream, protocol_name) =
                 inbound_protocol.upgrade_inbound(inbound_stream, PROTOCOL_NAME).await.unwrap();
             assert_eq!(query, received_query);
+            assert_eq!(correlation_id, None);
+            assert_eq!(protocol_name, PROTOCOL_NAME);
             for response in dummy_data() {
                 write_message(response, &mut stream).await.unwrap();
             }
         },
         async move {
-            let mut stream =
+            let (mut stream, protocol_name) =
                 outbound_protocol.upgrade_outbound(outbound_stream, PROTOCOL_NAME).await.unwrap();
+            assert_eq!(protocol_name, PROTOCOL_NAME);
             for expected_response in dummy_data() {
                 let response =
                     read_message::<protobuf::BasicMessage, _>(&mut stream).await.unwrap().unwrap();
@@ -57,10 +76,67 @@ async fn positive_flow() {
     );
 }
 
+#[tokio::test]
+async fn inbound_receives_the_correlation_id_of_the_query() {
+    let (inbound_stream, outbound_stream, _) = get_connected_streams().await;
+
+    let query = protobuf::BasicMessage { number: 1 };
+    let outbound_protocol = OutboundProtocol {
+        query: query.clone(),
+        correlation_id: 3,
+        correlated_protocol_names: HashSet::from([PROTOCOL_NAME]),
+        protocol_names: vec![PROTOCOL_NAME],
+    };
+    let inbound_protocol = InboundProtocol::<protobuf::BasicMessage>::new(vec![PROTOCOL_NAME]);
+
+    let ((received_query, correlation_id, _stream, _), _) = tokio::join!(
+        async move { inbound_protocol.upgrade_inbound(inbound_stream, PROTOCOL_NAME).await.unwrap() },
+        async move { outbound_protocol.upgrade_outbound(outbound_stream, PROTOCOL_NAME).await.unwrap() },
+    );
+    assert_eq!(received_query, query);
+    assert_eq!(correlation_id, Some(3));
+}
+
+#[tokio::test]
+async fn outbound_doesnt_send_the_correlation_id_on_a_protocol_that_doesnt_support_it() {
+    let (mut inbound_stream, outbound_stream, _) = get_connected_streams().await;
+
+    let query = protobuf::BasicMessage { number: 1 };
+    let outbound_protocol = OutboundProtocol {
+        query: query.clone(),
+        correlation_id: 3,
+        correlated_protocol_names: HashSet::from([PROTOCOL_NAME]),
+        protocol_names: vec![PROTOCOL_NAME, OLD_PROTOCOL_NAME],
+    };
+    let response = dummy_data()[0].clone();
+
+    tokio::join!(
+        // A peer that only supports the old protocol and doesn't know correlation ids.
+        async {
+            let received_query = read_message::<protobuf::BasicMessage, _>(&mut inbound_stream)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(received_query, query);
+            write_message(response.clone(), &mut inbound_stream).await.unwrap();
+        },
+        async {
+            let (mut stream, protocol_name) = outbound_protocol
+                .upgrade_outbound(outbound_stream, OLD_PROTOCOL_NAME)
+                .await
+                .unwrap();
+            assert_eq!(protocol_name, OLD_PROTOCOL_NAME);
+            let received_response =
+                read_message::<protobuf::BasicMessage, _>(&mut stream).await.unwrap().unwrap();
+            assert_eq!(received_response, response);
+        }
+    );
+}
+
 #[tokio::test]
 async fn outbound_sends_invalid_request() {
     let (inbound_stream, mut outbound_stream, _) = get_connected_streams().await;
-    let inbound_protocol = InboundProtocol::<protobuf::BasicMessage>::new(PROTOCOL_NAME);
+    let inbound_protocol = InboundProtocol::<protobuf::BasicMessage>::new(vec![PROTOCOL_NAME]);
 
     tokio::join!(
         async move {
@@ -78,7 +154,7 @@ async fn outbound_sends_invalid_request() {
 #[tokio::test]
 async fn outbound_sends_no_request() {
     let (inbound_stream, mut outbound_stream, _) = get_connected_streams().await;
-    let inbound_protocol = InboundProtocol::<protobuf::BasicMessage>::new(PROTOCOL_NAME);
+    let inbound_protocol = InboundProtocol::<protobuf::BasicMessage>::new(vec![PROTOCOL_NAME]);
 
     tokio::join!(
         async move {
@@ -0,0 +1,143 @@
+//! A harness for testing [`Behaviour`]s end to end: two swarms that run a behaviour each and are
+//! connected to each other over an in-memory transport, and helpers for driving them.
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{PeerId, Swarm};
+use libp2p_swarm_test::SwarmExt;
+
+use super::behaviour::{Behaviour, Event};
+use super::{Config, InboundSessionId, SessionId};
+use crate::messages::protobuf;
+use crate::test_utils::create_swarm_with_config;
+
+// How long the helpers that exchange messages wait for each step of the exchange.
+const EXCHANGE_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) type TestBehaviour = Behaviour<protobuf::BasicMessage, protobuf::BasicMessage>;
+pub(crate) type TestEvent = Event<protobuf::BasicMessage, protobuf::BasicMessage>;
+
+/// The swarm that reported an event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Side {
+    /// The swarm that sends the queries.
+    Outbound,
+    /// The swarm that answers the queries.
+    Inbound,
+}
+
+/// Two swarms that run a [`Behaviour`] each and are connected to each other. The outbound swarm
+/// sends the queries and the inbound swarm answers them, but each can do both.
+pub(crate) struct ConnectedBehaviours {
+    pub outbound_swarm: Swarm<TestBehaviour>,
+    pub inbound_swarm: Swarm<TestBehaviour>,
+}
+
+impl ConnectedBehaviours {
+    /// Creates the swarms, each with the swarm settings of its config, and connects them. The
+    /// substream timeouts of the configs must be short enough to be added to the current time, so
+    /// the one of [`Config::get_test_config`] doesn't fit.
+    pub async fn new(outbound_config: Config, inbound_config: Config) -> Self {
+        let mut outbound_swarm = create_swarm_with_config(
+            |peer_id| TestBehaviour::new(outbound_config.clone(), peer_id),
+            &outbound_config,
+        );
+        let mut inbound_swarm = create_swarm_with_config(
+            |peer_id| TestBehaviour::new(inbound_config.clone(), peer_id),
+            &inbound_config,
+        );
+        inbound_swarm.listen().with_memory_addr_external().await;
+        outbound_swarm.connect(&mut inbound_swarm).await;
+        Self { outbound_swarm, inbound_swarm }
+    }
+
+    pub fn inbound_peer_id(&self) -> PeerId {
+        *self.inbound_swarm.local_peer_id()
+    }
+
+    pub fn outbound_behaviour(&mut self) -> &mut TestBehaviour {
+        self.outbound_swarm.behaviour_mut()
+    }
+
+    pub fn inbound_behaviour(&mut self) -> &mut TestBehaviour {
+        self.inbound_swarm.behaviour_mut()
+    }
+
+    /// Polls both swarms and passes the events that their behaviours report to the predicate, until
+    /// it returns a value or the timeout passes. Returns the value, or None if the timeout passed
+    /// first. The other events of the swarms are dropped.
+    pub async fn step_until<T>(
+        &mut self,
+        timeout: Duration,
+        mut predicate: impl FnMut(Side, TestEvent) -> Option<T>,
+    ) -> Option<T> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let (side, swarm_event) = tokio::select! {
+                    swarm_event = self.outbound_swarm.select_next_some() => {
+                        (Side::Outbound, swarm_event)
+                    }
+                    swarm_event = self.inbound_swarm.select_next_some() => {
+                        (Side::Inbound, swarm_event)
+                    }
+                };
+                let SwarmEvent::Behaviour(event) = swarm_event else {
+                    continue;
+                };
+                if let Some(value) = predicate(side, event) {
+                    return value;
+                }
+            }
+        })
+        .await
+        .ok()
+    }
+
+    /// Sends the query from the outbound swarm, answers it from the inbound swarm with the given
+    /// data and closes the inbound session. Returns the data that the outbound swarm received
+    /// until the inbound swarm closed the session. Panics if a step of the exchange times out.
+    pub async fn exchange(
+        &mut self,
+        query: protobuf::BasicMessage,
+        data: Vec<protobuf::BasicMessage>,
+    ) -> Vec<protobuf::BasicMessage> {
+        let inbound_peer_id = self.inbound_peer_id();
+        let outbound_session_id =
+            self.outbound_behaviour().send_query(query, inbound_peer_id, None, None).unwrap();
+
+        let inbound_session_id: InboundSessionId = self
+            .step_until(EXCHANGE_STEP_TIMEOUT, |side, event| match (side, event) {
+                (Side::Inbound, Event::NewInboundSession { inbound_session_id, .. }) => {
+                    Some(inbound_session_id)
+                }
+                _ => None,
+            })
+            .await
+            .expect("The inbound swarm didn't get the query in time.");
+        for message in data {
+            self.inbound_behaviour().send_data(message, inbound_session_id).unwrap();
+        }
+        self.inbound_behaviour().close_session(inbound_session_id.into()).unwrap();
+
+        let mut received_data = Vec::new();
+        self.step_until(EXCHANGE_STEP_TIMEOUT, |side, event| match (side, event) {
+            (Side::Outbound, Event::ReceivedData { outbound_session_id: session_id, data })
+                if session_id == outbound_session_id =>
+            {
+                received_data.push(data);
+                None
+            }
+            (Side::Outbound, Event::SessionClosedByPeer { session_id, .. })
+                if session_id == SessionId::OutboundSessionId(outbound_session_id) =>
+            {
+                Some(())
+            }
+            _ => None,
+        })
+        .await
+        .expect("The outbound swarm didn't get the data in time.");
+        received_data
+    }
+}
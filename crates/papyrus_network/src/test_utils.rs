@@ -4,18 +4,28 @@ use std::collections::hash_map::{Keys, ValuesMut};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::num::NonZeroUsize;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
 use futures::stream::{Stream as StreamTrait, StreamExt};
-use libp2p::swarm::{NetworkBehaviour, StreamProtocol, Swarm, SwarmEvent};
-use libp2p::{PeerId, Stream};
+use libp2p::core::transport::MemoryTransport;
+use libp2p::core::upgrade::Version;
+use libp2p::identity::Keypair;
+use libp2p::swarm::{self, NetworkBehaviour, StreamProtocol, Swarm, SwarmEvent};
+use libp2p::{plaintext, tcp, yamux, PeerId, Stream, Transport};
 use libp2p_swarm_test::SwarmExt;
 use tokio::task::JoinHandle;
 use tokio_stream::StreamExt as TokioStreamExt;
 
 use crate::messages::protobuf;
+use crate::streamed_data_protocol::{
+    Config,
+    PeerScore,
+    ProtocolExtension,
+    DEFAULT_IDLE_CONNECTION_TIMEOUT,
+};
 
 /// Create two streams that are connected to each other. Return them and a join handle for a thread
 /// that will perform the sends between the streams (this thread will run forever so it shouldn't
@@ -49,9 +59,35 @@ pub(crate) fn dummy_data() -> Vec<protobuf::BasicMessage> {
     ]
 }
 
-impl crate::streamed_data_protocol::Config {
+impl Config {
     pub fn get_test_config() -> Self {
-        Self { substream_timeout: Duration::MAX, protocol_name: StreamProtocol::new("/") }
+        Self {
+            substream_timeout: Duration::MAX,
+            protocol_names: vec![StreamProtocol::new("/")],
+            protocol_extensions: HashMap::from([(
+                StreamProtocol::new("/"),
+                HashSet::from([
+                    ProtocolExtension::KeepAlive,
+                    ProtocolExtension::CloseTrailer,
+                    ProtocolExtension::CorrelationId,
+                ]),
+            )]),
+            keep_alive_interval: None,
+            max_outbound_retries: 0,
+            retry_backoff: Duration::ZERO,
+            peer_score_threshold: PeerScore::MIN,
+            peer_ban_cooldown: Duration::ZERO,
+            max_queued_data_bytes: None,
+            max_queued_data_bytes_per_session: None,
+            max_unanswered_inbound_sessions: None,
+            read_buffer_size: NonZeroUsize::MIN,
+            max_pending_outbound_sessions: None,
+            idle_connection_timeout: DEFAULT_IDLE_CONNECTION_TIMEOUT,
+            allowed_peers: None,
+            denied_peers: HashSet::new(),
+            pending_events_warning_threshold: None,
+            advertised_addresses: vec![],
+        }
     }
 }
 
@@ -103,17 +139,39 @@ impl<K: Unpin + Clone + Eq + Hash, V: StreamTrait + Unpin> StreamTrait for Strea
     }
 }
 
+/// Create a swarm like `Swarm::new_ephemeral` does, but with the swarm settings of the given
+/// config. The behaviour is created from the peer id of the swarm.
+pub(crate) fn create_swarm_with_config<TBehaviour: NetworkBehaviour + Send>(
+    behaviour_gen: impl FnOnce(PeerId) -> TBehaviour,
+    config: &Config,
+) -> Swarm<TBehaviour> {
+    let identity = Keypair::generate_ed25519();
+    let peer_id = PeerId::from(identity.public());
+    let transport = MemoryTransport::default()
+        .or_transport(tcp::tokio::Transport::default())
+        .upgrade(Version::V1)
+        .authenticate(plaintext::Config::new(&identity))
+        .multiplex(yamux::Config::default())
+        .timeout(Duration::from_secs(20))
+        .boxed();
+    let swarm_config = config.apply_to_swarm_config(swarm::Config::with_tokio_executor());
+    let mut swarm = Swarm::new(transport, behaviour_gen(peer_id), peer_id, swarm_config);
+    config.apply_to_swarm(&mut swarm);
+    swarm
+}
+
 /// Create num_swarms swarms and connect each pair of swarms. Return them as a combined stream of
 /// events.
 pub(crate) async fn create_fully_connected_swarms_stream<TBehaviour: NetworkBehaviour + Send>(
     num_swarms: usize,
-    behaviour_gen: impl Fn() -> TBehaviour,
+    behaviour_gen: impl Fn(PeerId) -> TBehaviour,
 ) -> StreamHashMap<PeerId, Swarm<TBehaviour>>
 where
     <TBehaviour as NetworkBehaviour>::ToSwarm: Debug,
 {
-    let mut swarms =
-        (0..num_swarms).map(|_| Swarm::new_ephemeral(|_| behaviour_gen())).collect::<Vec<_>>();
+    let mut swarms = (0..num_swarms)
+        .map(|_| Swarm::new_ephemeral(|keypair| behaviour_gen(keypair.public().to_peer_id())))
+        .collect::<Vec<_>>();
 
     for swarm in &mut swarms {
         swarm.listen().with_memory_addr_external().await;
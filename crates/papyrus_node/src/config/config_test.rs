@@ -19,7 +19,7 @@ use tempfile::NamedTempFile;
 use test_utils::get_absolute_path;
 use validator::Validate;
 
-use crate::config::{node_command, NodeConfig, DEFAULT_CONFIG_PATH};
+use crate::config::{node_command, NodeConfig, NodeSubcommand, DEFAULT_CONFIG_PATH};
 
 // Returns the required and generated params in default_config.json with the default value from the
 // config presentation.
@@ -93,6 +93,32 @@ fn test_default_config_process() {
     assert_eq!(NodeConfig::load_and_process(get_args(vec![])).unwrap(), NodeConfig::default());
 }
 
+#[test]
+fn test_dump_config_subcommand() {
+    let out_file = NamedTempFile::new().unwrap();
+    let out_path = out_file.path().to_str().unwrap();
+    let args = get_args(vec![
+        "--rpc.max_events_keys",
+        "1234",
+        "dump-config",
+        "--public-only",
+        "--out",
+        out_path,
+    ]);
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    let (config, subcommand) = NodeConfig::load_and_process_with_subcommand(args).unwrap();
+    assert_eq!(
+        subcommand,
+        Some(NodeSubcommand::DumpConfig { public_only: true, out: Some(out_file.path().into()) })
+    );
+
+    config.dump_presentation(true, Some(out_file.path())).unwrap();
+    let dumped_config: Value =
+        serde_json::from_reader(File::open(out_file.path()).unwrap()).unwrap();
+    assert_eq!(dumped_config, get_config_presentation(&config, false).unwrap());
+    assert_eq!(dumped_config["rpc"]["max_events_keys"], json!(1234));
+}
+
 #[test]
 fn test_update_dumped_config_by_command() {
     let args =
@@ -100,6 +126,32 @@ fn test_update_dumped_config_by_command() {
     env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
     let config = NodeConfig::load_and_process(args).unwrap();
 
-    assert_eq!(config.rpc.max_events_keys, 1234);
+    assert_eq!(config.rpc.unwrap().max_events_keys, 1234);
     assert_eq!(config.storage.db_config.path_prefix.to_str(), Some("/abc"));
 }
+
+#[test]
+fn load_sync_only_config() {
+    let args = get_args(vec!["--rpc.#is_none", "true", "--monitoring_gateway.#is_none", "true"]);
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    let config = NodeConfig::load_and_process(args).unwrap();
+
+    assert_eq!(config.rpc, None);
+    assert_eq!(config.monitoring_gateway, None);
+    assert!(config.sync.is_some());
+}
+
+#[test]
+fn num_sync_workers_must_not_exceed_the_concurrent_requests() {
+    let mut config = NodeConfig::default();
+    config.central.concurrent_requests = 4;
+    config.sync.as_mut().unwrap().num_sync_workers = 4;
+    assert!(config.validate().is_ok());
+
+    config.sync.as_mut().unwrap().num_sync_workers = 5;
+    assert!(config.validate().is_err());
+
+    // Without the sync there are no workers.
+    config.sync = None;
+    assert!(config.validate().is_ok());
+}
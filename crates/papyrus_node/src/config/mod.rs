@@ -10,11 +10,12 @@ use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::{env, fs, io};
 
-use clap::{arg, value_parser, Arg, ArgMatches, Command};
+use clap::{arg, value_parser, Arg, ArgAction, ArgMatches, Command};
 use itertools::{chain, Itertools};
 use papyrus_base_layer::ethereum_base_layer_contract::EthereumBaseLayerConfig;
 use papyrus_config::dumping::{append_sub_config_name, ser_optional_sub_config, SerializeConfig};
-use papyrus_config::loading::load_and_process_config;
+use papyrus_config::loading::load_and_process_config_with_subcommand;
+use papyrus_config::presentation::get_config_presentation;
 use papyrus_config::{ConfigError, ParamPath, SerializedParam};
 use papyrus_monitoring_gateway::MonitoringGatewayConfig;
 use papyrus_rpc::RpcConfig;
@@ -26,25 +27,36 @@ use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use starknet_api::core::ChainId;
 use starknet_client::RetryConfig;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
+use crate::tracing_config::TracingConfig;
 use crate::version::VERSION_FULL;
 
 // The path of the default configuration file, provided as part of the crate.
 pub const DEFAULT_CONFIG_PATH: &str = "config/default_config.json";
 
+const DUMP_CONFIG_SUBCOMMAND: &str = "dump-config";
+const VALIDATE_CONFIG_SUBCOMMAND: &str = "validate-config";
+
 /// The configurations of the various components of the node.
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Validate)]
+#[validate(schema(function = "validate_node_config"))]
 pub struct NodeConfig {
+    /// None if the JSON-RPC server should be disabled.
+    #[validate]
+    pub rpc: Option<RpcConfig>,
     #[validate]
-    pub rpc: RpcConfig,
     pub central: CentralSourceConfig,
     pub base_layer: EthereumBaseLayerConfig,
-    pub monitoring_gateway: MonitoringGatewayConfig,
+    /// None if the monitoring gateway should be disabled.
+    pub monitoring_gateway: Option<MonitoringGatewayConfig>,
     #[validate]
     pub storage: StorageConfig,
     /// None if the syncing should be disabled.
+    #[validate]
     pub sync: Option<SyncConfig>,
+    #[validate]
+    pub tracing: TracingConfig,
 }
 
 // Default configuration values.
@@ -53,23 +65,39 @@ impl Default for NodeConfig {
         NodeConfig {
             central: CentralSourceConfig::default(),
             base_layer: EthereumBaseLayerConfig::default(),
-            rpc: RpcConfig::default(),
-            monitoring_gateway: MonitoringGatewayConfig::default(),
+            rpc: Some(RpcConfig::default()),
+            monitoring_gateway: Some(MonitoringGatewayConfig::default()),
             storage: StorageConfig::default(),
             sync: Some(SyncConfig::default()),
+            tracing: TracingConfig::default(),
         }
     }
 }
 
+// Validates the constraints between the configs of different components.
+fn validate_node_config(config: &NodeConfig) -> Result<(), ValidationError> {
+    // The concurrent requests of the central source are split between the sync workers, and each
+    // worker needs at least one of them.
+    if let Some(sync_config) = &config.sync {
+        if sync_config.num_sync_workers > config.central.concurrent_requests {
+            return Err(ValidationError::new(
+                "sync.num_sync_workers should be at most central.concurrent_requests",
+            ));
+        }
+    }
+    Ok(())
+}
+
 impl SerializeConfig for NodeConfig {
     fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
         chain!(
             append_sub_config_name(self.central.dump(), "central"),
             append_sub_config_name(self.base_layer.dump(), "base_layer"),
-            append_sub_config_name(self.rpc.dump(), "rpc"),
-            append_sub_config_name(self.monitoring_gateway.dump(), "monitoring_gateway"),
+            ser_optional_sub_config(&self.rpc, "rpc"),
+            ser_optional_sub_config(&self.monitoring_gateway, "monitoring_gateway"),
             append_sub_config_name(self.storage.dump(), "storage"),
             ser_optional_sub_config(&self.sync, "sync"),
+            append_sub_config_name(self.tracing.dump(), "tracing"),
         )
         .collect()
     }
@@ -79,14 +107,77 @@ impl NodeConfig {
     /// Creates a config object. Selects the values from the default file and from resources with
     /// higher priority.
     pub fn load_and_process(args: Vec<String>) -> Result<Self, ConfigError> {
+        Ok(Self::load_and_process_with_subcommand(args)?.0)
+    }
+
+    /// Same as [`NodeConfig::load_and_process`], and also returns the subcommand that was given,
+    /// if any.
+    pub fn load_and_process_with_subcommand(
+        args: Vec<String>,
+    ) -> Result<(Self, Option<NodeSubcommand>), ConfigError> {
         let default_config_file = std::fs::File::open(Path::new(DEFAULT_CONFIG_PATH))?;
-        load_and_process_config(default_config_file, node_command(), args)
+        let (config, subcommand) =
+            load_and_process_config_with_subcommand(default_config_file, node_command(), args)?;
+        let subcommand = subcommand.map(|(name, matches)| match name.as_str() {
+            DUMP_CONFIG_SUBCOMMAND => NodeSubcommand::DumpConfig {
+                public_only: matches.get_flag("public-only"),
+                out: matches.get_one::<PathBuf>("out").cloned(),
+            },
+            VALIDATE_CONFIG_SUBCOMMAND => NodeSubcommand::ValidateConfig,
+            _ => unreachable!("The node command has no {name} subcommand."),
+        });
+        Ok((config, subcommand))
+    }
+
+    /// Writes the config as JSON to the given file, or to stdout if no file is given. If
+    /// public_only is set, the private parameters are omitted.
+    pub fn dump_presentation(
+        &self,
+        public_only: bool,
+        out: Option<&Path>,
+    ) -> Result<(), ConfigError> {
+        let config_presentation = get_config_presentation(self, !public_only)?;
+        let config_presentation = serde_json::to_string_pretty(&config_presentation)?;
+        match out {
+            Some(path) => fs::write(path, config_presentation)?,
+            None => println!("{config_presentation}"),
+        }
+        Ok(())
     }
 }
 
+/// A subcommand that runs instead of the node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeSubcommand {
+    /// Prints the loaded config, after applying the config files and the overriding arguments.
+    DumpConfig { public_only: bool, out: Option<PathBuf> },
+    /// Validates the loaded config, and exits with a nonzero code if it's invalid.
+    ValidateConfig,
+}
+
 /// The command line interface of this node.
 pub fn node_command() -> Command {
     Command::new("Papyrus")
         .version(VERSION_FULL)
         .about("Papyrus is a StarkNet full node written in Rust.")
+        .subcommand(
+            Command::new(DUMP_CONFIG_SUBCOMMAND)
+                .about("Prints the loaded config and exits without running the node.")
+                .arg(
+                    Arg::new("public-only")
+                        .long("public-only")
+                        .action(ArgAction::SetTrue)
+                        .help("Omit the private parameters from the config."),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Write the config to the given file instead of stdout."),
+                ),
+        )
+        .subcommand(
+            Command::new(VALIDATE_CONFIG_SUBCOMMAND)
+                .about("Validates the loaded config and exits without running the node."),
+        )
 }
@@ -6,4 +6,5 @@
 pub mod config;
 #[cfg(test)]
 mod precision_test;
+pub mod tracing_config;
 pub mod version;
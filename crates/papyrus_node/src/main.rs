@@ -2,110 +2,267 @@
 mod main_test;
 
 use std::env::args;
+use std::future::{pending, Future};
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use papyrus_common::pending_classes::PendingClasses;
+use papyrus_common::sync_progress::{SyncProgressEvent, SyncStatus};
 use papyrus_common::BlockHashAndNumber;
-use papyrus_config::presentation::get_config_presentation;
+use papyrus_config::presentation::get_detailed_config_presentation;
 use papyrus_config::validators::config_validate;
 use papyrus_config::ConfigError;
 use papyrus_monitoring_gateway::MonitoringServer;
-use papyrus_node::config::NodeConfig;
+use papyrus_node::config::{NodeConfig, NodeSubcommand};
+use papyrus_node::tracing_config::{SpanSampler, TracingConfig};
 use papyrus_node::version::VERSION_FULL;
 use papyrus_rpc::run_server;
-use papyrus_storage::{open_storage, StorageReader, StorageWriter};
+use papyrus_storage::db::DbError;
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::{open_storage, StorageError, StorageReader, StorageWriter};
 use papyrus_sync::sources::base_layer::{BaseLayerSourceError, EthereumBaseLayerSource};
 use papyrus_sync::sources::central::{CentralError, CentralSource};
 use papyrus_sync::sources::pending::PendingSource;
-use papyrus_sync::{StateSync, StateSyncError};
-use starknet_api::block::BlockHash;
-use starknet_api::hash::{StarkFelt, GENESIS_HASH};
-use starknet_api::stark_felt;
+use papyrus_sync::{StateSync, StateSyncError, SyncConfig};
 use starknet_client::reader::objects::pending_data::PendingBlock;
 use starknet_client::reader::PendingData;
-use tokio::sync::RwLock;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{broadcast, oneshot, watch, RwLock};
 use tracing::metadata::LevelFilter;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 
 // TODO(yair): Add to config.
 const DEFAULT_LEVEL: LevelFilter = LevelFilter::INFO;
 
-async fn run_threads(config: NodeConfig) -> anyhow::Result<()> {
-    let (storage_reader, storage_writer) = open_storage(config.storage.clone())?;
-
-    // Monitoring server.
-    let monitoring_server = MonitoringServer::new(
-        config.monitoring_gateway.clone(),
-        get_config_presentation(&config, true)?,
-        get_config_presentation(&config, false)?,
-        storage_reader.clone(),
-        VERSION_FULL,
-    )?;
-    let monitoring_server_handle = monitoring_server.spawn_server().await;
+// The amount of sync progress events that can wait for the monitoring gateway.
+const SYNC_PROGRESS_CHANNEL_CAPACITY: usize = 1000;
 
-    // The sync is the only writer of the syncing state.
-    let shared_highest_block = Arc::new(RwLock::new(None));
-    let pending_data = Arc::new(RwLock::new(PendingData {
+// The pending block is built on top of the latest stored block, or on top of the configured parent
+// hash of the genesis block if the storage has no blocks. Until the first pending data is
+// downloaded, the chain-specific fields of the pending block are those of the latest stored block
+// rather than defaults, which may not fit the chain.
+fn initial_pending_data(
+    config: &NodeConfig,
+    storage_reader: &StorageReader,
+) -> Result<PendingData, StorageError> {
+    let txn = storage_reader.begin_ro_txn()?;
+    let Some(latest_block_number) = txn.get_header_marker()?.prev() else {
+        let genesis_parent_hash = config.sync.unwrap_or_default().genesis_parent_hash;
+        return Ok(PendingData {
+            block: PendingBlock { parent_block_hash: genesis_parent_hash, ..Default::default() },
+            ..Default::default()
+        });
+    };
+    let latest_header = txn
+        .get_block_header(latest_block_number)?
+        .expect("Block before the header marker must have header in the database.");
+    let starknet_version = txn.get_starknet_version(latest_block_number)?.unwrap_or_default();
+    Ok(PendingData {
         block: PendingBlock {
-            parent_block_hash: BlockHash(stark_felt!(GENESIS_HASH)),
+            parent_block_hash: latest_header.block_hash,
+            eth_l1_gas_price: latest_header.eth_l1_gas_price,
+            strk_l1_gas_price: latest_header.strk_l1_gas_price,
+            timestamp: latest_header.timestamp,
+            sequencer_address: latest_header.sequencer,
+            starknet_version: starknet_version.0,
             ..Default::default()
         },
         ..Default::default()
-    }));
+    })
+}
+
+// Compacts the storage through its writer, once nothing else reads from it. The compaction is
+// skipped if there isn't enough free disk space for it, or if a reader of the storage is still
+// alive, e.g. of an RPC request that outlived the shutdown grace period, since it's only an
+// optimization.
+fn compact_storage(storage_writer: StorageWriter) -> anyhow::Result<()> {
+    match storage_writer.compact() {
+        Ok((_, _, report)) => {
+            info!(
+                "Compacted the storage on shutdown, reclaiming {} bytes.",
+                report.bytes_reclaimed()
+            );
+            Ok(())
+        }
+        Err(StorageError::InnerError(
+            err @ (DbError::NotEnoughDiskSpace { .. } | DbError::EnvInUse),
+        )) => {
+            warn!("Skipped compacting the storage on shutdown: {err}");
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Runs the node until one of its tasks stops, or until the shutdown signal resolves, in which case
+// the node shuts down gracefully.
+async fn run_threads(
+    config: NodeConfig,
+    shutdown_signal: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let compact_on_shutdown = config.storage.compact_on_shutdown;
+    let (storage_reader, storage_writer) = open_storage(config.storage.clone())?;
+    let (sync_progress_sender, sync_progress_receiver) =
+        broadcast::channel(SYNC_PROGRESS_CHANNEL_CAPACITY);
+
+    // Monitoring server. If it isn't required, the node runs without it when it fails.
+    let monitoring_server = match &config.monitoring_gateway {
+        Some(monitoring_gateway_config) => match MonitoringServer::new(
+            monitoring_gateway_config.clone(),
+            get_detailed_config_presentation(&config, true)?,
+            get_detailed_config_presentation(&config, false)?,
+            storage_reader.clone(),
+            VERSION_FULL,
+            sync_progress_receiver,
+        ) {
+            Ok(monitoring_server) => Some(monitoring_server),
+            Err(err) if !monitoring_gateway_config.monitoring_required => {
+                error!("Failed creating the monitoring server, running without it: {err}");
+                None
+            }
+            Err(err) => return Err(err.into()),
+        },
+        None => None,
+    };
+    let initial_sync_complete = monitoring_server
+        .as_ref()
+        .map_or_else(Arc::default, |monitoring_server| monitoring_server.initial_sync_complete());
+    // A disabled component never stops, so it doesn't stop the node.
+    let mut monitoring_server_handle = match monitoring_server {
+        Some(monitoring_server) => monitoring_server.spawn_server().await,
+        None => tokio::spawn(pending()),
+    };
+
+    // The sync is the only writer of the syncing state.
+    let shared_highest_block = Arc::new(RwLock::new(None));
+    let pending_data = Arc::new(RwLock::new(initial_pending_data(&config, &storage_reader)?));
     let pending_classes = Arc::new(RwLock::new(PendingClasses::default()));
 
     // JSON-RPC server.
-    let (_, server_handle) = run_server(
-        &config.rpc,
-        shared_highest_block.clone(),
-        pending_data.clone(),
-        pending_classes.clone(),
-        storage_reader.clone(),
-        VERSION_FULL,
-    )
-    .await?;
-    let server_handle_future = tokio::spawn(server_handle.stopped());
-
-    // Sync task.
-    let sync_future = run_sync(
-        config,
-        shared_highest_block,
-        pending_data,
-        pending_classes,
-        storage_reader.clone(),
-        storage_writer,
-    );
-    let sync_handle = tokio::spawn(sync_future);
+    let mut rpc_server_handle = None;
+    let mut server_handle_future = match &config.rpc {
+        Some(rpc_config) => {
+            let (_, server_handle) = run_server(
+                rpc_config,
+                shared_highest_block.clone(),
+                pending_data.clone(),
+                pending_classes.clone(),
+                sync_progress_sender.clone(),
+                storage_reader.clone(),
+                VERSION_FULL,
+            )
+            .await?;
+            rpc_server_handle = Some(server_handle.clone());
+            tokio::spawn(server_handle.stopped())
+        }
+        None => tokio::spawn(pending()),
+    };
+
+    // The node becomes ready once the sync catches up with the latest block for the first time,
+    // or right away if it doesn't sync.
+    let (sync_status_sender, sync_status_receiver) = watch::channel(SyncStatus::default());
+    if config.sync.is_some() {
+        tokio::spawn(flag_initial_sync_complete(sync_status_receiver, initial_sync_complete));
+    } else {
+        initial_sync_complete.store(true, Ordering::Release);
+    }
+
+    // Sync task. It holds the storage writer until it's stopped, and then hands it back.
+    let (sync_stop_sender, sync_stop_receiver) = oneshot::channel();
+    let (mut sync_handle, idle_storage_writer) = match config.sync {
+        Some(sync_config) => (
+            tokio::spawn(run_sync(
+                sync_config,
+                config,
+                shared_highest_block,
+                pending_data,
+                pending_classes,
+                storage_reader.clone(),
+                storage_writer,
+                sync_progress_sender,
+                sync_status_sender,
+                sync_stop_receiver,
+            )),
+            None,
+        ),
+        None => (tokio::spawn(pending()), Some(storage_writer)),
+    };
 
     tokio::select! {
-        res = server_handle_future => {
+        res = &mut server_handle_future => {
             error!("RPC server stopped.");
             res?
         }
-        res = monitoring_server_handle => {
+        res = &mut monitoring_server_handle => {
             error!("Monitoring server stopped.");
             res??
         }
-        res = sync_handle => {
+        res = &mut sync_handle => {
             error!("Sync stopped.");
-            res??
+            res??;
+        }
+        _ = shutdown_signal => {
+            info!("Shutting down.");
+            // The sync is stopped first, so that it doesn't write to the storage anymore. Stopping
+            // the other tasks drops the storage readers they hold.
+            let storage_writer = match idle_storage_writer {
+                Some(storage_writer) => {
+                    sync_handle.abort();
+                    storage_writer
+                }
+                None => {
+                    // Fails only if the sync already stopped, in which case it's awaited anyway.
+                    let _ = sync_stop_sender.send(());
+                    sync_handle.await??
+                }
+            };
+            if let Some(rpc_server_handle) = rpc_server_handle {
+                if !rpc_server_handle.drain().await {
+                    warn!("Stopped the RPC server before all its requests completed.");
+                }
+            }
+            server_handle_future.abort();
+            let _ = server_handle_future.await;
+            monitoring_server_handle.abort();
+            let _ = monitoring_server_handle.await;
+            drop(storage_reader);
+            if compact_on_shutdown {
+                compact_storage(storage_writer)?;
+            }
+            info!("Shut down.");
+            return Ok(());
         }
     };
     error!("Task ended with unexpected Ok.");
     return Ok(());
 
+    async fn flag_initial_sync_complete(
+        mut sync_status_receiver: watch::Receiver<SyncStatus>,
+        initial_sync_complete: Arc<AtomicBool>,
+    ) {
+        // Fails only if the sync stopped, which stops the node anyway.
+        if sync_status_receiver.wait_for(|status| status.initial_sync_complete).await.is_ok() {
+            initial_sync_complete.store(true, Ordering::Release);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn run_sync(
+        sync_config: SyncConfig,
         config: NodeConfig,
         shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
         pending_data: Arc<RwLock<PendingData>>,
         pending_classes: Arc<RwLock<PendingClasses>>,
         storage_reader: StorageReader,
         storage_writer: StorageWriter,
-    ) -> Result<(), StateSyncError> {
-        let Some(sync_config) = config.sync else { return Ok(()) };
+        sync_progress_sender: broadcast::Sender<SyncProgressEvent>,
+        sync_status_sender: watch::Sender<SyncStatus>,
+        stop_signal: oneshot::Receiver<()>,
+    ) -> Result<StorageWriter, StateSyncError> {
         let central_source =
             CentralSource::new(config.central.clone(), VERSION_FULL, storage_reader.clone())
                 .map_err(CentralError::ClientCreation)?;
@@ -123,16 +280,26 @@ async fn run_threads(config: NodeConfig) -> anyhow::Result<()> {
             base_layer_source,
             storage_reader.clone(),
             storage_writer,
+            sync_progress_sender,
+            sync_status_sender,
         );
-        sync.run().await
+        // The stop signal also resolves if its sender is dropped.
+        tokio::select! {
+            res = sync.run() => res?,
+            _ = stop_signal => {}
+        }
+        Ok(sync.into_storage_writer())
     }
 }
 
 // TODO(yair): add dynamic level filtering.
 // TODO(dan): filter out logs from dependencies (happens when RUST_LOG=DEBUG)
 // TODO(yair): define and implement configurable filtering.
-fn configure_tracing() {
-    let fmt_layer = fmt::layer().compact().with_target(false);
+fn configure_tracing(config: &TracingConfig) {
+    let fmt_layer = fmt::layer()
+        .compact()
+        .with_target(false)
+        .with_filter(SpanSampler::new(config.span_sampling_rate));
     let level_filter_layer =
         EnvFilter::builder().with_default_directive(DEFAULT_LEVEL.into()).from_env_lossy();
 
@@ -143,19 +310,67 @@ fn configure_tracing() {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = NodeConfig::load_and_process(args().collect());
+    let config = NodeConfig::load_and_process_with_subcommand(args().collect());
     if let Err(ConfigError::CommandInput(clap_err)) = config {
         clap_err.exit();
     }
+    let (config, subcommand) = config?;
+    // Handled before configuring the tracing so that logs don't get mixed with the output of the
+    // subcommands.
+    match subcommand {
+        Some(NodeSubcommand::DumpConfig { public_only, out }) => {
+            config.dump_presentation(public_only, out.as_deref())?;
+            return Ok(());
+        }
+        Some(NodeSubcommand::ValidateConfig) => {
+            if let Err(errors) = config_validate(&config) {
+                // Each validation error is printed on its own line.
+                eprint!("{errors}");
+                exit(1);
+            }
+            println!("The config is valid.");
+            return Ok(());
+        }
+        None => {}
+    }
 
-    configure_tracing();
+    configure_tracing(&config.tracing);
 
-    let config = config?;
     if let Err(errors) = config_validate(&config) {
         error!("{}", errors);
         exit(1);
     }
 
     info!("Booting up.");
-    run_threads(config).await
+    run_threads(config, shutdown_signal()).await
+}
+
+// Resolves once the node is asked to shut down, by Ctrl-C (SIGINT) or, on Unix, by SIGTERM. A
+// signal that can't be listened to never resolves, so if neither can, the node keeps running until
+// it's killed.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen to the Ctrl-C signal: {err}.");
+            pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => {
+                error!("Failed to listen to the SIGTERM signal: {err}.");
+                pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
@@ -1,7 +1,66 @@
+use std::env;
+use std::future::{pending, ready};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use papyrus_monitoring_gateway::{BindKind, MonitoringGatewayConfig};
 use papyrus_node::config::NodeConfig;
+use papyrus_storage::header::{HeaderStorageWriter, StarknetVersion};
+use papyrus_storage::open_storage;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockTimestamp, GasPrice};
+use starknet_api::core::{ChainId, ContractAddress, PatriciaKey};
+use starknet_api::hash::{StarkFelt, StarkHash};
+use starknet_api::{patricia_key, stark_felt};
 use tempfile::TempDir;
+use test_utils::get_absolute_path;
+use tracing::subscriber::DefaultGuard;
+
+use crate::{initial_pending_data, run_threads};
+
+const LOG_TIMEOUT: Duration = Duration::from_secs(30);
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Collects the logs that are written to it.
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl LogBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+// Captures the logs of the current thread until the returned guard is dropped. The tasks of the
+// tests run on the thread of the test.
+fn capture_logs() -> (LogBuffer, DefaultGuard) {
+    let logs = LogBuffer::default();
+    let log_writer = logs.clone();
+    let subscriber =
+        tracing_subscriber::fmt().with_writer(move || log_writer.clone()).with_ansi(false).finish();
+    (logs, tracing::subscriber::set_default(subscriber))
+}
 
-use crate::run_threads;
+// Waits until a log that contains the message is written.
+async fn wait_for_log(logs: &LogBuffer, message: &str) {
+    tokio::time::timeout(LOG_TIMEOUT, async {
+        while !logs.contents().contains(message) {
+            tokio::time::sleep(LOG_POLL_INTERVAL).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Timed out waiting for the log {message:?}."));
+}
 
 #[tokio::test]
 async fn run_threads_stop() {
@@ -10,5 +69,157 @@ async fn run_threads_stop() {
 
     // Error when not supplying legal central URL.
     config.central.url = "_not_legal_url".to_string();
-    assert!(run_threads(config.clone()).await.is_err());
+    assert!(run_threads(config.clone(), pending()).await.is_err());
+}
+
+#[tokio::test]
+async fn sync_only_node_does_not_bind_rpc_port() {
+    let temp_dir = TempDir::new().unwrap();
+    let rpc_address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    let config = NodeConfig::load_and_process(
+        [
+            "Papyrus",
+            "--base_layer.node_url",
+            "https://mainnet.infura.io/v3/1234",
+            "--storage.db_config.path_prefix",
+            temp_dir.path().to_str().unwrap(),
+            "--rpc.server_address",
+            &rpc_address.to_string(),
+            "--rpc.#is_none",
+            "true",
+            "--monitoring_gateway.#is_none",
+            "true",
+        ]
+        .map(String::from)
+        .to_vec(),
+    )
+    .unwrap();
+    assert_eq!(config.rpc, None);
+
+    let (logs, _subscriber_guard) = capture_logs();
+    let node_handle = tokio::spawn(run_threads(config, pending()));
+    // The sync starts after the RPC server would have been bound.
+    wait_for_log(&logs, "State sync started.").await;
+    assert!(!node_handle.is_finished());
+    // The configured address of the RPC server is still free.
+    TcpListener::bind(rpc_address).unwrap();
+    node_handle.abort();
+}
+
+#[tokio::test]
+async fn node_keeps_running_when_optional_monitoring_fails_to_bind() {
+    let temp_dir = TempDir::new().unwrap();
+    // Occupy the address of the monitoring server.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut config = NodeConfig::default();
+    config.storage.db_config.path_prefix = temp_dir.path().into();
+    config.rpc = None;
+    let monitoring_gateway = MonitoringGatewayConfig {
+        server_address: BindKind::Tcp(listener.local_addr().unwrap()),
+        monitoring_required: false,
+        ..Default::default()
+    };
+    config.monitoring_gateway = Some(monitoring_gateway);
+
+    let (logs, _subscriber_guard) = capture_logs();
+    let node_handle = tokio::spawn(run_threads(config, pending()));
+    wait_for_log(&logs, "Monitoring server failed, running without it").await;
+    assert!(!node_handle.is_finished());
+    node_handle.abort();
+}
+
+// The metrics recorder is global, so creating a monitoring server that collects metrics fails once
+// another one was created.
+#[tokio::test]
+async fn node_keeps_running_when_optional_monitoring_fails_to_be_created() {
+    let (logs, _subscriber_guard) = capture_logs();
+    let mut temp_dirs = Vec::new();
+    let mut node_handles = Vec::new();
+    for _ in 0..2 {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = NodeConfig::default();
+        config.storage.db_config.path_prefix = temp_dir.path().into();
+        config.rpc = None;
+        config.monitoring_gateway = Some(MonitoringGatewayConfig {
+            server_address: BindKind::Tcp("127.0.0.1:0".parse().unwrap()),
+            collect_metrics: true,
+            monitoring_required: false,
+            ..Default::default()
+        });
+        temp_dirs.push(temp_dir);
+        node_handles.push(tokio::spawn(run_threads(config, pending())));
+    }
+
+    wait_for_log(&logs, "Failed creating the monitoring server, running without it").await;
+    for node_handle in node_handles {
+        assert!(!node_handle.is_finished());
+        node_handle.abort();
+    }
+}
+
+#[test]
+fn initial_pending_data_of_custom_chain() {
+    let genesis_parent_hash = BlockHash(stark_felt!("0x1234"));
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = NodeConfig::default();
+    config.storage.db_config.path_prefix = temp_dir.path().into();
+    config.storage.db_config.chain_id = ChainId("SN_CUSTOM".to_owned());
+    config.sync.as_mut().unwrap().genesis_parent_hash = genesis_parent_hash;
+    let (storage_reader, _storage_writer) = open_storage(config.storage.clone()).unwrap();
+
+    let pending_data = initial_pending_data(&config, &storage_reader).unwrap();
+    assert_eq!(pending_data.block.parent_block_hash, genesis_parent_hash);
+}
+
+#[test]
+fn initial_pending_data_is_derived_from_the_latest_block() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = NodeConfig::default();
+    config.storage.db_config.path_prefix = temp_dir.path().into();
+    let (storage_reader, mut storage_writer) = open_storage(config.storage.clone()).unwrap();
+    let header = BlockHeader {
+        block_hash: BlockHash(stark_felt!("0x1")),
+        eth_l1_gas_price: GasPrice(10),
+        strk_l1_gas_price: GasPrice(20),
+        sequencer: ContractAddress(patricia_key!("0x2")),
+        timestamp: BlockTimestamp(30),
+        ..BlockHeader::default()
+    };
+    let starknet_version = StarknetVersion("0.13.0".to_owned());
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &header)
+        .unwrap()
+        .update_starknet_version(&BlockNumber(0), &starknet_version)
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let pending_block = initial_pending_data(&config, &storage_reader).unwrap().block;
+    assert_eq!(pending_block.parent_block_hash, header.block_hash);
+    assert_eq!(pending_block.eth_l1_gas_price, header.eth_l1_gas_price);
+    assert_eq!(pending_block.strk_l1_gas_price, header.strk_l1_gas_price);
+    assert_eq!(pending_block.sequencer_address, header.sequencer);
+    assert_eq!(pending_block.timestamp, header.timestamp);
+    assert_eq!(pending_block.starknet_version, starknet_version.0);
+    assert!(pending_block.transactions.is_empty());
+}
+
+#[tokio::test]
+async fn storage_is_compacted_once_on_shutdown() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = NodeConfig::default();
+    config.storage.db_config.path_prefix = temp_dir.path().into();
+    config.storage.compact_on_shutdown = true;
+    config.rpc = None;
+    config.monitoring_gateway = None;
+    config.sync = None;
+
+    let (logs, _subscriber_guard) = capture_logs();
+    // The node is asked to shut down right away.
+    run_threads(config, ready(())).await.unwrap();
+
+    assert_eq!(logs.contents().matches("Compacted the storage on shutdown").count(), 1);
 }
@@ -0,0 +1,82 @@
+//! Configuration of the tracing of the node.
+//!
+//! Creating every span is costly when the debug or trace levels are enabled, since the spans of
+//! these levels are created on hot paths. [`SpanSampler`] records only a fraction of these spans,
+//! which gives representative traces for a fraction of the cost. Spans of higher levels and all
+//! the events, in particular errors and warnings, are always recorded.
+
+#[cfg(test)]
+#[path = "tracing_config_test.rs"]
+mod tracing_config_test;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use papyrus_config::dumping::{ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use serde::{Deserialize, Serialize};
+use tracing::subscriber::Interest;
+use tracing::{Level, Metadata};
+use tracing_subscriber::layer::{Context, Filter};
+use validator::Validate;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Validate)]
+pub struct TracingConfig {
+    /// The fraction of the debug and trace level spans that are recorded, between 0 and 1.
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub span_sampling_rate: f64,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        TracingConfig { span_sampling_rate: 1.0 }
+    }
+}
+
+impl SerializeConfig for TracingConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        BTreeMap::from_iter([ser_param(
+            "span_sampling_rate",
+            &self.span_sampling_rate,
+            "The fraction of the debug and trace level spans that are recorded, between 0 and 1. \
+             Spans of higher levels and all the events are always recorded.",
+            ParamPrivacyInput::Public,
+        )])
+    }
+}
+
+/// A filter that records a fraction of the debug and trace level spans, and everything else.
+pub struct SpanSampler {
+    rate: f64,
+    // The number of spans that were sampled so far, whether they were recorded or not.
+    num_sampled_spans: AtomicU64,
+}
+
+impl SpanSampler {
+    pub fn new(rate: f64) -> Self {
+        Self { rate, num_sampled_spans: AtomicU64::new(0) }
+    }
+
+    // Records a span whenever the number of spans that should have been recorded so far passes an
+    // integer, so that exactly a `rate` fraction of the spans is recorded over time.
+    fn should_record(&self) -> bool {
+        let n = self.num_sampled_spans.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.rate).floor() > (n * self.rate).floor()
+    }
+}
+
+fn is_sampled(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span() && *metadata.level() > Level::INFO
+}
+
+impl<S> Filter<S> for SpanSampler {
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: &Context<'_, S>) -> bool {
+        !is_sampled(metadata) || self.should_record()
+    }
+
+    // The sampled spans are decided on every time they are created, so the decision can't be
+    // cached for their callsite.
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if is_sampled(metadata) { Interest::sometimes() } else { Interest::always() }
+    }
+}
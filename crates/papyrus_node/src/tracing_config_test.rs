@@ -0,0 +1,64 @@
+use std::sync::{Arc, Mutex};
+
+use pretty_assertions::assert_eq;
+use tracing::span::{Attributes, Id};
+use tracing::{debug_span, error, info_span, Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::tracing_config::SpanSampler;
+
+// Records the names of the created spans and the levels of the events.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<&'static str>>>,
+    event_levels: Arc<Mutex<Vec<Level>>>,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Recorder {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+        self.spans.lock().unwrap().push(attrs.metadata().name());
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        self.event_levels.lock().unwrap().push(*event.metadata().level());
+    }
+}
+
+fn record_with_sampling_rate(rate: f64, f: impl FnOnce()) -> Recorder {
+    let recorder = Recorder::default();
+    let subscriber = Registry::default().with(recorder.clone().with_filter(SpanSampler::new(rate)));
+    tracing::subscriber::with_default(subscriber, f);
+    recorder
+}
+
+#[test]
+fn no_sampled_spans_are_recorded_with_zero_rate() {
+    let recorder = record_with_sampling_rate(0.0, || {
+        let _info_span = info_span!("info_span").entered();
+        for _ in 0..10 {
+            let _debug_span = debug_span!("debug_span").entered();
+            error!("An error in a span that isn't recorded.");
+        }
+    });
+    assert_eq!(*recorder.spans.lock().unwrap(), vec!["info_span"]);
+    assert_eq!(*recorder.event_levels.lock().unwrap(), vec![Level::ERROR; 10]);
+}
+
+#[test]
+fn sampled_spans_are_recorded_at_the_rate() {
+    let recorder = record_with_sampling_rate(0.25, || {
+        for _ in 0..100 {
+            let _debug_span = debug_span!("debug_span").entered();
+        }
+    });
+    assert_eq!(recorder.spans.lock().unwrap().len(), 25);
+
+    let recorder = record_with_sampling_rate(1.0, || {
+        for _ in 0..100 {
+            let _debug_span = debug_span!("debug_span").entered();
+        }
+    });
+    assert_eq!(recorder.spans.lock().unwrap().len(), 100);
+}
@@ -0,0 +1,43 @@
+use std::process::{Command, Output};
+
+use tempfile::tempdir;
+use test_utils::get_absolute_path;
+
+// Runs the validate-config subcommand of the node binary with the given arguments.
+fn validate_config(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_papyrus_node"))
+        // The default config file path is relative to the root of the repository.
+        .current_dir(get_absolute_path(""))
+        .args(args)
+        .arg("validate-config")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn validate_config_accepts_valid_config() {
+    let dir = tempdir().unwrap();
+    let output =
+        validate_config(&["--storage.db_config.path_prefix", dir.path().to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn validate_config_rejects_invalid_config() {
+    let dir = tempdir().unwrap();
+    let output = validate_config(&[
+        "--storage.db_config.path_prefix",
+        dir.path().join("missing_dir").to_str().unwrap(),
+        "--central.concurrent_requests",
+        "0",
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let error_lines = stderr.lines().collect::<Vec<_>>();
+    assert_eq!(error_lines.len(), 2);
+    assert!(error_lines.iter().any(|line| line.contains("central.concurrent_requests")));
+    assert!(error_lines.iter().any(|line| line.contains("storage.db_config.path_prefix")));
+}
@@ -60,6 +60,7 @@ pub fn get_methods_from_supported_apis(
     storage_reader: StorageReader,
     max_events_chunk_size: usize,
     max_events_keys: usize,
+    starknet_version_override: Option<String>,
     starting_block: BlockHashAndNumber,
     shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     pending_data: Arc<RwLock<PendingData>>,
@@ -73,6 +74,7 @@ pub fn get_methods_from_supported_apis(
         storage_reader,
         max_events_chunk_size,
         max_events_keys,
+        starknet_version_override,
         starting_block,
         shared_highest_block,
         pending_data,
@@ -118,6 +120,7 @@ pub trait JsonRpcServerImpl: Sized {
         storage_reader: StorageReader,
         max_events_chunk_size: usize,
         max_events_keys: usize,
+        starknet_version_override: Option<String>,
         starting_block: BlockHashAndNumber,
         shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
         pending_data: Arc<RwLock<PendingData>>,
@@ -135,6 +138,7 @@ struct JsonRpcServerImplGenerator {
     storage_reader: StorageReader,
     max_events_chunk_size: usize,
     max_events_keys: usize,
+    starknet_version_override: Option<String>,
     starting_block: BlockHashAndNumber,
     shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     pending_data: Arc<RwLock<PendingData>>,
@@ -149,6 +153,7 @@ type JsonRpcServerImplParams = (
     StorageReader,
     usize,
     usize,
+    Option<String>,
     BlockHashAndNumber,
     Arc<RwLock<Option<BlockHashAndNumber>>>,
     Arc<RwLock<PendingData>>,
@@ -164,6 +169,7 @@ impl JsonRpcServerImplGenerator {
             self.storage_reader,
             self.max_events_chunk_size,
             self.max_events_keys,
+            self.starknet_version_override,
             self.starting_block,
             self.shared_highest_block,
             self.pending_data,
@@ -182,6 +188,7 @@ impl JsonRpcServerImplGenerator {
             storage_reader,
             max_events_chunk_size,
             max_events_keys,
+            starknet_version_override,
             starting_block,
             shared_highest_block,
             pending_data,
@@ -195,6 +202,7 @@ impl JsonRpcServerImplGenerator {
                 storage_reader,
                 max_events_chunk_size,
                 max_events_keys,
+                starknet_version_override,
                 starting_block,
                 shared_highest_block,
                 pending_data,
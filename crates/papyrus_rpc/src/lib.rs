@@ -6,9 +6,11 @@ mod api;
 mod compression_utils;
 mod middleware;
 mod pending;
+mod rate_limiter;
 mod rpc_metrics;
 #[cfg(test)]
 mod rpc_test;
+mod subscriptions;
 mod syncing_state;
 #[cfg(test)]
 mod test_utils;
@@ -20,17 +22,30 @@ mod version_config;
 use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
+use futures::future::join_all;
 use jsonrpsee::core::RpcResult;
-use jsonrpsee::server::{ServerBuilder, ServerHandle};
+use jsonrpsee::server::{BatchRequestConfig, ServerBuilder, ServerHandle};
 use jsonrpsee::types::error::ErrorCode::InternalError;
 use jsonrpsee::types::error::INTERNAL_ERROR_MSG;
 use jsonrpsee::types::ErrorObjectOwned;
 use papyrus_common::pending_classes::PendingClasses;
+use papyrus_common::sync_progress::SyncProgressEvent;
 use papyrus_common::BlockHashAndNumber;
-use papyrus_config::dumping::{append_sub_config_name, ser_param, SerializeConfig};
+use papyrus_config::converters::{
+    deserialize_seconds_to_duration,
+    deserialize_vec,
+    serialize_slice,
+};
+use papyrus_config::dumping::{
+    append_sub_config_name,
+    ser_optional_param,
+    ser_param,
+    SerializeConfig,
+};
 use papyrus_config::validators::{validate_ascii, validate_path_exists};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use papyrus_storage::base_layer::BaseLayerStorageReader;
@@ -45,12 +60,16 @@ use starknet_api::core::ChainId;
 use starknet_client::reader::PendingData;
 use starknet_client::writer::StarknetGatewayClient;
 use starknet_client::RetryConfig;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use tracing::{debug, error, info, instrument};
-use validator::Validate;
+use url::Url;
+use validator::{Validate, ValidationError};
 
 use crate::api::get_methods_from_supported_apis;
-use crate::middleware::{deny_requests_with_unsupported_path, proxy_rpc_request};
+use crate::middleware::{deny_requests_with_unsupported_path, get_cors_layer, proxy_rpc_request};
+use crate::rate_limiter::apply_rate_limits;
+pub use crate::rate_limiter::{RateLimitConfig, RATE_LIMIT_EXCEEDED_CODE};
+use crate::subscriptions::get_subscription_methods;
 use crate::syncing_state::get_last_synced_block;
 pub use crate::v0_4::transaction::{
     InvokeTransaction as InvokeTransactionRPC0_4,
@@ -59,21 +78,42 @@ pub use crate::v0_4::transaction::{
 };
 pub use crate::v0_4::write_api_result::AddInvokeOkResult as AddInvokeOkResultRPC0_4;
 
-/// Maximum size of a supported transaction body - 10MB.
-pub const SERVER_MAX_BODY_SIZE: u32 = 10 * 1024 * 1024;
-
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Validate)]
 pub struct RpcConfig {
     #[validate(custom = "validate_ascii")]
     pub chain_id: ChainId,
     pub server_address: String,
+    // More addresses the server listens on, each either IP:PORT or unix:PATH for a Unix socket.
+    #[serde(deserialize_with = "deserialize_vec")]
+    #[validate(custom = "validate_additional_server_addresses")]
+    pub additional_server_addresses: Vec<String>,
     pub max_events_chunk_size: usize,
     pub max_events_keys: usize,
+    // Reported as the Starknet version of every block instead of the version it was created in.
+    #[validate(custom = "validate_starknet_version")]
+    pub starknet_version_override: Option<String>,
     pub collect_metrics: bool,
     pub starknet_url: String,
+    #[validate]
     pub starknet_gateway_retry_config: RetryConfig,
     #[validate(custom = "validate_path_exists")]
     pub execution_config: PathBuf,
+    #[validate(range(min = 1))]
+    pub max_connection_backlog: u32,
+    #[validate(range(min = 1))]
+    pub max_request_body_size: u32,
+    #[validate(range(min = 1))]
+    pub max_response_body_size: u32,
+    #[validate(range(min = 1))]
+    pub max_batch_size: u32,
+    #[validate(range(min = 1))]
+    pub max_connections: u32,
+    pub rate_limit: RateLimitConfig,
+    #[serde(deserialize_with = "deserialize_vec")]
+    #[validate(custom = "validate_cors_allowed_origins")]
+    pub cors_allowed_origins: Vec<String>,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub shutdown_grace_period: Duration,
 }
 
 impl Default for RpcConfig {
@@ -81,8 +121,10 @@ impl Default for RpcConfig {
         RpcConfig {
             chain_id: ChainId("SN_MAIN".to_string()),
             server_address: String::from("0.0.0.0:8080"),
+            additional_server_addresses: Vec::new(),
             max_events_chunk_size: 1000,
             max_events_keys: 100,
+            starknet_version_override: None,
             collect_metrics: false,
             starknet_url: String::from("https://alpha-mainnet.starknet.io/"),
             starknet_gateway_retry_config: RetryConfig {
@@ -91,6 +133,16 @@ impl Default for RpcConfig {
                 max_retries: 5,
             },
             execution_config: PathBuf::from("config/execution/mainnet.json"),
+            max_connection_backlog: 1024,
+            // 10MB.
+            max_request_body_size: 10 * 1024 * 1024,
+            // 10MB.
+            max_response_body_size: 10 * 1024 * 1024,
+            max_batch_size: 100,
+            max_connections: 100,
+            rate_limit: RateLimitConfig::default(),
+            cors_allowed_origins: Vec::new(),
+            shutdown_grace_period: Duration::from_secs(10),
         }
     }
 }
@@ -110,6 +162,14 @@ impl SerializeConfig for RpcConfig {
                 "IP:PORT of the node`s JSON-RPC server.",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "additional_server_addresses",
+                &serialize_slice(&self.additional_server_addresses),
+                "'address1 address2 ...' more addresses for the JSON-RPC server to listen on, \
+                 each either IP:PORT or unix:PATH for a Unix socket. Unix sockets are supported \
+                 only on Unix platforms.",
+                ParamPrivacyInput::Public,
+            ),
             ser_param(
                 "max_events_chunk_size",
                 &self.max_events_chunk_size,
@@ -140,6 +200,52 @@ impl SerializeConfig for RpcConfig {
                 "Path to the execution configuration file.",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "max_connection_backlog",
+                &self.max_connection_backlog,
+                "Maximum number of messages waiting to be sent on a single WebSocket connection. \
+                 A subscription whose client falls behind this backlog is closed. Must be positive.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_request_body_size",
+                &self.max_request_body_size,
+                "Maximum size in bytes of a request body. Must be positive.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_response_body_size",
+                &self.max_response_body_size,
+                "Maximum size in bytes of a response body. Must be positive.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "cors_allowed_origins",
+                &serialize_slice(&self.cors_allowed_origins),
+                "'origin1 origin2 ...' the origins allowed to make cross-origin requests, or '*' \
+                 for any origin. If empty, no CORS headers are sent.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_batch_size",
+                &self.max_batch_size,
+                "Maximum number of requests in a batch request. Must be positive.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_connections",
+                &self.max_connections,
+                "Maximum number of concurrently open connections. New connections are refused \
+                 while the server is at the limit. Must be positive.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "shutdown_grace_period",
+                &self.shutdown_grace_period.as_secs(),
+                "Time in seconds to wait for in-flight requests to complete when the server is \
+                 drained.",
+                ParamPrivacyInput::Public,
+            ),
         ]);
         let mut retry_config_dump = append_sub_config_name(
             self.starknet_gateway_retry_config.dump(),
@@ -153,10 +259,74 @@ impl SerializeConfig for RpcConfig {
             );
         }
         self_params_dump.append(&mut retry_config_dump);
+        self_params_dump.extend(ser_optional_param(
+            &self.starknet_version_override,
+            String::from("0.13.1"),
+            "starknet_version_override",
+            "The Starknet version to report for every block, including the pending one, instead \
+             of the version the block was created in. If not set, the original versions are \
+             reported.",
+            ParamPrivacyInput::Public,
+        ));
+        self_params_dump.append(&mut append_sub_config_name(self.rate_limit.dump(), "rate_limit"));
         self_params_dump
     }
 }
 
+/// Allows cross-origin requests from any origin.
+pub const ANY_ORIGIN: &str = "*";
+
+fn validate_cors_allowed_origins(origins: &[String]) -> Result<(), ValidationError> {
+    if origins.len() > 1 && origins.iter().any(|origin| origin == ANY_ORIGIN) {
+        return Err(ValidationError::new("'*' can't be combined with other origins"));
+    }
+    for origin in origins.iter().filter(|origin| *origin != ANY_ORIGIN) {
+        let is_valid_origin =
+            Url::parse(origin).is_ok_and(|url| url.origin().ascii_serialization() == *origin);
+        if !is_valid_origin {
+            let mut error = ValidationError::new("malformed origin");
+            error.message =
+                Some(format!("{origin} is not of the form scheme://host[:port].").into());
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+// The prefix of additional server addresses that are paths of Unix sockets.
+const UNIX_SOCKET_PREFIX: &str = "unix:";
+
+fn validate_additional_server_addresses(addresses: &[String]) -> Result<(), ValidationError> {
+    for address in addresses {
+        let Some(path) = address.strip_prefix(UNIX_SOCKET_PREFIX) else {
+            continue;
+        };
+        let (code, message) = if !cfg!(unix) {
+            ("unsupported Unix socket", "Unix sockets are supported only on Unix platforms")
+        } else if path.is_empty() {
+            ("empty Unix socket path", "the path of the socket is missing")
+        } else {
+            continue;
+        };
+        let mut error = ValidationError::new(code);
+        error.message = Some(format!("Can't listen on {address}: {message}.").into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn validate_starknet_version(version: &str) -> Result<(), ValidationError> {
+    let is_valid_version =
+        version.split('.').all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+    if !is_valid_version {
+        let mut error = ValidationError::new("malformed Starknet version");
+        error.message =
+            Some(format!("{version} is not a dot-separated list of numbers, e.g. 0.13.1.").into());
+        return Err(error);
+    }
+    Ok(())
+}
+
 fn internal_server_error(err: impl Display) -> ErrorObjectOwned {
     error!("{}: {}", INTERNAL_ERROR_MSG, err);
     ErrorObjectOwned::owned(InternalError.code(), INTERNAL_ERROR_MSG, None::<()>)
@@ -200,23 +370,59 @@ fn get_block_status<Mode: TransactionKind>(
 #[derive(Clone, Debug, PartialEq)]
 struct ContinuationTokenAsStruct(EventIndex);
 
+/// A handle to a running JSON-RPC server, which covers all the addresses it listens on.
+#[derive(Clone, Debug)]
+pub struct RpcServerHandle {
+    // A handle for each TCP address, in the order of the local addresses.
+    handles: Vec<ServerHandle>,
+    local_addrs: Vec<SocketAddr>,
+    shutdown_grace_period: Duration,
+}
+
+impl RpcServerHandle {
+    /// Returns the TCP addresses the server listens on, starting with the server address and
+    /// followed by the additional server addresses that aren't Unix sockets.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Waits for the server to stop on all of its addresses.
+    pub async fn stopped(self) {
+        join_all(self.handles.into_iter().map(|handle| handle.stopped())).await;
+    }
+
+    /// Stops accepting new connections, and waits up to the shutdown grace period for the requests
+    /// in flight to complete. Returns whether they all completed within the grace period.
+    pub async fn drain(self) -> bool {
+        for handle in &self.handles {
+            // Fails only if the server was already stopped, in which case there's nothing to
+            // drain.
+            let _ = handle.stop();
+        }
+        let shutdown_grace_period = self.shutdown_grace_period;
+        tokio::time::timeout(shutdown_grace_period, self.stopped()).await.is_ok()
+    }
+}
+
 #[instrument(skip(storage_reader), level = "debug", err)]
 pub async fn run_server(
     config: &RpcConfig,
     shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     pending_data: Arc<RwLock<PendingData>>,
     pending_classes: Arc<RwLock<PendingClasses>>,
+    sync_progress_sender: broadcast::Sender<SyncProgressEvent>,
     storage_reader: StorageReader,
     node_version: &'static str,
-) -> anyhow::Result<(SocketAddr, ServerHandle)> {
+) -> anyhow::Result<(SocketAddr, RpcServerHandle)> {
     let starting_block = get_last_synced_block(storage_reader.clone())?;
     debug!("Starting JSON-RPC.");
-    let methods = get_methods_from_supported_apis(
+    let mut methods = get_methods_from_supported_apis(
         &config.chain_id,
         config.execution_config.clone().try_into()?,
-        storage_reader,
+        storage_reader.clone(),
         config.max_events_chunk_size,
         config.max_events_keys,
+        config.starknet_version_override.clone(),
         starting_block,
         shared_highest_block,
         pending_data,
@@ -227,27 +433,123 @@ pub async fn run_server(
             config.starknet_gateway_retry_config,
         )?),
     );
-    let addr;
-    let handle;
-    let server_builder =
-        ServerBuilder::default().max_request_body_size(SERVER_MAX_BODY_SIZE).set_middleware(
-            tower::ServiceBuilder::new()
-                .filter_async(deny_requests_with_unsupported_path)
-                .filter_async(proxy_rpc_request),
-        );
+    methods.merge(get_subscription_methods(storage_reader, sync_progress_sender)?)?;
+    let methods = apply_rate_limits(methods, &config.rate_limit)?;
+    let max_request_body_size = config.max_request_body_size;
+    // A server is started for each TCP address, and the limits apply to each of them separately.
+    let server_builder = || {
+        ServerBuilder::default()
+            .max_request_body_size(max_request_body_size)
+            .max_response_body_size(config.max_response_body_size)
+            .set_batch_request_config(BatchRequestConfig::Limit(config.max_batch_size))
+            .max_connections(config.max_connections)
+            .set_message_buffer_capacity(config.max_connection_backlog)
+            .set_middleware(
+                tower::ServiceBuilder::new()
+                    // Answers preflight requests, so it comes before the filters.
+                    .option_layer(get_cors_layer(&config.cors_allowed_origins))
+                    .filter_async(deny_requests_with_unsupported_path)
+                    .filter_async(move |req| proxy_rpc_request(req, max_request_body_size)),
+            )
+    };
 
-    if config.collect_metrics {
-        let server = server_builder
-            .set_logger(MetricLogger::new(&methods))
-            .build(&config.server_address)
-            .await?;
-        addr = server.local_addr()?;
-        handle = server.start(methods);
-    } else {
-        let server = server_builder.build(&config.server_address).await?;
-        addr = server.local_addr()?;
-        handle = server.start(methods);
+    let (unix_socket_paths, additional_tcp_addresses): (Vec<_>, Vec<_>) = config
+        .additional_server_addresses
+        .iter()
+        .partition(|address| address.starts_with(UNIX_SOCKET_PREFIX));
+    let mut handles = Vec::new();
+    let mut local_addrs = Vec::new();
+    for address in std::iter::once(&config.server_address).chain(additional_tcp_addresses) {
+        let (addr, handle) = if config.collect_metrics {
+            let server =
+                server_builder().set_logger(MetricLogger::new(&methods)).build(address).await?;
+            (server.local_addr()?, server.start(methods.clone()))
+        } else {
+            let server = server_builder().build(address).await?;
+            (server.local_addr()?, server.start(methods.clone()))
+        };
+        info!(local_address = %addr, "JSON-RPC is running.");
+        handles.push(handle);
+        local_addrs.push(addr);
+    }
+    // Unix sockets are served by the server of the server address.
+    for address in unix_socket_paths {
+        let path = Path::new(&address[UNIX_SOCKET_PREFIX.len()..]);
+        serve_over_unix_socket(path, local_addrs[0], handles[0].clone())?;
+        info!(unix_socket = %path.display(), "JSON-RPC is running.");
+    }
+
+    Ok((
+        local_addrs[0],
+        RpcServerHandle {
+            handles,
+            local_addrs,
+            shutdown_grace_period: config.shutdown_grace_period,
+        },
+    ))
+}
+
+// Serves the server listening on the given TCP address over a Unix socket at the given path too,
+// by forwarding each connection of the socket to the TCP address, until the server is stopped.
+#[cfg(unix)]
+fn serve_over_unix_socket(
+    path: &Path,
+    server_addr: SocketAddr,
+    server_handle: ServerHandle,
+) -> anyhow::Result<()> {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::os::unix::fs::FileTypeExt;
+
+    use tokio::io::copy_bidirectional;
+    use tokio::net::{TcpStream, UnixListener};
+    use tracing::warn;
+
+    // A socket that was left by a previous run would fail the binding.
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
     }
-    info!(local_address = %addr, "JSON-RPC is running.");
-    Ok((addr, handle))
+    let listener = UnixListener::bind(path)?;
+    let target_addr: SocketAddr = match server_addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => (Ipv4Addr::LOCALHOST, server_addr.port()).into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, server_addr.port()).into(),
+        _ => server_addr,
+    };
+    tokio::spawn(async move {
+        loop {
+            let accepted = tokio::select! {
+                _ = server_handle.clone().stopped() => break,
+                accepted = listener.accept() => accepted,
+            };
+            let mut unix_stream = match accepted {
+                Ok((unix_stream, _)) => unix_stream,
+                Err(err) => {
+                    warn!("Failed to accept a connection on the JSON-RPC Unix socket: {err}");
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                match TcpStream::connect(target_addr).await {
+                    Ok(mut tcp_stream) => {
+                        let _ = copy_bidirectional(&mut unix_stream, &mut tcp_stream).await;
+                    }
+                    Err(err) => {
+                        warn!("Failed to forward a JSON-RPC Unix socket connection: {err}")
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_over_unix_socket(
+    path: &Path,
+    _server_addr: SocketAddr,
+    _server_handle: ServerHandle,
+) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "Can't serve JSON-RPC over the Unix socket {}: not a Unix platform.",
+        path.display()
+    )
 }
@@ -1,28 +1,51 @@
-use hyper::{Body, Request};
+use hyper::header::HeaderValue;
+use hyper::{header, Body, Method, Request};
 use jsonrpsee::core::http_helpers::read_body;
+use jsonrpsee::core::GenericTransportError;
 use regex::Regex;
 use tower::BoxError;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::{debug, instrument};
 
 use crate::version_config::{VersionState, VERSION_CONFIG, VERSION_PATTERN};
-use crate::SERVER_MAX_BODY_SIZE;
+use crate::ANY_ORIGIN;
 
 /// [`Tower`] middleware intended to proxy method requests to the right version of the API.
 /// The middleware reads the JsonRPC request body and request path
 /// then prefixes the method name with the appropriate version identifier.
 /// It returns a new [`hyper::Request`] object with the new method name.
+/// WebSocket upgrade requests have no body, and are passed through unchanged.
+/// Bodies larger than the maximum size are left for the server to reject.
 ///
 /// # Arguments
 /// * req - [`hyper::Request`] object passed by the server.
+/// * max_request_body_size - the maximum size of the request body the server accepts.
 ///
 /// [`Tower`]: https://crates.io/crates/tower
-pub(crate) async fn proxy_rpc_request(req: Request<Body>) -> Result<Request<Body>, BoxError> {
+pub(crate) async fn proxy_rpc_request(
+    req: Request<Body>,
+    max_request_body_size: u32,
+) -> Result<Request<Body>, BoxError> {
     debug!("proxy_rpc_request -> Request received: {:?}", req);
+    if is_websocket_upgrade_request(&req) {
+        return Ok(req);
+    }
     let uri = &req.uri().clone();
     let prefix = get_version_as_prefix(uri.path())?;
-    let (parts, body) = req.into_parts();
-    let (body_bytes, is_single) =
-        read_body(&parts.headers, body, SERVER_MAX_BODY_SIZE).await.map_err(BoxError::from)?;
+    let (mut parts, body) = req.into_parts();
+    let (body_bytes, is_single) = match read_body(&parts.headers, body, max_request_body_size).await
+    {
+        Ok(res) => res,
+        Err(GenericTransportError::TooLarge) => {
+            // Declare the oversize length so the server responds with the standard JSON-RPC
+            // error without reading the body again.
+            parts
+                .headers
+                .insert(header::CONTENT_LENGTH, (u64::from(max_request_body_size) + 1).into());
+            return Ok(Request::from_parts(parts, Body::empty()));
+        }
+        Err(err) => return Err(BoxError::from(err)),
+    };
     let new_body = match is_single {
         true => {
             let body = serde_json::from_slice::<jsonrpsee::types::Request<'_>>(&body_bytes)?;
@@ -55,6 +78,27 @@ pub(crate) async fn deny_requests_with_unsupported_path(
     }
 }
 
+/// Returns a [`CorsLayer`] that allows cross-origin requests from the given origins, or None if
+/// there are none.
+pub(crate) fn get_cors_layer(allowed_origins: &[String]) -> Option<CorsLayer> {
+    if allowed_origins.is_empty() {
+        return None;
+    }
+    let allow_origin = if allowed_origins.iter().any(|origin| origin == ANY_ORIGIN) {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(allowed_origins.iter().map(|origin| {
+            HeaderValue::from_str(origin).expect("Allowed origins should be validated.")
+        }))
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::POST])
+            .allow_headers([header::CONTENT_TYPE]),
+    )
+}
+
 fn add_version_to_method_name_in_body(
     mut vec_body: Vec<jsonrpsee::types::Request<'_>>,
     prefix: &str,
@@ -116,6 +160,13 @@ fn get_version_as_prefix(path: &str) -> Result<&str, BoxError> {
     Ok(version_id.name)
 }
 
+fn is_websocket_upgrade_request(req: &Request<Body>) -> bool {
+    req.headers()
+        .get(header::UPGRADE)
+        .and_then(|upgrade| upgrade.to_str().ok())
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+}
+
 fn is_supported_path(path: &str) -> bool {
     let re = Regex::new((r"^\/rpc\/".to_string() + VERSION_PATTERN + "$").as_str())
         .expect("should be a valid regex");
@@ -0,0 +1,175 @@
+#[cfg(test)]
+#[path = "rate_limiter_test.rs"]
+mod rate_limiter_test;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures_util::FutureExt;
+use jsonrpsee::types::ErrorObjectOwned;
+use jsonrpsee::{MethodCallback, MethodResponse, Methods};
+use papyrus_config::converters::{deserialize_optional_map, serialize_optional_map};
+use papyrus_config::dumping::{ser_optional_param, ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// The error code for requests that exceeded the rate limit of their method, as defined in
+/// EIP-1474.
+pub const RATE_LIMIT_EXCEEDED_CODE: i32 = -32005;
+const RATE_LIMIT_EXCEEDED_MSG: &str = "Limit exceeded";
+
+/// Limits on the number of requests per second the server handles for each method. The limits
+/// apply to the method in all the API versions together, and don't apply to subscriptions.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct RateLimitConfig {
+    /// The limit of every method without a limit of its own. If None, these methods are
+    /// unlimited.
+    pub default_requests_per_second: Option<u32>,
+    /// The limits of specific methods, by the method name, e.g. "starknet_traceTransaction".
+    #[serde(deserialize_with = "deserialize_method_limits")]
+    pub method_requests_per_second: HashMap<String, u32>,
+}
+
+impl SerializeConfig for RateLimitConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        let method_requests_per_second = (!self.method_requests_per_second.is_empty()).then(|| {
+            self.method_requests_per_second
+                .iter()
+                .map(|(method, limit)| (method.clone(), limit.to_string()))
+                .collect()
+        });
+        let mut dump = BTreeMap::from_iter([ser_param(
+            "method_requests_per_second",
+            &serialize_optional_map(&method_requests_per_second),
+            "'method1:limit1 method2:limit2 ...' the maximum number of requests per second for \
+             specific methods.",
+            ParamPrivacyInput::Public,
+        )]);
+        dump.extend(ser_optional_param(
+            &self.default_requests_per_second,
+            100,
+            "default_requests_per_second",
+            "The maximum number of requests per second for each method without a limit of its own.",
+            ParamPrivacyInput::Public,
+        ));
+        dump
+    }
+}
+
+fn deserialize_method_limits<'de, D>(de: D) -> Result<HashMap<String, u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(raw_limits) = deserialize_optional_map(de)? else {
+        return Ok(HashMap::new());
+    };
+    raw_limits
+        .into_iter()
+        .map(|(method, raw_limit)| {
+            let limit = raw_limit.parse::<u32>().map_err(|_| {
+                D::Error::custom(format!("limit \"{raw_limit}\" of {method} is not a number"))
+            })?;
+            Ok((method, limit))
+        })
+        .collect()
+}
+
+// A token bucket that allows bursts of up to a second's worth of requests.
+struct TokenBucket {
+    requests_per_second: u32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests_per_second: u32) -> Self {
+        Self {
+            requests_per_second,
+            tokens: requests_per_second.into(),
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let capacity = f64::from(self.requests_per_second);
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * capacity;
+        self.tokens = (self.tokens + refill).min(capacity);
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Returns the methods with their callbacks wrapped to reject requests that exceed the limits.
+pub(crate) fn apply_rate_limits(
+    methods: Methods,
+    config: &RateLimitConfig,
+) -> anyhow::Result<Methods> {
+    // The methods of all the versions share the bucket of their unversioned name.
+    let mut buckets = HashMap::<String, Arc<Mutex<TokenBucket>>>::new();
+    let mut limited_methods = Methods::new();
+    for versioned_name in methods.method_names() {
+        let callback =
+            methods.method(versioned_name).expect("Method names should be registered.").clone();
+        let name = get_unversioned_method_name(versioned_name);
+        let Some(limit) = config
+            .method_requests_per_second
+            .get(&name)
+            .copied()
+            .or(config.default_requests_per_second)
+        else {
+            limited_methods.verify_and_insert(versioned_name, callback)?;
+            continue;
+        };
+        let bucket = buckets
+            .entry(name.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(TokenBucket::new(limit))))
+            .clone();
+        let try_acquire =
+            move || bucket.lock().expect("Bucket lock should not be poisoned.").try_acquire();
+        let callback = match callback {
+            MethodCallback::Sync(method) => {
+                MethodCallback::Sync(Arc::new(move |id, params, max_response_size| {
+                    if !try_acquire() {
+                        return MethodResponse::error(id, rate_limit_exceeded_error(&name));
+                    }
+                    method(id, params, max_response_size)
+                }))
+            }
+            MethodCallback::Async(method) => MethodCallback::Async(Arc::new(
+                move |id, params, connection_id, max_response_size| {
+                    if !try_acquire() {
+                        let response = MethodResponse::error(id, rate_limit_exceeded_error(&name));
+                        return async move { response }.boxed();
+                    }
+                    method(id, params, connection_id, max_response_size)
+                },
+            )),
+            subscription_callback => subscription_callback,
+        };
+        limited_methods.verify_and_insert(versioned_name, callback)?;
+    }
+    Ok(limited_methods)
+}
+
+// Strips the version from names such as "starknet_V0_6_blockNumber".
+fn get_unversioned_method_name(versioned_name: &str) -> String {
+    match versioned_name.rsplit_once('_') {
+        Some((_, method)) => format!("starknet_{method}"),
+        None => versioned_name.to_owned(),
+    }
+}
+
+fn rate_limit_exceeded_error(method: &str) -> ErrorObjectOwned {
+    ErrorObjectOwned::owned(
+        RATE_LIMIT_EXCEEDED_CODE,
+        RATE_LIMIT_EXCEEDED_MSG,
+        Some(format!("Too many requests to {method}, try again later.")),
+    )
+}
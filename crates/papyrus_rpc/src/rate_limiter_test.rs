@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
+use jsonrpsee::core::Error;
+use jsonrpsee::{rpc_params, Methods, RpcModule};
+
+use super::{apply_rate_limits, RateLimitConfig, RATE_LIMIT_EXCEEDED_CODE};
+
+fn get_test_methods() -> Methods {
+    let mut module = RpcModule::new(());
+    module.register_method("starknet_V0_6_expensive", |_, _| 0_u8).unwrap();
+    module.register_async_method("starknet_V0_5_expensive", |_, _| async { 0_u8 }).unwrap();
+    module.register_method("starknet_V0_6_cheap", |_, _| 0_u8).unwrap();
+    module.into()
+}
+
+async fn call(methods: &Methods, method: &str) -> Result<u8, Error> {
+    methods.call(method, rpc_params![]).await
+}
+
+#[tokio::test]
+async fn method_over_its_limit_is_throttled() {
+    let config = RateLimitConfig {
+        default_requests_per_second: None,
+        method_requests_per_second: HashMap::from([("starknet_expensive".to_owned(), 2)]),
+    };
+    let methods = apply_rate_limits(get_test_methods(), &config).unwrap();
+
+    // The versions of a method share its limit.
+    call(&methods, "starknet_V0_6_expensive").await.unwrap();
+    call(&methods, "starknet_V0_5_expensive").await.unwrap();
+    for method in ["starknet_V0_6_expensive", "starknet_V0_5_expensive"] {
+        let err = call(&methods, method).await.unwrap_err();
+        assert_matches!(err, Error::Call(error) if error.code() == RATE_LIMIT_EXCEEDED_CODE);
+    }
+
+    // Methods without a limit aren't throttled.
+    for _ in 0..10 {
+        call(&methods, "starknet_V0_6_cheap").await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn default_limit_applies_to_each_method_separately() {
+    let config = RateLimitConfig {
+        default_requests_per_second: Some(1),
+        method_requests_per_second: HashMap::from([("starknet_expensive".to_owned(), 2)]),
+    };
+    let methods = apply_rate_limits(get_test_methods(), &config).unwrap();
+
+    call(&methods, "starknet_V0_6_cheap").await.unwrap();
+    call(&methods, "starknet_V0_6_cheap").await.unwrap_err();
+    // The method's own limit overrides the default one.
+    call(&methods, "starknet_V0_6_expensive").await.unwrap();
+    call(&methods, "starknet_V0_6_expensive").await.unwrap();
+    call(&methods, "starknet_V0_6_expensive").await.unwrap_err();
+}
@@ -29,6 +29,7 @@ use crate::test_utils::{
     get_test_pending_classes,
     get_test_pending_data,
     get_test_rpc_config,
+    get_test_sync_progress_sender,
 };
 
 #[test]
@@ -163,6 +164,7 @@ async fn server_metrics() {
         get_test_highest_block(),
         get_test_pending_data(),
         get_test_pending_classes(),
+        get_test_sync_progress_sender(),
         storage_reader,
         "NODE VERSION",
     )
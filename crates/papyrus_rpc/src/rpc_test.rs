@@ -1,32 +1,56 @@
 use std::error::Error as StdError;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{panic, vec};
 
 use assert_matches::assert_matches;
 use futures_util::future::join_all;
 use hyper::{header, Body, Request};
-use jsonrpsee::core::client::ClientT;
+use jsonrpsee::core::client::{ClientT, SubscriptionClientT};
 use jsonrpsee::core::http_helpers::read_body;
 use jsonrpsee::core::{Error, RpcResult};
 use jsonrpsee::http_client::HttpClientBuilder;
+use jsonrpsee::rpc_params;
+use jsonrpsee::types::error::{OVERSIZED_REQUEST_CODE, TOO_BIG_BATCH_REQUEST_CODE};
 use jsonrpsee::types::ErrorObjectOwned;
+use jsonrpsee::ws_client::WsClientBuilder;
+use papyrus_common::sync_progress::{SyncProgressEvent, SyncProgressEventKind};
+use papyrus_common::BlockHashAndNumber;
 use papyrus_storage::base_layer::BaseLayerStorageWriter;
 use papyrus_storage::header::HeaderStorageWriter;
 use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::StorageReader;
 use pretty_assertions::assert_eq;
 use rand::seq::SliceRandom;
-use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockStatus};
+use serde_json::json;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockStatus, BlockTimestamp};
+use starknet_api::hash::StarkFelt;
+use starknet_client::RetryConfig;
 use test_utils::get_rng;
+use tokio::sync::{broadcast, RwLock};
 use tower::BoxError;
+use validator::Validate;
 
 use crate::middleware::proxy_rpc_request;
+use crate::subscriptions::{NewHead, SUBSCRIBE_NEW_HEADS_METHOD, UNSUBSCRIBE_NEW_HEADS_METHOD};
 use crate::test_utils::{
     get_test_highest_block,
     get_test_pending_classes,
     get_test_pending_data,
     get_test_rpc_config,
+    get_test_sync_progress_sender,
+};
+use crate::version_config::{VERSION_0_6, VERSION_CONFIG};
+use crate::{
+    get_block_status,
+    run_server,
+    validate_additional_server_addresses,
+    validate_cors_allowed_origins,
+    validate_starknet_version,
+    RpcConfig,
+    RpcServerHandle,
 };
-use crate::version_config::VERSION_CONFIG;
-use crate::{get_block_status, run_server, SERVER_MAX_BODY_SIZE};
 
 #[tokio::test]
 async fn run_server_no_blocks() {
@@ -40,6 +64,7 @@ async fn run_server_no_blocks() {
         shared_highest_block,
         pending_data,
         pending_classes,
+        get_test_sync_progress_sender(),
         storage_reader,
         "NODE VERSION",
     )
@@ -64,7 +89,9 @@ async fn run_server_no_blocks() {
 async fn get_json_rpc_body(request: Request<Body>) -> Vec<u8> {
     let (res_parts, res_body) = request.into_parts();
     let (body_bytes, _is_single) =
-        read_body(&res_parts.headers, res_body, SERVER_MAX_BODY_SIZE).await.unwrap();
+        read_body(&res_parts.headers, res_body, get_test_rpc_config().max_request_body_size)
+            .await
+            .unwrap();
     body_bytes
 }
 
@@ -79,7 +106,8 @@ async fn call_proxy_request_get_method_in_out(
         .header(header::CONTENT_TYPE, "application/json")
         .body(Body::from(request_body.unwrap()))
         .unwrap();
-    let res = proxy_rpc_request(req_no_version).await?;
+    let res =
+        proxy_rpc_request(req_no_version, get_test_rpc_config().max_request_body_size).await?;
     let body_bytes = get_json_rpc_body(res).await;
     digest_body_and_assert(is_batch_request, body_bytes, params, method_name)
 }
@@ -232,3 +260,392 @@ fn serialization_precision() {
     let deserialized = serde_json::to_string(&serialized).unwrap();
     assert_eq!(input, deserialized);
 }
+
+#[tokio::test]
+async fn subscribe_new_heads() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let header = BlockHeader {
+        block_hash: BlockHash(StarkFelt::from(1u128)),
+        timestamp: BlockTimestamp(1000),
+        ..Default::default()
+    };
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(header.block_number, &header)
+        .unwrap()
+        .commit()
+        .unwrap();
+    let (sync_progress_sender, _) = broadcast::channel(10);
+    let (addr, _handle) = run_server(
+        &get_test_rpc_config(),
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        sync_progress_sender.clone(),
+        storage_reader,
+        "NODE VERSION",
+    )
+    .await
+    .unwrap();
+
+    let client = WsClientBuilder::default()
+        .build(format!("ws://{addr:?}/rpc/{}", VERSION_0_6.name))
+        .await
+        .unwrap();
+    let mut subscription = client
+        .subscribe::<NewHead, _>(
+            SUBSCRIBE_NEW_HEADS_METHOD,
+            rpc_params![],
+            UNSUBSCRIBE_NEW_HEADS_METHOD,
+        )
+        .await
+        .unwrap();
+    assert_eq!(sync_progress_sender.receiver_count(), 1);
+
+    // Only committed blocks are notified.
+    for kind in [SyncProgressEventKind::BlockDownloaded, SyncProgressEventKind::BlockCommitted] {
+        sync_progress_sender.send(SyncProgressEvent::new(header.block_number, kind)).unwrap();
+    }
+    let new_head = subscription.next().await.unwrap().unwrap();
+    assert_eq!(
+        new_head,
+        NewHead {
+            block_number: header.block_number,
+            block_hash: header.block_hash,
+            timestamp: header.timestamp,
+        }
+    );
+
+    // Unsubscribing releases the subscription of the sync progress events.
+    subscription.unsubscribe().await.unwrap();
+    while sync_progress_sender.receiver_count() > 0 {
+        tokio::task::yield_now().await;
+    }
+
+    // So does disconnecting.
+    let _subscription = client
+        .subscribe::<NewHead, _>(
+            SUBSCRIBE_NEW_HEADS_METHOD,
+            rpc_params![],
+            UNSUBSCRIBE_NEW_HEADS_METHOD,
+        )
+        .await
+        .unwrap();
+    assert_eq!(sync_progress_sender.receiver_count(), 1);
+    drop(client);
+    while sync_progress_sender.receiver_count() > 0 {
+        tokio::task::yield_now().await;
+    }
+}
+
+// Posts the body as is, and returns the parsed JSON-RPC response.
+async fn post_raw_request(addr: SocketAddr, body: String) -> serde_json::Value {
+    let response = reqwest::Client::new()
+        .post(format!("http://{addr}/rpc/{}", VERSION_0_6.name))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    serde_json::from_str(&response.text().await.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn reject_batch_exceeding_max_batch_size() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let config = RpcConfig { max_batch_size: 2, ..get_test_rpc_config() };
+    let (addr, _handle) = run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        get_test_sync_progress_sender(),
+        storage_reader,
+        "NODE VERSION",
+    )
+    .await
+    .unwrap();
+    let batch = (0..3)
+        .map(|id| json!({"jsonrpc": "2.0", "id": id, "method": "starknet_blockNumber"}))
+        .collect::<Vec<_>>();
+
+    let response = post_raw_request(addr, json!(batch).to_string()).await;
+    assert_eq!(response["error"]["code"], TOO_BIG_BATCH_REQUEST_CODE);
+}
+
+#[tokio::test]
+async fn reject_request_exceeding_max_request_body_size() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let config = RpcConfig { max_request_body_size: 100, ..get_test_rpc_config() };
+    let (addr, _handle) = run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        get_test_sync_progress_sender(),
+        storage_reader,
+        "NODE VERSION",
+    )
+    .await
+    .unwrap();
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": "starknet_getBlockWithTxHashes",
+        "params": ["a".repeat(100)],
+    });
+
+    let response = post_raw_request(addr, request.to_string()).await;
+    assert_eq!(response["error"]["code"], OVERSIZED_REQUEST_CODE);
+}
+
+#[tokio::test]
+async fn connections_beyond_max_connections_are_refused() {
+    const MAX_CONNECTIONS: u32 = 2;
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let config = RpcConfig { max_connections: MAX_CONNECTIONS, ..get_test_rpc_config() };
+    let (addr, _handle) = run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        get_test_sync_progress_sender(),
+        storage_reader,
+        "NODE VERSION",
+    )
+    .await
+    .unwrap();
+    let url = format!("ws://{addr}/rpc/{}", VERSION_0_6.name);
+
+    let mut clients = Vec::new();
+    for _ in 0..MAX_CONNECTIONS {
+        clients.push(WsClientBuilder::default().build(&url).await.unwrap());
+    }
+    assert!(WsClientBuilder::default().build(&url).await.is_err());
+
+    // The connections that were opened before reaching the limit are still usable. WebSocket
+    // requests aren't routed by their path, so they name the method of the version.
+    for client in clients {
+        let spec_version: String =
+            client.request("starknet_V0_6_specVersion", rpc_params![]).await.unwrap();
+        assert!(!spec_version.is_empty());
+    }
+}
+
+// Sends a preflight request from the origin, and returns the origin the server allowed, if any.
+async fn send_preflight_request(addr: SocketAddr, origin: &str) -> Option<String> {
+    let response = reqwest::Client::new()
+        .request(reqwest::Method::OPTIONS, format!("http://{addr}/rpc/{}", VERSION_0_6.name))
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .send()
+        .await
+        .unwrap();
+    response
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .map(|allowed_origin| allowed_origin.to_str().unwrap().to_owned())
+}
+
+async fn run_server_with_cors_allowed_origins(
+    storage_reader: StorageReader,
+    cors_allowed_origins: &[&str],
+) -> (SocketAddr, RpcServerHandle) {
+    let cors_allowed_origins =
+        cors_allowed_origins.iter().map(|origin| origin.to_string()).collect();
+    run_server(
+        &RpcConfig { cors_allowed_origins, ..get_test_rpc_config() },
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        get_test_sync_progress_sender(),
+        storage_reader,
+        "NODE VERSION",
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn cors_preflight() {
+    const ALLOWED_ORIGIN: &str = "http://allowed.com";
+    const OTHER_ORIGIN: &str = "http://other.com";
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+
+    let (addr, _handle) = run_server_with_cors_allowed_origins(storage_reader.clone(), &[]).await;
+    assert_eq!(send_preflight_request(addr, ALLOWED_ORIGIN).await, None);
+
+    let (addr, _handle) =
+        run_server_with_cors_allowed_origins(storage_reader.clone(), &[ALLOWED_ORIGIN]).await;
+    assert_eq!(send_preflight_request(addr, ALLOWED_ORIGIN).await.as_deref(), Some(ALLOWED_ORIGIN));
+    assert_eq!(send_preflight_request(addr, OTHER_ORIGIN).await, None);
+
+    let (addr, _handle) = run_server_with_cors_allowed_origins(storage_reader, &["*"]).await;
+    assert_eq!(send_preflight_request(addr, OTHER_ORIGIN).await.as_deref(), Some("*"));
+}
+
+#[test]
+fn cors_allowed_origins_validation() {
+    let to_origins =
+        |origins: &[&str]| origins.iter().map(|origin| origin.to_string()).collect::<Vec<_>>();
+    for valid_origins in [&[][..], &["*"], &["http://a.com", "https://b.com:8080"]] {
+        assert!(validate_cors_allowed_origins(&to_origins(valid_origins)).is_ok());
+    }
+    for invalid_origins in
+        [&["a.com"][..], &["http://a.com/"], &["http://a.com/path"], &["*", "http://a.com"]]
+    {
+        assert!(validate_cors_allowed_origins(&to_origins(invalid_origins)).is_err());
+    }
+}
+
+async fn run_server_with_shutdown_grace_period(
+    storage_reader: StorageReader,
+    shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+    shutdown_grace_period: Duration,
+) -> (SocketAddr, RpcServerHandle) {
+    run_server(
+        &RpcConfig { shutdown_grace_period, ..get_test_rpc_config() },
+        shared_highest_block,
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        get_test_sync_progress_sender(),
+        storage_reader,
+        "NODE VERSION",
+    )
+    .await
+    .unwrap()
+}
+
+fn syncing_request() -> String {
+    json!({"jsonrpc": "2.0", "id": 0, "method": "starknet_syncing"}).to_string()
+}
+
+#[tokio::test]
+async fn drain_completes_in_flight_requests() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let shared_highest_block = get_test_highest_block();
+    let (addr, handle) = run_server_with_shutdown_grace_period(
+        storage_reader,
+        shared_highest_block.clone(),
+        Duration::from_secs(10),
+    )
+    .await;
+
+    // starknet_syncing reads the highest block, so holding its lock keeps the request in flight.
+    let highest_block_guard = shared_highest_block.write().await;
+    let in_flight_request = tokio::spawn(post_raw_request(addr, syncing_request()));
+    // Let the request reach the server before the drain begins.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let drain = tokio::spawn(handle.drain());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!drain.is_finished());
+
+    drop(highest_block_guard);
+    let response = in_flight_request.await.unwrap();
+    assert_eq!(response["result"], json!(false));
+    assert!(drain.await.unwrap());
+}
+
+#[tokio::test]
+async fn drain_gives_up_after_shutdown_grace_period() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let shared_highest_block = get_test_highest_block();
+    let (addr, handle) = run_server_with_shutdown_grace_period(
+        storage_reader,
+        shared_highest_block.clone(),
+        Duration::from_millis(100),
+    )
+    .await;
+
+    let _highest_block_guard = shared_highest_block.write().await;
+    let _in_flight_request = tokio::spawn(post_raw_request(addr, syncing_request()));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!handle.drain().await);
+}
+
+#[tokio::test]
+async fn requests_succeed_on_all_server_addresses() {
+    let ((storage_reader, _), temp_dir) = get_test_storage();
+    let socket_path = temp_dir.path().join("rpc.sock");
+    let mut additional_server_addresses = vec![String::from("127.0.0.1:0")];
+    if cfg!(unix) {
+        additional_server_addresses.push(format!("unix:{}", socket_path.display()));
+    }
+    let config = RpcConfig { additional_server_addresses, ..get_test_rpc_config() };
+    let (addr, handle) = run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        get_test_sync_progress_sender(),
+        storage_reader,
+        "NODE VERSION",
+    )
+    .await
+    .unwrap();
+
+    let local_addrs = handle.local_addrs();
+    assert_eq!(local_addrs.len(), 2);
+    assert_eq!(local_addrs[0], addr);
+    assert_ne!(local_addrs[0], local_addrs[1]);
+    for addr in local_addrs {
+        let response = post_raw_request(*addr, syncing_request()).await;
+        assert_eq!(response["result"], json!(false));
+    }
+
+    #[cfg(unix)]
+    {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let body = syncing_request();
+        let request = format!(
+            "POST /rpc/{} HTTP/1.1\r\nHost: localhost\r\nContent-Type: \
+             application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            VERSION_0_6.name,
+            body.len()
+        );
+        let mut stream = tokio::net::UnixStream::connect(&socket_path).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "Unexpected response: {response}");
+        assert!(response.contains(r#""result":false"#), "Unexpected response: {response}");
+    }
+}
+
+#[test]
+fn additional_server_addresses_validation() {
+    assert!(validate_additional_server_addresses(&["127.0.0.1:0".to_string()]).is_ok());
+    assert!(validate_additional_server_addresses(&["unix:".to_string()]).is_err());
+    assert_eq!(
+        validate_additional_server_addresses(&["unix:/tmp/rpc.sock".to_string()]).is_ok(),
+        cfg!(unix)
+    );
+}
+
+#[test]
+fn starknet_version_validation() {
+    assert!(validate_starknet_version("0.13.1").is_ok());
+    assert!(validate_starknet_version("0.13.1.1").is_ok());
+    for version in ["", "0.13.", "0..1", "v0.13.1", "0.13.1-rc"] {
+        assert!(validate_starknet_version(version).is_err(), "{version} should be invalid");
+    }
+}
+
+#[test]
+fn starknet_gateway_retry_config_validation() {
+    let is_retry_config_invalid = |starknet_gateway_retry_config| {
+        let config = RpcConfig { starknet_gateway_retry_config, ..get_test_rpc_config() };
+        config
+            .validate()
+            .is_err_and(|errors| errors.errors().contains_key("starknet_gateway_retry_config"))
+    };
+    let retry_config =
+        RetryConfig { retry_base_millis: 30, retry_max_delay_millis: 30000, max_retries: 5 };
+    assert!(!is_retry_config_invalid(retry_config));
+    assert!(is_retry_config_invalid(RetryConfig {
+        retry_max_delay_millis: retry_config.retry_base_millis - 1,
+        ..retry_config
+    }));
+}
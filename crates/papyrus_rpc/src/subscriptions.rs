@@ -0,0 +1,96 @@
+use jsonrpsee::core::server::TrySendError;
+use jsonrpsee::core::SubscriptionResult;
+use jsonrpsee::{Methods, PendingSubscriptionSink, RpcModule, SubscriptionMessage};
+use papyrus_common::sync_progress::{SyncProgressEvent, SyncProgressEventKind};
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::StorageReader;
+use serde::{Deserialize, Serialize};
+use starknet_api::block::{BlockHash, BlockNumber, BlockTimestamp};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+pub(crate) const SUBSCRIBE_NEW_HEADS_METHOD: &str = "starknet_subscribeNewHeads";
+pub(crate) const NEW_HEADS_NOTIFICATION: &str = "starknet_newHeads";
+pub(crate) const UNSUBSCRIBE_NEW_HEADS_METHOD: &str = "starknet_unsubscribeNewHeads";
+
+/// A notification that is sent to the subscribers of new heads whenever a block is committed.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct NewHead {
+    pub block_number: BlockNumber,
+    pub block_hash: BlockHash,
+    pub timestamp: BlockTimestamp,
+}
+
+struct SubscriptionContext {
+    storage_reader: StorageReader,
+    sync_progress_sender: broadcast::Sender<SyncProgressEvent>,
+}
+
+/// Returns the WebSocket subscription methods. The subscriptions aren't versioned, and are served
+/// on every supported path.
+pub(crate) fn get_subscription_methods(
+    storage_reader: StorageReader,
+    sync_progress_sender: broadcast::Sender<SyncProgressEvent>,
+) -> anyhow::Result<Methods> {
+    let mut module = RpcModule::new(SubscriptionContext { storage_reader, sync_progress_sender });
+    module.register_subscription(
+        SUBSCRIBE_NEW_HEADS_METHOD,
+        NEW_HEADS_NOTIFICATION,
+        UNSUBSCRIBE_NEW_HEADS_METHOD,
+        |_params, pending, context| async move { subscribe_new_heads(pending, &context).await },
+    )?;
+    Ok(module.into())
+}
+
+async fn subscribe_new_heads(
+    pending: PendingSubscriptionSink,
+    context: &SubscriptionContext,
+) -> SubscriptionResult {
+    // Subscribe before accepting so that no block committed after the subscription is missed.
+    let mut receiver = context.sync_progress_sender.subscribe();
+    let mut sink = pending.accept().await?;
+    loop {
+        let event = tokio::select! {
+            // The client unsubscribed or disconnected.
+            _ = sink.closed() => return Ok(()),
+            event = receiver.recv() => event,
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                debug!("New heads subscription skipped {skipped} sync progress events.");
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        if event.kind != SyncProgressEventKind::BlockCommitted {
+            continue;
+        }
+        let Some(new_head) = get_new_head(&context.storage_reader, event.block_number)? else {
+            // The block was reverted before it was read.
+            continue;
+        };
+        match sink.try_send(SubscriptionMessage::from_json(&new_head)?) {
+            Ok(()) => {}
+            Err(TrySendError::Closed(_)) => return Ok(()),
+            Err(err @ TrySendError::Full(_)) => {
+                // The client doesn't read the notifications fast enough, so its backlog is full.
+                warn!("Closing a new heads subscription: {err}.");
+                return Err(err.into());
+            }
+        }
+    }
+}
+
+fn get_new_head(
+    storage_reader: &StorageReader,
+    block_number: BlockNumber,
+) -> anyhow::Result<Option<NewHead>> {
+    let header = storage_reader.begin_ro_txn()?.get_block_header(block_number)?;
+    Ok(header.map(|header| NewHead {
+        block_number: header.block_number,
+        block_hash: header.block_hash,
+        timestamp: header.timestamp,
+    }))
+}
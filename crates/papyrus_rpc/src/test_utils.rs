@@ -7,6 +7,7 @@ use jsonrpsee::server::RpcModule;
 use jsonrpsee::types::ErrorObjectOwned;
 use jsonschema::JSONSchema;
 use papyrus_common::pending_classes::PendingClasses;
+use papyrus_common::sync_progress::SyncProgressEvent;
 use papyrus_common::BlockHashAndNumber;
 use papyrus_storage::test_utils::get_test_storage_by_scope;
 use papyrus_storage::{StorageScope, StorageWriter};
@@ -19,7 +20,7 @@ use starknet_client::reader::PendingData;
 use starknet_client::writer::MockStarknetWriter;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 
 use crate::api::JsonRpcServerImpl;
 use crate::version_config::{VersionId, VERSION_PATTERN};
@@ -52,6 +53,10 @@ pub(crate) fn get_test_pending_classes() -> Arc<RwLock<PendingClasses>> {
     Arc::new(RwLock::new(PendingClasses::default()))
 }
 
+pub(crate) fn get_test_sync_progress_sender() -> broadcast::Sender<SyncProgressEvent> {
+    broadcast::channel(1).0
+}
+
 pub(crate) fn get_test_rpc_server_and_storage_writer<T: JsonRpcServerImpl>()
 -> (RpcModule<T>, StorageWriter) {
     get_test_rpc_server_and_storage_writer_from_params(None, None, None, None, None)
@@ -63,6 +68,24 @@ pub(crate) fn get_test_rpc_server_and_storage_writer_from_params<T: JsonRpcServe
     pending_data: Option<Arc<RwLock<PendingData>>>,
     pending_classes: Option<Arc<RwLock<PendingClasses>>>,
     storage_scope: Option<StorageScope>,
+) -> (RpcModule<T>, StorageWriter) {
+    get_test_rpc_server_and_storage_writer_from_config(
+        get_test_rpc_config(),
+        mock_client,
+        shared_highest_block,
+        pending_data,
+        pending_classes,
+        storage_scope,
+    )
+}
+
+pub(crate) fn get_test_rpc_server_and_storage_writer_from_config<T: JsonRpcServerImpl>(
+    config: RpcConfig,
+    mock_client: Option<MockStarknetWriter>,
+    shared_highest_block: Option<Arc<RwLock<Option<BlockHashAndNumber>>>>,
+    pending_data: Option<Arc<RwLock<PendingData>>>,
+    pending_classes: Option<Arc<RwLock<PendingClasses>>>,
+    storage_scope: Option<StorageScope>,
 ) -> (RpcModule<T>, StorageWriter) {
     let mock_client = mock_client.unwrap_or_default();
     let shared_highest_block = shared_highest_block.unwrap_or(get_test_highest_block());
@@ -71,7 +94,6 @@ pub(crate) fn get_test_rpc_server_and_storage_writer_from_params<T: JsonRpcServe
     let storage_scope = storage_scope.unwrap_or_default();
 
     let ((storage_reader, storage_writer), _temp_dir) = get_test_storage_by_scope(storage_scope);
-    let config = get_test_rpc_config();
     let mock_client_arc = Arc::new(mock_client);
     (
         T::new(
@@ -80,6 +102,7 @@ pub(crate) fn get_test_rpc_server_and_storage_writer_from_params<T: JsonRpcServe
             storage_reader,
             config.max_events_chunk_size,
             config.max_events_keys,
+            config.starknet_version_override,
             BlockHashAndNumber::default(),
             shared_highest_block,
             pending_data,
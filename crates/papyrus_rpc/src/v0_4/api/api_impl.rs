@@ -139,6 +139,7 @@ pub struct JsonRpcServerV0_4Impl {
     pub storage_reader: StorageReader,
     pub max_events_chunk_size: usize,
     pub max_events_keys: usize,
+    pub starknet_version_override: Option<String>,
     pub starting_block: BlockHashAndNumber,
     pub shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     pub pending_data: Arc<RwLock<PendingData>>,
@@ -1432,6 +1433,7 @@ impl JsonRpcServerImpl for JsonRpcServerV0_4Impl {
         storage_reader: StorageReader,
         max_events_chunk_size: usize,
         max_events_keys: usize,
+        starknet_version_override: Option<String>,
         starting_block: BlockHashAndNumber,
         shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
         pending_data: Arc<RwLock<PendingData>>,
@@ -1444,6 +1446,7 @@ impl JsonRpcServerImpl for JsonRpcServerV0_4Impl {
             storage_reader,
             max_events_chunk_size,
             max_events_keys,
+            starknet_version_override,
             starting_block,
             shared_highest_block,
             pending_data,
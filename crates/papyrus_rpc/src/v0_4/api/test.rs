@@ -164,6 +164,7 @@ use crate::test_utils::{
     get_test_rpc_config,
     get_test_rpc_server_and_storage_writer,
     get_test_rpc_server_and_storage_writer_from_params,
+    get_test_sync_progress_sender,
     method_name_to_spec_method_name,
     raw_call,
     validate_schema,
@@ -2898,6 +2899,7 @@ async fn serialize_returns_valid_json() {
         get_test_highest_block(),
         get_test_pending_data(),
         get_test_pending_classes(),
+        get_test_sync_progress_sender(),
         storage_reader,
         NODE_VERSION,
     )
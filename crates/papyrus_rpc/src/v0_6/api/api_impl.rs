@@ -141,6 +141,7 @@ pub struct JsonRpcServerV0_6Impl {
     pub storage_reader: StorageReader,
     pub max_events_chunk_size: usize,
     pub max_events_keys: usize,
+    pub starknet_version_override: Option<String>,
     pub starting_block: BlockHashAndNumber,
     pub shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     pub pending_data: Arc<RwLock<PendingData>>,
@@ -186,7 +187,7 @@ impl JsonRpcServer for JsonRpcServerV0_6Impl {
                     price_in_wei: block.eth_l1_gas_price,
                     price_in_fri: block.strk_l1_gas_price,
                 },
-                starknet_version: block.starknet_version,
+                starknet_version: self.reported_starknet_version(block.starknet_version),
             };
             let header = GeneralBlockHeader::PendingBlockHeader(pending_block_header);
             let client_transactions = block.transactions;
@@ -203,8 +204,9 @@ impl JsonRpcServer for JsonRpcServerV0_6Impl {
 
         let block_number = get_accepted_block_number(&txn, block_id)?;
         let status = get_block_status(&txn, block_number)?;
-        let header =
-            GeneralBlockHeader::BlockHeader(get_block_header_by_number(&txn, block_number)?.into());
+        let mut header: BlockHeader = get_block_header_by_number(&txn, block_number)?.into();
+        header.starknet_version = self.reported_starknet_version(header.starknet_version);
+        let header = GeneralBlockHeader::BlockHeader(header);
         let transaction_hashes = get_block_tx_hashes_by_number(&txn, block_number)?;
 
         Ok(Block {
@@ -229,7 +231,7 @@ impl JsonRpcServer for JsonRpcServerV0_6Impl {
                     price_in_wei: block.eth_l1_gas_price,
                     price_in_fri: block.strk_l1_gas_price,
                 },
-                starknet_version: block.starknet_version,
+                starknet_version: self.reported_starknet_version(block.starknet_version),
             };
             let header = GeneralBlockHeader::PendingBlockHeader(pending_block_header);
             let client_transactions = block.transactions;
@@ -255,8 +257,9 @@ impl JsonRpcServer for JsonRpcServerV0_6Impl {
 
         let block_number = get_accepted_block_number(&txn, block_id)?;
         let status = get_block_status(&txn, block_number)?;
-        let header =
-            GeneralBlockHeader::BlockHeader(get_block_header_by_number(&txn, block_number)?.into());
+        let mut header: BlockHeader = get_block_header_by_number(&txn, block_number)?.into();
+        header.starknet_version = self.reported_starknet_version(header.starknet_version);
+        let header = GeneralBlockHeader::BlockHeader(header);
         // TODO(dvir): consider create a vector of (transaction, transaction_index) first and get
         // the transaction hashes by the index.
         let transactions = get_block_txs_by_number(&txn, block_number)?;
@@ -1496,6 +1499,13 @@ fn do_event_keys_match_filter(event_content: &EventContent, filter: &EventFilter
     })
 }
 
+impl JsonRpcServerV0_6Impl {
+    // The Starknet version to report for a block that was created in the given version.
+    fn reported_starknet_version(&self, starknet_version: String) -> String {
+        self.starknet_version_override.clone().unwrap_or(starknet_version)
+    }
+}
+
 impl JsonRpcServerImpl for JsonRpcServerV0_6Impl {
     fn new(
         chain_id: ChainId,
@@ -1503,6 +1513,7 @@ impl JsonRpcServerImpl for JsonRpcServerV0_6Impl {
         storage_reader: StorageReader,
         max_events_chunk_size: usize,
         max_events_keys: usize,
+        starknet_version_override: Option<String>,
         starting_block: BlockHashAndNumber,
         shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
         pending_data: Arc<RwLock<PendingData>>,
@@ -1515,6 +1526,7 @@ impl JsonRpcServerImpl for JsonRpcServerV0_6Impl {
             storage_reader,
             max_events_chunk_size,
             max_events_keys,
+            starknet_version_override,
             starting_block,
             shared_highest_block,
             pending_data,
@@ -167,7 +167,9 @@ use crate::test_utils::{
     get_test_pending_data,
     get_test_rpc_config,
     get_test_rpc_server_and_storage_writer,
+    get_test_rpc_server_and_storage_writer_from_config,
     get_test_rpc_server_and_storage_writer_from_params,
+    get_test_sync_progress_sender,
     method_name_to_spec_method_name,
     raw_call,
     validate_schema,
@@ -179,6 +181,7 @@ use crate::{
     internal_server_error_with_msg,
     run_server,
     ContinuationTokenAsStruct,
+    RpcConfig,
 };
 
 const NODE_VERSION: &str = "NODE VERSION";
@@ -826,6 +829,66 @@ async fn get_block_w_transaction_hashes() {
     };
 }
 
+#[tokio::test]
+async fn starknet_version_override_is_reported_for_every_block() {
+    let method_name = "starknet_V0_6_getBlockWithTxHashes";
+    let starknet_version_override = "0.13.1".to_owned();
+    let config = RpcConfig {
+        starknet_version_override: Some(starknet_version_override.clone()),
+        ..get_test_rpc_config()
+    };
+    let pending_data = get_test_pending_data();
+    let (module, mut storage_writer) = get_test_rpc_server_and_storage_writer_from_config::<
+        JsonRpcServerImpl,
+    >(
+        config, None, None, Some(pending_data.clone()), None, None
+    );
+
+    let block = get_test_block(1, None, None, None);
+    let original_starknet_version = StarknetVersion("0.12.3".to_owned());
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(block.header.block_number, &block.header)
+        .unwrap()
+        .update_starknet_version(&block.header.block_number, &original_starknet_version)
+        .unwrap()
+        .append_body(block.header.block_number, block.body)
+        .unwrap()
+        .append_state_diff(block.header.block_number, StateDiff::default(), IndexMap::new())
+        .unwrap()
+        .commit()
+        .unwrap();
+    {
+        let pending_block = &mut pending_data.write().await.block;
+        pending_block.parent_block_hash = block.header.block_hash;
+        pending_block.starknet_version = original_starknet_version.0;
+    }
+
+    let res_block =
+        module.call::<_, Block>(method_name, [BlockId::Tag(Tag::Latest)]).await.unwrap();
+    let GeneralBlockHeader::BlockHeader(block_header) = res_block.header else {
+        panic!("Unexpected block_header type. Expected BlockHeader.")
+    };
+    assert_eq!(block_header.starknet_version, starknet_version_override);
+
+    let res_block =
+        module.call::<_, Block>(method_name, [BlockId::Tag(Tag::Pending)]).await.unwrap();
+    let GeneralBlockHeader::PendingBlockHeader(pending_block_header) = res_block.header else {
+        panic!("Unexpected block_header type. Expected PendingBlockHeader.")
+    };
+    assert_eq!(pending_block_header.starknet_version, starknet_version_override);
+
+    // A pending block that isn't up to date is built from the latest block.
+    pending_data.write().await.block.parent_block_hash = BlockHash(random::<u64>().into());
+    let res_block =
+        module.call::<_, Block>(method_name, [BlockId::Tag(Tag::Pending)]).await.unwrap();
+    let GeneralBlockHeader::PendingBlockHeader(pending_block_header) = res_block.header else {
+        panic!("Unexpected block_header type. Expected PendingBlockHeader.")
+    };
+    assert_eq!(pending_block_header.starknet_version, starknet_version_override);
+}
+
 #[tokio::test]
 async fn get_class() {
     let method_name = "starknet_V0_6_getClass";
@@ -3116,6 +3179,7 @@ async fn serialize_returns_valid_json() {
         get_test_highest_block(),
         get_test_pending_data(),
         get_test_pending_classes(),
+        get_test_sync_progress_sender(),
         storage_reader,
         NODE_VERSION,
     )
@@ -0,0 +1,19 @@
+//! Compares the iteration over the keys of a table with big values to the iteration over its
+//! entries, which deserializes the values too.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use papyrus_storage::test_utils::BigValuesTable;
+
+const NUM_ENTRIES: u32 = 256;
+const VALUE_SIZE: usize = 1 << 16; // 64KB
+
+fn iterate_big_values_table(c: &mut Criterion) {
+    let table = BigValuesTable::new(NUM_ENTRIES, VALUE_SIZE);
+    let mut group = c.benchmark_group("iterate_big_values_table");
+    group.bench_function("keys", |b| b.iter(|| table.count_keys()));
+    group.bench_function("entries", |b| b.iter(|| table.count_entries()));
+    group.finish();
+}
+
+criterion_group!(benches, iterate_big_values_table);
+criterion_main!(benches);
@@ -11,7 +11,7 @@
 //! ```
 //! use papyrus_storage::base_layer::{BaseLayerStorageReader, BaseLayerStorageWriter};
 //! use papyrus_storage::open_storage;
-//! # use papyrus_storage::{db::DbConfig, StorageConfig};
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
 //! # use starknet_api::core::ChainId;
 //! use starknet_api::block::BlockNumber;
 //!
@@ -24,6 +24,10 @@
 //! #     min_size: 1 << 20,    // 1MB
 //! #     max_size: 1 << 35,    // 32GB
 //! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
 //! # };
 //! # let storage_config = StorageConfig{db_config, ..Default::default()};
 //! let (reader, mut writer) = open_storage(storage_config)?;
@@ -70,7 +74,7 @@ where
 impl<'env, Mode: TransactionKind> BaseLayerStorageReader for StorageTxn<'env, Mode> {
     fn get_base_layer_block_marker(&self) -> StorageResult<BlockNumber> {
         let markers_table = self.open_table(&self.tables.markers)?;
-        Ok(markers_table.get(&self.txn, &MarkerKind::BaseLayerBlock)?.unwrap_or_default())
+        Ok(markers_table.get_or_default(&self.txn, &MarkerKind::BaseLayerBlock)?)
     }
 }
 
@@ -0,0 +1,196 @@
+//! Interface for writing many operations to the storage in transactions of a bounded size.
+//!
+//! Committing a transaction for every operation hurts the throughput of bulk writes, and a single
+//! transaction for all of them grows without a bound. [`StorageWriter::with_batch`] groups the
+//! operations into batches of a given size and commits each batch in its own transaction. When the
+//! operations arrive slowly, [`StorageWriter::with_timed_batch`] also bounds the time they wait
+//! before they're committed, as long as the writing loop calls [`WriteBatch::commit_if_due`] while
+//! it waits for the next operation.
+//!
+//! # Example
+//! ```
+//! use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+//! use papyrus_storage::open_storage;
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
+//! use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+//! use starknet_api::hash::StarkFelt;
+//!
+//! # let dir_handle = tempfile::tempdir().unwrap();
+//! # let db_config = DbConfig {
+//! #     path_prefix: dir_handle.path().to_path_buf(),
+//! #     chain_id: starknet_api::core::ChainId("SN_MAIN".to_owned()),
+//! #     enforce_file_exists: false,
+//! #     min_size: 1 << 20,    // 1MB
+//! #     max_size: 1 << 35,    // 32GB
+//! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
+//! # };
+//! # let storage_config = StorageConfig{db_config, ..Default::default()};
+//! let (reader, mut writer) = open_storage(storage_config)?;
+//! let num_committed_batches = writer.with_batch(2, |batch| {
+//!     for i in 0..5 {
+//!         let header = BlockHeader {
+//!             block_hash: BlockHash(StarkFelt::from(i)),
+//!             block_number: BlockNumber(i),
+//!             ..BlockHeader::default()
+//!         };
+//!         batch.write(move |txn| txn.append_header(BlockNumber(i), &header))?;
+//!     }
+//!     Ok(())
+//! })?;
+//! assert_eq!(num_committed_batches, 3);
+//! assert_eq!(reader.begin_ro_txn()?.get_header_marker()?, BlockNumber(5));
+//! # Ok::<(), papyrus_storage::StorageError>(())
+//! ```
+
+#[cfg(test)]
+#[path = "batch_test.rs"]
+mod batch_test;
+
+use std::time::{Duration, Instant};
+
+use crate::db::{RO, RW};
+use crate::{StorageReader, StorageResult, StorageTxn, StorageWriter};
+
+type BatchOperation<'a> =
+    Box<dyn for<'env> FnOnce(StorageTxn<'env, RW>) -> StorageResult<StorageTxn<'env, RW>> + 'a>;
+
+/// Operations that are written to the storage in batches. The operations of a batch are applied
+/// when the batch is full or, for a timed batch, when its first operation waited long enough, in a
+/// single transaction that is committed right after.
+pub struct WriteBatch<'a> {
+    writer: &'a mut StorageWriter,
+    max_ops: usize,
+    max_batch_interval: Option<Duration>,
+    pending_operations: Vec<BatchOperation<'a>>,
+    // The time the first of the pending operations was written.
+    batch_start: Option<Instant>,
+    num_committed_batches: usize,
+}
+
+impl StorageWriter {
+    /// Runs `f` with a [`WriteBatch`] that commits a transaction for every `max_ops` operations
+    /// written to it, and for the remaining operations once `f` returns. Each batch is committed
+    /// entirely or not at all, so if an operation or `f` fails, the storage holds the operations of
+    /// all the batches that were committed before. Returns the number of committed batches.
+    ///
+    /// # Panics
+    /// If `max_ops` is 0.
+    pub fn with_batch<'a, F>(&'a mut self, max_ops: usize, f: F) -> StorageResult<usize>
+    where
+        F: FnOnce(&mut WriteBatch<'a>) -> StorageResult<()>,
+    {
+        self.run_batch(max_ops, None, f)
+    }
+
+    /// Like [`with_batch`](Self::with_batch), but a batch is also committed once
+    /// `max_batch_interval` elapsed since its first operation, even if the batch isn't full. This
+    /// bounds the time the operations of a slow stream of writes stay uncommitted, as well as the
+    /// size of the transactions of a fast one. The interval is checked when an operation is
+    /// written and when [`WriteBatch::commit_if_due`] is called, so `f` should call it while it
+    /// waits for the next operation.
+    ///
+    /// # Panics
+    /// If `max_ops` is 0.
+    pub fn with_timed_batch<'a, F>(
+        &'a mut self,
+        max_ops: usize,
+        max_batch_interval: Duration,
+        f: F,
+    ) -> StorageResult<usize>
+    where
+        F: FnOnce(&mut WriteBatch<'a>) -> StorageResult<()>,
+    {
+        self.run_batch(max_ops, Some(max_batch_interval), f)
+    }
+
+    fn run_batch<'a, F>(
+        &'a mut self,
+        max_ops: usize,
+        max_batch_interval: Option<Duration>,
+        f: F,
+    ) -> StorageResult<usize>
+    where
+        F: FnOnce(&mut WriteBatch<'a>) -> StorageResult<()>,
+    {
+        assert!(max_ops > 0, "A batch should contain at least one operation.");
+        let mut batch = WriteBatch {
+            writer: self,
+            max_ops,
+            max_batch_interval,
+            pending_operations: Vec::with_capacity(max_ops),
+            batch_start: None,
+            num_committed_batches: 0,
+        };
+        f(&mut batch)?;
+        batch.commit()?;
+        Ok(batch.num_committed_batches)
+    }
+}
+
+impl<'a> WriteBatch<'a> {
+    /// Adds an operation to the current batch, and commits the batch if it's full or its interval
+    /// elapsed. The operation gets the transaction of the batch and returns it, like the writing
+    /// functions of [`StorageTxn`].
+    pub fn write<Op>(&mut self, operation: Op) -> StorageResult<()>
+    where
+        Op: for<'env> FnOnce(StorageTxn<'env, RW>) -> StorageResult<StorageTxn<'env, RW>> + 'a,
+    {
+        self.batch_start.get_or_insert_with(Instant::now);
+        self.pending_operations.push(Box::new(operation));
+        self.commit_if_due()?;
+        Ok(())
+    }
+
+    /// Commits the current batch if it's full or its interval elapsed, and returns whether it was
+    /// committed. Writing loops that may wait long between operations should call it while they
+    /// wait, so that the pending operations aren't held until the next write.
+    pub fn commit_if_due(&mut self) -> StorageResult<bool> {
+        let interval_elapsed = match (self.batch_start, self.max_batch_interval) {
+            (Some(batch_start), Some(max_batch_interval)) => {
+                batch_start.elapsed() >= max_batch_interval
+            }
+            _ => false,
+        };
+        if self.pending_operations.len() < self.max_ops && !interval_elapsed {
+            return Ok(false);
+        }
+        self.commit()?;
+        Ok(true)
+    }
+
+    /// Commits the operations of the current batch, and returns a transaction of `reader` that
+    /// sees the storage right after the commit, including the operations of all the batches so
+    /// far. See [`StorageTxn::commit_and_read`].
+    pub fn commit_and_read<'r>(
+        &mut self,
+        reader: &'r StorageReader,
+    ) -> StorageResult<StorageTxn<'r, RO>> {
+        self.commit()?;
+        reader.begin_ro_txn()
+    }
+
+    /// Returns the number of batches that were committed so far.
+    pub fn num_committed_batches(&self) -> usize {
+        self.num_committed_batches
+    }
+
+    // Applies the pending operations in a single transaction and commits it. The pending
+    // operations are discarded even if one of them fails.
+    fn commit(&mut self) -> StorageResult<()> {
+        self.batch_start = None;
+        if self.pending_operations.is_empty() {
+            return Ok(());
+        }
+        let mut txn = self.writer.begin_rw_txn()?;
+        for operation in self.pending_operations.drain(..) {
+            txn = operation(txn)?;
+        }
+        txn.commit()?;
+        self.num_committed_batches += 1;
+        Ok(())
+    }
+}
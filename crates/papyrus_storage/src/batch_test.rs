@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::hash::StarkFelt;
+
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::test_utils::get_test_storage;
+use crate::StorageError;
+
+fn header(block_number: u64) -> BlockHeader {
+    BlockHeader {
+        block_hash: BlockHash(StarkFelt::from(block_number)),
+        block_number: BlockNumber(block_number),
+        ..BlockHeader::default()
+    }
+}
+
+#[test]
+fn with_batch_commits_every_max_ops_writes() {
+    const NUM_HEADERS: u64 = 10_000;
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    let last_txn_id_before = reader.db_stats().unwrap().last_txn_id;
+
+    let num_committed_batches = writer
+        .with_batch(1000, |batch| {
+            for block_number in 0..NUM_HEADERS {
+                let header = header(block_number);
+                batch.write(move |txn| txn.append_header(BlockNumber(block_number), &header))?;
+            }
+            assert_eq!(batch.num_committed_batches(), 10);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(num_committed_batches, 10);
+    assert_eq!(reader.db_stats().unwrap().last_txn_id - last_txn_id_before, 10);
+
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(NUM_HEADERS));
+    for block_number in 0..NUM_HEADERS {
+        assert_eq!(
+            txn.get_block_header(BlockNumber(block_number)).unwrap(),
+            Some(header(block_number))
+        );
+    }
+}
+
+#[test]
+fn with_batch_commits_the_remaining_writes() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    let num_committed_batches = writer
+        .with_batch(2, |batch| {
+            for block_number in 0..3 {
+                let header = header(block_number);
+                batch.write(move |txn| txn.append_header(BlockNumber(block_number), &header))?;
+            }
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(num_committed_batches, 2);
+    assert_eq!(reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(3));
+
+    // Nothing is committed for an empty batch.
+    assert_eq!(writer.with_batch(2, |_batch| Ok(())).unwrap(), 0);
+}
+
+#[test]
+fn failed_batch_is_not_committed() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    let result = writer.with_batch(2, |batch| {
+        for block_number in 0..3 {
+            let header = header(block_number);
+            batch.write(move |txn| txn.append_header(BlockNumber(block_number), &header))?;
+        }
+        // Fails the batch of the third header, since block 4 isn't the next block.
+        batch.write(|txn| txn.append_header(BlockNumber(4), &header(4)))
+    });
+    assert_matches!(result, Err(StorageError::MarkerMismatch { .. }));
+
+    // Only the first batch was committed.
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(2));
+    assert_eq!(txn.get_block_header(BlockNumber(2)).unwrap(), None);
+}
+
+#[test]
+fn timed_batch_commits_when_its_interval_elapses() {
+    const MAX_BATCH_INTERVAL: Duration = Duration::from_millis(50);
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    let num_committed_batches = writer
+        .with_timed_batch(1000, MAX_BATCH_INTERVAL, |batch| {
+            for block_number in 0..2 {
+                let header = header(block_number);
+                batch.write(move |txn| txn.append_header(BlockNumber(block_number), &header))?;
+            }
+            assert_eq!(batch.num_committed_batches(), 0);
+
+            // The batch is committed although it holds fewer than max_ops operations.
+            std::thread::sleep(MAX_BATCH_INTERVAL);
+            batch.write(|txn| txn.append_header(BlockNumber(2), &header(2)))?;
+            assert_eq!(batch.num_committed_batches(), 1);
+            assert_eq!(reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(3));
+
+            // The interval of the next batch starts at its first operation.
+            batch.write(|txn| txn.append_header(BlockNumber(3), &header(3)))?;
+            assert_eq!(batch.num_committed_batches(), 1);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(num_committed_batches, 2);
+    assert_eq!(reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(4));
+}
+
+#[test]
+fn timed_batch_is_committed_when_due_without_another_write() {
+    const MAX_BATCH_INTERVAL: Duration = Duration::from_millis(50);
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    writer
+        .with_timed_batch(1000, MAX_BATCH_INTERVAL, |batch| {
+            // Nothing is due for an empty batch.
+            assert!(!batch.commit_if_due()?);
+
+            batch.write(|txn| txn.append_header(BlockNumber(0), &header(0)))?;
+            assert!(!batch.commit_if_due()?);
+            assert_eq!(reader.begin_ro_txn()?.get_header_marker()?, BlockNumber(0));
+
+            // The writing loop is idle until the interval elapses.
+            std::thread::sleep(MAX_BATCH_INTERVAL);
+            assert!(batch.commit_if_due()?);
+            assert_eq!(batch.num_committed_batches(), 1);
+            assert_eq!(reader.begin_ro_txn()?.get_header_marker()?, BlockNumber(1));
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+fn commit_and_read_sees_the_committed_writes() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    let txn = writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &header(0))
+        .unwrap()
+        .commit_and_read(&reader)
+        .unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(1));
+    assert_eq!(txn.get_block_header(BlockNumber(0)).unwrap(), Some(header(0)));
+
+    writer
+        .with_batch(1000, |batch| {
+            batch.write(|txn| txn.append_header(BlockNumber(1), &header(1)))?;
+            let batch_txn = batch.commit_and_read(&reader)?;
+            assert_eq!(batch.num_committed_batches(), 1);
+            assert_eq!(batch_txn.get_header_marker()?, BlockNumber(2));
+            assert_eq!(batch_txn.get_block_header(BlockNumber(1))?, Some(header(1)));
+            Ok(())
+        })
+        .unwrap();
+    // The transaction keeps seeing the storage as it was when it began.
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(1));
+}
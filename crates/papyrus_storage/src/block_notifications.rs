@@ -0,0 +1,48 @@
+//! Notifications about the blocks that are committed to the storage.
+//!
+//! A [`BlockCommitted`] notification is broadcast for each block whose header was committed, by
+//! any writer of the storage and not only by the sync. The notifications are broadcast through a
+//! channel that is given to the storage with [`StorageBuilder::notify_block_commits`], and
+//! consumers get them by subscribing to it. Blocks that are reverted aren't notified about.
+//!
+//! [`StorageBuilder::notify_block_commits`]: crate::StorageBuilder::notify_block_commits
+
+#[cfg(test)]
+#[path = "block_notifications_test.rs"]
+mod block_notifications_test;
+
+use starknet_api::block::BlockNumber;
+use tokio::sync::broadcast;
+
+/// A notification that the header of a block was committed to the storage.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BlockCommitted {
+    /// The number of the committed block.
+    pub block_number: BlockNumber,
+}
+
+// Notifies about the blocks that a write transaction commits: the blocks between the header marker
+// when the transaction began and the header marker when it's committed.
+pub(crate) struct BlockCommittedNotifier {
+    sender: broadcast::Sender<BlockCommitted>,
+    header_marker_at_begin: BlockNumber,
+}
+
+impl BlockCommittedNotifier {
+    pub(crate) fn new(
+        sender: broadcast::Sender<BlockCommitted>,
+        header_marker_at_begin: BlockNumber,
+    ) -> Self {
+        Self { sender, header_marker_at_begin }
+    }
+
+    // Called after the transaction was committed successfully.
+    pub(crate) fn notify(self, committed_header_marker: BlockNumber) {
+        let mut block_number = self.header_marker_at_begin;
+        while block_number < committed_header_marker {
+            // Sending fails only when there are no subscribers, which isn't an error.
+            let _ = self.sender.send(BlockCommitted { block_number });
+            block_number = block_number.next();
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use assert_matches::assert_matches;
+use starknet_api::block::{BlockHeader, BlockNumber};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::TryRecvError;
+
+use crate::block_notifications::BlockCommitted;
+use crate::header::HeaderStorageWriter;
+use crate::test_utils::get_test_config;
+use crate::StorageBuilder;
+
+#[test]
+fn committed_block_is_notified_once() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (sender, mut receiver) = broadcast::channel(10);
+    let mut builder = StorageBuilder::new(config);
+    builder.notify_block_commits(sender);
+    let (_reader, mut writer) = builder.open().unwrap();
+
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+    assert_eq!(receiver.try_recv().unwrap(), BlockCommitted { block_number: BlockNumber(0) });
+    assert_matches!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+    // A commit that doesn't advance the header marker isn't notified about.
+    writer.begin_rw_txn().unwrap().commit().unwrap();
+    assert_matches!(receiver.try_recv(), Err(TryRecvError::Empty));
+}
@@ -7,7 +7,7 @@ use test_utils::{get_test_block, get_test_body};
 
 use crate::body::events::ThinTransactionOutput;
 use crate::body::{BodyStorageReader, BodyStorageWriter, TransactionIndex};
-use crate::db::{DbError, KeyAlreadyExistsError};
+use crate::db::{DbError, KeyAlreadyExistsError, PageStart};
 use crate::test_utils::{get_test_storage, get_test_storage_by_scope};
 use crate::{StorageError, StorageScope, StorageWriter};
 
@@ -381,6 +381,55 @@ async fn revert_transactions() {
     );
 }
 
+#[test]
+fn transactions_are_paged_through_once_while_blocks_are_appended() {
+    const PAGE_SIZE: usize = 3;
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    let body = get_test_block(8, None, None, None).body;
+    let get_body = |transactions: std::ops::Range<usize>| BlockBody {
+        transactions: body.transactions[transactions.clone()].to_vec(),
+        transaction_outputs: body.transaction_outputs[transactions.clone()].to_vec(),
+        transaction_hashes: body.transaction_hashes[transactions].to_vec(),
+    };
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_body(BlockNumber(0), get_body(0..4))
+        .unwrap()
+        .append_body(BlockNumber(1), get_body(4..6))
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let mut paged_transactions = Vec::new();
+    let mut continuation_token = None;
+    let first_index = TransactionIndex(BlockNumber(0), TransactionOffsetInBlock(0));
+    loop {
+        let txn = reader.begin_ro_txn().unwrap();
+        let start = match &continuation_token {
+            Some(token) => PageStart::After(token),
+            None => PageStart::Key(&first_index),
+        };
+        let (page, next_token) = txn.get_transactions_page(start, PAGE_SIZE).unwrap();
+        paged_transactions.extend(page.into_iter().map(|(_, transaction)| transaction));
+        if paged_transactions.len() == PAGE_SIZE {
+            // A block appended between the pages is paged through after the blocks before it.
+            writer
+                .begin_rw_txn()
+                .unwrap()
+                .append_body(BlockNumber(2), get_body(6..8))
+                .unwrap()
+                .commit()
+                .unwrap();
+        }
+        continuation_token = next_token;
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+    assert_eq!(paged_transactions, body.transactions);
+}
+
 fn append_2_bodies(writer: &mut StorageWriter) {
     writer
         .begin_rw_txn()
@@ -8,7 +8,7 @@
 //! use papyrus_storage::open_storage;
 //! use papyrus_storage::body::TransactionIndex;
 //! use papyrus_storage::body::events::{EventIndex, EventsReader};
-//! # use papyrus_storage::{db::DbConfig, StorageConfig};
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
 //! # use starknet_api::core::ChainId;
 //! # use starknet_api::block::BlockNumber;
 //! use starknet_api::core::ContractAddress;
@@ -24,6 +24,10 @@
 //! #     min_size: 1 << 20,    // 1MB
 //! #     max_size: 1 << 35,    // 32GB
 //! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
 //! # };
 //! # let storage_config = StorageConfig{db_config, ..Default::default()};
 //! // The API allows read-only interactions with the events. To write events, use the body writer.
@@ -10,7 +10,7 @@
 //!  # Example
 //! ```
 //! use papyrus_storage::open_storage;
-//! # use papyrus_storage::{db::DbConfig, StorageConfig};
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
 //! # use starknet_api::core::ChainId;
 //! use starknet_api::block::{Block, BlockNumber};
 //! use papyrus_storage::body::{BodyStorageReader, BodyStorageWriter};
@@ -24,6 +24,10 @@
 //! #     min_size: 1 << 20,    // 1MB
 //! #     max_size: 1 << 35,    // 32GB
 //! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
 //! # };
 //! let block = Block::default();
 //! # let storage_config = StorageConfig{db_config, ..Default::default()};
@@ -61,7 +65,8 @@ use tracing::debug;
 
 use crate::body::events::{EventIndex, ThinTransactionOutput};
 use crate::db::serialization::{NoVersionValueWrapper, StorageSerde};
-use crate::db::{DbTransaction, TableHandle, TransactionKind, RW};
+use crate::db::{DbTransaction, Page, PageStart, TableHandle, TransactionKind, RW};
+use crate::prune::PruneStorageReader;
 use crate::{MarkerKind, MarkersTable, StorageError, StorageResult, StorageScope, StorageTxn};
 
 type TransactionsTable<'env> =
@@ -115,6 +120,15 @@ pub trait BodyStorageReader {
         tx_index: &TransactionIndex,
     ) -> StorageResult<Option<TransactionHash>>;
 
+    /// Returns up to `limit` transactions from the given start on, ordered by their indices, and a
+    /// token for getting the next page with [`PageStart::After`] if there are more transactions.
+    /// Transactions that are stored between the pages don't shift the next pages.
+    fn get_transactions_page(
+        &self,
+        start: PageStart<'_, TransactionIndex>,
+        limit: usize,
+    ) -> StorageResult<Page<TransactionIndex, Transaction>>;
+
     /// Returns the transactions and their execution status of the block with the given number.
     fn get_block_transactions(
         &self,
@@ -134,7 +148,8 @@ pub trait BodyStorageReader {
     ) -> StorageResult<Option<Vec<ThinTransactionOutput>>>;
 }
 
-type RevertedBlockBody =
+/// The transactions, transaction outputs, transaction hashes and events of a reverted block body.
+pub type RevertedBlockBody =
     (Vec<Transaction>, Vec<ThinTransactionOutput>, Vec<TransactionHash>, Vec<Vec<EventContent>>);
 
 /// Interface for updating data related to the block body.
@@ -159,7 +174,7 @@ where
 impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
     fn get_body_marker(&self) -> StorageResult<BlockNumber> {
         let markers_table = self.open_table(&self.tables.markers)?;
-        Ok(markers_table.get(&self.txn, &MarkerKind::Body)?.unwrap_or_default())
+        Ok(markers_table.get_or_default(&self.txn, &MarkerKind::Body)?)
     }
 
     fn get_transaction(
@@ -224,6 +239,16 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
         Ok(idx)
     }
 
+    fn get_transactions_page(
+        &self,
+        start: PageStart<'_, TransactionIndex>,
+        limit: usize,
+    ) -> StorageResult<Page<TransactionIndex, Transaction>> {
+        let transactions_table = self.open_table(&self.tables.transactions)?;
+        let page = transactions_table.scan_page(&self.txn, start, limit)?;
+        Ok(page)
+    }
+
     fn get_block_transactions(
         &self,
         block_number: BlockNumber,
@@ -259,7 +284,8 @@ impl<'env, Mode: TransactionKind> StorageTxn<'env, Mode> {
         block_number: BlockNumber,
         table: TableHandle<'env, TransactionIndex, NoVersionValueWrapper<V>>,
     ) -> StorageResult<Option<Vec<V>>> {
-        if self.get_body_marker()? <= block_number {
+        // Pruned blocks are reported as missing, like blocks that weren't stored yet.
+        if self.get_body_marker()? <= block_number || block_number < self.get_pruned_marker()? {
             return Ok(None);
         }
         let mut cursor = table.cursor(&self.txn)?;
@@ -383,6 +409,45 @@ impl<'env> BodyStorageWriter for StorageTxn<'env, RW> {
     }
 }
 
+impl<'env> StorageTxn<'env, RW> {
+    // Deletes the transactions, transaction outputs, transaction hashes and events of the given
+    // block without changing the body marker, so that the block still counts as stored.
+    pub(crate) fn delete_body(&self, block_number: BlockNumber) -> StorageResult<()> {
+        if self.scope == StorageScope::StateOnly {
+            return Ok(());
+        }
+        let Some(transaction_outputs) = self.get_block_transaction_outputs(block_number)? else {
+            return Ok(());
+        };
+
+        let transactions_table = self.open_table(&self.tables.transactions)?;
+        let transaction_outputs_table = self.open_table(&self.tables.transaction_outputs)?;
+        let transaction_hash_to_idx_table =
+            self.open_table(&self.tables.transaction_hash_to_idx)?;
+        let transaction_idx_to_hash_table =
+            self.open_table(&self.tables.transaction_idx_to_hash)?;
+        let events_table = self.open_table(&self.tables.events)?;
+
+        for (offset, tx_output) in transaction_outputs.iter().enumerate() {
+            let tx_index = TransactionIndex(block_number, TransactionOffsetInBlock(offset));
+            for (index, from_address) in
+                tx_output.events_contract_addresses_as_ref().iter().enumerate()
+            {
+                let key =
+                    (*from_address, EventIndex(tx_index, EventIndexInTransactionOutput(index)));
+                events_table.delete(&self.txn, &key)?;
+            }
+            if let Some(tx_hash) = self.get_transaction_hash_by_idx(&tx_index)? {
+                transaction_hash_to_idx_table.delete(&self.txn, &tx_hash)?;
+            }
+            transactions_table.delete(&self.txn, &tx_index)?;
+            transaction_outputs_table.delete(&self.txn, &tx_index)?;
+            transaction_idx_to_hash_table.delete(&self.txn, &tx_index)?;
+        }
+        Ok(())
+    }
+}
+
 fn write_transactions<'env>(
     block_body: &BlockBody,
     txn: &DbTransaction<'env, RW>,
@@ -459,7 +524,7 @@ fn update_marker<'env>(
     block_number: BlockNumber,
 ) -> StorageResult<()> {
     // Make sure marker is consistent.
-    let body_marker = markers_table.get(txn, &MarkerKind::Body)?.unwrap_or_default();
+    let body_marker = markers_table.get_or_default(txn, &MarkerKind::Body)?;
     if body_marker != block_number {
         return Err(StorageError::MarkerMismatch { expected: body_marker, found: block_number });
     };
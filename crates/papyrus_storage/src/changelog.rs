@@ -0,0 +1,282 @@
+//! Interface for copying the changes of the storage to a replica, to back it up incrementally.
+//!
+//! While [`StorageConfig::enable_changelog`] is set, each write transaction records the changes it
+//! makes, to the database tables and to the files of the storage, in a changelog table. Read the
+//! changes that were committed after a given transaction with [`StorageTxn::changeset_since`],
+//! and apply them to a replica with [`StorageWriter::apply_changeset`]. The replica applies the
+//! changes of each transaction atomically, so an interrupted backup is resumed by reading the
+//! changes since the last transaction that was applied.
+//!
+//! The changelog keeps every change, including the contents of the values, so it grows with the
+//! storage. It's disabled by default.
+//! # Example
+//! ```
+//! use papyrus_storage::body::BodyStorageWriter;
+//! use papyrus_storage::header::HeaderStorageWriter;
+//! use papyrus_storage::open_storage;
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
+//! # use starknet_api::core::ChainId;
+//! use starknet_api::block::{BlockBody, BlockHeader, BlockNumber};
+//!
+//! # let dir_handle = tempfile::tempdir().unwrap();
+//! # let replica_dir_handle = tempfile::tempdir().unwrap();
+//! # let db_config = DbConfig {
+//! #     path_prefix: dir_handle.path().to_path_buf(),
+//! #     chain_id: ChainId("SN_MAIN".to_owned()),
+//! #     enforce_file_exists: false,
+//! #     min_size: 1 << 20,    // 1MB
+//! #     max_size: 1 << 35,    // 32GB
+//! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
+//! # };
+//! # let replica_db_config =
+//! #     DbConfig { path_prefix: replica_dir_handle.path().to_path_buf(), ..db_config.clone() };
+//! # let storage_config = StorageConfig{db_config, enable_changelog: true, ..Default::default()};
+//! # let replica_config = StorageConfig{db_config: replica_db_config, ..Default::default()};
+//! let (reader, mut writer) = open_storage(storage_config)?;
+//! let (_replica_reader, mut replica_writer) = open_storage(replica_config)?;
+//! writer.begin_rw_txn()?.append_header(BlockNumber(0), &BlockHeader::default())?.commit()?;
+//! // Copy all the changes to the replica, and remember the last transaction that was copied.
+//! let last_applied_txn_id =
+//!     replica_writer.apply_changeset(reader.begin_ro_txn()?.changeset_since(0)?)?;
+//!
+//! writer.begin_rw_txn()?.append_body(BlockNumber(0), BlockBody::default())?.commit()?;
+//! // Copy only the changes that were made since.
+//! let txn = reader.begin_ro_txn()?;
+//! replica_writer.apply_changeset(txn.changeset_since(last_applied_txn_id.unwrap())?)?;
+//! # Ok::<(), papyrus_storage::StorageError>(())
+//! ```
+//!
+//! [`StorageConfig::enable_changelog`]: crate::StorageConfig::enable_changelog
+//! [`StorageTxn::changeset_since`]: crate::StorageTxn::changeset_since
+
+#[cfg(test)]
+#[path = "changelog_test.rs"]
+mod changelog_test;
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::serialization::{NoVersionValueWrapper, StorageSerde, StorageSerdeError};
+use crate::db::{DbCursor, RO, RW};
+use crate::mmap_file::LocationInFile;
+use crate::{OffsetKind, StorageError, StorageResult, StorageTxn, StorageWriter};
+
+/// The id of a write transaction of the storage. The ids increase with each committed transaction.
+/// The id of the last committed transaction is [`DbEnvStats::last_txn_id`].
+///
+/// [`DbEnvStats::last_txn_id`]: crate::db::db_stats::DbEnvStats::last_txn_id
+pub type TxnId = u64;
+
+// The key of an entry in the changelog: the transaction that made the change and the number of
+// the change within the transaction.
+pub(crate) type ChangelogKey = (TxnId, u32);
+
+pub(crate) const CHANGELOG_TABLE_NAME: &str = "changelog";
+
+// A cursor of the changelog table.
+type ChangelogCursor<'txn> =
+    DbCursor<'txn, RO, ChangelogKey, NoVersionValueWrapper<ChangelogEntry>>;
+
+/// A change that a write transaction made to the storage.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Change {
+    /// The transaction that made the change.
+    pub txn_id: TxnId,
+    /// The change itself.
+    pub operation: ChangeOperation,
+}
+
+/// The kinds of changes to the storage.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ChangeOperation {
+    /// A key of a table was set to a value.
+    Put {
+        /// The name of the table.
+        table: String,
+        /// The serialized key.
+        key: Vec<u8>,
+        /// The serialized value.
+        value: Vec<u8>,
+    },
+    /// A key of a table was deleted.
+    Delete {
+        /// The name of the table.
+        table: String,
+        /// The serialized key.
+        key: Vec<u8>,
+    },
+    /// Bytes were appended to a file of the storage, starting at the given offset.
+    FileAppend {
+        /// The file that was appended to.
+        file: OffsetKind,
+        /// The offset in the file the bytes were written at.
+        offset: usize,
+        /// The bytes that were appended.
+        bytes: Vec<u8>,
+    },
+}
+
+// An entry of the changelog. Appends to the files are recorded by their location, and their bytes
+// are read from the file when the changeset is read.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum ChangelogEntry {
+    Put { table: String, key: Vec<u8>, value: Vec<u8> },
+    Delete { table: String, key: Vec<u8> },
+    FileAppend { file: OffsetKind, location: LocationInFile },
+}
+
+impl<'env> StorageTxn<'env, RO> {
+    /// Returns the changes that were committed after the transaction with the given id, ordered by
+    /// the transactions that made them. The changes are read from the snapshot of this transaction
+    /// as the iterator advances. Only the transactions that were committed while the changelog was
+    /// enabled are recorded.
+    pub fn changeset_since<'txn>(
+        &'txn self,
+        txn_id: TxnId,
+    ) -> StorageResult<ChangesetIter<'txn, 'env>> {
+        let changelog_table = self.open_table(&self.tables.changelog)?;
+        let mut cursor = changelog_table.cursor(&self.txn)?;
+        let current = cursor.lower_bound(&(txn_id + 1, 0))?;
+        Ok(ChangesetIter { txn: self, cursor, current })
+    }
+}
+
+/// An iterator over the changes of a changeset, returned by [`StorageTxn::changeset_since`]. The
+/// contents of the changes are read when they're reached, so they're never all held in memory.
+pub struct ChangesetIter<'txn, 'env> {
+    txn: &'txn StorageTxn<'env, RO>,
+    cursor: ChangelogCursor<'txn>,
+    current: Option<(ChangelogKey, ChangelogEntry)>,
+}
+
+impl ChangesetIter<'_, '_> {
+    fn next_change(&mut self) -> StorageResult<Option<Change>> {
+        let Some(((txn_id, _), entry)) = self.current.take() else { return Ok(None) };
+        let operation = match entry {
+            ChangelogEntry::Put { table, key, value } => ChangeOperation::Put { table, key, value },
+            ChangelogEntry::Delete { table, key } => ChangeOperation::Delete { table, key },
+            ChangelogEntry::FileAppend { file, location } => ChangeOperation::FileAppend {
+                file,
+                offset: location.offset(),
+                bytes: self.txn.file_handlers.get_bytes(file, location)?,
+            },
+        };
+        self.current = self.cursor.next()?;
+        Ok(Some(Change { txn_id, operation }))
+    }
+}
+
+impl Iterator for ChangesetIter<'_, '_> {
+    type Item = StorageResult<Change>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_change().transpose()
+    }
+}
+
+impl StorageWriter {
+    /// Applies changes that were read with [`StorageTxn::changeset_since`] from another storage.
+    /// The changes of each transaction are committed together, so if applying the changeset or
+    /// reading it fails, the storage holds the changes of all the transactions before the one that
+    /// failed. Returns the id of the last transaction that was applied, to resume from, or None if
+    /// the changeset is empty.
+    pub fn apply_changeset(
+        &mut self,
+        changeset: impl IntoIterator<Item = StorageResult<Change>>,
+    ) -> StorageResult<Option<TxnId>> {
+        let table_names = self.table_names();
+        let mut last_applied_txn_id = None;
+        let mut changeset = changeset.into_iter().peekable();
+        while let Some(change) = changeset.next() {
+            let Change { txn_id, operation } = change?;
+            let txn = self.begin_rw_txn()?;
+            txn.apply_change(operation, &table_names)?;
+            while let Some(change) = changeset
+                .next_if(|change| change.as_ref().map_or(true, |change| change.txn_id == txn_id))
+            {
+                txn.apply_change(change?.operation, &table_names)?;
+            }
+            txn.commit()?;
+            last_applied_txn_id = Some(txn_id);
+        }
+        Ok(last_applied_txn_id)
+    }
+}
+
+impl<'env> StorageTxn<'env, RW> {
+    fn apply_change(
+        &self,
+        operation: ChangeOperation,
+        table_names: &[&'static str],
+    ) -> StorageResult<()> {
+        let table_name = |table: String| {
+            table_names
+                .iter()
+                .find(|name| **name == table)
+                .copied()
+                .ok_or(StorageError::UnknownTable { table_name: table })
+        };
+        match operation {
+            ChangeOperation::Put { table, key, value } => {
+                self.txn.put_raw(table_name(table)?, key, value)?
+            }
+            ChangeOperation::Delete { table, key } => {
+                self.txn.delete_raw(table_name(table)?, key)?
+            }
+            ChangeOperation::FileAppend { file, offset, bytes } => {
+                let file_offset = self.file_handlers.offset(file);
+                if file_offset != offset {
+                    return Err(StorageError::FileOffsetMismatch { file, offset, file_offset });
+                }
+                let location = self.file_handlers.append_bytes(file, &bytes);
+                self.txn.record_change(&ChangelogEntry::FileAppend { file, location })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StorageSerde for ChangelogEntry {
+    fn serialize_into(&self, res: &mut impl std::io::Write) -> Result<(), StorageSerdeError> {
+        match self {
+            ChangelogEntry::Put { table, key, value } => {
+                0u8.serialize_into(res)?;
+                table.serialize_into(res)?;
+                key.serialize_into(res)?;
+                value.serialize_into(res)
+            }
+            ChangelogEntry::Delete { table, key } => {
+                1u8.serialize_into(res)?;
+                table.serialize_into(res)?;
+                key.serialize_into(res)
+            }
+            ChangelogEntry::FileAppend { file, location } => {
+                2u8.serialize_into(res)?;
+                file.serialize_into(res)?;
+                location.serialize_into(res)
+            }
+        }
+    }
+
+    fn deserialize_from(bytes: &mut impl std::io::Read) -> Option<Self> {
+        match u8::deserialize_from(bytes)? {
+            0 => Some(ChangelogEntry::Put {
+                table: String::deserialize_from(bytes)?,
+                key: Vec::deserialize_from(bytes)?,
+                value: Vec::deserialize_from(bytes)?,
+            }),
+            1 => Some(ChangelogEntry::Delete {
+                table: String::deserialize_from(bytes)?,
+                key: Vec::deserialize_from(bytes)?,
+            }),
+            2 => Some(ChangelogEntry::FileAppend {
+                file: OffsetKind::deserialize_from(bytes)?,
+                location: LocationInFile::deserialize_from(bytes)?,
+            }),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use assert_matches::assert_matches;
+use cairo_lang_starknet::casm_contract_class::CasmContractClass;
+use indexmap::IndexMap;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::hash::StarkFelt;
+use starknet_api::stark_felt;
+use starknet_api::state::StateNumber;
+use test_utils::{get_test_body, get_test_state_diff};
+
+use crate::body::{BodyStorageReader, BodyStorageWriter};
+use crate::changelog::{Change, ChangeOperation};
+use crate::compiled_class::{CasmStorageReader, CasmStorageWriter};
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::state::{StateStorageReader, StateStorageWriter};
+use crate::test_utils::get_test_config;
+use crate::{open_storage, OffsetKind, StorageConfig, StorageError, StorageReader, StorageResult};
+
+// Asserts that the replica holds the same data as the storage, in all the tables but the
+// changelog.
+fn assert_same_state(reader: &StorageReader, replica_reader: &StorageReader) {
+    let tables_stats = reader.db_tables_stats().unwrap().tables_stats;
+    let replica_tables_stats = replica_reader.db_tables_stats().unwrap().tables_stats;
+    for (name, stats) in tables_stats.iter().filter(|(name, _)| *name != "changelog") {
+        assert_eq!(stats.entries, replica_tables_stats[name].entries, "table {name}");
+    }
+    assert_eq!(reader.get_markers().unwrap(), replica_reader.get_markers().unwrap());
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let replica_txn = replica_reader.begin_ro_txn().unwrap();
+    let state_marker = txn.get_state_marker().unwrap();
+    for block_number in (0..txn.get_header_marker().unwrap().0).map(BlockNumber) {
+        assert_eq!(
+            txn.get_block_header(block_number).unwrap(),
+            replica_txn.get_block_header(block_number).unwrap()
+        );
+        assert_eq!(
+            txn.get_block_transactions(block_number).unwrap(),
+            replica_txn.get_block_transactions(block_number).unwrap()
+        );
+        assert_eq!(
+            txn.get_state_diff(block_number).unwrap(),
+            replica_txn.get_state_diff(block_number).unwrap()
+        );
+    }
+    let state_number = StateNumber::right_before_block(state_marker);
+    for class_hash in txn.get_state_diff(BlockNumber(0)).unwrap().unwrap().declared_classes.keys() {
+        assert_eq!(
+            txn.get_state_reader()
+                .unwrap()
+                .get_class_definition_at(state_number, class_hash)
+                .unwrap(),
+            replica_txn
+                .get_state_reader()
+                .unwrap()
+                .get_class_definition_at(state_number, class_hash)
+                .unwrap()
+        );
+        assert_eq!(txn.get_casm(class_hash).unwrap(), replica_txn.get_casm(class_hash).unwrap());
+    }
+}
+
+#[test]
+fn replica_catches_up_with_changesets() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) =
+        open_storage(StorageConfig { enable_changelog: true, ..config }).unwrap();
+    let (replica_config, _replica_temp_dir) = get_test_config(None);
+    let (replica_reader, mut replica_writer) = open_storage(replica_config).unwrap();
+
+    let state_diff = get_test_state_diff();
+    let class_hash = *state_diff.declared_classes.keys().next().unwrap();
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .append_body(BlockNumber(0), get_test_body(2, None, None, None))
+        .unwrap()
+        .append_state_diff(BlockNumber(0), state_diff, IndexMap::new())
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // Copy everything that was written until now.
+    let txn = reader.begin_ro_txn().unwrap();
+    let last_applied_txn_id =
+        replica_writer.apply_changeset(txn.changeset_since(0).unwrap()).unwrap().unwrap();
+    assert_eq!(last_applied_txn_id, reader.db_stats().unwrap().last_txn_id);
+    assert_same_state(&reader, &replica_reader);
+
+    // Write more, including a deletion and appends to a file.
+    let header = BlockHeader {
+        block_hash: BlockHash(stark_felt!("0x1")),
+        block_number: BlockNumber(1),
+        ..BlockHeader::default()
+    };
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_casm(&class_hash, &CasmContractClass::default())
+        .unwrap()
+        .append_header(BlockNumber(1), &header)
+        .unwrap()
+        .commit()
+        .unwrap();
+    writer.begin_rw_txn().unwrap().revert_header(BlockNumber(1)).unwrap().0.commit().unwrap();
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(1), &header)
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // Copy only the changes since the last copy.
+    let txn = reader.begin_ro_txn().unwrap();
+    let changeset = txn
+        .changeset_since(last_applied_txn_id)
+        .unwrap()
+        .collect::<StorageResult<Vec<_>>>()
+        .unwrap();
+    assert!(changeset.iter().all(|change| change.txn_id > last_applied_txn_id));
+    assert!(changeset.iter().any(|change| matches!(
+        change.operation,
+        ChangeOperation::FileAppend { file: OffsetKind::Casm, .. }
+    )));
+    assert!(
+        changeset.iter().any(|change| matches!(change.operation, ChangeOperation::Delete { .. }))
+    );
+    let last_applied_txn_id =
+        replica_writer.apply_changeset(changeset.into_iter().map(Ok)).unwrap();
+    assert_eq!(last_applied_txn_id, Some(reader.db_stats().unwrap().last_txn_id));
+    assert_same_state(&reader, &replica_reader);
+    assert!(replica_reader.begin_ro_txn().unwrap().get_casm(&class_hash).unwrap().is_some());
+
+    // Nothing changed since.
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.changeset_since(last_applied_txn_id.unwrap()).unwrap().count(), 0);
+    assert_eq!(replica_writer.apply_changeset(Vec::new()).unwrap(), None);
+}
+
+#[test]
+fn changelog_is_disabled_by_default() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) = open_storage(config).unwrap();
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+    assert_eq!(reader.begin_ro_txn().unwrap().changeset_since(0).unwrap().count(), 0);
+}
+
+#[test]
+fn apply_changeset_rejects_inconsistent_changes() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (_reader, mut writer) = open_storage(config).unwrap();
+
+    let unknown_table = Change {
+        txn_id: 1,
+        operation: ChangeOperation::Put {
+            table: "unknown_table".to_owned(),
+            key: vec![0],
+            value: vec![0],
+        },
+    };
+    assert_matches!(
+        writer.apply_changeset([Ok(unknown_table)]),
+        Err(StorageError::UnknownTable { table_name }) if table_name == "unknown_table"
+    );
+
+    // The file ends at offset 0, so an append at another offset is missing the data before it.
+    let append_after_gap = Change {
+        txn_id: 1,
+        operation: ChangeOperation::FileAppend {
+            file: OffsetKind::Casm,
+            offset: 10,
+            bytes: vec![1, 2, 3],
+        },
+    };
+    assert_matches!(
+        writer.apply_changeset([Ok(append_after_gap)]),
+        Err(StorageError::FileOffsetMismatch {
+            file: OffsetKind::Casm,
+            offset: 10,
+            file_offset: 0
+        })
+    );
+}
@@ -6,7 +6,7 @@
 //! # Example
 //! ```
 //! use papyrus_storage::open_storage;
-//! # use papyrus_storage::{db::DbConfig, StorageConfig};
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
 //! # use starknet_api::core::ChainId;
 //! use cairo_lang_starknet::casm_contract_class::CasmContractClass;
 //! use papyrus_storage::compiled_class::{CasmStorageReader, CasmStorageWriter};
@@ -21,6 +21,10 @@
 //! #     min_size: 1 << 20,    // 1MB
 //! #     max_size: 1 << 35,    // 32GB
 //! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
 //! # };
 //! # let storage_config = StorageConfig{db_config, ..Default::default()};
 //! let (reader, mut writer) = open_storage(storage_config)?;
@@ -42,6 +46,7 @@ use papyrus_proc_macros::latency_histogram;
 use starknet_api::block::BlockNumber;
 use starknet_api::core::ClassHash;
 
+use crate::changelog::ChangelogEntry;
 use crate::db::serialization::NoVersionValueWrapper;
 use crate::db::{DbTransaction, TableHandle, TransactionKind, RW};
 use crate::mmap_file::LocationInFile;
@@ -77,7 +82,7 @@ impl<'env, Mode: TransactionKind> CasmStorageReader for StorageTxn<'env, Mode> {
 
     fn get_compiled_class_marker(&self) -> StorageResult<BlockNumber> {
         let markers_table = self.open_table(&self.tables.markers)?;
-        Ok(markers_table.get(&self.txn, &MarkerKind::CompiledClass)?.unwrap_or_default())
+        Ok(markers_table.get_or_default(&self.txn, &MarkerKind::CompiledClass)?)
     }
 }
 
@@ -90,6 +95,7 @@ impl<'env> CasmStorageWriter for StorageTxn<'env, RW> {
         let file_offset_table = self.txn.open_table(&self.tables.file_offsets)?;
 
         let location = self.file_handlers.append_casm(casm);
+        self.txn.record_change(&ChangelogEntry::FileAppend { file: OffsetKind::Casm, location })?;
         casm_table.insert(&self.txn, class_hash, &location)?;
         file_offset_table.upsert(&self.txn, &OffsetKind::Casm, &location.next_offset())?;
         update_marker(
@@ -112,7 +118,7 @@ fn update_marker<'env>(
 ) -> StorageResult<()> {
     // The marker needs to update if we reached the last class from the state diff. We can continue
     // advancing it if the next blocks don't have declared classes.
-    let mut block_number = markers_table.get(txn, &MarkerKind::CompiledClass)?.unwrap_or_default();
+    let mut block_number = markers_table.get_or_default(txn, &MarkerKind::CompiledClass)?;
     loop {
         let Some(state_diff_location) = state_diffs_table.get(txn, &block_number)? else {
             break;
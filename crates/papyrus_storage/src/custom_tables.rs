@@ -0,0 +1,160 @@
+//! Interface for handling tables that are added to the storage by its users, on top of the
+//! built-in tables.
+//!
+//! Register the tables with [`StorageBuilder::add_table`] before opening the storage, and use the
+//! returned [`CustomTable`] to read and write it with [`CustomTablesStorageReader`] and
+//! [`CustomTablesStorageWriter`] using a [`StorageTxn`].
+//! # Example
+//! ```
+//! use papyrus_storage::custom_tables::{
+//!     CustomTable,
+//!     CustomTablesStorageReader,
+//!     CustomTablesStorageWriter,
+//! };
+//! use papyrus_storage::StorageBuilder;
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
+//! # use starknet_api::core::ChainId;
+//! use starknet_api::block::BlockNumber;
+//!
+//! # let dir_handle = tempfile::tempdir().unwrap();
+//! # let dir = dir_handle.path().to_path_buf();
+//! # let db_config = DbConfig {
+//! #     path_prefix: dir,
+//! #     chain_id: ChainId("SN_MAIN".to_owned()),
+//! #     enforce_file_exists: false,
+//! #     min_size: 1 << 20,    // 1MB
+//! #     max_size: 1 << 35,    // 32GB
+//! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
+//! # };
+//! # let storage_config = StorageConfig{db_config, ..Default::default()};
+//! let mut builder = StorageBuilder::new(storage_config);
+//! let table: CustomTable<String, BlockNumber> = builder.add_table("my_table")?;
+//! let (reader, mut writer) = builder.open()?;
+//! writer
+//!     .begin_rw_txn()?                                            // Start a RW transaction.
+//!     .upsert_custom(&table, &"key".to_owned(), &BlockNumber(3))? // Write to the custom table.
+//!     .commit()?; // Commit the transaction.
+//! let value = reader.begin_ro_txn()?.get_custom(&table, &"key".to_owned())?;
+//! assert_eq!(value, Some(BlockNumber(3)));
+//! # Ok::<(), papyrus_storage::StorageError>(())
+//! ```
+//!
+//! [`StorageBuilder::add_table`]: crate::StorageBuilder::add_table
+
+#[cfg(test)]
+#[path = "custom_tables_test.rs"]
+mod custom_tables_test;
+
+use std::fmt::Debug;
+
+use crate::db::serialization::{NoVersionValueWrapper, StorageSerde};
+use crate::db::{TableIdentifier, TransactionKind, RW};
+use crate::{StorageResult, StorageTxn};
+
+/// A table that was added to the storage with [`StorageBuilder::add_table`], whose keys are of
+/// type K and whose values are of type V.
+///
+/// [`StorageBuilder::add_table`]: crate::StorageBuilder::add_table
+pub struct CustomTable<K, V>
+where
+    K: StorageSerde + Ord + Clone + Debug,
+    V: StorageSerde + Debug,
+{
+    pub(crate) table_id: TableIdentifier<K, NoVersionValueWrapper<V>>,
+}
+
+impl<K, V> CustomTable<K, V>
+where
+    K: StorageSerde + Ord + Clone + Debug,
+    V: StorageSerde + Debug,
+{
+    /// Returns the name of the table.
+    pub fn name(&self) -> &'static str {
+        self.table_id.name
+    }
+}
+
+impl<K, V> Debug for CustomTable<K, V>
+where
+    K: StorageSerde + Ord + Clone + Debug,
+    V: StorageSerde + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomTable").field("name", &self.name()).finish()
+    }
+}
+
+/// Interface for reading data from the custom tables.
+pub trait CustomTablesStorageReader {
+    /// Returns the value of the key in the table.
+    fn get_custom<K, V>(&self, table: &CustomTable<K, V>, key: &K) -> StorageResult<Option<V>>
+    where
+        K: StorageSerde + Ord + Clone + Debug,
+        V: StorageSerde + Debug + Clone + Send + Sync + 'static;
+}
+
+/// Interface for writing data to the custom tables.
+pub trait CustomTablesStorageWriter
+where
+    Self: Sized,
+{
+    /// Sets the value of the key in the table, overwriting its existing value if there is one.
+    // To enforce that no commit happen after a failure, we consume and return Self on success.
+    fn upsert_custom<K, V>(
+        self,
+        table: &CustomTable<K, V>,
+        key: &K,
+        value: &V,
+    ) -> StorageResult<Self>
+    where
+        K: StorageSerde + Ord + Clone + Debug,
+        V: StorageSerde + Debug;
+
+    /// Deletes the key from the table, if it's there.
+    fn delete_custom<K, V>(self, table: &CustomTable<K, V>, key: &K) -> StorageResult<Self>
+    where
+        K: StorageSerde + Ord + Clone + Debug,
+        V: StorageSerde + Debug;
+}
+
+impl<'env, Mode: TransactionKind> CustomTablesStorageReader for StorageTxn<'env, Mode> {
+    fn get_custom<K, V>(&self, table: &CustomTable<K, V>, key: &K) -> StorageResult<Option<V>>
+    where
+        K: StorageSerde + Ord + Clone + Debug,
+        V: StorageSerde + Debug + Clone + Send + Sync + 'static,
+    {
+        let custom_table = self.open_table(&table.table_id)?;
+        Ok(custom_table.get(&self.txn, key)?)
+    }
+}
+
+impl<'env> CustomTablesStorageWriter for StorageTxn<'env, RW> {
+    fn upsert_custom<K, V>(
+        self,
+        table: &CustomTable<K, V>,
+        key: &K,
+        value: &V,
+    ) -> StorageResult<Self>
+    where
+        K: StorageSerde + Ord + Clone + Debug,
+        V: StorageSerde + Debug,
+    {
+        let custom_table = self.open_table(&table.table_id)?;
+        custom_table.upsert(&self.txn, key, value)?;
+        Ok(self)
+    }
+
+    fn delete_custom<K, V>(self, table: &CustomTable<K, V>, key: &K) -> StorageResult<Self>
+    where
+        K: StorageSerde + Ord + Clone + Debug,
+        V: StorageSerde + Debug,
+    {
+        let custom_table = self.open_table(&table.table_id)?;
+        custom_table.delete(&self.txn, key)?;
+        Ok(self)
+    }
+}
@@ -0,0 +1,70 @@
+use assert_matches::assert_matches;
+use starknet_api::block::BlockNumber;
+
+use crate::custom_tables::{CustomTable, CustomTablesStorageReader, CustomTablesStorageWriter};
+use crate::db::MAX_CUSTOM_TABLES;
+use crate::test_utils::get_test_config;
+use crate::{StorageBuilder, StorageError};
+
+#[test]
+fn write_and_read_custom_table() {
+    let (config, _temp_dir) = get_test_config(None);
+    let mut builder = StorageBuilder::new(config);
+    let table: CustomTable<String, BlockNumber> = builder.add_table("custom_table").unwrap();
+    let (reader, mut writer) = builder.open().unwrap();
+    let key = "key".to_owned();
+
+    assert_eq!(reader.begin_ro_txn().unwrap().get_custom(&table, &key).unwrap(), None);
+
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .upsert_custom(&table, &key, &BlockNumber(5))
+        .unwrap()
+        .commit()
+        .unwrap();
+    let value = reader.begin_ro_txn().unwrap().get_custom(&table, &key).unwrap();
+    assert_eq!(value, Some(BlockNumber(5)));
+
+    writer.begin_rw_txn().unwrap().delete_custom(&table, &key).unwrap().commit().unwrap();
+    assert_eq!(reader.begin_ro_txn().unwrap().get_custom(&table, &key).unwrap(), None);
+}
+
+#[test]
+fn custom_table_name_collision() {
+    let (config, _temp_dir) = get_test_config(None);
+    let mut builder = StorageBuilder::new(config);
+
+    let result = builder.add_table::<String, BlockNumber>("headers");
+    assert_matches!(
+        result,
+        Err(StorageError::TableNameCollision { table_name }) if table_name == "headers"
+    );
+
+    builder.add_table::<String, BlockNumber>("custom_table").unwrap();
+    let result = builder.add_table::<String, BlockNumber>("custom_table");
+    assert_matches!(
+        result,
+        Err(StorageError::TableNameCollision { table_name }) if table_name == "custom_table"
+    );
+}
+
+#[test]
+fn too_many_custom_tables() {
+    let (config, _temp_dir) = get_test_config(None);
+    let mut builder = StorageBuilder::new(config);
+    let names: Vec<&'static str> = (0..=MAX_CUSTOM_TABLES)
+        .map(|i| &*Box::leak(format!("custom_table_{i}").into_boxed_str()))
+        .collect();
+    for name in &names[..MAX_CUSTOM_TABLES] {
+        builder.add_table::<String, BlockNumber>(name).unwrap();
+    }
+    let result = builder.add_table::<String, BlockNumber>(names[MAX_CUSTOM_TABLES]);
+    assert_matches!(
+        result,
+        Err(StorageError::TooManyCustomTables { max_custom_tables: MAX_CUSTOM_TABLES })
+    );
+
+    // The storage opens with all the custom tables.
+    builder.open().unwrap();
+}
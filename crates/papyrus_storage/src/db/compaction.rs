@@ -0,0 +1,94 @@
+#[cfg(test)]
+#[path = "compaction_test.rs"]
+mod compaction_test;
+
+use std::ffi::CString;
+use std::path::Path;
+use std::sync::Arc;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use super::{DbConfig, DbError, DbResult, DbWriter};
+
+// The directory in the database directory that the compacted copy is written to, so that it's on
+// the same file system as the database file and can replace it atomically.
+const COMPACTION_DIR_NAME: &str = "compaction";
+const DB_FILE_NAME: &str = "mdbx.dat";
+
+/// The outcome of compacting the database file.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// The size of the database file before the compaction, in bytes.
+    pub size_before: u64,
+    /// The size of the database file after the compaction, in bytes.
+    pub size_after: u64,
+}
+
+impl CompactionReport {
+    /// The number of bytes that the compaction returned to the file system.
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+impl DbWriter {
+    // Copies the live pages of the database to a new file, and replaces the database file with
+    // it. The environment is closed before the replacement, so the writer is consumed, and there
+    // must be no readers of the environment left. The database should be reopened afterwards.
+    pub(crate) fn compact(self, config: &DbConfig) -> DbResult<CompactionReport> {
+        if Arc::strong_count(&self.env) > 1 {
+            return Err(DbError::EnvInUse);
+        }
+        let db_dir = config.path();
+        let db_file_path = db_dir.join(DB_FILE_NAME);
+        let size_before = fs::metadata(&db_file_path)?.len();
+
+        // The compacted copy is at most as large as the used pages of the database.
+        let info = self.env.info()?;
+        let page_size = u64::from(self.env.stat()?.page_size());
+        let required = (info.last_pgno() as u64 + 1) * page_size;
+        let available = fs2::available_space(&db_dir)?;
+        if available < required {
+            return Err(DbError::NotEnoughDiskSpace { required, available });
+        }
+
+        let compaction_dir = db_dir.join(COMPACTION_DIR_NAME);
+        if compaction_dir.exists() {
+            // Left by a compaction that was interrupted before replacing the database file.
+            fs::remove_dir_all(&compaction_dir)?;
+        }
+        fs::create_dir(&compaction_dir)?;
+        let compacted_file_path = compaction_dir.join(DB_FILE_NAME);
+        copy_compacted(&self, &compacted_file_path)?;
+
+        // Closing the environment before replacing its file.
+        drop(self);
+        fs::rename(&compacted_file_path, &db_file_path)?;
+        fs::remove_dir_all(&compaction_dir)?;
+        let size_after = fs::metadata(&db_file_path)?.len();
+        Ok(CompactionReport { size_before, size_after })
+    }
+}
+
+// Writes a copy of the database without its free pages to the given path.
+fn copy_compacted(db_writer: &DbWriter, path: &Path) -> DbResult<()> {
+    // The database library takes the path as a UTF-8 C string on every platform.
+    let path = path.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The path {path:?} is not valid UTF-8."),
+        )
+    })?;
+    let path =
+        CString::new(path).expect("The path of the compacted copy should not contain a nul byte.");
+    // Safe because the environment is open for as long as the writer lives, and the path is a
+    // valid C string.
+    let err_code = unsafe {
+        mdbx_sys::mdbx_env_copy(db_writer.env.ptr(), path.as_ptr(), mdbx_sys::MDBX_CP_COMPACT)
+    };
+    match err_code {
+        0 => Ok(()),
+        err_code => Err(libmdbx::Error::from_err_code(err_code).into()),
+    }
+}
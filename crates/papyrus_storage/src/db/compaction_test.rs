@@ -0,0 +1,96 @@
+use std::fs;
+
+use assert_matches::assert_matches;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::hash::StarkFelt;
+
+use crate::db::serialization::NoVersionValueWrapper;
+use crate::db::{open_env, DbConfig, DbError};
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::test_utils::{get_test_config, get_test_storage};
+use crate::StorageError;
+
+const NUM_ENTRIES: u32 = 10_000;
+// Every KEPT_ENTRIES_STEP-th entry is kept, and the others are deleted.
+const KEPT_ENTRIES_STEP: u32 = 100;
+
+#[test]
+fn compaction_reclaims_the_space_of_deleted_entries() {
+    let (config, _temp_dir) = get_test_config(None);
+    // The file grows and shrinks in steps of the growth step, so it's small enough for the file
+    // to shrink after the compaction.
+    let db_config = DbConfig { growth_step: 1 << 20, ..config.db_config };
+    let db_file_path = db_config.path().join("mdbx.dat");
+    let (reader, mut writer) = open_env(&db_config).unwrap();
+    let table_id = writer.create_table::<u32, NoVersionValueWrapper<Vec<u8>>>("table").unwrap();
+
+    let txn = writer.begin_rw_txn().unwrap();
+    let table = txn.open_table(&table_id).unwrap();
+    for key in 0..NUM_ENTRIES {
+        table.insert(&txn, &key, &vec![0; 1024]).unwrap();
+    }
+    txn.commit().unwrap();
+    let txn = writer.begin_rw_txn().unwrap();
+    let table = txn.open_table(&table_id).unwrap();
+    for key in (0..NUM_ENTRIES).filter(|key| key % KEPT_ENTRIES_STEP != 0) {
+        table.delete(&txn, &key).unwrap();
+    }
+    txn.commit().unwrap();
+    let size_before = fs::metadata(&db_file_path).unwrap().len();
+
+    // The database can't be compacted while it has readers.
+    assert_matches!(writer.compact(&db_config), Err(DbError::EnvInUse));
+    assert_eq!(fs::metadata(&db_file_path).unwrap().len(), size_before);
+    drop(reader);
+
+    let (reader, writer) = open_env(&db_config).unwrap();
+    drop(reader);
+    let report = writer.compact(&db_config).unwrap();
+    assert_eq!(report.size_before, size_before);
+    assert_eq!(report.size_after, fs::metadata(&db_file_path).unwrap().len());
+    assert!(
+        report.bytes_reclaimed() > size_before / 2,
+        "Only {} of {size_before} bytes were reclaimed.",
+        report.bytes_reclaimed()
+    );
+    assert!(!db_config.path().join("compaction").exists());
+
+    // The entries that weren't deleted are kept.
+    let (reader, _writer) = open_env(&db_config).unwrap();
+    let txn = reader.begin_ro_txn().unwrap();
+    let table = txn.open_table(&table_id).unwrap();
+    for key in 0..NUM_ENTRIES {
+        let value = table.get(&txn, &key).unwrap();
+        assert_eq!(value.is_some(), key % KEPT_ENTRIES_STEP == 0, "Unexpected value of {key}.");
+    }
+}
+
+#[test]
+fn compacted_storage_is_reopened() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+    drop(reader);
+
+    let (reader, mut writer, _report) = writer.compact().unwrap();
+    assert_eq!(reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(1));
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(
+            BlockNumber(1),
+            &BlockHeader { block_hash: BlockHash(StarkFelt::ONE), ..BlockHeader::default() },
+        )
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // The new reader prevents another compaction.
+    assert_matches!(writer.compact().err().unwrap(), StorageError::InnerError(DbError::EnvInUse));
+}
@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+use std::ffi::{c_char, CStr};
+
 use human_bytes::human_bytes;
 use serde::{Deserialize, Serialize};
 
@@ -24,6 +27,19 @@ pub struct DbTableStats {
     pub db_portion: f64,
 }
 
+/// The size of a single table, for finding the tables that take most of the database.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbTableSize {
+    /// Number of branch pages in the table.
+    pub branch_pages: usize,
+    /// Number of leaf pages in the table.
+    pub leaf_pages: usize,
+    /// Number of overflow pages in the table.
+    pub overflow_pages: usize,
+    /// The size of all the pages of the table in bytes.
+    pub total_bytes: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 /// Statics about the whole database.
 pub struct DbWholeStats {
@@ -43,6 +59,84 @@ pub struct DbWholeStats {
     pub page_size: u64,
 }
 
+/// Information about the database environment, as reported by libmdbx.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DbEnvStats {
+    /// Size of the memory map of the database.
+    #[serde(serialize_with = "readable_bytes")]
+    pub map_size: u64,
+    /// Size of the used pages of the database, including the free ones that may be reused.
+    #[serde(serialize_with = "readable_bytes")]
+    pub used_size: u64,
+    /// Id of the latest committed transaction.
+    pub last_txn_id: u64,
+    /// Number of tables in the database.
+    pub num_tables: usize,
+    /// The read transactions that are open for longer than the configured threshold, from the
+    /// oldest. Empty if the read transactions aren't tracked.
+    pub stale_read_txns: Vec<StaleReadTxn>,
+}
+
+/// A read transaction that is open for too long, and prevents the database from reclaiming the
+/// pages that were freed after it began.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StaleReadTxn {
+    /// The number of seconds the transaction is open.
+    pub age_secs: u64,
+    /// Where the transaction began, if backtraces are enabled.
+    pub backtrace: Option<String>,
+}
+
+/// The version of libmdbx the storage was built with and the options it was built with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LibmdbxVersion {
+    /// The version, in the form `<major>.<minor>.<release>.<revision>`.
+    pub version: String,
+    /// The description of the source revision, as given by `git describe`.
+    pub git_describe: String,
+    /// The commit of the source revision.
+    pub git_commit: String,
+    /// The platform libmdbx was built for.
+    pub build_target: String,
+    /// The build options of libmdbx, e.g. `MDBX_DEBUG` and `MDBX_TXN_CHECKOWNER`.
+    pub build_options: String,
+    /// The compiler libmdbx was built with.
+    pub build_compiler: String,
+    /// The flags libmdbx was compiled with.
+    pub build_flags: String,
+}
+
+/// Returns the version of libmdbx the storage was built with. The version is embedded in the
+/// binary, so it's read without accessing the database.
+pub fn libmdbx_version() -> LibmdbxVersion {
+    // SAFETY: libmdbx initializes these statics at compile time and never modifies them, and their
+    // strings are either null or valid null-terminated strings.
+    unsafe {
+        let version = &mdbx_sys::mdbx_version;
+        let build = &mdbx_sys::mdbx_build;
+        LibmdbxVersion {
+            version: format!(
+                "{}.{}.{}.{}",
+                version.major, version.minor, version.release, version.revision
+            ),
+            git_describe: c_str_to_string(version.git.describe),
+            git_commit: c_str_to_string(version.git.commit),
+            build_target: c_str_to_string(build.target),
+            build_options: c_str_to_string(build.options),
+            build_compiler: c_str_to_string(build.compiler),
+            build_flags: c_str_to_string(build.flags),
+        }
+    }
+}
+
+// Copies a string of libmdbx. A null pointer is copied as an empty string.
+unsafe fn c_str_to_string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
 impl DbReader {
     // Returns statistics about a specific table in the database.
     pub(crate) fn get_table_stats(&self, name: &str) -> DbResult<DbTableStats> {
@@ -60,6 +154,27 @@ impl DbReader {
         })
     }
 
+    // Returns the size of each table in the database, including the custom tables, all read from
+    // the same transaction.
+    pub(crate) fn get_tables_size(&self) -> DbResult<BTreeMap<String, DbTableSize>> {
+        let db_txn = self.begin_ro_txn()?;
+        let mut tables_size = BTreeMap::new();
+        for name in db_txn.list_tables()? {
+            let table = db_txn.txn.open_table(Some(&name))?;
+            let stat = db_txn.txn.table_stat(&table)?;
+            tables_size.insert(
+                name,
+                DbTableSize {
+                    branch_pages: stat.branch_pages(),
+                    leaf_pages: stat.leaf_pages(),
+                    overflow_pages: stat.overflow_pages(),
+                    total_bytes: stat.total_size(),
+                },
+            );
+        }
+        Ok(tables_size)
+    }
+
     // Returns statistics about the whole database.
     pub(crate) fn get_db_stats(&self) -> DbResult<DbWholeStats> {
         let stat = self.env.stat()?;
@@ -72,6 +187,28 @@ impl DbReader {
             page_size: stat.page_size().into(),
         })
     }
+
+    // Returns information about the database environment.
+    pub(crate) fn get_env_stats(&self) -> DbResult<DbEnvStats> {
+        let info = self.env.info()?;
+        let page_size = u64::from(self.env.stat()?.page_size());
+        let db_txn = self.begin_ro_txn()?;
+        // The entries of the main table are the other tables.
+        let main_table = db_txn.txn.open_table(None)?;
+        let num_tables = db_txn.txn.table_stat(&main_table)?.entries();
+        Ok(DbEnvStats {
+            map_size: info.map_size() as u64,
+            // Page numbers start from 0.
+            used_size: (info.last_pgno() as u64 + 1) * page_size,
+            last_txn_id: info.last_txnid() as u64,
+            num_tables,
+            stale_read_txns: self
+                .read_txn_tracker
+                .as_ref()
+                .map(|tracker| tracker.get_stale_txns())
+                .unwrap_or_default(),
+        })
+    }
 }
 
 // Serialize bytes as a human readable string.
@@ -1,11 +1,37 @@
+use std::error::Error;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 use assert_matches::assert_matches;
 use libmdbx::PageSize;
+use mdbx_sys::{MDBX_NOMETASYNC, MDBX_SAFE_NOSYNC, MDBX_SYNC_DURABLE, MDBX_UTTERLY_NOSYNC};
 use pretty_assertions::assert_eq;
+use starknet_api::core::ChainId;
 use tempfile::TempDir;
 
-use crate::db::serialization::NoVersionValueWrapper;
-use crate::db::{get_page_size, open_env, DbError, DbIter, DbReader, DbResult, DbWriter};
+use crate::db::db_stats::libmdbx_version;
+use crate::db::read_txn_tracker::{Clock, ReadTxnTracker};
+use crate::db::serialization::{NoVersionValueWrapper, ValueSerde};
+use crate::db::write_txn_watchdog::WriteTxnWatchdog;
+use crate::db::{
+    get_page_size,
+    open_env,
+    ContinuationToken,
+    DbConfig,
+    DbError,
+    DbIter,
+    DbKeyIter,
+    DbReader,
+    DbResult,
+    DbWriter,
+    DeserializationError,
+    PageStart,
+    SyncMode,
+    TableIdentifier,
+};
 use crate::test_utils::get_test_config;
+use crate::{open_storage, StorageConfig, StorageError};
 
 fn get_test_env() -> ((DbReader, DbWriter), TempDir) {
     let (config, temp_dir) = get_test_config(None);
@@ -61,6 +87,52 @@ fn open_env_with_enforce_file_exists() {
     assert_matches!(result, Err(DbError::FileDoesNotExist(_)));
 }
 
+#[test]
+fn open_storage_failure_reports_path_and_chain() {
+    let (config, _temp_dir) = get_test_config(None);
+    let chain_id = ChainId("SN_TEST".to_owned());
+    let db_config =
+        DbConfig { enforce_file_exists: true, chain_id: chain_id.clone(), ..config.db_config };
+    let path = db_config.path();
+    let Err(err) = open_storage(StorageConfig { db_config, ..config }) else {
+        panic!("Unexpected Ok.");
+    };
+
+    let message = err.to_string();
+    assert!(message.contains(path.to_str().unwrap()), "{message}");
+    assert!(message.contains("SN_TEST"), "{message}");
+    assert_matches!(
+        err.source().and_then(|source| source.downcast_ref::<DbError>()),
+        Some(DbError::FileDoesNotExist(file_path)) if file_path.starts_with(&path)
+    );
+    assert_matches!(
+        err,
+        StorageError::OpenFailed { chain_id: err_chain_id, .. } if err_chain_id == chain_id
+    );
+}
+
+#[test]
+fn open_env_with_sync_mode() {
+    // libmdbx skips syncing the metadata in the no-sync modes as well.
+    for (sync_mode, expected_flags) in [
+        (SyncMode::Durable, MDBX_SYNC_DURABLE),
+        (SyncMode::NoMetaSync, MDBX_NOMETASYNC),
+        (SyncMode::SafeNoSync, MDBX_SAFE_NOSYNC | MDBX_NOMETASYNC),
+        (SyncMode::UtterlyNoSync, MDBX_UTTERLY_NOSYNC | MDBX_NOMETASYNC),
+    ] {
+        let (config, _temp_dir) = get_test_config(None);
+        let db_config = DbConfig { sync_mode, ..config.db_config };
+        let (reader, _writer) = open_env(&db_config).unwrap();
+
+        let mut flags = 0;
+        // Safe since the environment is open for as long as the reader holds it.
+        let res = unsafe { mdbx_sys::mdbx_env_get_flags(reader.env.ptr(), &mut flags) };
+        assert_eq!(res, 0);
+        let sync_mode_flags = MDBX_NOMETASYNC | MDBX_UTTERLY_NOSYNC;
+        assert_eq!(flags & sync_mode_flags, expected_flags, "{sync_mode:?}");
+    }
+}
+
 #[test]
 fn txns_scenarios() {
     // Create an environment and a table.
@@ -102,6 +174,258 @@ fn txns_scenarios() {
     assert_eq!(table.get(&txn3, b"key").unwrap(), None);
 }
 
+#[test]
+fn rw_txn_reads_its_own_writes() {
+    // Create an environment and a table.
+    let ((reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+
+    let txn0 = reader.begin_ro_txn().unwrap();
+    let table = txn0.open_table(&table_id).unwrap();
+
+    // Every write is visible to the transaction before it's committed.
+    let wtxn = writer.begin_rw_txn().unwrap();
+    table.insert(&wtxn, b"key", b"data0").unwrap();
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), Some(*b"data0"));
+    table.upsert(&wtxn, b"key", b"data1").unwrap();
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), Some(*b"data1"));
+    table.delete(&wtxn, b"key").unwrap();
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), None);
+    table.insert(&wtxn, b"key", b"data2").unwrap();
+
+    // The uncommitted writes aren't visible to other transactions.
+    let txn1 = reader.begin_ro_txn().unwrap();
+    assert_eq!(table.get(&txn1, b"key").unwrap(), None);
+
+    // Nor after the transaction is dropped without a commit.
+    drop(wtxn);
+    let txn2 = reader.begin_ro_txn().unwrap();
+    assert_eq!(table.get(&txn2, b"key").unwrap(), None);
+}
+
+#[test]
+fn malformed_values_fail_to_deserialize() {
+    let ((reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+    // The same table, with values that are too short for it.
+    let malformed_table_id =
+        TableIdentifier::<[u8; 3], NoVersionValueWrapper<[u8; 2]>>::new("table");
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    wtxn.open_table(&malformed_table_id).unwrap().insert(&wtxn, b"key", b"da").unwrap();
+    wtxn.commit().unwrap();
+
+    // A missing key isn't an error, but a malformed value is.
+    let txn = reader.begin_ro_txn().unwrap();
+    let table = txn.open_table(&table_id).unwrap();
+    assert_eq!(table.get(&txn, b"abc").unwrap(), None);
+    let expected_key = format!("{:?}", b"key");
+    assert_matches!(
+        table.get(&txn, b"key"),
+        Err(DbError::InnerDeserialization(DeserializationError { table_name: "table", key }))
+        if key == expected_key
+    );
+    let mut cursor = table.cursor(&txn).unwrap();
+    assert_matches!(
+        cursor.next(),
+        Err(DbError::InnerDeserialization(DeserializationError { table_name: "table", key }))
+        if key == expected_key
+    );
+}
+
+#[test]
+fn get_or_default_of_present_and_absent_keys() {
+    let ((reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    wtxn.open_table(&table_id).unwrap().insert(&wtxn, b"key", b"data0").unwrap();
+    wtxn.commit().unwrap();
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let table = txn.open_table(&table_id).unwrap();
+    assert_eq!(table.get_or_default(&txn, b"key").unwrap(), *b"data0");
+    assert_eq!(table.get_or_default(&txn, b"abc").unwrap(), [0; 5]);
+}
+
+#[test]
+fn upsert_and_delete_report_existing_keys() {
+    // Create an environment and a table.
+    let ((_reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    let table = wtxn.open_table(&table_id).unwrap();
+
+    // Upserting a new key inserts it, and upserting it again overwrites its value.
+    assert!(!table.upsert(&wtxn, b"key", b"data0").unwrap());
+    assert!(table.upsert(&wtxn, b"key", b"data1").unwrap());
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), Some(*b"data1"));
+
+    // Deleting a key reports whether it was present.
+    assert!(table.delete(&wtxn, b"key").unwrap());
+    let bytes_written = wtxn.bytes_written.get();
+    assert!(!table.delete(&wtxn, b"key").unwrap());
+    assert!(!table.delete(&wtxn, b"abc").unwrap());
+    // Deleting a missing key isn't recorded as a write.
+    assert_eq!(wtxn.bytes_written.get(), bytes_written);
+    wtxn.commit().unwrap();
+}
+
+#[test]
+fn deleting_a_missing_raw_key_isnt_recorded_as_a_write() {
+    let ((_reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    let table = wtxn.open_table(&table_id).unwrap();
+    table.insert(&wtxn, b"key", b"data0").unwrap();
+
+    let bytes_written = wtxn.bytes_written.get();
+    wtxn.delete_raw("table", b"key".to_vec()).unwrap();
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), None);
+    assert!(wtxn.bytes_written.get() > bytes_written);
+
+    let bytes_written = wtxn.bytes_written.get();
+    wtxn.delete_raw("table", b"key".to_vec()).unwrap();
+    wtxn.delete_raw("table", b"abc".to_vec()).unwrap();
+    assert_eq!(wtxn.bytes_written.get(), bytes_written);
+    wtxn.commit().unwrap();
+}
+
+#[test]
+fn compare_and_swap_writes_only_if_the_value_is_the_expected_one() {
+    let ((_reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    let table = wtxn.open_table(&table_id).unwrap();
+
+    // An absent key is expected as None.
+    assert!(!table.compare_and_swap(&wtxn, b"key", Some(b"data0"), b"data1").unwrap());
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), None);
+    assert!(table.compare_and_swap(&wtxn, b"key", None, b"data0").unwrap());
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), Some(*b"data0"));
+
+    // A present key isn't swapped unless its value is the expected one.
+    assert!(!table.compare_and_swap(&wtxn, b"key", None, b"data1").unwrap());
+    assert!(!table.compare_and_swap(&wtxn, b"key", Some(b"data2"), b"data1").unwrap());
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), Some(*b"data0"));
+    assert!(table.compare_and_swap(&wtxn, b"key", Some(b"data0"), b"data1").unwrap());
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), Some(*b"data1"));
+    wtxn.commit().unwrap();
+}
+
+fn get_test_env_with_read_cache() -> ((DbReader, DbWriter), TempDir) {
+    let (config, temp_dir) = get_test_config(None);
+    let db_config = DbConfig { read_cache_capacity: NonZeroUsize::new(16), ..config.db_config };
+    (open_env(&db_config).expect("Failed to open environment."), temp_dir)
+}
+
+#[test]
+fn read_cache_serves_repeated_reads() {
+    let ((reader, mut writer), _temp_dir) = get_test_env_with_read_cache();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    let table = wtxn.open_table(&table_id).unwrap();
+    table.insert(&wtxn, b"key", b"data0").unwrap();
+    wtxn.commit().unwrap();
+
+    // Only the first read deserializes the value, in this transaction and in the next ones.
+    let txn = reader.begin_ro_txn().unwrap();
+    let table = txn.open_table(&table_id).unwrap();
+    for _ in 0..5 {
+        assert_eq!(table.get(&txn, b"key").unwrap(), Some(*b"data0"));
+    }
+    let txn = reader.begin_ro_txn().unwrap();
+    for _ in 0..5 {
+        assert_eq!(table.get(&txn, b"key").unwrap(), Some(*b"data0"));
+    }
+    let read_cache = reader.read_cache.as_ref().unwrap();
+    assert_eq!(read_cache.stats(), (9, 1));
+}
+
+#[test]
+fn read_cache_is_invalidated_by_writes() {
+    let ((reader, mut writer), _temp_dir) = get_test_env_with_read_cache();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+
+    let txn0 = reader.begin_ro_txn().unwrap();
+    let table = txn0.open_table(&table_id).unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    table.insert(&wtxn, b"key", b"data0").unwrap();
+    wtxn.commit().unwrap();
+
+    // Cache the value.
+    let txn1 = reader.begin_ro_txn().unwrap();
+    assert_eq!(table.get(&txn1, b"key").unwrap(), Some(*b"data0"));
+
+    // Update the value.
+    let wtxn = writer.begin_rw_txn().unwrap();
+    table.upsert(&wtxn, b"key", b"data1").unwrap();
+    // A write transaction reads its own writes rather than the cached value.
+    assert_eq!(table.get(&wtxn, b"key").unwrap(), Some(*b"data1"));
+    wtxn.commit().unwrap();
+
+    // New transactions see the new value, and cache it.
+    let txn2 = reader.begin_ro_txn().unwrap();
+    assert_eq!(table.get(&txn2, b"key").unwrap(), Some(*b"data1"));
+    assert_eq!(table.get(&txn2, b"key").unwrap(), Some(*b"data1"));
+
+    // Older transactions keep seeing their own snapshot rather than the newer cached value.
+    assert_eq!(table.get(&txn0, b"key").unwrap(), None);
+    assert_eq!(table.get(&txn1, b"key").unwrap(), Some(*b"data0"));
+    assert_eq!(table.get(&txn2, b"key").unwrap(), Some(*b"data1"));
+
+    // Delete the value.
+    let wtxn = writer.begin_rw_txn().unwrap();
+    table.delete(&wtxn, b"key").unwrap();
+    wtxn.commit().unwrap();
+
+    let txn3 = reader.begin_ro_txn().unwrap();
+    assert_eq!(table.get(&txn3, b"key").unwrap(), None);
+    assert_eq!(table.get(&txn2, b"key").unwrap(), Some(*b"data1"));
+}
+
+#[test]
+fn read_cache_doesnt_block_or_serve_stale_values_during_commits() {
+    let ((reader, mut writer), _temp_dir) = get_test_env_with_read_cache();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let table = txn.open_table(&table_id).unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    table.insert(&wtxn, b"key", b"data0").unwrap();
+    wtxn.commit().unwrap();
+
+    // Cache the value.
+    let txn0 = reader.begin_ro_txn().unwrap();
+    assert_eq!(table.get(&txn0, b"key").unwrap(), Some(*b"data0"));
+
+    let mut wtxn = writer.begin_rw_txn().unwrap();
+    table.upsert(&wtxn, b"key", b"data1").unwrap();
+    let txn_read_cache = wtxn.read_cache.take().unwrap();
+    txn_read_cache
+        .commit(|| {
+            wtxn.commit()?;
+            // Between the commit and the eviction of its keys, older transactions are still
+            // served, and new transactions read the new value rather than the cached one.
+            assert_eq!(table.get(&txn0, b"key").unwrap(), Some(*b"data0"));
+            let txn1 = reader.begin_ro_txn().unwrap();
+            assert_eq!(table.get(&txn1, b"key").unwrap(), Some(*b"data1"));
+            Ok(())
+        })
+        .unwrap();
+
+    let txn2 = reader.begin_ro_txn().unwrap();
+    assert_eq!(table.get(&txn2, b"key").unwrap(), Some(*b"data1"));
+    let read_cache = reader.read_cache.as_ref().unwrap();
+    assert_eq!(read_cache.stats(), (1, 3));
+}
+
 #[test]
 fn insert_duplicate_key() {
     // Create an environment and a table.
@@ -171,7 +495,167 @@ fn table_stats() {
     assert_eq!(empty_stat.leaf_pages, 0);
 }
 
+#[test]
+fn tables_size() {
+    let ((reader, mut writer), _temp_dir) = get_test_env();
+    let big_table_id =
+        writer.create_table::<[u8; 4], NoVersionValueWrapper<[u8; 100]>>("big_table").unwrap();
+    let small_table_id =
+        writer.create_table::<[u8; 4], NoVersionValueWrapper<[u8; 100]>>("small_table").unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    let big_table = wtxn.open_table(&big_table_id).unwrap();
+    for i in 0..1000_u32 {
+        big_table.insert(&wtxn, &i.to_be_bytes(), &[0; 100]).unwrap();
+    }
+    let small_table = wtxn.open_table(&small_table_id).unwrap();
+    small_table.insert(&wtxn, &0_u32.to_be_bytes(), &[0; 100]).unwrap();
+    wtxn.commit().unwrap();
+
+    let tables_size = reader.get_tables_size().unwrap();
+    assert_eq!(tables_size.keys().collect::<Vec<_>>(), vec!["big_table", "small_table"]);
+    let big_table_size = tables_size["big_table"];
+    let small_table_size = tables_size["small_table"];
+    assert!(big_table_size.branch_pages > 0);
+    assert!(big_table_size.leaf_pages > small_table_size.leaf_pages);
+    let page_size = u64::from(reader.env.stat().unwrap().page_size());
+    assert_eq!(
+        big_table_size.total_bytes,
+        (big_table_size.branch_pages + big_table_size.leaf_pages + big_table_size.overflow_pages)
+            as u64
+            * page_size
+    );
+    assert!(big_table_size.total_bytes > 10 * small_table_size.total_bytes);
+}
+
+#[test]
+fn list_tables_returns_the_tables_on_disk() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) = open_env(&config.db_config).unwrap();
+    writer.create_untyped_table("table_a").unwrap();
+    writer.create_untyped_table("table_b").unwrap();
+    drop((reader, writer));
+
+    // Tables that the reopened environment doesn't create are still listed.
+    let (reader, _writer) = open_env(&config.db_config).unwrap();
+    let tables = reader.begin_ro_txn().unwrap().list_tables().unwrap();
+    assert_eq!(tables, vec!["table_a", "table_b"]);
+}
+
 use super::{MDBX_MAX_PAGESIZE, MDBX_MIN_PAGESIZE};
+#[test]
+fn env_stats() {
+    // Create an environment and a table.
+    let ((reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 3], NoVersionValueWrapper<[u8; 5]>>("table").unwrap();
+    let stats = reader.get_env_stats().unwrap();
+    assert!(stats.map_size > 0);
+    assert!(stats.used_size > 0);
+    assert_eq!(stats.num_tables, 1);
+
+    // Committing a write transaction advances the last transaction id.
+    let wtxn = writer.begin_rw_txn().unwrap();
+    let table = wtxn.open_table(&table_id).unwrap();
+    table.insert(&wtxn, b"key", b"data0").unwrap();
+    wtxn.commit().unwrap();
+    let new_stats = reader.get_env_stats().unwrap();
+    assert!(new_stats.last_txn_id > stats.last_txn_id);
+    assert!(new_stats.used_size >= stats.used_size);
+}
+
+#[test]
+fn stale_read_txns_are_reported() {
+    const STALE_THRESHOLD: Duration = Duration::from_secs(60);
+    let ((mut reader, _writer), _temp_dir) = get_test_env();
+    let now = Arc::new(Mutex::new(Instant::now()));
+    let clock_now = now.clone();
+    let clock: Clock = Arc::new(move || *clock_now.lock().unwrap());
+    reader.read_txn_tracker = Some(Arc::new(ReadTxnTracker::with_clock(STALE_THRESHOLD, clock)));
+
+    let txn = reader.begin_ro_txn().unwrap();
+    assert!(reader.get_env_stats().unwrap().stale_read_txns.is_empty());
+
+    // Advance the clock past the threshold.
+    *now.lock().unwrap() += STALE_THRESHOLD + Duration::from_secs(1);
+    let stale_read_txns = reader.get_env_stats().unwrap().stale_read_txns;
+    assert_eq!(stale_read_txns.len(), 1);
+    assert_eq!(stale_read_txns[0].age_secs, STALE_THRESHOLD.as_secs() + 1);
+
+    // A closed transaction isn't reported.
+    drop(txn);
+    assert!(reader.get_env_stats().unwrap().stale_read_txns.is_empty());
+}
+
+// Collects the logs that are written to it.
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn long_write_txns_are_logged() {
+    const TIMEOUT: Duration = Duration::from_secs(60);
+    let ((_reader, mut writer), _temp_dir) = get_test_env();
+    let now = Arc::new(Mutex::new(Instant::now()));
+    let clock_now = now.clone();
+    let clock: Clock = Arc::new(move || *clock_now.lock().unwrap());
+    let watchdog = Arc::new(WriteTxnWatchdog::with_clock(TIMEOUT, clock));
+    writer.write_txn_watchdog = Some(watchdog.clone());
+
+    let logs = LogBuffer::default();
+    let log_writer = logs.clone();
+    let subscriber =
+        tracing_subscriber::fmt().with_writer(move || log_writer.clone()).with_ansi(false).finish();
+    tracing::subscriber::with_default(subscriber, || {
+        let txn = writer.begin_rw_txn().unwrap();
+        assert!(!watchdog.check());
+
+        // Advance the clock past the timeout. The transaction is logged only once.
+        *now.lock().unwrap() += TIMEOUT + Duration::from_secs(1);
+        assert!(watchdog.check());
+        assert!(!watchdog.check());
+        txn.commit().unwrap();
+
+        // A committed transaction isn't watched anymore.
+        assert!(!watchdog.check());
+    });
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let warnings = logs.lines().filter(|line| line.contains("WARN")).collect::<Vec<_>>();
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0].contains("is open for 61s, which blocks all the other writes"));
+    assert!(warnings[1].contains("was closed after being open for 61s"));
+}
+
+#[test]
+fn writing_past_the_max_size_fails_with_map_full() {
+    let (config, _temp_dir) = get_test_config(None);
+    let db_config = DbConfig {
+        min_size: 1 << 20,    // 1MB
+        max_size: 1 << 21,    // 2MB
+        growth_step: 1 << 20, // 1MB
+        ..config.db_config
+    };
+    let (_reader, mut writer) = open_env(&db_config).unwrap();
+    let table_id = writer.create_table::<[u8; 4], NoVersionValueWrapper<u32>>("table").unwrap();
+
+    let wtxn = writer.begin_rw_txn().unwrap();
+    let table = wtxn.open_table(&table_id).unwrap();
+    let result = (0..u32::MAX).try_for_each(|key| table.insert(&wtxn, &key.to_be_bytes(), &key));
+    let err = result.unwrap_err();
+    assert_matches!(err, DbError::MapFull(libmdbx::Error::MapFull));
+    // The original error of the database library is kept as the source.
+    assert!(err.source().is_some());
+}
+
 #[test]
 fn get_page_size_test() {
     // Good values.
@@ -222,3 +706,97 @@ fn test_iter() {
         index += 1;
     }
 }
+
+// Values that are written as bytes, and fail the test if they are read.
+#[derive(Debug)]
+struct UnreadableValue;
+
+impl ValueSerde for UnreadableValue {
+    type Value = Vec<u8>;
+
+    fn serialize(obj: &Self::Value) -> Result<Vec<u8>, DbError> {
+        NoVersionValueWrapper::<Vec<u8>>::serialize(obj)
+    }
+
+    fn deserialize(_bytes: &mut impl std::io::Read) -> Option<Self::Value> {
+        panic!("The value should not be deserialized.");
+    }
+}
+
+#[test]
+fn key_iteration_skips_the_values() {
+    const NUM_ENTRIES: u32 = 16;
+    const VALUE_SIZE: usize = 1 << 16;
+    let ((reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 4], UnreadableValue>("table").unwrap();
+    let wtxn = writer.begin_rw_txn().unwrap();
+    let table = wtxn.open_table(&table_id).unwrap();
+    for i in 0..NUM_ENTRIES {
+        table.append(&wtxn, &i.to_be_bytes(), &vec![0; VALUE_SIZE]).unwrap();
+    }
+    wtxn.commit().unwrap();
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let table = txn.open_table(&table_id).unwrap();
+    let mut cursor = table.cursor(&txn).unwrap();
+    let keys = DbKeyIter::new(&mut cursor).collect::<DbResult<Vec<_>>>().unwrap();
+    assert_eq!(keys, (0..NUM_ENTRIES).map(u32::to_be_bytes).collect::<Vec<_>>());
+}
+
+#[test]
+fn libmdbx_version_is_parseable() {
+    let version = libmdbx_version();
+    assert!(!version.version.is_empty());
+    let version_parts = version
+        .version
+        .split('.')
+        .map(|part| part.parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(version_parts.len(), 4);
+    // The version doesn't change at runtime.
+    assert_eq!(version, libmdbx_version());
+}
+
+#[test]
+fn paging_through_a_table_yields_each_entry_once() {
+    const PAGE_SIZE: usize = 3;
+    let ((reader, mut writer), _temp_dir) = get_test_env();
+    let table_id = writer.create_table::<[u8; 4], NoVersionValueWrapper<u32>>("table").unwrap();
+    let insert = |writer: &mut DbWriter, keys: &[u32]| {
+        let wtxn = writer.begin_rw_txn().unwrap();
+        let table = wtxn.open_table(&table_id).unwrap();
+        for key in keys {
+            table.insert(&wtxn, &key.to_be_bytes(), key).unwrap();
+        }
+        wtxn.commit().unwrap();
+    };
+    insert(&mut writer, &(0..10).map(|i| i * 2).collect::<Vec<_>>());
+
+    let mut scanned_values = Vec::new();
+    let mut continuation_token: Option<ContinuationToken> = None;
+    let start_key = 1_u32.to_be_bytes();
+    loop {
+        // Each page is read in its own transaction, like the pages of separate requests.
+        let txn = reader.begin_ro_txn().unwrap();
+        let table = txn.open_table(&table_id).unwrap();
+        let start = match &continuation_token {
+            Some(token) => PageStart::After(token),
+            None => PageStart::Key(&start_key),
+        };
+        let (page, next_token) = table.scan_page(&txn, start, PAGE_SIZE).unwrap();
+        assert!(page.len() <= PAGE_SIZE);
+        scanned_values.extend(page.into_iter().map(|(_, value)| value));
+        if scanned_values.len() == PAGE_SIZE {
+            // Entries written between the pages are scanned only if they're past the last page.
+            insert(&mut writer, &[3, 13]);
+        }
+        // The token survives a round trip through its bytes.
+        continuation_token =
+            next_token.map(|token| ContinuationToken::from_bytes(token.as_bytes().to_vec()));
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+    assert_eq!(scanned_values, vec![2, 4, 6, 8, 10, 12, 13, 14, 16, 18]);
+}
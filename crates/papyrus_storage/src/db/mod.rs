@@ -8,38 +8,60 @@
 //! - The keys are always sorted and range lookups are supported.
 //!
 //! Guarantees:
-//! - The serialization is consistent across code versions (though, not necessarily across
-//!   machines).
+//! - The serialization is consistent across code versions and across machines: the integers are
+//!   encoded in big-endian, or as varints, regardless of the byte order of the machine. Big-endian
+//!   keeps the order of the serialized integer keys the same as the order of the integers.
+//! - The database files are portable between machines with the same byte order, e.g. between x86
+//!   and ARM hosts. The layout of the pages of libmdbx itself depends on the byte order.
 
 #[cfg(test)]
 mod db_test;
 
+/// Compaction of the database file, to return its free pages to the file system.
+pub mod compaction;
 /// Statistics and information about the database.
 pub mod db_stats;
+mod read_cache;
+mod read_txn_tracker;
+mod write_txn_watchdog;
 // TODO(yair): Make the serialization module pub(crate).
 #[doc(hidden)]
 pub mod serialization;
 
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::result;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use libmdbx::{Cursor, Geometry, PageSize, TableFlags, WriteFlags, WriteMap};
-use papyrus_config::dumping::{ser_param, SerializeConfig};
+use libmdbx::{Cursor, DatabaseFlags, Geometry, Mode, PageSize, TableFlags, WriteFlags, WriteMap};
+use papyrus_config::converters::deserialize_optional_seconds_to_duration;
+use papyrus_config::dumping::{ser_optional_param, ser_param, SerializeConfig};
 use papyrus_config::validators::{validate_ascii, validate_path_exists};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use serde::{Deserialize, Serialize};
 use starknet_api::core::ChainId;
+use tracing::warn;
 use validator::Validate;
 
-use self::serialization::{Key, ValueSerde};
+use self::read_cache::{ReadCache, TxnReadCache};
+use self::read_txn_tracker::{ReadTxnTracker, TrackedReadTxn};
+use self::serialization::{Key, StorageSerdeEx, ValueSerde};
+use self::write_txn_watchdog::{WatchedWriteTxn, WriteTxnWatchdog};
+use crate::changelog::{ChangelogEntry, ChangelogKey, CHANGELOG_TABLE_NAME};
+use crate::{STORAGE_COMMIT_LATENCY_SECONDS, STORAGE_COMMIT_WRITTEN_BYTES};
 
-// Maximum number of Sub-Databases.
-const MAX_DBS: usize = 19;
+/// The maximum number of custom tables that can be added to the storage, on top of the built-in
+/// tables.
+pub const MAX_CUSTOM_TABLES: usize = 13;
+
+// Maximum number of Sub-Databases: the built-in tables and the custom tables.
+const MAX_DBS: usize = 22 + MAX_CUSTOM_TABLES;
 
 // Note that NO_TLS mode is used by default.
 type EnvironmentKind = WriteMap;
@@ -67,6 +89,49 @@ pub struct DbConfig {
     pub max_size: usize,
     /// The growth step of the database.
     pub growth_step: isize,
+    /// The durability of the committed write transactions.
+    pub sync_mode: SyncMode,
+    /// The number of deserialized values to cache in memory for reads. If None, reads aren't
+    /// cached.
+    pub read_cache_capacity: Option<NonZeroUsize>,
+    /// The age after which an open read transaction is logged and reported as stale. If None, the
+    /// read transactions aren't tracked.
+    #[serde(deserialize_with = "deserialize_optional_seconds_to_duration")]
+    pub stale_read_txn_threshold: Option<Duration>,
+    /// The age after which an open write transaction is logged, since it blocks all the other
+    /// writes. If None, the write transactions aren't watched.
+    #[serde(deserialize_with = "deserialize_optional_seconds_to_duration")]
+    pub write_txn_timeout: Option<Duration>,
+}
+
+/// The durability modes of the database. The modes other than [`SyncMode::Durable`] speed up the
+/// writes by flushing them to the disk lazily, and are meant for syncing data that can be fetched
+/// again, e.g. when bootstrapping a node.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Every commit is flushed to the disk, so committed transactions survive a system crash.
+    #[default]
+    Durable,
+    /// The metadata isn't flushed on commit, so a system crash may undo the last committed
+    /// transaction.
+    NoMetaSync,
+    /// Nothing is flushed on commit, so a system crash may undo the transactions that were
+    /// committed since the last flush. The database can't get corrupted.
+    SafeNoSync,
+    /// Nothing is flushed on commit and the previous flushed state isn't kept, so a system crash
+    /// may corrupt the database.
+    UtterlyNoSync,
+}
+
+impl From<SyncMode> for libmdbx::SyncMode {
+    fn from(sync_mode: SyncMode) -> Self {
+        match sync_mode {
+            SyncMode::Durable => libmdbx::SyncMode::Durable,
+            SyncMode::NoMetaSync => libmdbx::SyncMode::NoMetaSync,
+            SyncMode::SafeNoSync => libmdbx::SyncMode::SafeNoSync,
+            SyncMode::UtterlyNoSync => libmdbx::SyncMode::UtterlyNoSync,
+        }
+    }
 }
 
 impl Default for DbConfig {
@@ -78,13 +143,17 @@ impl Default for DbConfig {
             min_size: 1 << 20,    // 1MB
             max_size: 1 << 40,    // 1TB
             growth_step: 1 << 32, // 4GB
+            sync_mode: SyncMode::Durable,
+            read_cache_capacity: None,
+            stale_read_txn_threshold: None,
+            write_txn_timeout: None,
         }
     }
 }
 
 impl SerializeConfig for DbConfig {
     fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
-        BTreeMap::from_iter([
+        let mut dumped_config = BTreeMap::from_iter([
             ser_param(
                 "path_prefix",
                 &self.path_prefix,
@@ -124,7 +193,42 @@ impl SerializeConfig for DbConfig {
                  grow.",
                 ParamPrivacyInput::Public,
             ),
-        ])
+            ser_param(
+                "sync_mode",
+                &self.sync_mode,
+                "The durability of the storage writes, one of Durable, NoMetaSync, SafeNoSync and \
+                 UtterlyNoSync. The modes other than Durable are faster, but may lose the latest \
+                 writes on a system crash, and UtterlyNoSync may even corrupt the storage.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        dumped_config.extend(ser_optional_param(
+            &self.read_cache_capacity,
+            NonZeroUsize::new(10_000).expect("The read cache capacity should be positive."),
+            "read_cache_capacity",
+            "The number of values read from the storage to cache in memory. If not set, reads \
+             aren't cached.",
+            ParamPrivacyInput::Public,
+        ));
+        dumped_config.extend(ser_optional_param(
+            &self.stale_read_txn_threshold.map(|threshold| threshold.as_secs()),
+            300,
+            "stale_read_txn_threshold",
+            "The number of seconds after which an open read transaction is logged and reported as \
+             stale, since it prevents the storage from reclaiming space. If not set, the read \
+             transactions aren't tracked.",
+            ParamPrivacyInput::Public,
+        ));
+        dumped_config.extend(ser_optional_param(
+            &self.write_txn_timeout.map(|timeout| timeout.as_secs()),
+            60,
+            "write_txn_timeout",
+            "The number of seconds after which an open write transaction is logged, since it \
+             blocks all the other writes to the storage. If not set, the write transactions \
+             aren't watched.",
+            ParamPrivacyInput::Public,
+        ));
+        dumped_config
     }
 }
 
@@ -138,24 +242,74 @@ impl DbConfig {
 /// An error that can occur when interacting with the database.
 #[derive(thiserror::Error, Debug)]
 pub enum DbError {
-    /// An error that occurred in the database library.
+    /// An error that occurred in the database library, other than the ones that have a variant
+    /// of their own.
     #[error(transparent)]
-    Inner(#[from] libmdbx::Error),
+    Inner(libmdbx::Error),
+    /// An error that occurred when the database reached its maximum size
+    /// ([`DbConfig::max_size`]).
+    #[error("The database reached its maximum size.")]
+    MapFull(#[source] libmdbx::Error),
+    /// An error that occurred when the database reached its maximum number of concurrent readers.
+    #[error("The database reached its maximum number of readers.")]
+    ReadersFull(#[source] libmdbx::Error),
+    /// An error that occurred when a requested key or value wasn't found by the database library.
+    #[error("The requested entry was not found in the database.")]
+    NotFound(#[source] libmdbx::Error),
     /// An error that occurred when tried to insert a key that already exists in a table.
     #[error(
         "Key '{}' already exists in table '{}'. Error when tried to insert value '{}'", .0.key,
         .0.table_name, .0.value
     )]
     KeyAlreadyExists(KeyAlreadyExistsError),
-    #[error("Deserialization failed.")]
-    /// An error that occurred during deserialization.
-    InnerDeserialization,
+    /// An error that occurred when deserializing an entry of a table, which means that the stored
+    /// data is malformed. Missing data isn't an error, and is returned as None instead.
+    #[error(
+        "Failed deserializing the entry of key '{}' in table '{}'.", .0.key, .0.table_name
+    )]
+    InnerDeserialization(DeserializationError),
     /// An error that occurred during serialization.
     #[error("Serialization failed.")]
     Serialization,
     /// An error that occurred when trying to open a db file that does not exist.
     #[error("The file '{0}' does not exist.")]
     FileDoesNotExist(PathBuf),
+    /// An error that occurred when accessing the files of the database.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// An error that occurred when trying to close the database while it's still read from.
+    #[error("The database is still in use by readers. Drop all the readers and try again.")]
+    EnvInUse,
+    /// An error that occurred when there isn't enough free disk space for an operation.
+    #[error(
+        "Not enough free disk space: {required} bytes are required, but only {available} bytes \
+         are available."
+    )]
+    NotEnoughDiskSpace {
+        /// The number of bytes the operation requires.
+        required: u64,
+        /// The number of free bytes on the disk.
+        available: u64,
+    },
+    /// An error that occurred when another user of a shared writer held it for longer than
+    /// taking it was allowed to wait.
+    #[error("The database writer was busy for longer than {timeout:?}.")]
+    WriterBusy {
+        /// How long taking the writer waited.
+        timeout: Duration,
+    },
+}
+
+impl From<libmdbx::Error> for DbError {
+    // Classifies the errors of the database library that callers may want to handle.
+    fn from(err: libmdbx::Error) -> Self {
+        match err {
+            libmdbx::Error::MapFull => DbError::MapFull(err),
+            libmdbx::Error::ReadersFull => DbError::ReadersFull(err),
+            libmdbx::Error::NotFound => DbError::NotFound(err),
+            _ => DbError::Inner(err),
+        }
+    }
 }
 
 type DbResult<V> = result::Result<V, DbError>;
@@ -178,6 +332,30 @@ impl KeyAlreadyExistsError {
     }
 }
 
+/// A helper struct for DbError::InnerDeserialization.
+#[derive(Debug)]
+pub struct DeserializationError {
+    /// The name of the table.
+    pub table_name: &'static str,
+    /// The key of the entry that failed to deserialize, or the hex of its bytes if the key itself
+    /// failed to deserialize.
+    pub key: String,
+}
+
+impl DeserializationError {
+    fn of_key(table_name: &'static str, key: &impl Debug) -> DbError {
+        DbError::InnerDeserialization(Self { table_name, key: format!("{:?}", key) })
+    }
+
+    fn of_key_bytes(table_name: &'static str, key_bytes: &[u8]) -> DbError {
+        let key = key_bytes.iter().fold(String::from("0x"), |mut key, byte| {
+            key.push_str(&format!("{byte:02x}"));
+            key
+        });
+        DbError::InnerDeserialization(Self { table_name, key })
+    }
+}
+
 /// Tries to open an MDBX environment and returns a reader and a writer to it.
 /// There is a single non clonable writer instance, to make sure there is only one write transaction
 ///  at any given moment.
@@ -187,6 +365,18 @@ pub(crate) fn open_env(config: &DbConfig) -> DbResult<(DbReader, DbWriter)> {
     if config.enforce_file_exists && !db_file_path.exists() {
         return Err(DbError::FileDoesNotExist(db_file_path));
     }
+    match config.sync_mode {
+        SyncMode::Durable => {}
+        SyncMode::NoMetaSync | SyncMode::SafeNoSync => warn!(
+            "The storage is opened in the {:?} sync mode, the latest writes may be lost on a \
+             system crash.",
+            config.sync_mode
+        ),
+        SyncMode::UtterlyNoSync => warn!(
+            "The storage is opened in the UtterlyNoSync sync mode, it may get corrupted on a \
+             system crash."
+        ),
+    }
     const MAX_READERS: u32 = 1 << 13; // 8K readers
     let env = Arc::new(
         Environment::new()
@@ -196,11 +386,26 @@ pub(crate) fn open_env(config: &DbConfig) -> DbResult<(DbReader, DbWriter)> {
                 page_size: Some(get_page_size(page_size::get())),
                 ..Default::default()
             })
+            .set_flags(DatabaseFlags {
+                mode: Mode::ReadWrite { sync_mode: config.sync_mode.into() },
+                ..Default::default()
+            })
             .set_max_tables(MAX_DBS)
             .set_max_readers(MAX_READERS)
             .open(&config.path())?,
     );
-    Ok((DbReader { env: env.clone() }, DbWriter { env }))
+    let read_cache = match config.read_cache_capacity {
+        Some(capacity) => Some(Arc::new(ReadCache::new(capacity, env.begin_ro_txn()?.id()))),
+        None => None,
+    };
+    let read_txn_tracker = config
+        .stale_read_txn_threshold
+        .map(|stale_threshold| Arc::new(ReadTxnTracker::new(stale_threshold)));
+    let write_txn_watchdog = config.write_txn_timeout.map(WriteTxnWatchdog::spawn);
+    Ok((
+        DbReader { env: env.clone(), read_cache: read_cache.clone(), read_txn_tracker },
+        DbWriter { env, read_cache, write_txn_watchdog },
+    ))
 }
 
 // Size in bytes.
@@ -221,34 +426,79 @@ fn get_page_size(os_page_size: usize) -> PageSize {
 #[derive(Clone, Debug)]
 pub(crate) struct DbReader {
     env: Arc<Environment>,
+    read_cache: Option<Arc<ReadCache>>,
+    read_txn_tracker: Option<Arc<ReadTxnTracker>>,
 }
 
 #[derive(Debug)]
 pub(crate) struct DbWriter {
     env: Arc<Environment>,
+    read_cache: Option<Arc<ReadCache>>,
+    write_txn_watchdog: Option<Arc<WriteTxnWatchdog>>,
 }
 
 impl DbReader {
     pub(crate) fn begin_ro_txn(&self) -> DbResult<DbReadTransaction<'_>> {
-        Ok(DbReadTransaction { txn: self.env.begin_ro_txn()? })
+        let txn = self.env.begin_ro_txn()?;
+        let read_cache = self.read_cache.as_ref().map(|read_cache| read_cache.txn_cache(txn.id()));
+        let tracked_txn = self.read_txn_tracker.as_ref().map(|tracker| tracker.track());
+        Ok(DbReadTransaction {
+            txn,
+            read_cache,
+            changelog: None,
+            bytes_written: Cell::new(0),
+            _tracked_txn: tracked_txn,
+            _watched_txn: None,
+        })
     }
 }
 
 type DbReadTransaction<'env> = DbTransaction<'env, RO>;
 
+impl<'env> DbReadTransaction<'env> {
+    // Returns the names of all the tables in the database file, including tables that the current
+    // code doesn't create, e.g. tables of an older schema.
+    pub(crate) fn list_tables(&self) -> DbResult<Vec<String>> {
+        // The keys of the main table are the names of the other tables.
+        let main_table = self.txn.open_table(None)?;
+        self.txn
+            .cursor(&main_table)?
+            .iter::<Cow<'_, [u8]>, ()>()
+            .map(|entry| Ok(String::from_utf8_lossy(&entry?.0).into_owned()))
+            .collect()
+    }
+}
+
 impl DbWriter {
     pub(crate) fn begin_rw_txn(&mut self) -> DbResult<DbWriteTransaction<'_>> {
-        Ok(DbWriteTransaction { txn: self.env.begin_rw_txn()? })
+        let txn = self.env.begin_rw_txn()?;
+        let read_cache = self.read_cache.as_ref().map(|read_cache| read_cache.txn_cache(txn.id()));
+        let watched_txn = self.write_txn_watchdog.as_ref().map(|watchdog| watchdog.watch(txn.id()));
+        Ok(DbWriteTransaction {
+            txn,
+            read_cache,
+            changelog: None,
+            bytes_written: Cell::new(0),
+            _tracked_txn: None,
+            _watched_txn: watched_txn,
+        })
     }
 
     pub(crate) fn create_table<K: Key + Debug, V: ValueSerde + Debug>(
         &mut self,
         name: &'static str,
     ) -> DbResult<TableIdentifier<K, V>> {
+        self.create_untyped_table(name)?;
+        Ok(TableIdentifier::new(name))
+    }
+
+    // Creates a table without a handle to it, for tables whose types are only known to their
+    // users.
+    pub(crate) fn create_untyped_table(&mut self, name: &'static str) -> DbResult<()> {
         let txn = self.env.begin_rw_txn()?;
         txn.create_table(Some(name), TableFlags::empty())?;
         txn.commit()?;
-        Ok(TableIdentifier { name, _key_type: PhantomData {}, _value_type: PhantomData {} })
+        Ok(())
     }
 }
 
@@ -256,7 +506,19 @@ type DbWriteTransaction<'env> = DbTransaction<'env, RW>;
 
 impl<'a> DbWriteTransaction<'a> {
     pub(crate) fn commit(self) -> DbResult<()> {
-        self.txn.commit()?;
+        let bytes_written = self.bytes_written.get();
+        let start_time = Instant::now();
+        let txn = self.txn;
+        let commit = || {
+            txn.commit()?;
+            Ok(())
+        };
+        match self.read_cache {
+            Some(read_cache) => read_cache.commit(commit)?,
+            None => commit()?,
+        };
+        metrics::histogram!(STORAGE_COMMIT_LATENCY_SECONDS, start_time.elapsed().as_secs_f64());
+        metrics::histogram!(STORAGE_COMMIT_WRITTEN_BYTES, bytes_written as f64);
         Ok(())
     }
 }
@@ -265,10 +527,94 @@ impl<'a> DbWriteTransaction<'a> {
 // Transaction wrappers.
 pub trait TransactionKind {
     type Internal: libmdbx::TransactionKind;
+    // Whether the reads of the transaction may be served from the read cache.
+    const READS_FROM_CACHE: bool;
 }
 
 pub(crate) struct DbTransaction<'env, Mode: TransactionKind> {
     txn: libmdbx::Transaction<'env, Mode::Internal, EnvironmentKind>,
+    // Set only if the read cache is enabled.
+    read_cache: Option<TxnReadCache>,
+    // Set only for write transactions, if the changelog is enabled.
+    changelog: Option<TxnChangelog>,
+    // Set only for read transactions, if their tracking is enabled.
+    _tracked_txn: Option<TrackedReadTxn>,
+    // Set only for write transactions, if their watching is enabled.
+    _watched_txn: Option<WatchedWriteTxn>,
+    // The total size of the keys and values that the transaction wrote. Always 0 for read
+    // transactions.
+    bytes_written: Cell<u64>,
+}
+
+// The position of a write transaction in the changelog.
+struct TxnChangelog {
+    txn_id: u64,
+    next_change: Cell<u32>,
+}
+
+impl<'env> DbTransaction<'env, RW> {
+    // Records the changes of the transaction in the changelog from now on.
+    pub(crate) fn enable_changelog(&mut self) {
+        self.changelog = Some(TxnChangelog { txn_id: self.txn.id(), next_change: Cell::new(0) });
+    }
+
+    // Appends an entry to the changelog. A no-op if the changelog isn't enabled.
+    pub(crate) fn record_change(&self, entry: &ChangelogEntry) -> DbResult<()> {
+        let Some(changelog) = &self.changelog else {
+            return Ok(());
+        };
+        let change_number = changelog.next_change.get();
+        changelog.next_change.set(change_number + 1);
+        let key: ChangelogKey = (changelog.txn_id, change_number);
+        let database = self.txn.open_table(Some(CHANGELOG_TABLE_NAME))?;
+        let (key, entry) = (StorageSerdeEx::serialize(&key)?, StorageSerdeEx::serialize(entry)?);
+        self.txn.put(&database, key, entry, WriteFlags::APPEND)?;
+        Ok(())
+    }
+
+    // Writes a serialized value to a table whose types are unknown to the caller.
+    pub(crate) fn put_raw(
+        &self,
+        table_name: &'static str,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> DbResult<()> {
+        let database = self.txn.open_table(Some(table_name))?;
+        self.txn.put(&database, &key, &value, WriteFlags::UPSERT)?;
+        self.record_write(table_name, key, Some(value))
+    }
+
+    // Deletes a serialized key from a table whose types are unknown to the caller.
+    pub(crate) fn delete_raw(&self, table_name: &'static str, key: Vec<u8>) -> DbResult<()> {
+        let database = self.txn.open_table(Some(table_name))?;
+        if self.txn.del(&database, &key, None)? {
+            self.record_write(table_name, key, None)?;
+        }
+        Ok(())
+    }
+
+    // Records a write of the key, where a None value means the key was deleted.
+    fn record_write(
+        &self,
+        table_name: &'static str,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    ) -> DbResult<()> {
+        let num_bytes = key.len() + value.as_ref().map_or(0, Vec::len);
+        self.bytes_written.set(self.bytes_written.get() + num_bytes as u64);
+        if self.changelog.is_some() {
+            let table = table_name.to_owned();
+            let key = key.clone();
+            self.record_change(&match value {
+                Some(value) => ChangelogEntry::Put { table, key, value },
+                None => ChangelogEntry::Delete { table, key },
+            })?;
+        }
+        if let Some(read_cache) = &self.read_cache {
+            read_cache.record_write(table_name, key);
+        }
+        Ok(())
+    }
 }
 
 impl<'a, Mode: TransactionKind> DbTransaction<'a, Mode> {
@@ -291,6 +637,12 @@ pub(crate) struct TableIdentifier<K: Key + Debug, V: ValueSerde + Debug> {
     _value_type: PhantomData<V>,
 }
 
+impl<K: Key + Debug, V: ValueSerde + Debug> TableIdentifier<K, V> {
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self { name, _key_type: PhantomData {}, _value_type: PhantomData {} }
+    }
+}
+
 pub(crate) struct TableHandle<'env, K: Key + Debug, V: ValueSerde + Debug> {
     database: libmdbx::Table<'env>,
     name: &'static str,
@@ -304,33 +656,92 @@ impl<'env, 'txn, K: Key + Debug, V: ValueSerde + Debug> TableHandle<'env, K, V>
         txn: &'txn DbTransaction<'env, Mode>,
     ) -> DbResult<DbCursor<'txn, Mode, K, V>> {
         let cursor = txn.txn.cursor(&self.database)?;
-        Ok(DbCursor { cursor, _key_type: PhantomData {}, _value_type: PhantomData {} })
+        Ok(DbCursor {
+            cursor,
+            table_name: self.name,
+            _key_type: PhantomData {},
+            _value_type: PhantomData {},
+        })
     }
 
+    // In a RW transaction, the value reflects the transaction's own writes even before it's
+    // committed.
     pub(crate) fn get<Mode: TransactionKind>(
         &'env self,
         txn: &'env DbTransaction<'env, Mode>,
         key: &K,
-    ) -> DbResult<Option<V::Value>> {
+    ) -> DbResult<Option<V::Value>>
+    where
+        V::Value: Clone + Send + Sync + 'static,
+    {
         // TODO: Support zero-copy. This might require a return type of Cow<'env, ValueType>.
         let bin_key = key.serialize()?;
+        let read_cache = txn.read_cache.as_ref().filter(|_| Mode::READS_FROM_CACHE);
+        if let Some(value) = read_cache.and_then(|cache| cache.get(self.name, &bin_key)) {
+            return Ok(Some(value));
+        }
         let Some(bytes) = txn.txn.get::<Cow<'env, [u8]>>(&self.database, &bin_key)? else {
             return Ok(None);
         };
-        let value = V::deserialize(&mut bytes.as_ref()).ok_or(DbError::InnerDeserialization)?;
+        let value = V::deserialize(&mut bytes.as_ref())
+            .ok_or_else(|| DeserializationError::of_key(self.name, key))?;
+        if let Some(read_cache) = read_cache {
+            read_cache.insert(self.name, bin_key, &value);
+        }
         Ok(Some(value))
     }
 
+    // Like get, but returns the default value if the key has no value.
+    pub(crate) fn get_or_default<Mode: TransactionKind>(
+        &'env self,
+        txn: &'env DbTransaction<'env, Mode>,
+        key: &K,
+    ) -> DbResult<V::Value>
+    where
+        V::Value: Clone + Default + Send + Sync + 'static,
+    {
+        Ok(self.get(txn, key)?.unwrap_or_default())
+    }
+
+    // Returns whether the key already had a value, which was overwritten.
     pub(crate) fn upsert(
         &'env self,
         txn: &DbTransaction<'env, RW>,
         key: &K,
         value: &V::Value,
-    ) -> DbResult<()> {
+    ) -> DbResult<bool> {
         let data = V::serialize(value)?;
         let bin_key = key.serialize()?;
-        txn.txn.put(&self.database, bin_key, data, WriteFlags::UPSERT)?;
-        Ok(())
+        // Positioning a cursor on the key tells whether it has a value, and lets an existing value
+        // be replaced in place without searching for the key again.
+        let mut cursor = txn.txn.cursor(&self.database)?;
+        let overwritten = cursor.set::<()>(&bin_key)?.is_some();
+        let flags = if overwritten { WriteFlags::CURRENT } else { WriteFlags::UPSERT };
+        cursor.put(&bin_key, &data, flags)?;
+        txn.record_write(self.name, bin_key, Some(data))?;
+        Ok(overwritten)
+    }
+
+    // Writes the new value only if the current value of the key is the expected one, where None
+    // means that the key has no value. Returns whether the value was written. The read and the
+    // write are in the same write transaction, so no other write can come between them.
+    // Not used by the storage yet.
+    #[allow(dead_code)]
+    pub(crate) fn compare_and_swap(
+        &'env self,
+        txn: &'env DbTransaction<'env, RW>,
+        key: &K,
+        expected: Option<&V::Value>,
+        new: &V::Value,
+    ) -> DbResult<bool>
+    where
+        V::Value: Clone + PartialEq + Send + Sync + 'static,
+    {
+        if self.get(txn, key)?.as_ref() != expected {
+            return Ok(false);
+        }
+        self.upsert(txn, key, new)?;
+        Ok(true)
     }
 
     pub(crate) fn insert(
@@ -341,7 +752,7 @@ impl<'env, 'txn, K: Key + Debug, V: ValueSerde + Debug> TableHandle<'env, K, V>
     ) -> DbResult<()> {
         let data = V::serialize(value)?;
         let bin_key = key.serialize()?;
-        txn.txn.put(&self.database, bin_key, data, WriteFlags::NO_OVERWRITE).map_err(|err| {
+        txn.txn.put(&self.database, &bin_key, &data, WriteFlags::NO_OVERWRITE).map_err(|err| {
             match err {
                 libmdbx::Error::KeyExist => {
                     DbError::KeyAlreadyExists(KeyAlreadyExistsError::new(self.name, key, value))
@@ -349,34 +760,83 @@ impl<'env, 'txn, K: Key + Debug, V: ValueSerde + Debug> TableHandle<'env, K, V>
                 _ => err.into(),
             }
         })?;
+        txn.record_write(self.name, bin_key, Some(data))?;
         Ok(())
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn delete(&'env self, txn: &DbTransaction<'env, RW>, key: &K) -> DbResult<()> {
+    // Writes a key that must be greater than all the keys in the table, which is faster than
+    // inserting it in the middle of the table.
+    pub(crate) fn append(
+        &'env self,
+        txn: &DbTransaction<'env, RW>,
+        key: &K,
+        value: &V::Value,
+    ) -> DbResult<()> {
+        let data = V::serialize(value)?;
         let bin_key = key.serialize()?;
-        txn.txn.del(&self.database, bin_key, None)?;
+        txn.txn.put(&self.database, &bin_key, &data, WriteFlags::APPEND)?;
+        txn.record_write(self.name, bin_key, Some(data))?;
         Ok(())
     }
+
+    // Returns up to `limit` entries from the given start, in the order of their keys, and a token
+    // for resuming the scan after them if the table has more entries. Since the scan resumes by
+    // key rather than by position, writes between the pages don't shift the following pages.
+    pub(crate) fn scan_page<Mode: TransactionKind>(
+        &'env self,
+        txn: &'txn DbTransaction<'env, Mode>,
+        start: PageStart<'_, K>,
+        limit: usize,
+    ) -> DbResult<Page<K, V::Value>> {
+        let mut cursor = self.cursor(txn)?;
+        let mut current = match start {
+            PageStart::Key(key) => cursor.lower_bound(key)?,
+            // The last entry of the previous page is skipped, unless it was deleted since.
+            PageStart::After(token) => match cursor.lower_bound_bytes(&token.0)? {
+                Some((key, _)) if key.serialize()? == token.0 => cursor.next()?,
+                first_entry => first_entry,
+            },
+        };
+        let mut page = Vec::new();
+        while page.len() < limit {
+            let Some(entry) = current else {
+                return Ok((page, None));
+            };
+            page.push(entry);
+            current = cursor.next()?;
+        }
+        let continuation_token = match (current, page.last()) {
+            (Some(_), Some((last_key, _))) => Some(ContinuationToken(last_key.serialize()?)),
+            _ => None,
+        };
+        Ok((page, continuation_token))
+    }
+
+    // Returns whether the key had a value, which was deleted.
+    pub(crate) fn delete(&'env self, txn: &DbTransaction<'env, RW>, key: &K) -> DbResult<bool> {
+        let bin_key = key.serialize()?;
+        let deleted = txn.txn.del(&self.database, &bin_key, None)?;
+        if deleted {
+            txn.record_write(self.name, bin_key, None)?;
+        }
+        Ok(deleted)
+    }
 }
 
 pub(crate) struct DbCursor<'txn, Mode: TransactionKind, K: Key, V: ValueSerde> {
     cursor: Cursor<'txn, Mode::Internal>,
+    table_name: &'static str,
     _key_type: PhantomData<K>,
     _value_type: PhantomData<V>,
 }
 
-impl<'txn, Mode: TransactionKind, K: Key, V: ValueSerde> DbCursor<'txn, Mode, K, V> {
+impl<'txn, Mode: TransactionKind, K: Key + Debug, V: ValueSerde> DbCursor<'txn, Mode, K, V> {
     pub(crate) fn prev(&mut self) -> DbResult<Option<(K, V::Value)>> {
         let prev_cursor_res = self.cursor.prev::<DbKeyType<'_>, DbValueType<'_>>()?;
         match prev_cursor_res {
             None => Ok(None),
             Some((key_bytes, value_bytes)) => {
-                let key =
-                    K::deserialize(&mut key_bytes.as_ref()).ok_or(DbError::InnerDeserialization)?;
-                let value = V::deserialize(&mut value_bytes.as_ref())
-                    .ok_or(DbError::InnerDeserialization)?;
-                Ok(Some((key, value)))
+                self.deserialize_entry(&key_bytes, &value_bytes).map(Some)
             }
         }
     }
@@ -387,31 +847,74 @@ impl<'txn, Mode: TransactionKind, K: Key, V: ValueSerde> DbCursor<'txn, Mode, K,
         match prev_cursor_res {
             None => Ok(None),
             Some((key_bytes, value_bytes)) => {
-                let key =
-                    K::deserialize(&mut key_bytes.as_ref()).ok_or(DbError::InnerDeserialization)?;
-                let value = V::deserialize(&mut value_bytes.as_ref())
-                    .ok_or(DbError::InnerDeserialization)?;
-                Ok(Some((key, value)))
+                self.deserialize_entry(&key_bytes, &value_bytes).map(Some)
             }
         }
     }
 
+    // Like next, but returns only the key, without reading the value.
+    pub(crate) fn next_key(&mut self) -> DbResult<Option<K>> {
+        let Some((key_bytes, ())) = self.cursor.next::<DbKeyType<'_>, ()>()? else {
+            return Ok(None);
+        };
+        let key = K::deserialize(&mut &key_bytes[..])
+            .ok_or_else(|| DeserializationError::of_key_bytes(self.table_name, &key_bytes))?;
+        Ok(Some(key))
+    }
+
     /// Position at first key greater than or equal to specified key.
     pub(crate) fn lower_bound(&mut self, key: &K) -> DbResult<Option<(K, V::Value)>> {
-        let key_bytes = key.serialize()?;
-        let prev_cursor_res =
-            self.cursor.set_range::<DbKeyType<'_>, DbValueType<'_>>(&key_bytes)?;
+        self.lower_bound_bytes(&key.serialize()?)
+    }
+
+    // Like lower_bound, with an already serialized key.
+    fn lower_bound_bytes(&mut self, key_bytes: &[u8]) -> DbResult<Option<(K, V::Value)>> {
+        let prev_cursor_res = self.cursor.set_range::<DbKeyType<'_>, DbValueType<'_>>(key_bytes)?;
         match prev_cursor_res {
             None => Ok(None),
             Some((key_bytes, value_bytes)) => {
-                let key =
-                    K::deserialize(&mut key_bytes.as_ref()).ok_or(DbError::InnerDeserialization)?;
-                let value = V::deserialize(&mut value_bytes.as_ref())
-                    .ok_or(DbError::InnerDeserialization)?;
-                Ok(Some((key, value)))
+                self.deserialize_entry(&key_bytes, &value_bytes).map(Some)
             }
         }
     }
+
+    fn deserialize_entry(&self, key_bytes: &[u8], value_bytes: &[u8]) -> DbResult<(K, V::Value)> {
+        let key = K::deserialize(&mut &key_bytes[..])
+            .ok_or_else(|| DeserializationError::of_key_bytes(self.table_name, key_bytes))?;
+        let value = V::deserialize(&mut &value_bytes[..])
+            .ok_or_else(|| DeserializationError::of_key(self.table_name, &key))?;
+        Ok((key, value))
+    }
+}
+
+/// Where a page of a paginated table scan starts.
+#[derive(Clone, Copy, Debug)]
+pub enum PageStart<'a, K> {
+    /// At the first entry whose key isn't smaller than the given key.
+    Key(&'a K),
+    /// Right after the last entry of the previous page.
+    After(&'a ContinuationToken),
+}
+
+/// The entries of a page of a paginated table scan, and a token for resuming the scan after them if
+/// the table has more entries.
+pub type Page<K, V> = (Vec<(K, V)>, Option<ContinuationToken>);
+
+/// An opaque token for resuming a paginated table scan after the last entry of the previous page.
+/// It holds the serialized key of that entry, so it stays valid while the table is written to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ContinuationToken(Vec<u8>);
+
+impl ContinuationToken {
+    /// Returns the bytes of the token, e.g. for passing it to a client.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Restores a token from the bytes returned by [`ContinuationToken::as_bytes`].
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
 }
 
 /// Iterator for iterating over a DB table
@@ -430,7 +933,7 @@ impl<'cursor, 'txn, Mode: TransactionKind, K: Key, V: ValueSerde>
     }
 }
 
-impl<'cursor, 'txn, Mode: TransactionKind, K: Key, V: ValueSerde> Iterator
+impl<'cursor, 'txn, Mode: TransactionKind, K: Key + Debug, V: ValueSerde> Iterator
     for DbIter<'cursor, 'txn, Mode, K, V>
 {
     type Item = DbResult<(K, V::Value)>;
@@ -441,12 +944,38 @@ impl<'cursor, 'txn, Mode: TransactionKind, K: Key, V: ValueSerde> Iterator
     }
 }
 
+/// Iterator for iterating over the keys of a DB table, without deserializing their values. Faster
+/// than [`DbIter`] for tables whose values are big.
+pub(crate) struct DbKeyIter<'cursor, 'txn, Mode: TransactionKind, K: Key, V: ValueSerde> {
+    cursor: &'cursor mut DbCursor<'txn, Mode, K, V>,
+}
+
+impl<'cursor, 'txn, Mode: TransactionKind, K: Key, V: ValueSerde>
+    DbKeyIter<'cursor, 'txn, Mode, K, V>
+{
+    #[allow(dead_code)]
+    pub(crate) fn new(cursor: &'cursor mut DbCursor<'txn, Mode, K, V>) -> Self {
+        Self { cursor }
+    }
+}
+
+impl<'cursor, 'txn, Mode: TransactionKind, K: Key + Debug, V: ValueSerde> Iterator
+    for DbKeyIter<'cursor, 'txn, Mode, K, V>
+{
+    type Item = DbResult<K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_key().transpose()
+    }
+}
+
 #[doc(hidden)]
 #[derive(Clone, Copy, Debug)]
 pub struct RO {}
 
 impl TransactionKind for RO {
     type Internal = libmdbx::RO;
+    const READS_FROM_CACHE: bool = true;
 }
 
 #[doc(hidden)]
@@ -455,4 +984,6 @@ pub struct RW {}
 
 impl TransactionKind for RW {
     type Internal = libmdbx::RW;
+    // A write transaction needs to see its own uncommitted writes.
+    const READS_FROM_CACHE: bool = false;
 }
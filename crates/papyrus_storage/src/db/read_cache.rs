@@ -0,0 +1,210 @@
+//! An in-memory cache of deserialized values, in front of the reads of the tables.
+//!
+//! Each cached value is tagged with the id of the snapshot it was read at. A write transaction
+//! evicts the keys it wrote right after it's committed. A read transaction whose snapshot id is
+//! at least the tag of a cached value, and which doesn't see a write whose keys weren't evicted
+//! yet, can be served the value, since any write to its key between the two snapshots would have
+//! evicted it. A read transaction caches the values it reads only if no write that was committed
+//! after its snapshot was evicted yet, since such a write may have changed them.
+//!
+//! The lock of the cache is only held while the entries are accessed, so transactions never wait
+//! for a commit to finish.
+//!
+//! Write transactions don't use the cache for reads, since they need to see their own writes.
+
+use std::any::Any;
+use std::borrow::Borrow;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use lru::LruCache;
+
+use super::DbResult;
+
+// The table name and the serialized key.
+#[derive(Hash, PartialEq, Eq)]
+struct CacheKey {
+    table_name: &'static str,
+    key: Vec<u8>,
+}
+
+// A view of a cache key that may borrow the serialized key, so that looking up a key doesn't need
+// to copy it. Its hash must be the same as the hash of the matching CacheKey.
+trait CacheKeyRef {
+    fn table_name(&self) -> &str;
+    fn key(&self) -> &[u8];
+}
+
+impl CacheKeyRef for CacheKey {
+    fn table_name(&self) -> &str {
+        self.table_name
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+impl CacheKeyRef for (&'static str, &[u8]) {
+    fn table_name(&self) -> &str {
+        self.0
+    }
+
+    fn key(&self) -> &[u8] {
+        self.1
+    }
+}
+
+impl<'a> Borrow<dyn CacheKeyRef + 'a> for CacheKey {
+    fn borrow(&self) -> &(dyn CacheKeyRef + 'a) {
+        self
+    }
+}
+
+impl Hash for dyn CacheKeyRef + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.table_name().hash(state);
+        self.key().hash(state);
+    }
+}
+
+impl PartialEq for dyn CacheKeyRef + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.table_name() == other.table_name() && self.key() == other.key()
+    }
+}
+
+impl Eq for dyn CacheKeyRef + '_ {}
+
+struct CacheEntry {
+    value: Arc<dyn Any + Send + Sync>,
+    // The id of the snapshot the value was read at.
+    read_at_txn_id: u64,
+}
+
+struct CacheState {
+    // The id of the last committed write transaction whose keys were evicted.
+    evicted_txn_id: u64,
+    // The id of the write transaction that is being committed, if its keys weren't evicted yet.
+    committing_txn_id: Option<u64>,
+    entries: LruCache<CacheKey, CacheEntry>,
+}
+
+pub(crate) struct ReadCache {
+    state: Mutex<CacheState>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl Debug for ReadCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadCache").field("capacity", &self.lock().entries.cap()).finish()
+    }
+}
+
+impl ReadCache {
+    // Creates an empty cache for a storage whose last committed transaction has the given id.
+    pub(crate) fn new(capacity: NonZeroUsize, last_txn_id: u64) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                evicted_txn_id: last_txn_id,
+                committing_txn_id: None,
+                entries: LruCache::new(capacity),
+            }),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    // Returns the view of the cache of the transaction with the given id. For a read transaction,
+    // this is the id of its snapshot.
+    pub(crate) fn txn_cache(self: &Arc<Self>, txn_id: u64) -> TxnReadCache {
+        TxnReadCache { cache: self.clone(), txn_id, written_keys: Mutex::default() }
+    }
+
+    // Returns the number of reads that were served from the cache and the number of reads that
+    // weren't.
+    #[cfg(test)]
+    pub(crate) fn stats(&self) -> (usize, usize) {
+        (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().expect("Read cache lock should not be poisoned.")
+    }
+}
+
+/// The view of the read cache of a single transaction.
+pub(crate) struct TxnReadCache {
+    cache: Arc<ReadCache>,
+    txn_id: u64,
+    // The keys written by a write transaction, to evict when it commits.
+    written_keys: Mutex<Vec<CacheKey>>,
+}
+
+impl TxnReadCache {
+    pub(crate) fn get<V: Clone + 'static>(
+        &self,
+        table_name: &'static str,
+        key: &[u8],
+    ) -> Option<V> {
+        let value = {
+            let mut state = self.cache.lock();
+            let sees_unevicted_write =
+                state.committing_txn_id.is_some_and(|txn_id| txn_id <= self.txn_id);
+            match state.entries.get(&(table_name, key) as &dyn CacheKeyRef) {
+                Some(entry) if entry.read_at_txn_id <= self.txn_id && !sees_unevicted_write => {
+                    entry.value.downcast_ref::<V>().cloned()
+                }
+                _ => None,
+            }
+        };
+        let counter = if value.is_some() { &self.cache.hits } else { &self.cache.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    pub(crate) fn insert<V: Clone + Send + Sync + 'static>(
+        &self,
+        table_name: &'static str,
+        key: Vec<u8>,
+        value: &V,
+    ) {
+        let mut state = self.cache.lock();
+        if state.evicted_txn_id > self.txn_id {
+            return;
+        }
+        let entry = CacheEntry { value: Arc::new(value.clone()), read_at_txn_id: self.txn_id };
+        state.entries.put(CacheKey { table_name, key }, entry);
+    }
+
+    pub(crate) fn record_write(&self, table_name: &'static str, key: Vec<u8>) {
+        self.written_keys
+            .lock()
+            .expect("Written keys lock should not be poisoned.")
+            .push(CacheKey { table_name, key });
+    }
+
+    // Commits the write transaction, and evicts the keys it wrote once it's committed. Until
+    // then, the transactions that see its writes aren't served from the cache.
+    pub(crate) fn commit(self, commit: impl FnOnce() -> DbResult<()>) -> DbResult<()> {
+        let written_keys =
+            self.written_keys.into_inner().expect("Written keys lock should not be poisoned.");
+        if written_keys.is_empty() {
+            return commit();
+        }
+        self.cache.lock().committing_txn_id = Some(self.txn_id);
+        let result = commit();
+        let mut state = self.cache.lock();
+        state.committing_txn_id = None;
+        // The keys are evicted even if the commit failed, since it may have been applied.
+        state.evicted_txn_id = self.txn_id;
+        for key in written_keys {
+            state.entries.pop(&key);
+        }
+        result
+    }
+}
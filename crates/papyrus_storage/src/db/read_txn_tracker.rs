@@ -0,0 +1,105 @@
+//! Tracking of the open read transactions, to find the ones that stay open for too long.
+//!
+//! An open read transaction pins the snapshot it reads from, so libmdbx can't reuse the pages that
+//! were freed after it. The tracking is only diagnostic: a stale transaction is logged and reported
+//! in the stats, with the backtrace of where it began if backtraces are enabled (see
+//! [`std::backtrace`]), but it isn't aborted, since aborting it in the middle of a read is unsafe.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use super::db_stats::StaleReadTxn;
+
+// The source of the current time.
+pub(crate) type Clock = Arc<dyn Fn() -> Instant + Send + Sync>;
+
+struct OpenReadTxn {
+    began_at: Instant,
+    backtrace: Backtrace,
+}
+
+pub(crate) struct ReadTxnTracker {
+    stale_threshold: Duration,
+    clock: Clock,
+    next_id: AtomicU64,
+    open_txns: Mutex<HashMap<u64, OpenReadTxn>>,
+}
+
+impl Debug for ReadTxnTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadTxnTracker").field("stale_threshold", &self.stale_threshold).finish()
+    }
+}
+
+impl ReadTxnTracker {
+    pub(crate) fn new(stale_threshold: Duration) -> Self {
+        Self::with_clock(stale_threshold, Arc::new(Instant::now))
+    }
+
+    pub(crate) fn with_clock(stale_threshold: Duration, clock: Clock) -> Self {
+        Self { stale_threshold, clock, next_id: AtomicU64::new(0), open_txns: Mutex::default() }
+    }
+
+    // Starts tracking a read transaction until the returned guard is dropped.
+    pub(crate) fn track(self: &Arc<Self>) -> TrackedReadTxn {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let open_txn = OpenReadTxn { began_at: (self.clock)(), backtrace: Backtrace::capture() };
+        self.lock().insert(id, open_txn);
+        TrackedReadTxn { tracker: self.clone(), id }
+    }
+
+    // Returns the read transactions that are open for longer than the threshold, and logs them.
+    pub(crate) fn get_stale_txns(&self) -> Vec<StaleReadTxn> {
+        let now = (self.clock)();
+        let open_txns = self.lock();
+        let mut stale_txns = open_txns
+            .values()
+            .filter_map(|open_txn| {
+                let age = now.saturating_duration_since(open_txn.began_at);
+                if age <= self.stale_threshold {
+                    return None;
+                }
+                let backtrace = match open_txn.backtrace.status() {
+                    BacktraceStatus::Captured => Some(open_txn.backtrace.to_string()),
+                    _ => None,
+                };
+                warn!(
+                    "A read transaction is open for {age:?}, which prevents the storage from \
+                     reclaiming space. Began at: {}",
+                    backtrace.as_deref().unwrap_or("unknown, enable RUST_BACKTRACE to see where")
+                );
+                Some(StaleReadTxn { age_secs: age.as_secs(), backtrace })
+            })
+            .collect::<Vec<_>>();
+        stale_txns.sort_by_key(|stale_txn| std::cmp::Reverse(stale_txn.age_secs));
+        stale_txns
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, OpenReadTxn>> {
+        self.open_txns.lock().expect("Open read transactions lock should not be poisoned.")
+    }
+}
+
+/// Tracks a read transaction while it's open.
+pub(crate) struct TrackedReadTxn {
+    tracker: Arc<ReadTxnTracker>,
+    id: u64,
+}
+
+impl Drop for TrackedReadTxn {
+    fn drop(&mut self) {
+        let Some(open_txn) = self.tracker.lock().remove(&self.id) else {
+            return;
+        };
+        let age = (self.tracker.clock)().saturating_duration_since(open_txn.began_at);
+        if age > self.tracker.stale_threshold {
+            warn!("A read transaction was closed after being open for {age:?}.");
+        }
+    }
+}
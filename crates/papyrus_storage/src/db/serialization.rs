@@ -29,6 +29,9 @@ impl<T: StorageSerde> StorageSerdeEx for T {
 }
 
 /// Trait for deserializing and serializing values into buffers.
+///
+/// The encoding must not depend on the machine, so that the database files are portable: integers
+/// are encoded in big-endian (or as varints), never in the native byte order.
 pub trait StorageSerde: Sized {
     fn serialize_into(&self, res: &mut impl std::io::Write) -> Result<(), StorageSerdeError>;
 
@@ -0,0 +1,128 @@
+//! Watching the open write transaction, to find one that stays open for too long.
+//!
+//! There is a single writer, so an open write transaction blocks all the other writes until it's
+//! committed or dropped. The watching is only diagnostic: a write transaction that is open for
+//! longer than the timeout is logged, with the backtrace of where it began if backtraces are
+//! enabled (see [`std::backtrace`]), but it isn't aborted, since it can't be aborted safely from
+//! outside the code that holds it.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use super::read_txn_tracker::Clock;
+
+// The minimal time between two checks of the background thread, so that a short timeout won't
+// make it spin.
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+struct OpenWriteTxn {
+    txn_id: u64,
+    began_at: Instant,
+    backtrace: Backtrace,
+    // Whether the transaction was already logged as open for too long.
+    reported: bool,
+}
+
+pub(crate) struct WriteTxnWatchdog {
+    timeout: Duration,
+    clock: Clock,
+    open_txn: Mutex<Option<OpenWriteTxn>>,
+}
+
+impl Debug for WriteTxnWatchdog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WriteTxnWatchdog").field("timeout", &self.timeout).finish()
+    }
+}
+
+impl WriteTxnWatchdog {
+    // Creates a watchdog with a background thread that checks the open write transaction
+    // periodically. The thread exits once the watchdog is dropped.
+    pub(crate) fn spawn(timeout: Duration) -> Arc<Self> {
+        let watchdog = Arc::new(Self::with_clock(timeout, Arc::new(Instant::now)));
+        let weak_watchdog = Arc::downgrade(&watchdog);
+        let check_interval = (timeout / 2).max(MIN_CHECK_INTERVAL);
+        thread::Builder::new()
+            .name("write_txn_watchdog".to_owned())
+            .spawn(move || run_checks(weak_watchdog, check_interval))
+            .expect("Failed to spawn the write transaction watchdog thread.");
+        watchdog
+    }
+
+    pub(crate) fn with_clock(timeout: Duration, clock: Clock) -> Self {
+        Self { timeout, clock, open_txn: Mutex::default() }
+    }
+
+    // Starts watching a write transaction until the returned guard is dropped.
+    pub(crate) fn watch(self: &Arc<Self>, txn_id: u64) -> WatchedWriteTxn {
+        let open_txn = OpenWriteTxn {
+            txn_id,
+            began_at: (self.clock)(),
+            backtrace: Backtrace::capture(),
+            reported: false,
+        };
+        *self.lock() = Some(open_txn);
+        WatchedWriteTxn { watchdog: self.clone() }
+    }
+
+    // Logs the open write transaction if it's open for longer than the timeout. Each transaction
+    // is logged once while it's open. Returns whether it was logged.
+    pub(crate) fn check(&self) -> bool {
+        let now = (self.clock)();
+        let mut open_txn = self.lock();
+        let Some(open_txn) = open_txn.as_mut() else {
+            return false;
+        };
+        let age = now.saturating_duration_since(open_txn.began_at);
+        if open_txn.reported || age <= self.timeout {
+            return false;
+        }
+        let backtrace = match open_txn.backtrace.status() {
+            BacktraceStatus::Captured => open_txn.backtrace.to_string(),
+            _ => "unknown, enable RUST_BACKTRACE to see where".to_owned(),
+        };
+        warn!(
+            "Write transaction {} is open for {age:?}, which blocks all the other writes to the \
+             storage. Began at: {backtrace}",
+            open_txn.txn_id
+        );
+        open_txn.reported = true;
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<OpenWriteTxn>> {
+        self.open_txn.lock().expect("Open write transaction lock should not be poisoned.")
+    }
+}
+
+fn run_checks(watchdog: Weak<WriteTxnWatchdog>, check_interval: Duration) {
+    loop {
+        thread::sleep(check_interval);
+        let Some(watchdog) = watchdog.upgrade() else {
+            return;
+        };
+        watchdog.check();
+    }
+}
+
+/// Watches a write transaction while it's open.
+pub(crate) struct WatchedWriteTxn {
+    watchdog: Arc<WriteTxnWatchdog>,
+}
+
+impl Drop for WatchedWriteTxn {
+    fn drop(&mut self) {
+        let Some(open_txn) = self.watchdog.lock().take() else {
+            return;
+        };
+        if open_txn.reported {
+            let age = (self.watchdog.clock)().saturating_duration_since(open_txn.began_at);
+            warn!("Write transaction {} was closed after being open for {age:?}.", open_txn.txn_id);
+        }
+    }
+}
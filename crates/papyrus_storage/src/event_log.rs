@@ -0,0 +1,137 @@
+//! An append-only log of events, for debugging the behavior of the storage users, e.g. the sync.
+//!
+//! Each event is keyed by the next number in the log, and holds the time it was appended at. The
+//! log is enabled with [`StorageConfig::enable_event_log`]. While it's disabled, appending to it is
+//! a no-op.
+//!
+//! Get the log of a [`StorageTxn`] with [`StorageTxn::event_log`].
+//! # Example
+//! ```
+//! use papyrus_storage::open_storage;
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
+//! # use starknet_api::core::ChainId;
+//!
+//! # let dir_handle = tempfile::tempdir().unwrap();
+//! # let dir = dir_handle.path().to_path_buf();
+//! # let db_config = DbConfig {
+//! #     path_prefix: dir,
+//! #     chain_id: ChainId("SN_MAIN".to_owned()),
+//! #     enforce_file_exists: false,
+//! #     min_size: 1 << 20,    // 1MB
+//! #     max_size: 1 << 35,    // 32GB
+//! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
+//! # };
+//! # let storage_config = StorageConfig{db_config, enable_event_log: true, ..Default::default()};
+//! let (reader, mut writer) = open_storage(storage_config)?;
+//! let txn = writer.begin_rw_txn()?; // Start a RW transaction.
+//! txn.event_log().append("Downloaded block 0.")?; // Append an event.
+//! txn.commit()?; // Commit the transaction.
+//! let events = reader.begin_ro_txn()?.event_log().get_last_events(10)?;
+//! assert_eq!(events[0].1.event, "Downloaded block 0.");
+//! # Ok::<(), papyrus_storage::StorageError>(())
+//! ```
+//!
+//! [`StorageConfig::enable_event_log`]: crate::StorageConfig::enable_event_log
+
+#[cfg(test)]
+#[path = "event_log_test.rs"]
+mod event_log_test;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::{TransactionKind, RW};
+use crate::{StorageResult, StorageTxn};
+
+/// The number of an event in the log. The events are numbered from 0, in the order they were
+/// appended.
+pub type EventLogKey = u64;
+
+/// An event in the log.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EventLogEntry {
+    /// The time the event was appended at, in milliseconds since the Unix epoch.
+    pub timestamp_millis: u64,
+    /// The description of the event.
+    pub event: String,
+}
+
+/// The event log, as seen by the transaction that opened it.
+pub struct EventLog<'txn, 'env, Mode: TransactionKind> {
+    txn: &'txn StorageTxn<'env, Mode>,
+}
+
+impl<'env, Mode: TransactionKind> StorageTxn<'env, Mode> {
+    /// Returns the event log, as seen by this transaction.
+    pub fn event_log(&self) -> EventLog<'_, 'env, Mode> {
+        EventLog { txn: self }
+    }
+}
+
+impl<'txn, 'env, Mode: TransactionKind> EventLog<'txn, 'env, Mode> {
+    /// Returns the last events in the log, at most `n` of them, from the oldest.
+    pub fn get_last_events(&self, n: usize) -> StorageResult<Vec<(EventLogKey, EventLogEntry)>> {
+        let event_log_table = self.txn.open_table(&self.txn.tables.event_log)?;
+        let mut cursor = event_log_table.cursor(&self.txn.txn)?;
+        let mut events = Vec::new();
+        // An unpositioned cursor moves back from the last event.
+        while events.len() < n {
+            let Some(event) = cursor.prev()? else {
+                break;
+            };
+            events.push(event);
+        }
+        events.reverse();
+        Ok(events)
+    }
+
+    /// Returns the events that were appended at the given time or later, from the oldest. The log
+    /// is walked back from the last event until an older event is found, so only the recent part
+    /// of the log is read.
+    pub fn events_since(
+        &self,
+        timestamp_millis: u64,
+    ) -> StorageResult<impl Iterator<Item = (EventLogKey, EventLogEntry)>> {
+        let event_log_table = self.txn.open_table(&self.txn.tables.event_log)?;
+        let mut cursor = event_log_table.cursor(&self.txn.txn)?;
+        let mut events = Vec::new();
+        while let Some(event) = cursor.prev()? {
+            if event.1.timestamp_millis < timestamp_millis {
+                break;
+            }
+            events.push(event);
+        }
+        Ok(events.into_iter().rev())
+    }
+}
+
+impl<'txn, 'env> EventLog<'txn, 'env, RW> {
+    /// Appends an event to the log, and returns its key. Returns None if the log is disabled.
+    pub fn append(&self, event: impl Into<String>) -> StorageResult<Option<EventLogKey>> {
+        if !self.txn.enable_event_log {
+            return Ok(None);
+        }
+        let event_log_table = self.txn.open_table(&self.txn.tables.event_log)?;
+        let mut cursor = event_log_table.cursor(&self.txn.txn)?;
+        let key = match cursor.prev()? {
+            Some((last_key, _)) => last_key + 1,
+            None => 0,
+        };
+        let entry =
+            EventLogEntry { timestamp_millis: current_timestamp_millis(), event: event.into() };
+        event_log_table.append(&self.txn.txn, &key, &entry)?;
+        Ok(Some(key))
+    }
+}
+
+// The current time, in milliseconds since the Unix epoch.
+fn current_timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis().try_into().unwrap_or(u64::MAX))
+}
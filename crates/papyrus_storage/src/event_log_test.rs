@@ -0,0 +1,81 @@
+use std::thread::sleep;
+use std::time::Duration;
+
+use super::current_timestamp_millis;
+use crate::test_utils::{get_test_config, get_test_storage};
+use crate::{open_storage, StorageConfig, StorageWriter};
+
+#[test]
+fn append_and_read_events() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) =
+        open_storage(StorageConfig { enable_event_log: true, ..config }).unwrap();
+    assert!(reader.begin_ro_txn().unwrap().event_log().get_last_events(10).unwrap().is_empty());
+
+    // Append events in a few transactions of the writer.
+    let txn = writer.begin_rw_txn().unwrap();
+    let event_log = txn.event_log();
+    assert_eq!(event_log.append("event 0").unwrap(), Some(0));
+    assert_eq!(event_log.append("event 1").unwrap(), Some(1));
+    txn.commit().unwrap();
+    let txn = writer.begin_rw_txn().unwrap();
+    for i in 2..5 {
+        assert_eq!(txn.event_log().append(format!("event {i}")).unwrap(), Some(i));
+    }
+    txn.commit().unwrap();
+
+    // The events are read back in the order they were appended.
+    let txn = reader.begin_ro_txn().unwrap();
+    let events = txn.event_log().get_last_events(3).unwrap();
+    let keys = events.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+    assert_eq!(keys, vec![2, 3, 4]);
+    let descriptions = events.iter().map(|(_, entry)| entry.event.as_str()).collect::<Vec<_>>();
+    assert_eq!(descriptions, vec!["event 2", "event 3", "event 4"]);
+    assert!(events.windows(2).all(|pair| pair[0].1.timestamp_millis <= pair[1].1.timestamp_millis));
+
+    let events = txn.event_log().get_last_events(10).unwrap();
+    assert_eq!(events.len(), 5);
+    assert_eq!(events[0].1.event, "event 0");
+}
+
+#[test]
+fn disabled_event_log_is_not_written() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+
+    let txn = writer.begin_rw_txn().unwrap();
+    assert_eq!(txn.event_log().append("event").unwrap(), None);
+    txn.commit().unwrap();
+
+    assert!(reader.begin_ro_txn().unwrap().event_log().get_last_events(10).unwrap().is_empty());
+}
+
+#[test]
+fn events_since_returns_only_the_recent_events() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) =
+        open_storage(StorageConfig { enable_event_log: true, ..config }).unwrap();
+    let append = |writer: &mut StorageWriter, event: &str| {
+        let txn = writer.begin_rw_txn().unwrap();
+        txn.event_log().append(event).unwrap();
+        txn.commit().unwrap();
+    };
+
+    append(&mut writer, "old event 0");
+    append(&mut writer, "old event 1");
+    // Sleeping so that the boundary is strictly between the old and the recent events.
+    sleep(Duration::from_millis(10));
+    let boundary = current_timestamp_millis();
+    sleep(Duration::from_millis(10));
+    append(&mut writer, "recent event 0");
+    append(&mut writer, "recent event 1");
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let events = txn.event_log().events_since(boundary).unwrap().collect::<Vec<_>>();
+    let keys = events.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+    assert_eq!(keys, vec![2, 3]);
+    let descriptions = events.iter().map(|(_, entry)| entry.event.as_str()).collect::<Vec<_>>();
+    assert_eq!(descriptions, vec!["recent event 0", "recent event 1"]);
+
+    assert_eq!(txn.event_log().events_since(0).unwrap().count(), 4);
+    assert_eq!(txn.event_log().events_since(u64::MAX).unwrap().count(), 0);
+}
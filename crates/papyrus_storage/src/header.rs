@@ -6,7 +6,7 @@
 //! # Example
 //! ```
 //! use papyrus_storage::open_storage;
-//! # use papyrus_storage::{db::DbConfig, StorageConfig};
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
 //! # use starknet_api::core::ChainId;
 //! use starknet_api::block::{Block, BlockNumber};
 //! use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
@@ -20,6 +20,10 @@
 //! #     min_size: 1 << 20,    // 1MB
 //! #     max_size: 1 << 35,    // 32GB
 //! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
 //! # };
 //! let block = Block::default();
 //! # let storage_config = StorageConfig{db_config, ..Default::default()};
@@ -103,7 +107,7 @@ pub struct StarknetVersion(pub String);
 impl<'env, Mode: TransactionKind> HeaderStorageReader for StorageTxn<'env, Mode> {
     fn get_header_marker(&self) -> StorageResult<BlockNumber> {
         let markers_table = self.open_table(&self.tables.markers)?;
-        Ok(markers_table.get(&self.txn, &MarkerKind::Header)?.unwrap_or_default())
+        Ok(markers_table.get_or_default(&self.txn, &MarkerKind::Header)?)
     }
 
     fn get_block_header(&self, block_number: BlockNumber) -> StorageResult<Option<BlockHeader>> {
@@ -235,7 +239,7 @@ fn update_marker<'env>(
     block_number: BlockNumber,
 ) -> StorageResult<()> {
     // Make sure marker is consistent.
-    let header_marker = markers_table.get(txn, &MarkerKind::Header)?.unwrap_or_default();
+    let header_marker = markers_table.get_or_default(txn, &MarkerKind::Header)?;
     if header_marker != block_number {
         return Err(StorageError::MarkerMismatch { expected: header_marker, found: block_number });
     };
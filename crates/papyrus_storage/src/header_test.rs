@@ -4,9 +4,11 @@ use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
 use starknet_api::hash::StarkFelt;
 use starknet_api::stark_felt;
 
+use crate::db::serialization::NoVersionValueWrapper;
+use crate::db::{DbError, DeserializationError, TableIdentifier};
 use crate::header::{HeaderStorageReader, HeaderStorageWriter, StarknetVersion};
-use crate::test_utils::get_test_storage;
-use crate::{StorageError, StorageWriter};
+use crate::test_utils::{get_test_config, get_test_storage};
+use crate::{open_storage, StorageConfig, StorageError, StorageWriter};
 
 #[tokio::test]
 async fn append_header() {
@@ -246,3 +248,83 @@ async fn starknet_version() {
         reader.begin_ro_txn().unwrap().get_starknet_version(BlockNumber(3)).unwrap();
     assert_eq!(block_3_starknet_version.unwrap(), second_version);
 }
+
+#[test]
+fn verify_parent_hashes_detects_broken_link() {
+    const BROKEN_BLOCK_NUMBER: BlockNumber = BlockNumber(3);
+    let (config, _temp_dir) = get_test_config(None);
+    {
+        let (_, mut writer) = open_storage(config.clone()).unwrap();
+        let mut txn = writer.begin_rw_txn().unwrap();
+        for i in 0..5_u64 {
+            let parent_hash = if BlockNumber(i) == BROKEN_BLOCK_NUMBER {
+                BlockHash(stark_felt!("0x99"))
+            } else {
+                BlockHash(StarkFelt::from(i))
+            };
+            let header = BlockHeader {
+                block_hash: BlockHash(StarkFelt::from(i + 1)),
+                parent_hash,
+                ..BlockHeader::default()
+            };
+            txn = txn.append_header(BlockNumber(i), &header).unwrap();
+        }
+        txn.commit().unwrap();
+    }
+
+    let Err(err) = open_storage(StorageConfig {
+        verify_parent_hashes: true,
+        parent_hash_verification_depth: None,
+        ..config.clone()
+    }) else {
+        panic!("Unexpected Ok.");
+    };
+    assert_matches!(
+        err,
+        StorageError::ParentHashMismatch { block_number, parent_hash, previous_block_hash }
+        if block_number == BROKEN_BLOCK_NUMBER
+            && parent_hash == BlockHash(stark_felt!("0x99"))
+            && previous_block_hash == BlockHash(StarkFelt::from(3_u64))
+    );
+
+    // The broken link is older than the verified blocks.
+    open_storage(StorageConfig {
+        verify_parent_hashes: true,
+        parent_hash_verification_depth: Some(1),
+        ..config
+    })
+    .unwrap();
+}
+
+#[test]
+fn missing_and_malformed_headers_are_distinguishable() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // Overwrite the stored header with bytes that aren't a header.
+    let malformed_headers_table =
+        TableIdentifier::<BlockNumber, NoVersionValueWrapper<[u8; 1]>>::new("headers");
+    let txn = writer.begin_rw_txn().unwrap();
+    txn.txn
+        .open_table(&malformed_headers_table)
+        .unwrap()
+        .upsert(&txn.txn, &BlockNumber(0), &[0])
+        .unwrap();
+    txn.commit().unwrap();
+
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_block_header(BlockNumber(1)).unwrap(), None);
+    assert_matches!(
+        txn.get_block_header(BlockNumber(0)),
+        Err(StorageError::InnerError(DbError::InnerDeserialization(DeserializationError {
+            table_name: "headers",
+            key,
+        }))) if key == "BlockNumber(0)"
+    );
+}
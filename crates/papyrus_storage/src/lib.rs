@@ -20,7 +20,7 @@
 //!
 //! ```
 //! use papyrus_storage::open_storage;
-//! # use papyrus_storage::{db::DbConfig, StorageConfig};
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
 //! use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter, StarknetVersion};    // Import the header API.
 //! use starknet_api::block::{BlockHeader, BlockNumber};
 //! use starknet_api::core::ChainId;
@@ -34,6 +34,10 @@
 //!     min_size: 1 << 20,    // 1MB
 //!     max_size: 1 << 35,    // 32GB
 //!     growth_step: 1 << 26, // 64MB
+//!     sync_mode: SyncMode::Durable,
+//!     read_cache_capacity: None,
+//!     stale_read_txn_threshold: None,
+//!     write_txn_timeout: None,
 //! };
 //! # let storage_config = StorageConfig{db_config, ..Default::default()};
 //! let (reader, mut writer) = open_storage(storage_config)?;
@@ -51,16 +55,26 @@
 //! [`libmdbx`]: https://docs.rs/libmdbx/latest/libmdbx/
 
 pub mod base_layer;
+pub mod batch;
+pub mod block_notifications;
 pub mod body;
+pub mod changelog;
 pub mod compiled_class;
+pub mod custom_tables;
 pub mod utils;
 // TODO(yair): Make the compression_utils module pub(crate) or extract it from the crate.
 #[doc(hidden)]
 pub mod compression_utils;
 pub mod db;
+pub mod event_log;
 pub mod header;
 pub mod mmap_file;
+pub mod prune;
+pub mod revert;
+pub mod seed;
 mod serializers;
+pub mod shared_writer;
+pub mod snapshot;
 pub mod state;
 mod version;
 
@@ -72,12 +86,16 @@ pub mod test_utils;
 
 use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use block_notifications::{BlockCommitted, BlockCommittedNotifier};
 use body::events::EventIndex;
 use cairo_lang_starknet::casm_contract_class::CasmContractClass;
-use db::db_stats::{DbTableStats, DbWholeStats};
-use db::serialization::{Key, NoVersionValueWrapper, ValueSerde};
+use db::compaction::CompactionReport;
+use db::db_stats::{DbEnvStats, DbTableSize, DbTableStats, DbWholeStats};
+use db::serialization::{Key, NoVersionValueWrapper, StorageSerde, ValueSerde};
 use mmap_file::{
     open_file,
     FileHandler,
@@ -87,21 +105,29 @@ use mmap_file::{
     Reader,
     Writer,
 };
-use papyrus_config::dumping::{append_sub_config_name, ser_param, SerializeConfig};
+use papyrus_config::dumping::{
+    append_sub_config_name,
+    ser_optional_param,
+    ser_param,
+    SerializeConfig,
+};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use serde::{Deserialize, Serialize};
 use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
-use starknet_api::core::{ClassHash, ContractAddress, Nonce};
+use starknet_api::core::{ChainId, ClassHash, ContractAddress, Nonce};
 use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
 use starknet_api::hash::StarkFelt;
 use starknet_api::state::{ContractClass, StorageKey, ThinStateDiff};
 use starknet_api::transaction::{EventContent, Transaction, TransactionHash};
-use tracing::{debug, warn};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
 use validator::Validate;
 use version::{StorageVersionError, Version};
 
 use crate::body::events::ThinTransactionOutput;
 use crate::body::TransactionIndex;
+use crate::changelog::{ChangelogEntry, ChangelogKey, CHANGELOG_TABLE_NAME};
+use crate::custom_tables::CustomTable;
 use crate::db::{
     open_env,
     DbConfig,
@@ -112,10 +138,14 @@ use crate::db::{
     TableHandle,
     TableIdentifier,
     TransactionKind,
+    MAX_CUSTOM_TABLES,
     RO,
     RW,
 };
-use crate::header::StarknetVersion;
+use crate::event_log::{EventLogEntry, EventLogKey};
+use crate::header::{HeaderStorageReader, StarknetVersion};
+use crate::prune::PruneStorageReader;
+use crate::revert::ArchivedBlock;
 use crate::state::data::IndexedDeprecatedContractClass;
 use crate::version::{VersionStorageReader, VersionStorageWriter};
 
@@ -129,24 +159,100 @@ pub const STORAGE_VERSION_STATE: Version = Version(9);
 /// This version is only checked for storages that store transactions (StorageScope::FullArchive).
 pub const STORAGE_VERSION_BLOCKS: Version = Version(9);
 
+/// The name of the gauge that holds the number of write transactions that were started and were
+/// not yet committed or aborted.
+pub const STORAGE_IN_FLIGHT_WRITE_OPERATIONS: &str = "storage_in_flight_write_operations";
+
+/// The name of the histogram of the durations of the commits of write transactions, in seconds.
+pub const STORAGE_COMMIT_LATENCY_SECONDS: &str = "storage_commit_latency_seconds";
+
+/// The name of the histogram of the number of bytes that each committed write transaction wrote:
+/// the total size of the keys and the values it wrote to the tables.
+pub const STORAGE_COMMIT_WRITTEN_BYTES: &str = "storage_commit_written_bytes";
+
 /// Opens a storage and returns a [`StorageReader`] and a [`StorageWriter`].
 pub fn open_storage(
     storage_config: StorageConfig,
 ) -> StorageResult<(StorageReader, StorageWriter)> {
-    let (db_reader, mut db_writer) = open_env(&storage_config.db_config)?;
+    StorageBuilder::new(storage_config).open()
+}
+
+/// A builder for opening a storage with custom tables, on top of the built-in tables. See
+/// [`custom_tables`].
+pub struct StorageBuilder {
+    storage_config: StorageConfig,
+    custom_table_names: Vec<&'static str>,
+    block_committed_sender: Option<broadcast::Sender<BlockCommitted>>,
+}
+
+impl StorageBuilder {
+    /// Creates a builder for a storage with the given config and no custom tables.
+    pub fn new(storage_config: StorageConfig) -> Self {
+        Self { storage_config, custom_table_names: Vec::new(), block_committed_sender: None }
+    }
+
+    /// Adds a custom table to the storage, and returns the handle to read and write it with.
+    /// The name must be unique among the built-in tables and the other custom tables, and at most
+    /// [`MAX_CUSTOM_TABLES`](db::MAX_CUSTOM_TABLES) custom tables can be added.
+    pub fn add_table<K, V>(&mut self, name: &'static str) -> StorageResult<CustomTable<K, V>>
+    where
+        K: StorageSerde + Ord + Clone + Debug,
+        V: StorageSerde + Debug,
+    {
+        if Tables::field_names().contains(&name) || self.custom_table_names.contains(&name) {
+            return Err(StorageError::TableNameCollision { table_name: name.to_owned() });
+        }
+        if self.custom_table_names.len() == MAX_CUSTOM_TABLES {
+            return Err(StorageError::TooManyCustomTables { max_custom_tables: MAX_CUSTOM_TABLES });
+        }
+        self.custom_table_names.push(name);
+        Ok(CustomTable { table_id: TableIdentifier::new(name) })
+    }
+
+    /// Makes the [`StorageWriter`] broadcast a [`BlockCommitted`] notification through the given
+    /// channel for each block whose header it commits. See [`block_notifications`].
+    pub fn notify_block_commits(&mut self, sender: broadcast::Sender<BlockCommitted>) {
+        self.block_committed_sender = Some(sender);
+    }
+
+    /// Opens the storage with its custom tables and returns a [`StorageReader`] and a
+    /// [`StorageWriter`].
+    pub fn open(self) -> StorageResult<(StorageReader, StorageWriter)> {
+        open_storage_with_custom_tables(
+            self.storage_config,
+            &self.custom_table_names,
+            self.block_committed_sender,
+        )
+    }
+}
+
+fn open_storage_with_custom_tables(
+    storage_config: StorageConfig,
+    custom_table_names: &[&'static str],
+    block_committed_sender: Option<broadcast::Sender<BlockCommitted>>,
+) -> StorageResult<(StorageReader, StorageWriter)> {
+    let (db_reader, mut db_writer) =
+        open_env(&storage_config.db_config).map_err(|source| StorageError::OpenFailed {
+            path: storage_config.db_config.path(),
+            chain_id: storage_config.db_config.chain_id.clone(),
+            source,
+        })?;
     let tables = Arc::new(Tables {
         block_hash_to_number: db_writer.create_table("block_hash_to_number")?,
         casms: db_writer.create_table("casms")?,
+        changelog: db_writer.create_table(CHANGELOG_TABLE_NAME)?,
         contract_storage: db_writer.create_table("contract_storage")?,
         declared_classes: db_writer.create_table("declared_classes")?,
         declared_classes_block: db_writer.create_table("declared_classes_block")?,
         deprecated_declared_classes: db_writer.create_table("deprecated_declared_classes")?,
         deployed_contracts: db_writer.create_table("deployed_contracts")?,
+        event_log: db_writer.create_table("event_log")?,
         events: db_writer.create_table("events")?,
         headers: db_writer.create_table("headers")?,
         markers: db_writer.create_table("markers")?,
         nonces: db_writer.create_table("nonces")?,
         file_offsets: db_writer.create_table("file_offsets")?,
+        reverted_blocks: db_writer.create_table("reverted_blocks")?,
         state_diffs: db_writer.create_table("state_diffs")?,
         transaction_hash_to_idx: db_writer.create_table("transaction_hash_to_idx")?,
         transaction_idx_to_hash: db_writer.create_table("transaction_idx_to_hash")?,
@@ -157,23 +263,44 @@ pub fn open_storage(
         starknet_version: db_writer.create_table("starknet_version")?,
         storage_version: db_writer.create_table("storage_version")?,
     });
+    for name in custom_table_names {
+        db_writer.create_untyped_table(name)?;
+    }
     let (file_writers, file_readers) = open_storage_files(
         &storage_config.db_config,
-        storage_config.mmap_file_config,
+        storage_config.mmap_file_config.clone(),
         db_reader.clone(),
         &tables.file_offsets,
     )?;
 
+    let in_flight_write_operations = Arc::new(AtomicUsize::new(0));
     let reader = StorageReader {
         db_reader,
         tables: tables.clone(),
         scope: storage_config.scope,
+        enable_event_log: storage_config.enable_event_log,
         file_readers,
+        in_flight_write_operations: in_flight_write_operations.clone(),
+    };
+    let writer = StorageWriter {
+        db_writer,
+        tables,
+        scope: storage_config.scope,
+        enable_event_log: storage_config.enable_event_log,
+        enable_changelog: storage_config.enable_changelog,
+        custom_table_names: custom_table_names.to_vec(),
+        file_writers,
+        in_flight_write_operations,
+        block_committed_sender,
+        storage_config: storage_config.clone(),
     };
-    let writer = StorageWriter { db_writer, tables, scope: storage_config.scope, file_writers };
 
-    let writer = set_version_if_needed(reader.clone(), writer)?;
+    let mut writer = set_version_if_needed(reader.clone(), writer)?;
     verify_storage_version(reader.clone())?;
+    writer.revert_dangling_writes()?;
+    if storage_config.verify_parent_hashes {
+        verify_parent_hashes(&reader, storage_config.parent_hash_verification_depth)?;
+    }
     Ok((reader, writer))
 }
 
@@ -309,6 +436,52 @@ fn verify_storage_version(reader: StorageReader) -> StorageResult<()> {
     }
 }
 
+// Verifies that the parent hash of each stored block matches the hash of the previous block and
+// returns an error for the first mismatch. If a depth is given, only the latest blocks are checked.
+// Blocks before the pruned marker are skipped, since a seeded storage has no headers before its
+// seeded parent block.
+fn verify_parent_hashes(reader: &StorageReader, depth: Option<u64>) -> StorageResult<()> {
+    let txn = reader.begin_ro_txn()?;
+    let header_marker = txn.get_header_marker()?;
+    let pruned_marker = txn.get_pruned_marker()?;
+    let first_block_to_verify = match depth {
+        Some(depth) => header_marker.0.saturating_sub(depth),
+        None => 0,
+    }
+    .max(pruned_marker.0)
+    .max(1);
+    if first_block_to_verify >= header_marker.0 {
+        return Ok(());
+    }
+    debug!(
+        "Verifying the parent hashes of blocks {first_block_to_verify} to {}.",
+        header_marker.0 - 1
+    );
+    let mut previous_block_hash =
+        get_stored_header(&txn, BlockNumber(first_block_to_verify - 1))?.block_hash;
+    for block_number in (first_block_to_verify..header_marker.0).map(BlockNumber) {
+        let header = get_stored_header(&txn, block_number)?;
+        if header.parent_hash != previous_block_hash {
+            return Err(StorageError::ParentHashMismatch {
+                block_number,
+                parent_hash: header.parent_hash,
+                previous_block_hash,
+            });
+        }
+        previous_block_hash = header.block_hash;
+    }
+    Ok(())
+}
+
+fn get_stored_header(
+    txn: &StorageTxn<'_, RO>,
+    block_number: BlockNumber,
+) -> StorageResult<BlockHeader> {
+    txn.get_block_header(block_number)?.ok_or(StorageError::DBInconsistency {
+        msg: format!("Header of block {block_number} not found."),
+    })
+}
+
 /// The categories of data to save in the storage.
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub enum StorageScope {
@@ -327,17 +500,24 @@ pub struct StorageReader {
     file_readers: FileHandlers<RO>,
     tables: Arc<Tables>,
     scope: StorageScope,
+    enable_event_log: bool,
+    in_flight_write_operations: Arc<AtomicUsize>,
 }
 
 impl StorageReader {
     /// Takes a snapshot of the current state of the storage and returns a [`StorageTxn`] for
     /// reading data from the storage.
+    /// All the reads of the transaction, from all the tables, see this snapshot, and writes that
+    /// are committed after it was taken aren't visible to the transaction. See [`snapshot`].
     pub fn begin_ro_txn(&self) -> StorageResult<StorageTxn<'_, RO>> {
         Ok(StorageTxn {
             txn: self.db_reader.begin_ro_txn()?,
             file_handlers: self.file_readers.clone(),
             tables: self.tables.clone(),
             scope: self.scope,
+            enable_event_log: self.enable_event_log,
+            _in_flight_write_guard: None,
+            block_committed_notifier: None,
         })
     }
 
@@ -347,7 +527,23 @@ impl StorageReader {
         for name in Tables::field_names() {
             tables_stats.insert(name.to_string(), self.db_reader.get_table_stats(name)?);
         }
-        Ok(DbStats { db_stats: self.db_reader.get_db_stats()?, tables_stats })
+        Ok(DbStats {
+            db_stats: self.db_reader.get_db_stats()?,
+            tables_stats,
+            in_flight_write_operations: self.in_flight_write_operations.load(Ordering::Acquire),
+        })
+    }
+
+    /// Returns the size of each table in the storage, including the custom tables, to find the
+    /// tables that take most of the database.
+    pub fn db_tables_size(&self) -> StorageResult<BTreeMap<String, DbTableSize>> {
+        Ok(self.db_reader.get_tables_size()?)
+    }
+
+    /// Returns information about the database environment, such as its size and its latest
+    /// transaction. It's cheaper than [`StorageReader::db_tables_stats`].
+    pub fn db_stats(&self) -> StorageResult<DbEnvStats> {
+        Ok(self.db_reader.get_env_stats()?)
     }
 
     /// Returns the scope of the storage.
@@ -358,24 +554,101 @@ impl StorageReader {
 
 /// A struct for starting RW transactions ([`StorageTxn`]) to the storage.
 /// There is a single non clonable writer instance, to make sure there is only one write transaction
-/// at any given moment.
+/// at any given moment. Users that need to share it can wrap it in a
+/// [`SharedStorageWriter`](shared_writer::SharedStorageWriter).
 pub struct StorageWriter {
     db_writer: DbWriter,
     file_writers: FileHandlers<RW>,
     tables: Arc<Tables>,
     scope: StorageScope,
+    enable_event_log: bool,
+    enable_changelog: bool,
+    custom_table_names: Vec<&'static str>,
+    in_flight_write_operations: Arc<AtomicUsize>,
+    block_committed_sender: Option<broadcast::Sender<BlockCommitted>>,
+    // Kept for reopening the storage after a compaction.
+    storage_config: StorageConfig,
 }
 
 impl StorageWriter {
     /// Takes a snapshot of the current state of the storage and returns a [`StorageTxn`] for
     /// reading and modifying data in the storage.
     pub fn begin_rw_txn(&mut self) -> StorageResult<StorageTxn<'_, RW>> {
-        Ok(StorageTxn {
-            txn: self.db_writer.begin_rw_txn()?,
+        let mut txn = self.db_writer.begin_rw_txn()?;
+        if self.enable_changelog {
+            txn.enable_changelog();
+        }
+        let mut storage_txn = StorageTxn {
+            txn,
             file_handlers: self.file_writers.clone(),
             tables: self.tables.clone(),
             scope: self.scope,
-        })
+            enable_event_log: self.enable_event_log,
+            _in_flight_write_guard: Some(InFlightWriteGuard::new(
+                self.in_flight_write_operations.clone(),
+            )),
+            block_committed_notifier: None,
+        };
+        if let Some(sender) = &self.block_committed_sender {
+            storage_txn.block_committed_notifier =
+                Some(BlockCommittedNotifier::new(sender.clone(), storage_txn.get_header_marker()?));
+        }
+        Ok(storage_txn)
+    }
+
+    /// Compacts the database file: copies the data to a new file without the free pages, for
+    /// example the pages that were freed by reverting blocks, and replaces the database file with
+    /// it. Returns a new reader and writer of the compacted storage, and a report of the space that
+    /// was reclaimed.
+    /// The database file grows and shrinks in steps of [`DbConfig::growth_step`], so the space is
+    /// reclaimed in whole growth steps.
+    ///
+    /// The database is closed for the replacement, so all the [`StorageReader`]s of the storage
+    /// must be dropped before, or [`DbError::EnvInUse`] is returned. Fails with
+    /// [`DbError::NotEnoughDiskSpace`] if there isn't enough free disk space for the copy. If the
+    /// compaction fails, the database file is left as it was, and the storage should be reopened.
+    pub fn compact(self) -> StorageResult<(StorageReader, StorageWriter, CompactionReport)> {
+        let StorageWriter {
+            db_writer,
+            storage_config,
+            custom_table_names,
+            block_committed_sender,
+            ..
+        } = self;
+        let report = db_writer.compact(&storage_config.db_config)?;
+        info!("Compacted the storage from {} to {} bytes.", report.size_before, report.size_after);
+        let (reader, writer) = open_storage_with_custom_tables(
+            storage_config,
+            &custom_table_names,
+            block_committed_sender,
+        )?;
+        Ok((reader, writer, report))
+    }
+
+    // Returns the names of all the tables in the storage, including the custom tables.
+    fn table_names(&self) -> Vec<&'static str> {
+        Tables::field_names().iter().chain(&self.custom_table_names).copied().collect()
+    }
+}
+
+// Counts a write transaction as in-flight from the moment it begins until it is dropped, either
+// after a commit or without one.
+struct InFlightWriteGuard {
+    in_flight_write_operations: Arc<AtomicUsize>,
+}
+
+impl InFlightWriteGuard {
+    fn new(in_flight_write_operations: Arc<AtomicUsize>) -> Self {
+        let current = in_flight_write_operations.fetch_add(1, Ordering::AcqRel) + 1;
+        metrics::gauge!(STORAGE_IN_FLIGHT_WRITE_OPERATIONS, current as f64);
+        Self { in_flight_write_operations }
+    }
+}
+
+impl Drop for InFlightWriteGuard {
+    fn drop(&mut self) {
+        let current = self.in_flight_write_operations.fetch_sub(1, Ordering::AcqRel) - 1;
+        metrics::gauge!(STORAGE_IN_FLIGHT_WRITE_OPERATIONS, current as f64);
     }
 }
 
@@ -386,13 +659,38 @@ pub struct StorageTxn<'env, Mode: TransactionKind> {
     file_handlers: FileHandlers<Mode>,
     tables: Arc<Tables>,
     scope: StorageScope,
+    enable_event_log: bool,
+    // Set only for RW transactions.
+    _in_flight_write_guard: Option<InFlightWriteGuard>,
+    // Set only for RW transactions of a writer that notifies about committed blocks.
+    block_committed_notifier: Option<BlockCommittedNotifier>,
 }
 
 impl<'env> StorageTxn<'env, RW> {
     /// Commits the changes made in the transaction to the storage.
     pub fn commit(self) -> StorageResult<()> {
+        let committed_header_marker = match self.block_committed_notifier {
+            Some(_) => Some(self.get_header_marker()?),
+            None => None,
+        };
         self.file_handlers.flush();
-        Ok(self.txn.commit()?)
+        self.txn.commit()?;
+        if let (Some(notifier), Some(committed_header_marker)) =
+            (self.block_committed_notifier, committed_header_marker)
+        {
+            notifier.notify(committed_header_marker);
+        }
+        Ok(())
+    }
+
+    /// Commits the changes made in the transaction to the storage, and returns a transaction of
+    /// `reader` that sees the storage as it was right after the commit. The storage has a single
+    /// writer, which this transaction borrows, so no other write is committed in between.
+    ///
+    /// `reader` should be a reader of the same storage as the transaction.
+    pub fn commit_and_read(self, reader: &StorageReader) -> StorageResult<StorageTxn<'_, RO>> {
+        self.commit()?;
+        reader.begin_ro_txn()
     }
 }
 
@@ -429,16 +727,19 @@ struct_field_names! {
     struct Tables {
         block_hash_to_number: TableIdentifier<BlockHash, NoVersionValueWrapper<BlockNumber>>,
         casms: TableIdentifier<ClassHash, NoVersionValueWrapper<LocationInFile>>,
+        changelog: TableIdentifier<ChangelogKey, NoVersionValueWrapper<ChangelogEntry>>,
         contract_storage: TableIdentifier<(ContractAddress, StorageKey, BlockNumber), NoVersionValueWrapper<StarkFelt>>,
         declared_classes: TableIdentifier<ClassHash, NoVersionValueWrapper<LocationInFile>>,
         declared_classes_block: TableIdentifier<ClassHash, NoVersionValueWrapper<BlockNumber>>,
         deprecated_declared_classes: TableIdentifier<ClassHash, NoVersionValueWrapper<IndexedDeprecatedContractClass>>,
         deployed_contracts: TableIdentifier<(ContractAddress, BlockNumber), NoVersionValueWrapper<ClassHash>>,
+        event_log: TableIdentifier<EventLogKey, NoVersionValueWrapper<EventLogEntry>>,
         events: TableIdentifier<(ContractAddress, EventIndex), NoVersionValueWrapper<EventContent>>,
         headers: TableIdentifier<BlockNumber, NoVersionValueWrapper<BlockHeader>>,
         markers: TableIdentifier<MarkerKind, NoVersionValueWrapper<BlockNumber>>,
         nonces: TableIdentifier<(ContractAddress, BlockNumber), NoVersionValueWrapper<Nonce>>,
         file_offsets: TableIdentifier<OffsetKind, NoVersionValueWrapper<usize>>,
+        reverted_blocks: TableIdentifier<(BlockNumber, BlockHash), NoVersionValueWrapper<ArchivedBlock>>,
         state_diffs: TableIdentifier<BlockNumber, NoVersionValueWrapper<LocationInFile>>,
         transaction_hash_to_idx: TableIdentifier<TransactionHash, NoVersionValueWrapper<TransactionIndex>>,
         transaction_idx_to_hash: TableIdentifier<TransactionIndex, NoVersionValueWrapper<TransactionHash>>,
@@ -504,30 +805,133 @@ pub enum StorageError {
          {compiled_class_marker}."
     )]
     InvalidBlockNumber { block: BlockNumber, compiled_class_marker: BlockNumber },
+    #[error(
+        "The parent hash {parent_hash:?} of block {block_number} doesn't match the hash \
+         {previous_block_hash:?} of the previous block."
+    )]
+    ParentHashMismatch {
+        block_number: BlockNumber,
+        parent_hash: BlockHash,
+        previous_block_hash: BlockHash,
+    },
+    #[error(
+        "Can't revert block {block_number} since it's finalized on the base layer (base layer \
+         marker {base_layer_marker})."
+    )]
+    RevertFinalizedBlock { block_number: BlockNumber, base_layer_marker: BlockNumber },
+    #[error(
+        "Can't revert block {block_number} since its body and state diff were pruned (pruned \
+         marker {pruned_marker})."
+    )]
+    RevertPrunedBlock { block_number: BlockNumber, pruned_marker: BlockNumber },
+    #[error("The table name {table_name} is already used by another table.")]
+    TableNameCollision { table_name: String },
+    #[error("Can't add more than {max_custom_tables} custom tables.")]
+    TooManyCustomTables { max_custom_tables: usize },
+    /// The database of the storage couldn't be opened.
+    #[error("Failed to open the storage of chain {} at {path:?}: {source}", chain_id.0)]
+    OpenFailed { path: PathBuf, chain_id: ChainId, source: DbError },
+    #[error("The storage has no table named {table_name}.")]
+    UnknownTable { table_name: String },
+    #[error(
+        "Can't append to the {file:?} file at offset {offset}, the file ends at offset \
+         {file_offset}."
+    )]
+    FileOffsetMismatch { file: OffsetKind, offset: usize, file_offset: usize },
 }
 
 /// A type alias that maps to std::result::Result<T, StorageError>.
 pub type StorageResult<V> = std::result::Result<V, StorageError>;
 
+/// The default number of latest blocks whose parent hash is verified when the storage is opened.
+pub const DEFAULT_PARENT_HASH_VERIFICATION_DEPTH: u64 = 1000;
+
 /// A struct for the configuration of the storage.
 #[allow(missing_docs)]
-#[derive(Serialize, Debug, Default, Deserialize, Clone, PartialEq, Validate)]
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Validate)]
 pub struct StorageConfig {
     #[validate]
     pub db_config: DbConfig,
     #[validate]
     pub mmap_file_config: MmapFileConfig,
     pub scope: StorageScope,
+    pub verify_parent_hashes: bool,
+    pub parent_hash_verification_depth: Option<u64>,
+    pub enable_event_log: bool,
+    pub enable_changelog: bool,
+    pub compact_on_shutdown: bool,
+    pub archive_reverted: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            db_config: DbConfig::default(),
+            mmap_file_config: MmapFileConfig::default(),
+            scope: StorageScope::default(),
+            verify_parent_hashes: false,
+            parent_hash_verification_depth: Some(DEFAULT_PARENT_HASH_VERIFICATION_DEPTH),
+            enable_event_log: false,
+            enable_changelog: false,
+            compact_on_shutdown: false,
+            archive_reverted: false,
+        }
+    }
 }
 
 impl SerializeConfig for StorageConfig {
     fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
-        let mut dumped_config = BTreeMap::from_iter([ser_param(
-            "scope",
-            &self.scope,
-            "The categories of data saved in storage.",
+        let mut dumped_config = BTreeMap::from_iter([
+            ser_param(
+                "scope",
+                &self.scope,
+                "The categories of data saved in storage.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "verify_parent_hashes",
+                &self.verify_parent_hashes,
+                "Whether to verify, when the storage is opened, that the parent hash of each \
+                 stored block matches the hash of the previous block.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "enable_event_log",
+                &self.enable_event_log,
+                "Whether to keep a log of events in the storage, for debugging.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "enable_changelog",
+                &self.enable_changelog,
+                "Whether to record the changes of each write transaction, to back up the storage \
+                 incrementally.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "compact_on_shutdown",
+                &self.compact_on_shutdown,
+                "Whether to compact the database when the node shuts down gracefully, returning \
+                 the space of its free pages to the file system. The compaction is skipped if \
+                 there isn't enough free disk space for it.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "archive_reverted",
+                &self.archive_reverted,
+                "Whether to keep the headers and bodies of reverted blocks in the storage, as a \
+                 record of the reorgs.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        dumped_config.extend(ser_optional_param(
+            &self.parent_hash_verification_depth,
+            DEFAULT_PARENT_HASH_VERIFICATION_DEPTH,
+            "parent_hash_verification_depth",
+            "The number of latest blocks whose parent hash is verified. If not set, all the \
+             stored blocks are verified.",
             ParamPrivacyInput::Public,
-        )]);
+        ));
         dumped_config
             .extend(append_sub_config_name(self.mmap_file_config.dump(), "mmap_file_config"));
         dumped_config.extend(append_sub_config_name(self.db_config.dump(), "db_config"));
@@ -542,6 +946,8 @@ pub struct DbStats {
     pub db_stats: DbWholeStats,
     /// A mapping from a table name in the database to its statistics.
     pub tables_stats: BTreeMap<String, DbTableStats>,
+    /// The number of write transactions that were started and were not yet committed or aborted.
+    pub in_flight_write_operations: usize,
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, PartialOrd, Ord)]
@@ -550,12 +956,15 @@ pub struct DbStats {
 // - CompiledClass <= State <= Header
 // - Body <= Header
 // - BaseLayerBlock <= Header
+// - Pruned <= Body, CompiledClass
 pub(crate) enum MarkerKind {
     Header,
     Body,
     State,
     CompiledClass,
     BaseLayerBlock,
+    // The first block whose body and state diff weren't pruned.
+    Pruned,
 }
 
 pub(crate) type MarkersTable<'env> =
@@ -593,6 +1002,18 @@ impl FileHandlers<RW> {
         self.clone().deprecated_contract_class.append(deprecated_contract_class)
     }
 
+    // Appends serialized bytes to the file of the given kind and returns their location.
+    fn append_bytes(&self, file: OffsetKind, bytes: &[u8]) -> LocationInFile {
+        match file {
+            OffsetKind::ThinStateDiff => self.clone().thin_state_diff.append_bytes(bytes),
+            OffsetKind::ContractClass => self.clone().contract_class.append_bytes(bytes),
+            OffsetKind::Casm => self.clone().casm.append_bytes(bytes),
+            OffsetKind::DeprecatedContractClass => {
+                self.clone().deprecated_contract_class.append_bytes(bytes)
+            }
+        }
+    }
+
     // TODO(dan): Consider 1. flushing only the relevant files, 2. flushing concurrently.
     fn flush(&self) {
         self.thin_state_diff.flush();
@@ -603,41 +1024,66 @@ impl FileHandlers<RW> {
 }
 
 impl<Mode: TransactionKind> FileHandlers<Mode> {
-    // Returns the thin state diff at the given location or an error in case it doesn't exist.
+    // Returns the offset at which the next object will be appended to the file of the given kind.
+    fn offset(&self, file: OffsetKind) -> usize {
+        match file {
+            OffsetKind::ThinStateDiff => self.thin_state_diff.offset(),
+            OffsetKind::ContractClass => self.contract_class.offset(),
+            OffsetKind::Casm => self.casm.offset(),
+            OffsetKind::DeprecatedContractClass => self.deprecated_contract_class.offset(),
+        }
+    }
+
+    // Returns the serialized bytes at the given location of the file of the given kind.
+    fn get_bytes(&self, file: OffsetKind, location: LocationInFile) -> StorageResult<Vec<u8>> {
+        Ok(match file {
+            OffsetKind::ThinStateDiff => self.thin_state_diff.get_bytes(location)?,
+            OffsetKind::ContractClass => self.contract_class.get_bytes(location)?,
+            OffsetKind::Casm => self.casm.get_bytes(location)?,
+            OffsetKind::DeprecatedContractClass => {
+                self.deprecated_contract_class.get_bytes(location)?
+            }
+        })
+    }
+
+    // Returns the thin state diff at the given location or an error in case it's malformed.
     fn get_thin_state_diff_unchecked(
         &self,
         location: LocationInFile,
     ) -> StorageResult<ThinStateDiff> {
         self.thin_state_diff.get(location)?.ok_or(StorageError::DBInconsistency {
-            msg: format!("ThinStateDiff at location {:?} not found.", location),
+            msg: format!("Failed deserializing the ThinStateDiff at location {:?}.", location),
         })
     }
 
-    // Returns the contract class at the given location or an error in case it doesn't exist.
+    // Returns the contract class at the given location or an error in case it's malformed.
     fn get_contract_class_unchecked(
         &self,
         location: LocationInFile,
     ) -> StorageResult<ContractClass> {
         self.contract_class.get(location)?.ok_or(StorageError::DBInconsistency {
-            msg: format!("ContractClass at location {:?} not found.", location),
+            msg: format!("Failed deserializing the ContractClass at location {:?}.", location),
         })
     }
 
-    // Returns the CASM at the given location or an error in case it doesn't exist.
+    // Returns the CASM at the given location or an error in case it's malformed.
     fn get_casm_unchecked(&self, location: LocationInFile) -> StorageResult<CasmContractClass> {
         self.casm.get(location)?.ok_or(StorageError::DBInconsistency {
-            msg: format!("CasmContractClass at location {:?} not found.", location),
+            msg: format!("Failed deserializing the CasmContractClass at location {:?}.", location),
         })
     }
 
-    // Returns the deprecated contract class at the given location or an error in case it doesn't
-    // exist.
+    // Returns the deprecated contract class at the given location or an error in case it's
+    // malformed.
     fn get_deprecated_contract_class_unchecked(
         &self,
         location: LocationInFile,
     ) -> StorageResult<DeprecatedContractClass> {
         self.deprecated_contract_class.get(location)?.ok_or(StorageError::DBInconsistency {
-            msg: format!("DeprecatedContractClass at location {:?} not found.", location),
+            msg: format!(
+                "Failed deserializing the DeprecatedContractClass at location {:?}.",
+                location
+            ),
         })
     }
 }
@@ -652,7 +1098,7 @@ fn open_storage_files(
     let table = db_transaction.open_table(file_offsets_table)?;
 
     let thin_state_diff_offset =
-        table.get(&db_transaction, &OffsetKind::ThinStateDiff)?.unwrap_or_default();
+        table.get_or_default(&db_transaction, &OffsetKind::ThinStateDiff)?;
     let (thin_state_diff_writer, thin_state_diff_reader) = open_file(
         mmap_file_config.clone(),
         db_config.path().join("thin_state_diff.dat"),
@@ -660,19 +1106,19 @@ fn open_storage_files(
     )?;
 
     let contract_class_offset =
-        table.get(&db_transaction, &OffsetKind::ContractClass)?.unwrap_or_default();
+        table.get_or_default(&db_transaction, &OffsetKind::ContractClass)?;
     let (contract_class_writer, contract_class_reader) = open_file(
         mmap_file_config.clone(),
         db_config.path().join("contract_class.dat"),
         contract_class_offset,
     )?;
 
-    let casm_offset = table.get(&db_transaction, &OffsetKind::Casm)?.unwrap_or_default();
+    let casm_offset = table.get_or_default(&db_transaction, &OffsetKind::Casm)?;
     let (casm_writer, casm_reader) =
         open_file(mmap_file_config.clone(), db_config.path().join("casm.dat"), casm_offset)?;
 
     let deprecated_contract_class_offset =
-        table.get(&db_transaction, &OffsetKind::DeprecatedContractClass)?.unwrap_or_default();
+        table.get_or_default(&db_transaction, &OffsetKind::DeprecatedContractClass)?;
     let (deprecated_contract_class_writer, deprecated_contract_class_reader) = open_file(
         mmap_file_config,
         db_config.path().join("deprecated_contract_class.dat"),
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use assert_matches::assert_matches;
 use pretty_assertions::assert_eq;
 use rand::Rng;
 use tempfile::tempdir;
@@ -44,6 +45,30 @@ fn write_read() {
     dir.close().unwrap();
 }
 
+#[test]
+fn get_bytes_rejects_locations_out_of_the_file() {
+    let dir = tempdir().unwrap();
+    let config = get_mmap_file_test_config();
+    let (mut writer, reader) =
+        open_file::<Vec<u8>>(config.clone(), dir.path().to_path_buf().join("test_get_bytes"), 0)
+            .unwrap();
+    let location = writer.append(&vec![1, 2, 3]);
+    assert_eq!(
+        reader.get_bytes(location).unwrap(),
+        StorageSerdeEx::serialize(&vec![1u8, 2, 3]).unwrap()
+    );
+
+    let file_size = config.growth_step;
+    let out_of_file = LocationInFile { offset: file_size - 1, len: 2 };
+    assert_matches!(
+        reader.get_bytes(out_of_file),
+        Err(MMapFileError::OutOfBounds { location, file_size: size })
+            if location == out_of_file && size == file_size
+    );
+    let overflowing = LocationInFile { offset: usize::MAX, len: 1 };
+    assert_matches!(reader.get_bytes(overflowing), Err(MMapFileError::OutOfBounds { .. }));
+}
+
 #[test]
 fn concurrent_reads() {
     let dir = tempdir().unwrap();
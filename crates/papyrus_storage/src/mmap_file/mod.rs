@@ -100,6 +100,15 @@ pub enum MMapFileError {
     /// Number conversion error.
     #[error(transparent)]
     TryFromInt(#[from] std::num::TryFromIntError),
+
+    /// A location that doesn't fit in the file.
+    #[error("Location {location:?} is out of the bounds of the file, of size {file_size}.")]
+    OutOfBounds {
+        /// The location that was read.
+        location: LocationInFile,
+        /// The size of the file.
+        file_size: usize,
+    },
 }
 
 /// A trait for writing to a memory mapped file.
@@ -113,7 +122,8 @@ pub trait Writer<V: StorageSerde> {
 
 /// A trait for reading from a memory mapped file.
 pub trait Reader<V: StorageSerde> {
-    /// Returns an object from the file.
+    /// Returns an object from the file, or None if the bytes at the location can't be deserialized
+    /// as an object.
     fn get(&self, location: LocationInFile) -> MmapFileResult<Option<V>>;
 }
 
@@ -127,6 +137,11 @@ pub struct LocationInFile {
 }
 
 impl LocationInFile {
+    /// Returns the offset of the object in the file.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     /// returns the next offset in the file.
     pub fn next_offset(&self) -> usize {
         self.offset + self.len
@@ -220,20 +235,16 @@ impl<V: StorageSerde> FileHandler<V, RW> {
             mmap_file.grow();
         }
     }
-}
 
-impl<V: StorageSerde + Debug> Writer<V> for FileHandler<V, RW> {
-    fn append(&mut self, val: &V) -> LocationInFile {
-        trace!("Inserting object: {:?}", val);
-        // TODO(dan): change serialize_into to return serialization size.
-        let len = val.serialize().expect("Should be able to serialize").len();
+    // Appends the serialization of an object to the file and returns its location.
+    pub(crate) fn append_bytes(&mut self, bytes: &[u8]) -> LocationInFile {
+        let len = bytes.len();
         let offset;
         {
             let mut mmap_file = self.mmap_file.lock().expect("Lock should not be poisoned");
             offset = mmap_file.offset;
             debug!("Inserting object at offset: {}", offset);
-            let mut mmap_slice = &mut mmap_file.mmap[offset..];
-            let _ = val.serialize_into(&mut mmap_slice);
+            mmap_file.mmap[offset..offset + len].copy_from_slice(bytes);
             mmap_file
                 .mmap
                 .flush_async_range(offset, len)
@@ -245,6 +256,37 @@ impl<V: StorageSerde + Debug> Writer<V> for FileHandler<V, RW> {
         self.grow_file_if_needed(location.next_offset());
         location
     }
+}
+
+impl<V: StorageSerde, Mode: TransactionKind> FileHandler<V, Mode> {
+    // Returns the offset at which the next object will be appended.
+    pub(crate) fn offset(&self) -> usize {
+        self.mmap_file.lock().expect("Lock should not be poisoned").offset
+    }
+
+    // Returns the serialization of the object at the given location.
+    pub(crate) fn get_bytes(&self, location: LocationInFile) -> MmapFileResult<Vec<u8>> {
+        let file_size = self.mmap_file.lock().expect("Lock should not be poisoned").size;
+        if location.offset.checked_add(location.len).is_none_or(|end| end > file_size) {
+            return Err(MMapFileError::OutOfBounds { location, file_size });
+        }
+        // Safe because the location is within the file, which is within the memory map.
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                self.memory_ptr.offset(location.offset.try_into()?),
+                location.len,
+            )
+        };
+        Ok(bytes.to_vec())
+    }
+}
+
+impl<V: StorageSerde + Debug> Writer<V> for FileHandler<V, RW> {
+    fn append(&mut self, val: &V) -> LocationInFile {
+        trace!("Inserting object: {:?}", val);
+        let bytes = val.serialize().expect("Should be able to serialize");
+        self.append_bytes(&bytes)
+    }
 
     fn flush(&self) {
         let mut mmap_file = self.mmap_file.lock().expect("Lock should not be poisoned");
@@ -0,0 +1,69 @@
+//! Interface for pruning the bodies and state diffs of old blocks from the storage, for nodes that
+//! only need the recent blocks.
+//!
+//! The headers of pruned blocks are kept, so queries about them that only need the header keep
+//! working. The transactions, transaction outputs, events and state diffs of pruned blocks are
+//! reported as missing. The state itself isn't pruned, since later blocks depend on it.
+
+#[cfg(test)]
+#[path = "prune_test.rs"]
+mod prune_test;
+
+use starknet_api::block::BlockNumber;
+use tracing::debug;
+
+use crate::body::BodyStorageReader;
+use crate::compiled_class::CasmStorageReader;
+use crate::db::TransactionKind;
+use crate::state::StateStorageReader;
+use crate::{MarkerKind, StorageResult, StorageTxn, StorageWriter};
+
+/// Interface for reading data related to pruning.
+pub trait PruneStorageReader {
+    /// The pruned marker is the first block whose body and state diff weren't pruned.
+    fn get_pruned_marker(&self) -> StorageResult<BlockNumber>;
+}
+
+impl<'env, Mode: TransactionKind> PruneStorageReader for StorageTxn<'env, Mode> {
+    fn get_pruned_marker(&self) -> StorageResult<BlockNumber> {
+        let markers_table = self.open_table(&self.tables.markers)?;
+        Ok(markers_table.get_or_default(&self.txn, &MarkerKind::Pruned)?)
+    }
+}
+
+impl StorageWriter {
+    /// Removes the bodies and state diffs of all the blocks before the given block, keeping their
+    /// headers, and returns the new pruned marker.
+    /// The blocks are pruned in several transactions, each of at most `max_blocks_per_txn`
+    /// blocks, so that pruning a long range doesn't hold the writer for long. Blocks whose body,
+    /// state diff or compiled classes weren't stored yet aren't pruned.
+    pub fn prune_blocks(
+        &mut self,
+        up_to: BlockNumber,
+        max_blocks_per_txn: usize,
+    ) -> StorageResult<BlockNumber> {
+        let max_blocks_per_txn = max_blocks_per_txn.max(1) as u64;
+        loop {
+            let txn = self.begin_rw_txn()?;
+            let pruned_marker = txn.get_pruned_marker()?;
+            let up_to = up_to
+                .min(txn.get_body_marker()?)
+                .min(txn.get_state_marker()?)
+                .min(txn.get_compiled_class_marker()?);
+            if pruned_marker >= up_to {
+                return Ok(pruned_marker);
+            }
+
+            let txn_up_to = BlockNumber(up_to.0.min(pruned_marker.0 + max_blocks_per_txn));
+            let state_diffs_table = txn.open_table(&txn.tables.state_diffs)?;
+            for block_number in pruned_marker.iter_up_to(txn_up_to) {
+                txn.delete_body(block_number)?;
+                state_diffs_table.delete(&txn.txn, &block_number)?;
+            }
+            let markers_table = txn.open_table(&txn.tables.markers)?;
+            markers_table.upsert(&txn.txn, &MarkerKind::Pruned, &txn_up_to)?;
+            txn.commit()?;
+            debug!("Pruned blocks {pruned_marker} to {txn_up_to} (exclusive).");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use assert_matches::assert_matches;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockBody, BlockNumber};
+use starknet_api::transaction::TransactionOffsetInBlock;
+use test_utils::get_test_block;
+
+use crate::body::{BodyStorageReader, TransactionIndex};
+use crate::header::HeaderStorageReader;
+use crate::prune::PruneStorageReader;
+use crate::state::StateStorageReader;
+use crate::test_utils::{append_block, get_test_header, get_test_storage};
+use crate::StorageError;
+
+const N_BLOCKS: u64 = 10;
+const TRANSACTIONS_PER_BLOCK: usize = 2;
+
+// Returns the bodies of N_BLOCKS blocks, each with its own transactions.
+fn get_test_bodies() -> Vec<BlockBody> {
+    let body = get_test_block(N_BLOCKS as usize * TRANSACTIONS_PER_BLOCK, Some(1), None, None).body;
+    (0..N_BLOCKS as usize)
+        .map(|i| {
+            let transactions = i * TRANSACTIONS_PER_BLOCK..(i + 1) * TRANSACTIONS_PER_BLOCK;
+            BlockBody {
+                transactions: body.transactions[transactions.clone()].to_vec(),
+                transaction_outputs: body.transaction_outputs[transactions.clone()].to_vec(),
+                transaction_hashes: body.transaction_hashes[transactions].to_vec(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn blocks_outside_the_window_are_pruned_while_syncing() {
+    const KEEP_BLOCKS: u64 = 3;
+    const MAX_BLOCKS_PER_TXN: usize = 2;
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    let bodies = get_test_bodies();
+
+    // Pruning after each block, like the sync does.
+    for (block_number, body) in (0..N_BLOCKS).map(BlockNumber).zip(bodies.iter().cloned()) {
+        append_block(&mut writer, block_number, body);
+        let up_to = BlockNumber((block_number.0 + 1).saturating_sub(KEEP_BLOCKS));
+        assert_eq!(writer.prune_blocks(up_to, MAX_BLOCKS_PER_TXN).unwrap(), up_to);
+    }
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let first_kept_block = BlockNumber(N_BLOCKS - KEEP_BLOCKS);
+    assert_eq!(txn.get_pruned_marker().unwrap(), first_kept_block);
+    for (block_number, body) in (0..N_BLOCKS).map(BlockNumber).zip(bodies) {
+        assert_eq!(
+            txn.get_block_header(block_number).unwrap(),
+            Some(get_test_header(block_number))
+        );
+        let first_transaction_index = TransactionIndex(block_number, TransactionOffsetInBlock(0));
+        if block_number < first_kept_block {
+            assert_eq!(txn.get_block_transactions(block_number).unwrap(), None);
+            assert_eq!(txn.get_block_transaction_outputs(block_number).unwrap(), None);
+            assert_eq!(txn.get_transaction_events(first_transaction_index).unwrap(), None);
+            assert_eq!(txn.get_transaction_idx_by_hash(&body.transaction_hashes[0]).unwrap(), None);
+            assert_eq!(txn.get_state_diff(block_number).unwrap(), None);
+        } else {
+            assert_eq!(txn.get_block_transactions(block_number).unwrap(), Some(body.transactions));
+            assert_eq!(
+                txn.get_transaction_idx_by_hash(&body.transaction_hashes[0]).unwrap(),
+                Some(first_transaction_index)
+            );
+            assert!(txn.get_state_diff(block_number).unwrap().is_some());
+        }
+    }
+}
+
+#[test]
+fn pruning_stops_at_the_stored_blocks() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    for (block_number, body) in (0..N_BLOCKS).map(BlockNumber).zip(get_test_bodies()) {
+        append_block(&mut writer, block_number, body);
+    }
+
+    // A long range is pruned over several transactions, and blocks that weren't stored yet are
+    // left alone.
+    assert_eq!(writer.prune_blocks(BlockNumber(N_BLOCKS + 5), 3).unwrap(), BlockNumber(N_BLOCKS));
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_pruned_marker().unwrap(), BlockNumber(N_BLOCKS));
+    assert_eq!(txn.get_block_transactions(BlockNumber(N_BLOCKS - 1)).unwrap(), None);
+    assert_eq!(
+        txn.get_block_header(BlockNumber(N_BLOCKS - 1)).unwrap(),
+        Some(get_test_header(BlockNumber(N_BLOCKS - 1)))
+    );
+}
+
+#[test]
+fn pruned_blocks_cant_be_reverted() {
+    let ((_reader, mut writer), _temp_dir) = get_test_storage();
+    for (block_number, body) in (0..2).map(BlockNumber).zip(get_test_bodies()) {
+        append_block(&mut writer, block_number, body);
+    }
+    writer.prune_blocks(BlockNumber(2), 1).unwrap();
+
+    assert_matches!(
+        writer.revert_block(BlockNumber(1)),
+        Err(StorageError::RevertPrunedBlock { block_number, pruned_marker })
+        if block_number == BlockNumber(1) && pruned_marker == BlockNumber(2)
+    );
+}
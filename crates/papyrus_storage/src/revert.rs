@@ -0,0 +1,191 @@
+//! Interface for reverting blocks from the storage, e.g. when the chain reorgs.
+//!
+//! While [`StorageConfig::archive_reverted`] is set, the header and body of each reverted block
+//! are kept in an archive of reverted blocks, keyed by the number and the hash of the block, to
+//! keep a record of the reorgs.
+//!
+//! When the storage is opened, the data of a block that was written above the marker of its table
+//! without the marker, e.g. by a write that was interrupted, is reverted, so that the storage
+//! always continues from the last consistent block.
+//!
+//! [`StorageConfig::archive_reverted`]: crate::StorageConfig::archive_reverted
+
+#[cfg(test)]
+#[path = "revert_test.rs"]
+mod revert_test;
+
+use serde::{Deserialize, Serialize};
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::transaction::{
+    EventContent,
+    Transaction,
+    TransactionHash,
+    TransactionOffsetInBlock,
+};
+use tracing::{debug, warn};
+
+use crate::base_layer::BaseLayerStorageReader;
+use crate::body::events::ThinTransactionOutput;
+use crate::body::{BodyStorageReader, BodyStorageWriter, RevertedBlockBody, TransactionIndex};
+use crate::db::{TransactionKind, RW};
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::prune::PruneStorageReader;
+use crate::state::{RevertedStateDiff, StateStorageReader, StateStorageWriter};
+use crate::{MarkerKind, StorageError, StorageResult, StorageScope, StorageTxn, StorageWriter};
+
+/// The data of a block that was removed from the storage.
+#[derive(Debug)]
+pub struct RevertedBlock {
+    /// The header of the block.
+    pub header: BlockHeader,
+    /// The body of the block, if it was stored.
+    pub body: Option<RevertedBlockBody>,
+    /// The state diff of the block, if it was stored.
+    pub state_diff: Option<RevertedStateDiff>,
+}
+
+/// A reverted block that was kept in the archive of reverted blocks.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ArchivedBlock {
+    /// The header of the block.
+    pub header: BlockHeader,
+    /// The body of the block, if it was stored.
+    pub body: Option<ArchivedBlockBody>,
+}
+
+/// The body of an archived reverted block.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ArchivedBlockBody {
+    /// The transactions of the block.
+    pub transactions: Vec<Transaction>,
+    /// The outputs of the transactions, without their events.
+    pub transaction_outputs: Vec<ThinTransactionOutput>,
+    /// The hashes of the transactions.
+    pub transaction_hashes: Vec<TransactionHash>,
+    /// The events of each transaction.
+    pub events: Vec<Vec<EventContent>>,
+}
+
+impl From<RevertedBlockBody> for ArchivedBlockBody {
+    fn from(
+        (transactions, transaction_outputs, transaction_hashes, events): RevertedBlockBody,
+    ) -> Self {
+        Self { transactions, transaction_outputs, transaction_hashes, events }
+    }
+}
+
+/// Interface for reading the archive of reverted blocks.
+pub trait RevertedBlocksStorageReader {
+    /// Returns the archived reverted blocks with the given number, ordered by their hash. Empty if
+    /// no block with this number was reverted while archiving was enabled.
+    fn get_reverted_blocks(&self, block_number: BlockNumber) -> StorageResult<Vec<ArchivedBlock>>;
+}
+
+impl<'env, Mode: TransactionKind> RevertedBlocksStorageReader for StorageTxn<'env, Mode> {
+    fn get_reverted_blocks(&self, block_number: BlockNumber) -> StorageResult<Vec<ArchivedBlock>> {
+        let reverted_blocks_table = self.open_table(&self.tables.reverted_blocks)?;
+        let mut cursor = reverted_blocks_table.cursor(&self.txn)?;
+        let mut current = cursor.lower_bound(&(block_number, BlockHash::default()))?;
+        let mut reverted_blocks = Vec::new();
+        while let Some(((current_block_number, _), archived_block)) = current {
+            if current_block_number != block_number {
+                break;
+            }
+            reverted_blocks.push(archived_block);
+            current = cursor.next()?;
+        }
+        Ok(reverted_blocks)
+    }
+}
+
+impl StorageWriter {
+    /// Removes the header, body and state diff of the block, along with the indices derived from
+    /// them, in a single transaction and returns the removed data.
+    /// Only the last block in the storage can be reverted, and reverting any other block is a
+    /// no-op that returns None. Blocks that are finalized on the base layer can't be reverted.
+    /// If [`crate::StorageConfig::archive_reverted`] is set, the header and body of the block are
+    /// moved to the archive of reverted blocks in the same transaction.
+    pub fn revert_block(
+        &mut self,
+        block_number: BlockNumber,
+    ) -> StorageResult<Option<RevertedBlock>> {
+        let archive_reverted = self.storage_config.archive_reverted;
+        let txn = self.begin_rw_txn()?;
+        let base_layer_marker = txn.get_base_layer_block_marker()?;
+        if block_number < base_layer_marker {
+            return Err(StorageError::RevertFinalizedBlock { block_number, base_layer_marker });
+        }
+        let pruned_marker = txn.get_pruned_marker()?;
+        if block_number < pruned_marker {
+            return Err(StorageError::RevertPrunedBlock { block_number, pruned_marker });
+        }
+
+        let (txn, Some(header)) = txn.revert_header(block_number)? else {
+            return Ok(None);
+        };
+        let (txn, body) = txn.revert_body(block_number)?;
+        let (txn, state_diff) = txn.revert_state_diff(block_number)?;
+        if archive_reverted {
+            let archived_block =
+                ArchivedBlock { header: header.clone(), body: body.clone().map(Into::into) };
+            let reverted_blocks_table = txn.open_table(&txn.tables.reverted_blocks)?;
+            reverted_blocks_table.upsert(
+                &txn.txn,
+                &(block_number, header.block_hash),
+                &archived_block,
+            )?;
+        }
+        txn.commit()?;
+        debug!(block_hash = %header.block_hash, "Reverted block {block_number}.");
+
+        Ok(Some(RevertedBlock { header, body, state_diff }))
+    }
+
+    // Reverts the header, body and state diff that were written for the block at the marker of
+    // their table without advancing the marker. The marker is advanced in the same transaction,
+    // so that the data is removed like the data of the last block.
+    pub(crate) fn revert_dangling_writes(&mut self) -> StorageResult<()> {
+        let mut txn = self.begin_rw_txn()?;
+
+        let header_marker = txn.get_header_marker()?;
+        if txn.get_block_header(header_marker)?.is_some() {
+            warn!("Reverting the dangling header of block {header_marker}.");
+            txn = advance_marker(txn, MarkerKind::Header, header_marker)?
+                .revert_header(header_marker)?
+                .0;
+        }
+
+        let body_marker = txn.get_body_marker()?;
+        if txn.scope != StorageScope::StateOnly {
+            let first_transaction_index =
+                TransactionIndex(body_marker, TransactionOffsetInBlock(0));
+            let transaction_outputs_table = txn.open_table(&txn.tables.transaction_outputs)?;
+            if transaction_outputs_table.get(&txn.txn, &first_transaction_index)?.is_some() {
+                warn!("Reverting the dangling body of block {body_marker}.");
+                txn =
+                    advance_marker(txn, MarkerKind::Body, body_marker)?.revert_body(body_marker)?.0;
+            }
+        }
+
+        let state_marker = txn.get_state_marker()?;
+        let state_diffs_table = txn.open_table(&txn.tables.state_diffs)?;
+        if state_diffs_table.get(&txn.txn, &state_marker)?.is_some() {
+            warn!("Reverting the dangling state diff of block {state_marker}.");
+            txn = advance_marker(txn, MarkerKind::State, state_marker)?
+                .revert_state_diff(state_marker)?
+                .0;
+        }
+
+        txn.commit()
+    }
+}
+
+fn advance_marker(
+    txn: StorageTxn<'_, RW>,
+    marker_kind: MarkerKind,
+    marker: BlockNumber,
+) -> StorageResult<StorageTxn<'_, RW>> {
+    let markers_table = txn.open_table(&txn.tables.markers)?;
+    markers_table.upsert(&txn.txn, &marker_kind, &marker.next())?;
+    Ok(txn)
+}
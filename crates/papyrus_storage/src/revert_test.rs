@@ -0,0 +1,164 @@
+use assert_matches::assert_matches;
+use indexmap::IndexMap;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockBody, BlockHash, BlockHeader, BlockNumber};
+use starknet_api::stark_felt;
+use starknet_api::state::StateDiff;
+use starknet_api::transaction::TransactionOffsetInBlock;
+use test_utils::get_test_body;
+
+use crate::base_layer::BaseLayerStorageWriter;
+use crate::body::{BodyStorageReader, BodyStorageWriter, TransactionIndex};
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::revert::{ArchivedBlock, ArchivedBlockBody, RevertedBlocksStorageReader};
+use crate::state::{StateStorageReader, StateStorageWriter};
+use crate::test_utils::{append_block, get_test_config, get_test_header, get_test_storage};
+use crate::{open_storage, MarkerKind, StorageConfig, StorageError, StorageReader, StorageWriter};
+
+fn append_blocks(writer: &mut StorageWriter, n_blocks: u64) {
+    for block_number in (0..n_blocks).map(BlockNumber) {
+        append_block(writer, block_number, BlockBody::default());
+    }
+}
+
+fn assert_markers(reader: &StorageReader, expected_marker: BlockNumber) {
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), expected_marker);
+    assert_eq!(txn.get_body_marker().unwrap(), expected_marker);
+    assert_eq!(txn.get_state_marker().unwrap(), expected_marker);
+}
+
+#[test]
+fn revert_top_blocks() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    append_blocks(&mut writer, 3);
+
+    for block_number in [BlockNumber(2), BlockNumber(1)] {
+        let reverted_block = writer.revert_block(block_number).unwrap().unwrap();
+        assert_eq!(reverted_block.header, get_test_header(block_number));
+        assert!(reverted_block.body.is_some());
+        assert!(reverted_block.state_diff.is_some());
+        assert_markers(&reader, block_number);
+        let block_hash = get_test_header(block_number).block_hash;
+        assert_eq!(
+            reader.begin_ro_txn().unwrap().get_block_number_by_hash(&block_hash).unwrap(),
+            None
+        );
+    }
+    assert_eq!(
+        reader.begin_ro_txn().unwrap().get_block_header(BlockNumber(0)).unwrap(),
+        Some(get_test_header(BlockNumber(0)))
+    );
+}
+
+#[test]
+fn revert_non_existing_block_is_noop() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    append_blocks(&mut writer, 2);
+
+    assert!(writer.revert_block(BlockNumber(1)).unwrap().is_some());
+    // Reverting the same block again, or a block that was never stored, does nothing.
+    assert!(writer.revert_block(BlockNumber(1)).unwrap().is_none());
+    assert!(writer.revert_block(BlockNumber(5)).unwrap().is_none());
+    assert_markers(&reader, BlockNumber(1));
+}
+
+#[test]
+fn revert_finalized_block_fails() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    append_blocks(&mut writer, 2);
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .update_base_layer_block_marker(&BlockNumber(2))
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let err = writer.revert_block(BlockNumber(1)).unwrap_err();
+    assert_matches!(
+        err,
+        StorageError::RevertFinalizedBlock { block_number, base_layer_marker }
+        if block_number == BlockNumber(1) && base_layer_marker == BlockNumber(2)
+    );
+    assert_markers(&reader, BlockNumber(2));
+}
+
+#[test]
+fn reverted_blocks_are_archived() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) =
+        open_storage(StorageConfig { archive_reverted: true, ..config }).unwrap();
+    append_blocks(&mut writer, 2);
+    let original_header = get_test_header(BlockNumber(1));
+    writer.revert_block(BlockNumber(1)).unwrap().unwrap();
+
+    // Another block with the same number is reverted after a reorg.
+    let reorged_header =
+        BlockHeader { block_hash: BlockHash(stark_felt!("0x99")), ..original_header.clone() };
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(1), &reorged_header)
+        .unwrap()
+        .append_body(BlockNumber(1), BlockBody::default())
+        .unwrap()
+        .append_state_diff(BlockNumber(1), StateDiff::default(), IndexMap::new())
+        .unwrap()
+        .commit()
+        .unwrap();
+    writer.revert_block(BlockNumber(1)).unwrap().unwrap();
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let expected_blocks = [original_header, reorged_header]
+        .map(|header| ArchivedBlock { header, body: Some(ArchivedBlockBody::default()) });
+    assert_eq!(txn.get_reverted_blocks(BlockNumber(1)).unwrap(), expected_blocks);
+    assert!(txn.get_reverted_blocks(BlockNumber(0)).unwrap().is_empty());
+    assert!(txn.get_reverted_blocks(BlockNumber(2)).unwrap().is_empty());
+}
+
+#[test]
+fn reverted_blocks_are_not_archived_by_default() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    append_blocks(&mut writer, 2);
+    writer.revert_block(BlockNumber(1)).unwrap().unwrap();
+
+    let txn = reader.begin_ro_txn().unwrap();
+    assert!(txn.get_reverted_blocks(BlockNumber(1)).unwrap().is_empty());
+}
+
+#[test]
+fn dangling_body_is_reverted_when_the_storage_is_opened() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) = open_storage(config.clone()).unwrap();
+    append_blocks(&mut writer, 2);
+
+    // Simulates a write of the body of block 2 that was interrupted before its marker was written.
+    let body = get_test_body(2, None, None, None);
+    let transaction_hash = body.transaction_hashes[0];
+    let txn = writer.begin_rw_txn().unwrap().append_body(BlockNumber(2), body.clone()).unwrap();
+    let markers_table = txn.open_table(&txn.tables.markers).unwrap();
+    markers_table.upsert(&txn.txn, &MarkerKind::Body, &BlockNumber(2)).unwrap();
+    txn.commit().unwrap();
+    assert!(
+        reader
+            .begin_ro_txn()
+            .unwrap()
+            .get_transaction_idx_by_hash(&transaction_hash)
+            .unwrap()
+            .is_some()
+    );
+    drop((reader, writer));
+
+    let (reader, mut writer) = open_storage(config).unwrap();
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_body_marker().unwrap(), BlockNumber(2));
+    let first_transaction_index = TransactionIndex(BlockNumber(2), TransactionOffsetInBlock(0));
+    assert_eq!(txn.get_transaction(first_transaction_index).unwrap(), None);
+    assert_eq!(txn.get_transaction_idx_by_hash(&transaction_hash).unwrap(), None);
+    // The consistent blocks are kept.
+    assert_markers(&reader, BlockNumber(2));
+
+    // The body can be written again.
+    writer.begin_rw_txn().unwrap().append_body(BlockNumber(2), body).unwrap().commit().unwrap();
+}
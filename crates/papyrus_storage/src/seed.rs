@@ -0,0 +1,57 @@
+//! Interface for starting the chain of the storage at a block other than genesis, e.g. when the
+//! state before that block is imported from a snapshot.
+//!
+//! Only the header of the block before the start block is stored, so that the following blocks can
+//! be verified against it. The blocks before the start block are reported as missing, like pruned
+//! blocks, even if some of them were already stored, and can't be reverted. The state of the
+//! storage only includes the state diffs from the start block on, so it isn't complete unless the
+//! state before it is imported separately.
+
+#[cfg(test)]
+#[path = "seed_test.rs"]
+mod seed_test;
+
+use starknet_api::block::BlockHeader;
+
+use crate::db::RW;
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::{MarkerKind, StorageError, StorageResult, StorageTxn};
+
+/// Interface for seeding the chain of the storage at a start block.
+pub trait SeedStorageWriter
+where
+    Self: Sized,
+{
+    /// Stores the given header, of the block before the start block, and moves the markers of
+    /// the blocks, their bodies, their state diffs and their compiled classes to the start block,
+    /// so that the next blocks are appended from there. Fails if the storage already holds the
+    /// header of the given block.
+    fn seed_start_block(self, parent_header: &BlockHeader) -> StorageResult<Self>;
+}
+
+impl<'env> SeedStorageWriter for StorageTxn<'env, RW> {
+    fn seed_start_block(self, parent_header: &BlockHeader) -> StorageResult<Self> {
+        let parent_block_number = parent_header.block_number;
+        let header_marker = self.get_header_marker()?;
+        if header_marker > parent_block_number {
+            return Err(StorageError::MarkerMismatch {
+                expected: header_marker,
+                found: parent_block_number,
+            });
+        }
+
+        {
+            let markers_table = self.open_table(&self.tables.markers)?;
+            markers_table.upsert(&self.txn, &MarkerKind::Header, &parent_block_number)?;
+        }
+        let txn = self.append_header(parent_block_number, parent_header)?;
+        let markers_table = txn.open_table(&txn.tables.markers)?;
+        let start_block = parent_block_number.next();
+        for marker_kind in
+            [MarkerKind::Body, MarkerKind::State, MarkerKind::CompiledClass, MarkerKind::Pruned]
+        {
+            markers_table.upsert(&txn.txn, &marker_kind, &start_block)?;
+        }
+        Ok(txn)
+    }
+}
@@ -0,0 +1,112 @@
+use assert_matches::assert_matches;
+use pretty_assertions::assert_eq;
+use starknet_api::block::BlockNumber;
+use starknet_api::hash::StarkFelt;
+use starknet_api::transaction::TransactionHash;
+use test_utils::get_test_body;
+
+use crate::body::{BodyStorageReader, BodyStorageWriter};
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::seed::SeedStorageWriter;
+use crate::snapshot::Markers;
+use crate::test_utils::{append_block, get_test_config, get_test_header, get_test_storage};
+use crate::{open_storage, StorageConfig, StorageError};
+
+#[test]
+fn blocks_are_appended_from_the_seeded_start_block() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    let body = get_test_body(1, None, None, None);
+    let mut stored_body = body.clone();
+    stored_body.transaction_hashes = vec![TransactionHash(StarkFelt::ONE)];
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &get_test_header(BlockNumber(0)))
+        .unwrap()
+        .append_body(BlockNumber(0), stored_body)
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let start_block = BlockNumber(5);
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .seed_start_block(&get_test_header(BlockNumber(4)))
+        .unwrap()
+        .commit()
+        .unwrap();
+    assert_eq!(
+        reader.get_markers().unwrap(),
+        Markers {
+            header: start_block,
+            body: start_block,
+            state: start_block,
+            compiled_class: start_block,
+            base_layer_block: BlockNumber(0),
+            pruned: start_block,
+        }
+    );
+
+    // The parent of the start block is stored, and the blocks before it are reported as missing.
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(
+        txn.get_block_header(BlockNumber(4)).unwrap(),
+        Some(get_test_header(BlockNumber(4)))
+    );
+    assert_eq!(txn.get_block_header(BlockNumber(3)).unwrap(), None);
+    assert_eq!(txn.get_block_transactions(BlockNumber(0)).unwrap(), None);
+
+    append_block(&mut writer, start_block, body.clone());
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_block_transactions(start_block).unwrap(), Some(body.transactions));
+}
+
+#[test]
+fn seeding_fails_if_the_parent_is_already_stored() {
+    let ((_reader, mut writer), _temp_dir) = get_test_storage();
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &get_test_header(BlockNumber(0)))
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let result = writer
+        .begin_rw_txn()
+        .unwrap()
+        .seed_start_block(&get_test_header(BlockNumber(0)))
+        .map(|_txn| ());
+    assert_matches!(
+        result,
+        Err(StorageError::MarkerMismatch { expected: BlockNumber(1), found: BlockNumber(0) })
+    );
+}
+
+#[test]
+fn seeded_storage_passes_parent_hash_verification() {
+    let (config, _temp_dir) = get_test_config(None);
+    {
+        let (_, mut writer) = open_storage(config.clone()).unwrap();
+        let mut txn = writer
+            .begin_rw_txn()
+            .unwrap()
+            .seed_start_block(&get_test_header(BlockNumber(4)))
+            .unwrap();
+        for i in 5..8_u64 {
+            txn = txn.append_header(BlockNumber(i), &get_test_header(BlockNumber(i))).unwrap();
+        }
+        txn.commit().unwrap();
+    }
+
+    // The headers before the seeded parent block were never stored, so they aren't verified.
+    for parent_hash_verification_depth in [None, Some(100)] {
+        open_storage(StorageConfig {
+            verify_parent_hashes: true,
+            parent_hash_verification_depth,
+            ..config.clone()
+        })
+        .unwrap();
+    }
+}
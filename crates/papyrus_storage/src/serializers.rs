@@ -122,8 +122,10 @@ use crate::compression_utils::{
     IsCompressed,
 };
 use crate::db::serialization::{StorageSerde, StorageSerdeError};
+use crate::event_log::EventLogEntry;
 use crate::header::StarknetVersion;
 use crate::mmap_file::LocationInFile;
+use crate::revert::{ArchivedBlock, ArchivedBlockBody};
 #[cfg(test)]
 use crate::serializers::serializers_test::{create_storage_serde_test, StorageSerdeTest};
 use crate::state::data::IndexedDeprecatedContractClass;
@@ -135,6 +137,16 @@ const COMPRESSION_THRESHOLD_BYTES: usize = 384;
 
 auto_storage_serde! {
     pub struct AccountDeploymentData(pub Vec<StarkFelt>);
+    pub struct ArchivedBlock {
+        pub header: BlockHeader,
+        pub body: Option<ArchivedBlockBody>,
+    }
+    pub struct ArchivedBlockBody {
+        pub transactions: Vec<Transaction>,
+        pub transaction_outputs: Vec<ThinTransactionOutput>,
+        pub transaction_hashes: Vec<TransactionHash>,
+        pub events: Vec<Vec<EventContent>>,
+    }
     pub struct BlockHash(pub StarkHash);
     pub struct BlockHeader {
         pub block_hash: BlockHash,
@@ -240,6 +252,10 @@ auto_storage_serde! {
         pub data: EventData,
     }
     pub struct EventData(pub Vec<StarkFelt>);
+    pub struct EventLogEntry {
+        pub timestamp_millis: u64,
+        pub event: String,
+    }
     struct EventIndex(pub TransactionIndex, pub EventIndexInTransactionOutput);
     pub struct EventIndexInTransactionOutput(pub usize);
     pub struct EventKey(pub StarkFelt);
@@ -277,6 +293,7 @@ auto_storage_serde! {
         State = 2,
         CompiledClass = 3,
         BaseLayerBlock = 4,
+        Pruned = 5,
     }
     pub struct MessageToL1 {
         pub to_address: EthAddress,
@@ -430,6 +447,7 @@ auto_storage_serde! {
     binary(u128, read_u128, write_u128);
 
 
+    (BlockNumber, BlockHash);
     (BlockNumber, TransactionOffsetInBlock);
     (BlockHash, ClassHash);
     (ContractAddress, BlockHash);
@@ -438,6 +456,7 @@ auto_storage_serde! {
     (ContractAddress, EventIndex);
     (ContractAddress, StorageKey, BlockHash);
     (ContractAddress, StorageKey, BlockNumber);
+    (u64, u32);
     (usize, Vec<Hint>);
     (usize, Vec<String>);
 }
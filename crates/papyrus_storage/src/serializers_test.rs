@@ -7,6 +7,7 @@ use starknet_api::block::BlockNumber;
 use starknet_api::core::ContractAddress;
 use starknet_api::hash::StarkHash;
 use starknet_api::state::StorageKey;
+use starknet_api::transaction::TransactionOffsetInBlock;
 use test_utils::{get_rng, read_json_file, GetTestInstance};
 
 use crate::db::serialization::StorageSerde;
@@ -74,6 +75,37 @@ fn block_number_endianness() {
     assert!(bytes_255 < bytes_256);
 }
 
+fn serialize<T: StorageSerde>(item: &T) -> Vec<u8> {
+    let mut serialized: Vec<u8> = Vec::new();
+    item.serialize_into(&mut serialized).unwrap();
+    serialized
+}
+
+// The encoding mustn't depend on the byte order of the machine, so that the database files are
+// portable.
+#[test]
+fn serialization_byte_layout() {
+    assert_eq!(serialize(&0x12_u8), [0x12]);
+    assert_eq!(serialize(&true), [0x01]);
+    assert_eq!(serialize(&0x01020304_u32), [0x01, 0x02, 0x03, 0x04]);
+    assert_eq!(
+        serialize(&0x0102030405060708_u64),
+        [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]
+    );
+    assert_eq!(serialize(&0x0102_u128), [[0; 14].as_slice(), &[0x01, 0x02]].concat());
+    assert_eq!(serialize(&0x0102_usize), [0, 0, 0, 0, 0, 0, 0x01, 0x02]);
+    assert_eq!(serialize(&BlockNumber(0x0102)), [0, 0, 0, 0, 0, 0, 0x01, 0x02]);
+    assert_eq!(
+        serialize(&(BlockNumber(1), TransactionOffsetInBlock(2))),
+        [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2]
+    );
+    assert_eq!(serialize(&Some(0x01_u8)), [0x01, 0x01]);
+    assert_eq!(serialize(&Option::<u8>::None), [0x00]);
+    // The length of a vector is encoded as a varint.
+    assert_eq!(serialize(&vec![0x07_u8; 300])[..3], [0xac, 0x02, 0x07]);
+    assert_eq!(serialize(&"ab".to_owned()), [0x02, b'a', b'b']);
+}
+
 // Make sure that the [`Hint`] schema is not modified. If it is, its encoding might change and a
 // storage migration is needed.
 #[test]
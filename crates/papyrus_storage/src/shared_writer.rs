@@ -0,0 +1,86 @@
+//! A handle for sharing the single [`StorageWriter`] between several users, e.g. tasks of the
+//! node that need to write, where taking the writer can time out.
+//!
+//! Only one user holds the writer at any given moment, so the storage still has a single write
+//! transaction open at most. A user that can't take the writer within its timeout fails with
+//! [`DbError::WriterBusy`] instead of stalling without notice.
+
+#[cfg(test)]
+#[path = "shared_writer_test.rs"]
+mod shared_writer_test;
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::db::DbError;
+use crate::{StorageResult, StorageWriter};
+
+/// A clonable handle to a [`StorageWriter`] that one user at a time can take.
+#[derive(Clone)]
+pub struct SharedStorageWriter {
+    inner: Arc<SharedWriterInner>,
+}
+
+struct SharedWriterInner {
+    // None while a user holds the writer.
+    writer: Mutex<Option<StorageWriter>>,
+    released: Condvar,
+}
+
+impl SharedStorageWriter {
+    /// Wraps the given writer.
+    pub fn new(writer: StorageWriter) -> Self {
+        Self {
+            inner: Arc::new(SharedWriterInner {
+                writer: Mutex::new(Some(writer)),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Takes the writer, waiting until the user that holds it releases it. Fails with
+    /// [`DbError::WriterBusy`] if it isn't released within the timeout.
+    pub fn try_lock_for(&self, timeout: Duration) -> StorageResult<SharedStorageWriterGuard> {
+        let writer = self.inner.writer.lock().expect("Shared writer should not be poisoned.");
+        let (mut writer, wait_result) = self
+            .inner
+            .released
+            .wait_timeout_while(writer, timeout, |writer| writer.is_none())
+            .expect("Shared writer should not be poisoned.");
+        if wait_result.timed_out() {
+            return Err(DbError::WriterBusy { timeout }.into());
+        }
+        let writer = writer.take().expect("Shared writer should be released.");
+        Ok(SharedStorageWriterGuard { writer: Some(writer), shared_writer: self.inner.clone() })
+    }
+}
+
+/// Holds the writer of a [`SharedStorageWriter`], and releases it to the next user when dropped.
+pub struct SharedStorageWriterGuard {
+    // Always set until the guard is dropped.
+    writer: Option<StorageWriter>,
+    shared_writer: Arc<SharedWriterInner>,
+}
+
+impl Deref for SharedStorageWriterGuard {
+    type Target = StorageWriter;
+
+    fn deref(&self) -> &Self::Target {
+        self.writer.as_ref().expect("Guard should hold the writer.")
+    }
+}
+
+impl DerefMut for SharedStorageWriterGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.writer.as_mut().expect("Guard should hold the writer.")
+    }
+}
+
+impl Drop for SharedStorageWriterGuard {
+    fn drop(&mut self) {
+        *self.shared_writer.writer.lock().expect("Shared writer should not be poisoned.") =
+            self.writer.take();
+        self.shared_writer.released.notify_one();
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use assert_matches::assert_matches;
+
+use crate::db::DbError;
+use crate::shared_writer::SharedStorageWriter;
+use crate::test_utils::get_test_storage;
+use crate::StorageError;
+
+#[test]
+fn taking_the_writer_times_out_while_another_thread_holds_it() {
+    const TIMEOUT: Duration = Duration::from_millis(100);
+    let ((_reader, writer), _temp_dir) = get_test_storage();
+    let shared_writer = SharedStorageWriter::new(writer);
+
+    let (taken_sender, taken_receiver) = mpsc::channel();
+    let (release_sender, release_receiver) = mpsc::channel::<()>();
+    let holding_thread = thread::spawn({
+        let shared_writer = shared_writer.clone();
+        move || {
+            let mut writer = shared_writer.try_lock_for(TIMEOUT).unwrap();
+            let txn = writer.begin_rw_txn().unwrap();
+            taken_sender.send(()).unwrap();
+            release_receiver.recv().unwrap();
+            txn.commit().unwrap();
+        }
+    });
+    taken_receiver.recv().unwrap();
+
+    let start_time = Instant::now();
+    assert_matches!(
+        shared_writer.try_lock_for(TIMEOUT).err(),
+        Some(StorageError::InnerError(DbError::WriterBusy { timeout })) if timeout == TIMEOUT
+    );
+    assert!(start_time.elapsed() >= TIMEOUT);
+
+    // The writer is taken once the holding thread releases it.
+    release_sender.send(()).unwrap();
+    shared_writer
+        .try_lock_for(Duration::from_secs(60))
+        .unwrap()
+        .begin_rw_txn()
+        .unwrap()
+        .commit()
+        .unwrap();
+    holding_thread.join().unwrap();
+}
@@ -0,0 +1,146 @@
+//! Interface for reading the data of a block from a single snapshot of the storage.
+//!
+//! All the reads of a [`StorageTxn`] see the storage as it was when the transaction began, across
+//! all the tables, so a [`BlockSnapshot`] never mixes data from before and after a commit. To keep
+//! this guarantee, read all the data that has to be consistent from the same transaction instead
+//! of beginning a new one for each read.
+//!
+//! # Example
+//! ```
+//! use papyrus_storage::open_storage;
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
+//! use starknet_api::block::BlockNumber;
+//!
+//! # let dir_handle = tempfile::tempdir().unwrap();
+//! # let dir = dir_handle.path().to_path_buf();
+//! # let db_config = DbConfig {
+//! #     path_prefix: dir,
+//! #     chain_id: starknet_api::core::ChainId("SN_MAIN".to_owned()),
+//! #     enforce_file_exists: false,
+//! #     min_size: 1 << 20,    // 1MB
+//! #     max_size: 1 << 35,    // 32GB
+//! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
+//! # };
+//! # let storage_config = StorageConfig{db_config, ..Default::default()};
+//! let (reader, _writer) = open_storage(storage_config)?;
+//! let txn = reader.begin_ro_txn()?;
+//! let block = txn.get_block_snapshot(BlockNumber(0));
+//! // The header and the transactions are read from the same snapshot.
+//! assert_eq!(block.header()?, None);
+//! assert_eq!(block.transactions()?, None);
+//! # Ok::<(), papyrus_storage::StorageError>(())
+//! ```
+//!
+//! Similarly, [`Markers`] holds the markers of all the data kinds, read from a single snapshot, so
+//! they never contradict each other.
+
+#[cfg(test)]
+#[path = "snapshot_test.rs"]
+mod snapshot_test;
+
+use serde::{Deserialize, Serialize};
+use starknet_api::block::{BlockHeader, BlockNumber};
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{Transaction, TransactionHash};
+
+use crate::base_layer::BaseLayerStorageReader;
+use crate::body::events::ThinTransactionOutput;
+use crate::body::BodyStorageReader;
+use crate::compiled_class::CasmStorageReader;
+use crate::db::TransactionKind;
+use crate::header::{HeaderStorageReader, StarknetVersion};
+use crate::prune::PruneStorageReader;
+use crate::state::StateStorageReader;
+use crate::{StorageReader, StorageResult, StorageTxn};
+
+/// Typed accessors for the data of a single block, all read from the snapshot of the transaction
+/// that created it.
+pub struct BlockSnapshot<'txn, 'env, Mode: TransactionKind> {
+    txn: &'txn StorageTxn<'env, Mode>,
+    block_number: BlockNumber,
+}
+
+/// The markers of all the data kinds in the storage, read from a single snapshot. A marker is the
+/// first block number for which the corresponding data doesn't exist yet.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Markers {
+    /// The marker of the block headers.
+    pub header: BlockNumber,
+    /// The marker of the block bodies.
+    pub body: BlockNumber,
+    /// The marker of the state diffs.
+    pub state: BlockNumber,
+    /// The marker of the compiled classes.
+    pub compiled_class: BlockNumber,
+    /// The marker of the blocks that were accepted on the base layer.
+    pub base_layer_block: BlockNumber,
+    /// The first block whose body and state diff weren't pruned.
+    pub pruned: BlockNumber,
+}
+
+impl<'env, Mode: TransactionKind> StorageTxn<'env, Mode> {
+    /// Returns accessors for the data of the block, read from the snapshot of this transaction.
+    pub fn get_block_snapshot(&self, block_number: BlockNumber) -> BlockSnapshot<'_, 'env, Mode> {
+        BlockSnapshot { txn: self, block_number }
+    }
+
+    /// Returns the markers of all the data kinds, read from the snapshot of this transaction.
+    pub fn get_markers(&self) -> StorageResult<Markers> {
+        Ok(Markers {
+            header: self.get_header_marker()?,
+            body: self.get_body_marker()?,
+            state: self.get_state_marker()?,
+            compiled_class: self.get_compiled_class_marker()?,
+            base_layer_block: self.get_base_layer_block_marker()?,
+            pruned: self.get_pruned_marker()?,
+        })
+    }
+}
+
+impl StorageReader {
+    /// Returns the markers of all the data kinds, read from a single snapshot of the storage.
+    pub fn get_markers(&self) -> StorageResult<Markers> {
+        self.begin_ro_txn()?.get_markers()
+    }
+}
+
+impl<'txn, 'env, Mode: TransactionKind> BlockSnapshot<'txn, 'env, Mode> {
+    /// Returns the number of the block.
+    pub fn block_number(&self) -> BlockNumber {
+        self.block_number
+    }
+
+    /// Returns the header of the block.
+    pub fn header(&self) -> StorageResult<Option<BlockHeader>> {
+        self.txn.get_block_header(self.block_number)
+    }
+
+    /// Returns the Starknet version the block was created with.
+    pub fn starknet_version(&self) -> StorageResult<Option<StarknetVersion>> {
+        self.txn.get_starknet_version(self.block_number)
+    }
+
+    /// Returns the transactions of the block.
+    pub fn transactions(&self) -> StorageResult<Option<Vec<Transaction>>> {
+        self.txn.get_block_transactions(self.block_number)
+    }
+
+    /// Returns the transaction hashes of the block.
+    pub fn transaction_hashes(&self) -> StorageResult<Option<Vec<TransactionHash>>> {
+        self.txn.get_block_transaction_hashes(self.block_number)
+    }
+
+    /// Returns the transaction outputs of the block.
+    pub fn transaction_outputs(&self) -> StorageResult<Option<Vec<ThinTransactionOutput>>> {
+        self.txn.get_block_transaction_outputs(self.block_number)
+    }
+
+    /// Returns the state diff of the block.
+    pub fn state_diff(&self) -> StorageResult<Option<ThinStateDiff>> {
+        self.txn.get_state_diff(self.block_number)
+    }
+}
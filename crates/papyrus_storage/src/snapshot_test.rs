@@ -0,0 +1,96 @@
+use indexmap::IndexMap;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockHeader, BlockNumber};
+use starknet_api::state::{StateDiff, ThinStateDiff};
+use test_utils::get_test_body;
+
+use crate::body::BodyStorageWriter;
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::snapshot::Markers;
+use crate::state::StateStorageWriter;
+use crate::test_utils::get_test_storage;
+
+#[test]
+fn ro_txn_sees_a_consistent_snapshot_across_tables() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // A long-lived read transaction that begins before the write.
+    let old_txn = reader.begin_ro_txn().unwrap();
+
+    let body = get_test_body(2, None, None, None);
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_body(BlockNumber(0), body.clone())
+        .unwrap()
+        .append_state_diff(BlockNumber(0), StateDiff::default(), IndexMap::new())
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // The old transaction doesn't see the write in any of the tables.
+    let old_block = old_txn.get_block_snapshot(BlockNumber(0));
+    assert_eq!(old_block.header().unwrap(), Some(BlockHeader::default()));
+    assert_eq!(old_block.transactions().unwrap(), None);
+    assert_eq!(old_block.transaction_hashes().unwrap(), None);
+    assert_eq!(old_block.state_diff().unwrap(), None);
+
+    // A transaction that begins after the write sees it in all the tables.
+    let new_txn = reader.begin_ro_txn().unwrap();
+    let new_block = new_txn.get_block_snapshot(BlockNumber(0));
+    assert_eq!(new_block.header().unwrap(), old_txn.get_block_header(BlockNumber(0)).unwrap());
+    assert_eq!(new_block.transactions().unwrap(), Some(body.transactions));
+    assert_eq!(new_block.transaction_hashes().unwrap(), Some(body.transaction_hashes));
+    assert_eq!(new_block.state_diff().unwrap(), Some(ThinStateDiff::from(StateDiff::default())));
+}
+
+#[test]
+fn markers_are_read_from_a_single_snapshot() {
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+    assert_eq!(reader.get_markers().unwrap(), Markers::default());
+
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // The header was committed but the body wasn't yet.
+    let markers = reader.get_markers().unwrap();
+    assert_eq!(markers, Markers { header: BlockNumber(1), ..Markers::default() });
+    assert!(markers.header > markers.body);
+
+    // A transaction that begins before the body is committed keeps seeing the old markers.
+    let old_txn = reader.begin_ro_txn().unwrap();
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_body(BlockNumber(0), get_test_body(2, None, None, None))
+        .unwrap()
+        .commit()
+        .unwrap();
+    assert_eq!(old_txn.get_markers().unwrap(), markers);
+    assert_eq!(
+        reader.get_markers().unwrap(),
+        Markers { header: BlockNumber(1), body: BlockNumber(1), ..Markers::default() }
+    );
+
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_state_diff(BlockNumber(0), StateDiff::default(), IndexMap::new())
+        .unwrap()
+        .commit()
+        .unwrap();
+    assert_eq!(writer.prune_blocks(BlockNumber(1), 1).unwrap(), BlockNumber(1));
+    assert_eq!(reader.get_markers().unwrap().pruned, BlockNumber(1));
+}
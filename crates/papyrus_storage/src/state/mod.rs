@@ -10,7 +10,7 @@
 //! use papyrus_storage::open_storage;
 //! use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
 //! # use indexmap::IndexMap;
-//! # use papyrus_storage::{db::DbConfig, StorageConfig};
+//! # use papyrus_storage::{db::{DbConfig, SyncMode}, StorageConfig};
 //! # use starknet_api::block::BlockNumber;
 //! # use starknet_api::core::{ChainId, ContractAddress};
 //! use starknet_api::state::{StateDiff, StateNumber, ThinStateDiff};
@@ -24,6 +24,10 @@
 //! #     min_size: 1 << 20,    // 1MB
 //! #     max_size: 1 << 35,    // 32GB
 //! #     growth_step: 1 << 26, // 64MB
+//! #     sync_mode: SyncMode::Durable,
+//! #     read_cache_capacity: None,
+//! #     stale_read_txn_threshold: None,
+//! #     write_txn_timeout: None,
 //! # };
 //! # let storage_config = StorageConfig{db_config, ..Default::default()};
 //! let state_diff = StateDiff::default();
@@ -64,6 +68,7 @@ use starknet_api::hash::StarkFelt;
 use starknet_api::state::{ContractClass, StateDiff, StateNumber, StorageKey, ThinStateDiff};
 use tracing::debug;
 
+use crate::changelog::ChangelogEntry;
 use crate::db::serialization::NoVersionValueWrapper;
 use crate::db::{DbError, DbTransaction, TableHandle, TransactionKind, RW};
 use crate::mmap_file::LocationInFile;
@@ -122,7 +127,8 @@ pub trait StateStorageReader<Mode: TransactionKind> {
     fn get_state_reader(&self) -> StorageResult<StateReader<'_, Mode>>;
 }
 
-type RevertedStateDiff = (
+/// The state diff of a reverted block, with the classes it declared.
+pub type RevertedStateDiff = (
     ThinStateDiff,
     IndexMap<ClassHash, ContractClass>,
     IndexMap<ClassHash, DeprecatedContractClass>,
@@ -164,7 +170,7 @@ impl<'env, Mode: TransactionKind> StateStorageReader<Mode> for StorageTxn<'env,
     // The block number marker is the first block number that doesn't exist yet.
     fn get_state_marker(&self) -> StorageResult<BlockNumber> {
         let markers_table = self.open_table(&self.tables.markers)?;
-        Ok(markers_table.get(&self.txn, &MarkerKind::State)?.unwrap_or_default())
+        Ok(markers_table.get_or_default(&self.txn, &MarkerKind::State)?)
     }
     fn get_state_diff(&self, block_number: BlockNumber) -> StorageResult<Option<ThinStateDiff>> {
         let state_diffs_table = self.open_table(&self.tables.state_diffs)?;
@@ -451,6 +457,10 @@ impl<'env> StateStorageWriter for StorageTxn<'env, RW> {
         let (thin_state_diff, declared_classes, deprecated_declared_classes) =
             ThinStateDiff::from_state_diff(state_diff);
         let location = self.file_handlers.append_thin_state_diff(&thin_state_diff);
+        self.txn.record_change(&ChangelogEntry::FileAppend {
+            file: OffsetKind::ThinStateDiff,
+            location,
+        })?;
         state_diffs_table.insert(&self.txn, &block_number, &location)?;
         file_offset_table.upsert(&self.txn, &OffsetKind::ThinStateDiff, &location.next_offset())?;
 
@@ -536,7 +546,7 @@ impl<'env> StateStorageWriter for StorageTxn<'env, RW> {
             .unwrap_or_else(|| panic!("Missing state diff for block {block_number}."));
         markers_table.upsert(&self.txn, &MarkerKind::State, &block_number)?;
         let compiled_classes_marker =
-            markers_table.get(&self.txn, &MarkerKind::CompiledClass)?.unwrap_or_default();
+            markers_table.get_or_default(&self.txn, &MarkerKind::CompiledClass)?;
         if compiled_classes_marker == block_number.next() {
             markers_table.upsert(&self.txn, &MarkerKind::CompiledClass, &block_number)?;
         }
@@ -595,7 +605,7 @@ fn update_marker<'env>(
     block_number: BlockNumber,
 ) -> StorageResult<()> {
     // Make sure marker is consistent.
-    let state_marker = markers_table.get(txn, &MarkerKind::State)?.unwrap_or_default();
+    let state_marker = markers_table.get_or_default(txn, &MarkerKind::State)?;
     if state_marker != block_number {
         return Err(StorageError::MarkerMismatch { expected: state_marker, found: block_number });
     };
@@ -612,9 +622,9 @@ fn update_compiled_class_marker<'env>(
     state_diffs_table: &'env TableHandle<'_, BlockNumber, NoVersionValueWrapper<LocationInFile>>,
     file_handlers: &FileHandlers<RW>,
 ) -> StorageResult<()> {
-    let state_marker = markers_table.get(txn, &MarkerKind::State)?.unwrap_or_default();
+    let state_marker = markers_table.get_or_default(txn, &MarkerKind::State)?;
     let mut compiled_class_marker =
-        markers_table.get(txn, &MarkerKind::CompiledClass)?.unwrap_or_default();
+        markers_table.get_or_default(txn, &MarkerKind::CompiledClass)?;
     while compiled_class_marker < state_marker {
         let state_diff_location = state_diffs_table
             .get(txn, &compiled_class_marker)?
@@ -643,6 +653,10 @@ fn write_declared_classes<'env>(
 ) -> StorageResult<()> {
     for (class_hash, contract_class) in declared_classes {
         let location = file_handlers.append_contract_class(contract_class);
+        txn.record_change(&ChangelogEntry::FileAppend {
+            file: OffsetKind::ContractClass,
+            location,
+        })?;
         declared_classes_table.insert(txn, class_hash, &location)?;
         declared_classes_block_table.insert(txn, class_hash, &block_number)?;
         file_offset_table.upsert(txn, &OffsetKind::ContractClass, &location.next_offset())?;
@@ -663,6 +677,10 @@ fn write_deprecated_declared_classes<'env>(
             continue;
         }
         let location = file_handlers.append_deprecated_contract_class(&deprecated_contract_class);
+        txn.record_change(&ChangelogEntry::FileAppend {
+            file: OffsetKind::DeprecatedContractClass,
+            location,
+        })?;
         let value = IndexedDeprecatedContractClass { block_number, location_in_file: location };
         file_offset_table.upsert(
             txn,
@@ -1,11 +1,14 @@
-use starknet_api::block::BlockNumber;
+use starknet_api::block::{BlockHeader, BlockNumber};
 use starknet_api::core::ContractAddress;
 use starknet_api::transaction::{
+    EventContent,
     EventIndexInTransactionOutput,
     ExecutionResources,
     Fee,
     MessageToL1,
+    Transaction,
     TransactionExecutionStatus,
+    TransactionHash,
     TransactionOffsetInBlock,
 };
 use test_utils::{auto_impl_get_test_instance, get_number_of_variants, GetTestInstance};
@@ -20,14 +23,30 @@ use crate::body::events::{
 };
 use crate::body::TransactionIndex;
 use crate::compression_utils::IsCompressed;
+use crate::event_log::EventLogEntry;
 use crate::header::StarknetVersion;
 use crate::mmap_file::LocationInFile;
+use crate::revert::{ArchivedBlock, ArchivedBlockBody};
 use crate::state::data::IndexedDeprecatedContractClass;
 use crate::version::Version;
 use crate::{EventIndex, MarkerKind, OffsetKind};
 
 auto_impl_get_test_instance! {
+    pub struct ArchivedBlock {
+        pub header: BlockHeader,
+        pub body: Option<ArchivedBlockBody>,
+    }
+    pub struct ArchivedBlockBody {
+        pub transactions: Vec<Transaction>,
+        pub transaction_outputs: Vec<ThinTransactionOutput>,
+        pub transaction_hashes: Vec<TransactionHash>,
+        pub events: Vec<Vec<EventContent>>,
+    }
     struct EventIndex(pub TransactionIndex, pub EventIndexInTransactionOutput);
+    pub struct EventLogEntry {
+        pub timestamp_millis: u64,
+        pub event: String,
+    }
     pub struct IndexedDeprecatedContractClass {
         pub block_number: BlockNumber,
         pub location_in_file: LocationInFile,
@@ -1,11 +1,19 @@
 #![allow(clippy::unwrap_used)]
 //! Test utilities for the storage crate users.
 
+use indexmap::IndexMap;
+use starknet_api::block::{BlockBody, BlockHash, BlockHeader, BlockNumber};
 use starknet_api::core::ChainId;
+use starknet_api::hash::StarkFelt;
+use starknet_api::state::StateDiff;
 use tempfile::{tempdir, TempDir};
 
-use crate::db::DbConfig;
+use crate::body::BodyStorageWriter;
+use crate::db::serialization::NoVersionValueWrapper;
+use crate::db::{open_env, DbConfig, DbIter, DbKeyIter, DbReader, SyncMode, TableIdentifier};
+use crate::header::HeaderStorageWriter;
 use crate::mmap_file::MmapFileConfig;
+use crate::state::StateStorageWriter;
 use crate::{open_storage, StorageConfig, StorageReader, StorageScope, StorageWriter};
 
 /// Returns a db config and the temporary directory that holds this db.
@@ -26,9 +34,14 @@ pub fn get_test_config(storage_scope: Option<StorageScope>) -> (StorageConfig, T
                 min_size: 1 << 20,    // 1MB
                 max_size: 1 << 35,    // 32GB
                 growth_step: 1 << 26, // 64MB
+                sync_mode: SyncMode::Durable,
+                read_cache_capacity: None,
+                stale_read_txn_threshold: None,
+                write_txn_timeout: None,
             },
             scope: storage_scope,
             mmap_file_config: get_mmap_file_test_config(),
+            ..Default::default()
         },
         dir,
     )
@@ -58,3 +71,72 @@ pub fn get_test_storage_by_scope(
     let (config, temp_dir) = get_test_config(Some(storage_scope));
     ((open_storage(config).unwrap()), temp_dir)
 }
+
+/// Returns a header of the given block, whose hash and parent hash link it to the headers that
+/// this function returns for the blocks next to it.
+pub fn get_test_header(block_number: BlockNumber) -> BlockHeader {
+    BlockHeader {
+        block_number,
+        block_hash: BlockHash(StarkFelt::from(block_number.0 + 1)),
+        parent_hash: BlockHash(StarkFelt::from(block_number.0)),
+        ..Default::default()
+    }
+}
+
+/// Appends a block with the header of [`get_test_header`], the given body and an empty state diff
+/// in a single transaction.
+pub fn append_block(writer: &mut StorageWriter, block_number: BlockNumber, body: BlockBody) {
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(block_number, &get_test_header(block_number))
+        .unwrap()
+        .append_body(block_number, body)
+        .unwrap()
+        .append_state_diff(block_number, StateDiff::default(), IndexMap::new())
+        .unwrap()
+        .commit()
+        .unwrap();
+}
+
+/// A database table with big values, for comparing the iteration over its keys with the iteration
+/// over its entries.
+pub struct BigValuesTable {
+    reader: DbReader,
+    table_id: TableIdentifier<[u8; 4], NoVersionValueWrapper<Vec<u8>>>,
+    _temp_dir: TempDir,
+}
+
+impl BigValuesTable {
+    /// Creates a table with the given number of entries, whose values are of the given size.
+    pub fn new(num_entries: u32, value_size: usize) -> Self {
+        let (config, temp_dir) = get_test_config(None);
+        let (reader, mut writer) = open_env(&config.db_config).unwrap();
+        let table_id = writer
+            .create_table::<[u8; 4], NoVersionValueWrapper<Vec<u8>>>("big_values_table")
+            .unwrap();
+        let txn = writer.begin_rw_txn().unwrap();
+        let table = txn.open_table(&table_id).unwrap();
+        for key in 0..num_entries {
+            table.append(&txn, &key.to_be_bytes(), &vec![0; value_size]).unwrap();
+        }
+        txn.commit().unwrap();
+        Self { reader, table_id, _temp_dir: temp_dir }
+    }
+
+    /// Iterates over the keys of the table, and returns their number.
+    pub fn count_keys(&self) -> usize {
+        let txn = self.reader.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_table(&self.table_id).unwrap().cursor(&txn).unwrap();
+        let num_keys = DbKeyIter::new(&mut cursor).map(Result::unwrap).count();
+        num_keys
+    }
+
+    /// Iterates over the entries of the table, and returns their number.
+    pub fn count_entries(&self) -> usize {
+        let txn = self.reader.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_table(&self.table_id).unwrap().cursor(&txn).unwrap();
+        let num_entries = DbIter::new(&mut cursor).map(Result::unwrap).count();
+        num_entries
+    }
+}
@@ -0,0 +1,78 @@
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use papyrus_storage::db::{DbConfig, SyncMode};
+use papyrus_storage::header::HeaderStorageWriter;
+use papyrus_storage::mmap_file::MmapFileConfig;
+use papyrus_storage::{
+    open_storage,
+    StorageConfig,
+    STORAGE_COMMIT_LATENCY_SECONDS,
+    STORAGE_COMMIT_WRITTEN_BYTES,
+};
+use prometheus_parse::Value::Untyped;
+use starknet_api::block::{BlockHeader, BlockNumber};
+use starknet_api::core::ChainId;
+use tempfile::tempdir;
+use test_utils::prometheus_is_contained;
+
+// This test installs a global metrics recorder, so it's kept in its own test binary.
+#[test]
+fn commit_metrics() {
+    let handle = PrometheusBuilder::new().install_recorder().unwrap();
+    let dir = tempdir().unwrap();
+    let config = StorageConfig {
+        db_config: DbConfig {
+            path_prefix: dir.path().to_path_buf(),
+            chain_id: ChainId("".to_owned()),
+            enforce_file_exists: false,
+            min_size: 1 << 20,    // 1MB
+            max_size: 1 << 35,    // 32GB
+            growth_step: 1 << 26, // 64MB
+            sync_mode: SyncMode::Durable,
+            read_cache_capacity: None,
+            stale_read_txn_threshold: None,
+            write_txn_timeout: None,
+        },
+        mmap_file_config: MmapFileConfig {
+            max_size: 1 << 24,        // 16MB
+            growth_step: 1 << 20,     // 1MB
+            max_object_size: 1 << 16, // 64KB
+        },
+        ..Default::default()
+    };
+    let (_reader, mut writer) = open_storage(config).unwrap();
+    // Opening the storage may commit write transactions, so the metrics are compared to their
+    // values after it.
+    let num_commits = get_untyped(&handle, &format!("{STORAGE_COMMIT_LATENCY_SECONDS}_count"));
+    let bytes_written = get_untyped(&handle, &format!("{STORAGE_COMMIT_WRITTEN_BYTES}_sum"));
+
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+    assert_eq!(
+        get_untyped(&handle, &format!("{STORAGE_COMMIT_LATENCY_SECONDS}_count")),
+        num_commits + 1.0
+    );
+    assert_eq!(
+        get_untyped(&handle, &format!("{STORAGE_COMMIT_WRITTEN_BYTES}_count")),
+        num_commits + 1.0
+    );
+    assert!(get_untyped(&handle, &format!("{STORAGE_COMMIT_WRITTEN_BYTES}_sum")) > bytes_written);
+
+    // A transaction that isn't committed isn't measured.
+    drop(writer.begin_rw_txn().unwrap());
+    assert_eq!(
+        get_untyped(&handle, &format!("{STORAGE_COMMIT_LATENCY_SECONDS}_count")),
+        num_commits + 1.0
+    );
+}
+
+fn get_untyped(handle: &PrometheusHandle, metric_name: &str) -> f64 {
+    match prometheus_is_contained(handle.render(), metric_name, &[]) {
+        Some(Untyped(value)) => value,
+        value => panic!("Unexpected value {value:?} of {metric_name}."),
+    }
+}
@@ -0,0 +1,50 @@
+use metrics_exporter_prometheus::PrometheusBuilder;
+use papyrus_storage::db::{DbConfig, SyncMode};
+use papyrus_storage::mmap_file::MmapFileConfig;
+use papyrus_storage::{open_storage, StorageConfig, STORAGE_IN_FLIGHT_WRITE_OPERATIONS};
+use prometheus_parse::Value::Gauge;
+use starknet_api::core::ChainId;
+use tempfile::tempdir;
+use test_utils::prometheus_is_contained;
+
+// This test installs a global metrics recorder, so it's kept in its own test binary.
+#[test]
+fn in_flight_write_operations_gauge() {
+    let handle = PrometheusBuilder::new().install_recorder().unwrap();
+    let dir = tempdir().unwrap();
+    let config = StorageConfig {
+        db_config: DbConfig {
+            path_prefix: dir.path().to_path_buf(),
+            chain_id: ChainId("".to_owned()),
+            enforce_file_exists: false,
+            min_size: 1 << 20,    // 1MB
+            max_size: 1 << 35,    // 32GB
+            growth_step: 1 << 26, // 64MB
+            sync_mode: SyncMode::Durable,
+            read_cache_capacity: None,
+            stale_read_txn_threshold: None,
+            write_txn_timeout: None,
+        },
+        mmap_file_config: MmapFileConfig {
+            max_size: 1 << 24,        // 16MB
+            growth_step: 1 << 20,     // 1MB
+            max_object_size: 1 << 16, // 64KB
+        },
+        ..Default::default()
+    };
+    let (reader, mut writer) = open_storage(config).unwrap();
+
+    let txn = writer.begin_rw_txn().unwrap();
+    assert_eq!(
+        prometheus_is_contained(handle.render(), STORAGE_IN_FLIGHT_WRITE_OPERATIONS, &[]),
+        Some(Gauge(1f64))
+    );
+    assert_eq!(reader.db_tables_stats().unwrap().in_flight_write_operations, 1);
+
+    txn.commit().unwrap();
+    assert_eq!(
+        prometheus_is_contained(handle.render(), STORAGE_IN_FLIGHT_WRITE_OPERATIONS, &[]),
+        Some(Gauge(0f64))
+    );
+    assert_eq!(reader.db_tables_stats().unwrap().in_flight_write_operations, 0);
+}
@@ -4,6 +4,8 @@
 
 #[cfg(test)]
 mod sync_test;
+#[cfg(test)]
+mod test_utils;
 
 mod pending_sync;
 pub mod sources;
@@ -16,12 +18,13 @@ use std::time::Duration;
 use async_stream::try_stream;
 use cairo_lang_starknet::casm_contract_class::CasmContractClass;
 use chrono::{TimeZone, Utc};
-use futures_util::{pin_mut, select, Stream, StreamExt};
+use futures_util::{pin_mut, select, stream, Stream, StreamExt};
 use indexmap::IndexMap;
 use papyrus_common::pending_classes::PendingClasses;
+use papyrus_common::sync_progress::{SyncProgressEvent, SyncProgressEventKind, SyncStatus};
 use papyrus_common::{metrics as papyrus_metrics, BlockHashAndNumber};
 use papyrus_config::converters::deserialize_seconds_to_duration;
-use papyrus_config::dumping::{ser_param, SerializeConfig};
+use papyrus_config::dumping::{ser_optional_param, ser_param, SerializeConfig};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use papyrus_proc_macros::latency_histogram;
 use papyrus_storage::base_layer::BaseLayerStorageWriter;
@@ -29,6 +32,7 @@ use papyrus_storage::body::BodyStorageWriter;
 use papyrus_storage::compiled_class::{CasmStorageReader, CasmStorageWriter};
 use papyrus_storage::db::DbError;
 use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter, StarknetVersion};
+use papyrus_storage::seed::SeedStorageWriter;
 use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
 use papyrus_storage::{StorageError, StorageReader, StorageWriter};
 use serde::{Deserialize, Serialize};
@@ -36,14 +40,18 @@ use sources::base_layer::BaseLayerSourceError;
 use starknet_api::block::{Block, BlockHash, BlockNumber};
 use starknet_api::core::{ClassHash, CompiledClassHash};
 use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
+use starknet_api::hash::{StarkFelt, GENESIS_HASH};
+use starknet_api::stark_felt;
 use starknet_api::state::StateDiff;
 use starknet_client::reader::PendingData;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, mpsc, watch, RwLock};
+use tokio::task::JoinHandle;
 use tracing::{debug, error, info, instrument, trace, warn};
+use validator::Validate;
 
 use crate::pending_sync::sync_pending_data;
 use crate::sources::base_layer::{BaseLayerSourceTrait, EthereumBaseLayerSource};
-use crate::sources::central::{CentralError, CentralSource, CentralSourceTrait};
+use crate::sources::central::{CentralBlock, CentralError, CentralSource, CentralSourceTrait};
 use crate::sources::pending::{PendingError, PendingSource, PendingSourceTrait};
 
 // TODO(dvir): add to config.
@@ -53,7 +61,11 @@ const PENDING_SLEEP_DURATION: Duration = Duration::from_millis(500);
 // Sleep duration, in seconds, between sync progress checks.
 const SLEEP_TIME_SYNC_PROGRESS: Duration = Duration::from_secs(300);
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+// The maximal number of blocks that are pruned in a single transaction, so that catching up on a
+// long range of blocks to prune doesn't hold the storage writer for long.
+const PRUNE_MAX_BLOCKS_PER_TXN: usize = 100;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Validate)]
 pub struct SyncConfig {
     #[serde(deserialize_with = "deserialize_seconds_to_duration")]
     pub block_propagation_sleep_duration: Duration,
@@ -63,11 +75,31 @@ pub struct SyncConfig {
     pub recoverable_error_sleep_duration: Duration,
     pub blocks_max_stream_size: u32,
     pub state_updates_max_stream_size: u32,
+    // The parent hash of the first block of the chain. Used as the parent of the pending block
+    // while the storage has no blocks, so chains with a different genesis can set it.
+    pub genesis_parent_hash: BlockHash,
+    // The number of workers that download the blocks of a stream concurrently, each a contiguous
+    // part of them. The concurrent requests of the central source are split between the workers,
+    // and the blocks are still stored in order.
+    #[validate(range(min = 1))]
+    pub num_sync_workers: usize,
+    // If set, the bodies and state diffs of blocks older than the last prune_keep_blocks blocks
+    // are deleted once a block is stored. Headers are kept. Blocks that might still be reverted by
+    // a reorg must not be pruned, hence the minimum.
+    #[validate(range(min = 128))]
+    pub prune_keep_blocks: Option<u64>,
+    // If set and the stored blocks don't reach its parent, the sync starts from this block instead
+    // of the last stored block. The header of its parent is taken from the central source, so that
+    // the start block can be verified against it, and the blocks in the gap are left out of the
+    // storage. The state is therefore complete only if the state before the start block is
+    // imported, e.g. from a snapshot. Block 0 is the genesis block, which the sync starts from
+    // anyway.
+    pub start_block: Option<BlockNumber>,
 }
 
 impl SerializeConfig for SyncConfig {
     fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
-        BTreeMap::from_iter([
+        let mut dumped_config = BTreeMap::from_iter([
             ser_param(
                 "block_propagation_sleep_duration",
                 &self.block_propagation_sleep_duration.as_secs(),
@@ -99,7 +131,40 @@ impl SerializeConfig for SyncConfig {
                 "Max amount of state updates to download in a stream.",
                 ParamPrivacyInput::Public,
             ),
-        ])
+            ser_param(
+                "genesis_parent_hash",
+                &self.genesis_parent_hash,
+                "The parent hash of the first block of the chain. The pending block is built on \
+                 top of it until the first block is synced.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "num_sync_workers",
+                &self.num_sync_workers,
+                "The number of workers that download the blocks of a stream concurrently, each a \
+                 contiguous part of them with its share of the central source's concurrent \
+                 requests. Must be at least 1 and at most central.concurrent_requests.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        dumped_config.extend(ser_optional_param(
+            &self.prune_keep_blocks,
+            1000,
+            "prune_keep_blocks",
+            "If set, the bodies and state diffs of blocks older than this number of last blocks \
+             are deleted from the storage, keeping their headers. Must be at least 128.",
+            ParamPrivacyInput::Public,
+        ));
+        dumped_config.extend(ser_optional_param(
+            &self.start_block,
+            BlockNumber::default(),
+            "start_block",
+            "If set and the stored blocks don't reach its parent, the sync starts from this block \
+             and the blocks before it are left out of the storage, so the state must be imported \
+             separately. If not set, the sync starts from the last stored block.",
+            ParamPrivacyInput::Public,
+        ));
+        dumped_config
     }
 }
 
@@ -111,6 +176,10 @@ impl Default for SyncConfig {
             recoverable_error_sleep_duration: Duration::from_secs(3),
             blocks_max_stream_size: 1000,
             state_updates_max_stream_size: 1000,
+            genesis_parent_hash: BlockHash(stark_felt!(GENESIS_HASH)),
+            num_sync_workers: 1,
+            prune_keep_blocks: None,
+            start_block: None,
         }
     }
 }
@@ -131,6 +200,10 @@ pub struct GenericStateSync<
     base_layer_source: Arc<TBaseLayerSource>,
     reader: StorageReader,
     writer: StorageWriter,
+    // Publishes the progress of the sync, e.g. to the monitoring gateway.
+    progress_sender: broadcast::Sender<SyncProgressEvent>,
+    // Publishes how far the sync is from the latest block, e.g. for awaiting the initial catch-up.
+    status_sender: watch::Sender<SyncStatus>,
 }
 
 pub type StateSyncResult = Result<(), StateSyncError>;
@@ -154,6 +227,11 @@ pub enum StateSyncError {
         expected_parent_block_hash: BlockHash,
         stored_parent_block_hash: BlockHash,
     },
+    #[error(
+        "Can't start syncing from block {start_block}: its parent is missing from the central \
+         source."
+    )]
+    StartBlockParentMissing { start_block: BlockNumber },
     #[error("Header for block {block_number} wasn't found when trying to store base layer block.")]
     BaseLayerBlockWithoutMatchingHeader { block_number: BlockNumber },
     #[error(transparent)]
@@ -169,6 +247,38 @@ pub enum StateSyncError {
     },
 }
 
+/// An inconsistency that a dry run of the sync found in the data of the central source.
+#[derive(thiserror::Error, Clone, Debug, Eq, PartialEq)]
+pub enum SyncInconsistency {
+    #[error(
+        "The parent hash {parent_hash} of block {block_number} doesn't match the hash \
+         {previous_block_hash} of the previous block."
+    )]
+    ParentBlockHashMismatch {
+        block_number: BlockNumber,
+        parent_hash: BlockHash,
+        previous_block_hash: BlockHash,
+    },
+    #[error(
+        "The hash {central_block_hash} of block {block_number} doesn't match the hash \
+         {stored_block_hash} of the stored block."
+    )]
+    StoredBlockHashMismatch {
+        block_number: BlockNumber,
+        central_block_hash: BlockHash,
+        stored_block_hash: BlockHash,
+    },
+    #[error(
+        "The state diff of block {block_number} belongs to a block with hash \
+         {state_diff_block_hash} instead of {block_hash}."
+    )]
+    StateDiffBlockHashMismatch {
+        block_number: BlockNumber,
+        block_hash: BlockHash,
+        state_diff_block_hash: BlockHash,
+    },
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum SyncEvent {
@@ -253,11 +363,18 @@ impl<
         }
     }
 
+    /// Consumes the sync and returns its storage writer, e.g. for compacting the storage once the
+    /// sync stopped.
+    pub fn into_storage_writer(self) -> StorageWriter {
+        self.writer
+    }
+
     // Sync until encountering an error:
     //  1. If needed, revert blocks from the end of the chain.
     //  2. Create infinite block and state diff streams to fetch data from the central source.
     //  3. Fetch data from the streams with unblocking wait while there is no new data.
     async fn sync_while_ok(&mut self) -> StateSyncResult {
+        self.seed_start_block().await?;
         self.handle_block_reverts().await?;
         let block_stream = stream_new_blocks(
             self.reader.clone(),
@@ -266,9 +383,11 @@ impl<
             self.shared_highest_block.clone(),
             self.pending_data.clone(),
             self.pending_classes.clone(),
+            self.config.genesis_parent_hash,
             self.config.block_propagation_sleep_duration,
             PENDING_SLEEP_DURATION,
             self.config.blocks_max_stream_size,
+            self.config.num_sync_workers,
         )
         .fuse();
         let state_diff_stream = stream_new_state_diffs(
@@ -324,7 +443,19 @@ impl<
     async fn process_sync_event(&mut self, sync_event: SyncEvent) -> StateSyncResult {
         match sync_event {
             SyncEvent::BlockAvailable { block_number, block, starknet_version } => {
-                self.store_block(block_number, block, &starknet_version)
+                self.report_progress(block_number, SyncProgressEventKind::BlockDownloaded);
+                self.store_block(block_number, block, &starknet_version)?;
+                self.report_progress(block_number, SyncProgressEventKind::BlockCommitted);
+                let highest_block = *self.shared_highest_block.read().await;
+                let is_tip = highest_block
+                    .is_some_and(|highest_block| highest_block.block_number == block_number);
+                if is_tip {
+                    self.report_progress(block_number, SyncProgressEventKind::CaughtUpToTip);
+                }
+                if let Some(highest_block) = highest_block {
+                    self.update_status(block_number, highest_block.block_number);
+                }
+                Ok(())
             }
             SyncEvent::StateDiffAvailable {
                 block_number,
@@ -406,6 +537,7 @@ impl<
             papyrus_metrics::PAPYRUS_COMPILED_CLASS_MARKER,
             compiled_class_marker.0 as f64
         );
+        self.prune_blocks(block_number)?;
 
         // Info the user on syncing the block once all the data is stored.
         info!("Added block {} with hash {}.", block_number, block_hash);
@@ -413,6 +545,18 @@ impl<
         Ok(())
     }
 
+    // Prunes the blocks that are no longer among the last prune_keep_blocks blocks once the given
+    // block was stored, if pruning is enabled.
+    fn prune_blocks(&mut self, stored_block_number: BlockNumber) -> StateSyncResult {
+        let Some(keep_blocks) = self.config.prune_keep_blocks else {
+            return Ok(());
+        };
+        let up_to = BlockNumber(stored_block_number.next().0.saturating_sub(keep_blocks));
+        let pruned_marker = self.writer.prune_blocks(up_to, PRUNE_MAX_BLOCKS_PER_TXN)?;
+        debug!("Pruned the blocks before block {pruned_marker}.");
+        Ok(())
+    }
+
     #[latency_histogram("sync_store_compiled_class_latency_seconds")]
     #[instrument(skip(self, compiled_class), level = "debug", err)]
     fn store_compiled_class(
@@ -475,6 +619,32 @@ impl<
         Ok(())
     }
 
+    // If the stored blocks don't reach the parent of the configured start block, stores the header
+    // of the parent from the central source and moves the markers to the start block, so that the
+    // sync continues from there.
+    async fn seed_start_block(&mut self) -> StateSyncResult {
+        let Some(parent_block_number) = self.config.start_block.and_then(|block| block.prev())
+        else {
+            return Ok(());
+        };
+        if self.reader.begin_ro_txn()?.get_header_marker()? >= parent_block_number {
+            return Ok(());
+        }
+        let start_block = parent_block_number.next();
+        let mut parent_block_stream =
+            self.central_source.stream_new_blocks(parent_block_number, start_block);
+        let parent_header = match parent_block_stream.next().await {
+            Some(Ok((_block_number, block, _signature_data, _starknet_version))) => block.header,
+            Some(Err(CentralError::BlockNotFound { .. })) | None => {
+                return Err(StateSyncError::StartBlockParentMissing { start_block });
+            }
+            Some(Err(err)) => return Err(err.into()),
+        };
+        info!("Seeding the storage to start syncing from block {start_block}.");
+        self.writer.begin_rw_txn()?.seed_start_block(&parent_header)?.commit()?;
+        Ok(())
+    }
+
     // Compares the block's parent hash to the stored block.
     fn verify_parent_block_hash(
         &self,
@@ -515,6 +685,7 @@ impl<
 
         // Revert last blocks if needed.
         let mut last_block_in_storage = header_marker.prev();
+        let old_head_hash = self.get_stored_block_hash(last_block_in_storage)?;
         while let Some(block_number) = last_block_in_storage {
             if self.should_revert_block(block_number).await? {
                 self.revert_block(block_number)?;
@@ -523,9 +694,56 @@ impl<
                 break;
             }
         }
+
+        if let Some(old_head_hash) = old_head_hash {
+            let first_reverted_block = last_block_in_storage.map_or(BlockNumber(0), |bn| bn.next());
+            if first_reverted_block < header_marker {
+                let new_head_hash = self.get_stored_block_hash(last_block_in_storage)?;
+                self.report_progress(
+                    first_reverted_block,
+                    SyncProgressEventKind::ReorgDetected { old_head_hash, new_head_hash },
+                );
+            }
+        }
         Ok(())
     }
 
+    fn get_stored_block_hash(
+        &self,
+        block_number: Option<BlockNumber>,
+    ) -> Result<Option<BlockHash>, StateSyncError> {
+        let Some(block_number) = block_number else {
+            return Ok(None);
+        };
+        Ok(self
+            .reader
+            .begin_ro_txn()?
+            .get_block_header(block_number)?
+            .map(|header| header.block_hash))
+    }
+
+    fn report_progress(&self, block_number: BlockNumber, kind: SyncProgressEventKind) {
+        // Sending fails only when there are no subscribers, in which case the event isn't needed.
+        let _ = self.progress_sender.send(SyncProgressEvent::new(block_number, kind));
+    }
+
+    // Updates the status after storing a block, given the latest block of the central source.
+    fn update_status(&self, stored_block_number: BlockNumber, highest_block_number: BlockNumber) {
+        let blocks_behind = highest_block_number.0.saturating_sub(stored_block_number.0);
+        self.status_sender.send_if_modified(|status| {
+            let new_status = SyncStatus {
+                initial_sync_complete: status.initial_sync_complete || blocks_behind == 0,
+                blocks_behind: Some(blocks_behind),
+            };
+            if new_status.initial_sync_complete && !status.initial_sync_complete {
+                info!("Initial sync is complete, the sync reached block {stored_block_number}.");
+            }
+            let is_modified = *status != new_status;
+            *status = new_status;
+            is_modified
+        });
+    }
+
     // TODO(dan): update necessary metrics.
     // Deletes the block data from the storage.
     #[allow(clippy::expect_fun_call)]
@@ -555,6 +773,84 @@ impl<
         Ok(())
     }
 
+    /// Fetches the blocks in the range [from, up_to) and their state diffs from the central source
+    /// and verifies them the way the sync does, without writing anything to the storage. Blocks
+    /// that are already stored are compared to the stored ones as well, e.g. to confirm that an
+    /// upgraded node syncs the same chain. Returns the inconsistencies that were found, after
+    /// logging each of them.
+    pub async fn dry_run(
+        &self,
+        from: BlockNumber,
+        up_to: BlockNumber,
+    ) -> Result<Vec<SyncInconsistency>, StateSyncError> {
+        info!("Dry run of the sync started for blocks [{} - {}).", from, up_to);
+        let mut inconsistencies = Vec::new();
+
+        // The parent of the first block is known only if it's stored.
+        let mut previous_block_hash = match from.prev() {
+            None => Some(self.config.genesis_parent_hash),
+            Some(previous_block_number) => {
+                self.get_stored_block_hash(Some(previous_block_number))?
+            }
+        };
+        let mut block_hashes = Vec::new();
+        let mut block_stream = self.central_source.stream_new_blocks(from, up_to);
+        while let Some(maybe_block) = block_stream.next().await {
+            let (block_number, block, _block_signature_data, _starknet_version) = maybe_block?;
+            let block_hash = block.header.block_hash;
+            if let Some(previous_block_hash) = previous_block_hash {
+                if block.header.parent_hash != previous_block_hash {
+                    inconsistencies.push(SyncInconsistency::ParentBlockHashMismatch {
+                        block_number,
+                        parent_hash: block.header.parent_hash,
+                        previous_block_hash,
+                    });
+                }
+            }
+            if let Some(stored_block_hash) = self.get_stored_block_hash(Some(block_number))? {
+                if stored_block_hash != block_hash {
+                    inconsistencies.push(SyncInconsistency::StoredBlockHashMismatch {
+                        block_number,
+                        central_block_hash: block_hash,
+                        stored_block_hash,
+                    });
+                }
+            }
+            previous_block_hash = Some(block_hash);
+            block_hashes.push(block_hash);
+        }
+
+        let mut state_diff_stream = self.central_source.stream_state_updates(from, up_to);
+        while let Some(maybe_state_diff) = state_diff_stream.next().await {
+            let (block_number, state_diff_block_hash, _state_diff, _deployed_contract_classes) =
+                maybe_state_diff?;
+            let Some(block_hash) = usize::try_from(block_number.0 - from.0)
+                .ok()
+                .and_then(|index| block_hashes.get(index).copied())
+            else {
+                continue;
+            };
+            if state_diff_block_hash != block_hash {
+                inconsistencies.push(SyncInconsistency::StateDiffBlockHashMismatch {
+                    block_number,
+                    block_hash,
+                    state_diff_block_hash,
+                });
+            }
+        }
+
+        for inconsistency in &inconsistencies {
+            warn!("Dry run of the sync found an inconsistency: {}", inconsistency);
+        }
+        info!(
+            "Dry run of the sync finished for blocks [{} - {}) with {} inconsistencies.",
+            from,
+            up_to,
+            inconsistencies.len()
+        );
+        Ok(inconsistencies)
+    }
+
     /// Checks if centrals block hash at the block number is different from ours (or doesn't exist).
     /// If so, a revert is required.
     async fn should_revert_block(&self, block_number: BlockNumber) -> Result<bool, StateSyncError> {
@@ -584,9 +880,11 @@ fn stream_new_blocks<
     shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     pending_data: Arc<RwLock<PendingData>>,
     pending_classes: Arc<RwLock<PendingClasses>>,
+    genesis_parent_hash: BlockHash,
     block_propagation_sleep_duration: Duration,
     pending_sleep_duration: Duration,
     max_stream_size: u32,
+    num_workers: usize,
 ) -> impl Stream<Item = Result<SyncEvent, StateSyncError>> {
     try_stream! {
         loop {
@@ -610,6 +908,7 @@ fn stream_new_blocks<
                         pending_source.clone(),
                         pending_data.clone(),
                         pending_classes.clone(),
+                        genesis_parent_hash,
                         pending_sleep_duration,
                     ).await?;
                 }
@@ -621,8 +920,9 @@ fn stream_new_blocks<
             }
             let up_to = min(central_block_marker, BlockNumber(header_marker.0 + max_stream_size as u64));
             debug!("Downloading blocks [{} - {}).", header_marker, up_to);
-            let block_stream =
-                central_source.stream_new_blocks(header_marker, up_to).fuse();
+            let block_stream = download_blocks_with_workers(
+                central_source.clone(), header_marker, up_to, num_workers
+            ).fuse();
             pin_mut!(block_stream);
             while let Some(maybe_block) = block_stream.next().await {
                 let (block_number, block, _block_signature_data, starknet_version) = maybe_block?;
@@ -633,6 +933,63 @@ fn stream_new_blocks<
     }
 }
 
+// The number of blocks a worker downloads ahead of the blocks that are yielded. A worker that is
+// that far ahead waits for its blocks to be consumed.
+const MAX_BUFFERED_BLOCKS_PER_WORKER: usize = 100;
+
+// Aborts the download workers once the stream of their blocks is dropped, e.g. when the consumer
+// fails, instead of letting them download blocks no one will consume.
+struct DownloadWorkers(Vec<JoinHandle<()>>);
+
+impl Drop for DownloadWorkers {
+    fn drop(&mut self) {
+        for worker in &self.0 {
+            worker.abort();
+        }
+    }
+}
+
+// Downloads the blocks in [initial_block_number, up_to_block_number) with up to `num_workers`
+// tasks, each streaming a contiguous part of the range from the central source, and yields them in
+// order. The blocks that a worker downloads before the ones of the previous parts are yielded are
+// buffered, up to MAX_BUFFERED_BLOCKS_PER_WORKER blocks. The workers are aborted once the returned
+// stream is dropped.
+fn download_blocks_with_workers<TCentralSource: CentralSourceTrait + Sync + Send + 'static>(
+    central_source: Arc<TCentralSource>,
+    initial_block_number: BlockNumber,
+    up_to_block_number: BlockNumber,
+    num_workers: usize,
+) -> impl Stream<Item = Result<CentralBlock, CentralError>> {
+    let num_blocks = up_to_block_number.0.saturating_sub(initial_block_number.0);
+    let part_size = num_blocks.div_ceil(num_workers as u64).max(1);
+    let mut workers = Vec::new();
+    let part_receivers = (initial_block_number.0..up_to_block_number.0)
+        .step_by(part_size as usize)
+        .map(|part_start| {
+            let part_end = min(part_start + part_size, up_to_block_number.0);
+            let (sender, mut receiver) = mpsc::channel(MAX_BUFFERED_BLOCKS_PER_WORKER);
+            let central_source = central_source.clone();
+            workers.push(tokio::spawn(async move {
+                let block_stream = central_source
+                    .stream_new_blocks(BlockNumber(part_start), BlockNumber(part_end));
+                pin_mut!(block_stream);
+                while let Some(maybe_block) = block_stream.next().await {
+                    if sender.send(maybe_block).await.is_err() {
+                        break;
+                    }
+                }
+            }));
+            stream::poll_fn(move |cx| receiver.poll_recv(cx))
+        })
+        .collect::<Vec<_>>();
+    let workers = DownloadWorkers(workers);
+    stream::iter(part_receivers).flatten().map(move |maybe_block| {
+        // The stream owns the workers, so that they're aborted when it's dropped.
+        let _workers = &workers;
+        maybe_block
+    })
+}
+
 fn stream_new_state_diffs<TCentralSource: CentralSourceTrait + Sync + Send>(
     reader: StorageReader,
     central_source: Arc<TCentralSource>,
@@ -687,6 +1044,22 @@ pub fn sort_state_diff(diff: &mut StateDiff) {
     }
 }
 
+/// Returns the hash of the latest block in the storage, which is the parent of the pending block.
+/// If the storage has no blocks, returns the given parent hash of the genesis block.
+pub fn get_pending_parent_block_hash(
+    reader: &StorageReader,
+    genesis_parent_hash: BlockHash,
+) -> Result<BlockHash, StorageError> {
+    let txn = reader.begin_ro_txn()?;
+    let Some(latest_block_number) = txn.get_header_marker()?.prev() else {
+        return Ok(genesis_parent_hash);
+    };
+    Ok(txn
+        .get_block_header(latest_block_number)?
+        .expect("Block before the header marker must have header in the database.")
+        .block_hash)
+}
+
 pub type StateSync = GenericStateSync<CentralSource, PendingSource, EthereumBaseLayerSource>;
 
 impl StateSync {
@@ -701,7 +1074,15 @@ impl StateSync {
         base_layer_source: EthereumBaseLayerSource,
         reader: StorageReader,
         writer: StorageWriter,
+        progress_sender: broadcast::Sender<SyncProgressEvent>,
+        status_sender: watch::Sender<SyncStatus>,
     ) -> Self {
+        // Each download worker makes its share of the concurrent requests, rounded down, so that
+        // together they don't make more than the central source is configured to. The node config
+        // validation makes sure each worker gets at least one.
+        let mut central_source = central_source;
+        central_source.concurrent_requests =
+            (central_source.concurrent_requests / config.num_sync_workers).max(1);
         Self {
             config,
             shared_highest_block,
@@ -712,6 +1093,8 @@ impl StateSync {
             base_layer_source: Arc::new(base_layer_source),
             reader,
             writer,
+            progress_sender,
+            status_sender,
         }
     }
 }
@@ -775,13 +1158,37 @@ fn stream_new_base_layer_block<TBaseLayerSource: BaseLayerSourceTrait + Sync>(
             tokio::time::sleep(base_layer_propagation_sleep_duration).await;
             let txn = reader.begin_ro_txn()?;
             let header_marker = txn.get_header_marker()?;
-            match base_layer_source.latest_proved_block().await? {
+            let latest_proved_block = base_layer_source.latest_proved_block().await?;
+            let proved_marker = latest_proved_block
+                .map_or(BlockNumber(0), |(block_number, _block_hash)| block_number.next());
+            metrics::gauge!(
+                papyrus_metrics::PAPYRUS_UNPROVED_BLOCKS,
+                header_marker.0.saturating_sub(proved_marker.0) as f64
+            );
+            metrics::gauge!(
+                papyrus_metrics::PAPYRUS_BASE_LAYER_LAST_SUCCESSFUL_POLL,
+                Utc::now().timestamp() as f64
+            );
+            let is_proved_block_stored = match latest_proved_block {
+                Some((block_number, _block_hash)) if block_number < header_marker => {
+                    txn.get_block_header(block_number)?.is_some()
+                }
+                _ => true,
+            };
+            match latest_proved_block {
                 Some((block_number, _block_hash)) if header_marker <= block_number => {
                     debug!(
                         "Sync headers ({header_marker}) is behind the base layer tip \
                          ({block_number}), waiting for sync to advance."
                     );
                 }
+                // The sync started from a later block, so the proved block wasn't stored.
+                Some((block_number, _block_hash)) if !is_proved_block_stored => {
+                    debug!(
+                        "The base layer tip ({block_number}) is before the block the sync started \
+                         from, waiting for the base layer to advance."
+                    );
+                }
                 Some((block_number, block_hash)) => {
                     debug!("Returns a block from the base layer. Block number: {block_number}.");
                     yield SyncEvent::NewBaseLayerBlock { block_number, block_hash }
@@ -804,24 +1211,16 @@ fn check_sync_progress(
     reader: StorageReader,
 ) -> impl Stream<Item = Result<SyncEvent, StateSyncError>> {
     try_stream! {
-        let mut txn=reader.begin_ro_txn()?;
-        let mut header_marker=txn.get_header_marker()?;
-        let mut state_marker=txn.get_state_marker()?;
-        let mut casm_marker=txn.get_compiled_class_marker()?;
+        let mut markers=reader.get_markers()?;
         loop{
             tokio::time::sleep(SLEEP_TIME_SYNC_PROGRESS).await;
             debug!("Checking if sync stopped progress.");
-            txn=reader.begin_ro_txn()?;
-            let new_header_marker=txn.get_header_marker()?;
-            let new_state_marker=txn.get_state_marker()?;
-            let new_casm_marker=txn.get_compiled_class_marker()?;
-            if header_marker==new_header_marker || state_marker==new_state_marker || casm_marker==new_casm_marker{
+            let new_markers=reader.get_markers()?;
+            if markers.header==new_markers.header || markers.state==new_markers.state || markers.compiled_class==new_markers.compiled_class{
                 debug!("No progress in the sync. Return NoProgress event.");
                 yield SyncEvent::NoProgress;
             }
-            header_marker=new_header_marker;
-            state_marker=new_state_marker;
-            casm_marker=new_casm_marker;
+            markers=new_markers;
         }
     }
 }
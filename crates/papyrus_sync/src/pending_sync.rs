@@ -5,21 +5,16 @@ use std::time::Duration;
 use futures::stream::FuturesUnordered;
 use futures_util::{FutureExt, StreamExt};
 use papyrus_common::pending_classes::{PendingClasses, PendingClassesTrait};
-use papyrus_storage::header::HeaderStorageReader;
 use papyrus_storage::StorageReader;
-use starknet_api::block::{BlockHash, BlockNumber};
+use starknet_api::block::BlockHash;
 use starknet_api::core::ClassHash;
-// TODO(shahak): Consider adding genesis hash to the config to support chains that have
-// different genesis hash.
-use starknet_api::hash::{StarkFelt, GENESIS_HASH};
-use starknet_api::stark_felt;
 use starknet_client::reader::{DeclaredClassHashEntry, PendingData};
 use tokio::sync::RwLock;
 use tracing::{debug, trace};
 
 use crate::sources::central::CentralSourceTrait;
 use crate::sources::pending::PendingSourceTrait;
-use crate::StateSyncError;
+use crate::{get_pending_parent_block_hash, StateSyncError};
 
 // Update the pending data and return when a new block is discovered.
 pub(crate) async fn sync_pending_data<
@@ -31,23 +26,10 @@ pub(crate) async fn sync_pending_data<
     pending_source: Arc<TPendingSource>,
     pending_data: Arc<RwLock<PendingData>>,
     pending_classes: Arc<RwLock<PendingClasses>>,
+    genesis_parent_hash: BlockHash,
     sleep_duration: Duration,
 ) -> Result<(), StateSyncError> {
-    let txn = reader.begin_ro_txn()?;
-    let header_marker = txn.get_header_marker()?;
-    // TODO: Consider extracting this functionality to different а function.
-    let latest_block_hash = match header_marker {
-        BlockNumber(0) => BlockHash(stark_felt!(GENESIS_HASH)),
-        _ => {
-            txn.get_block_header(
-                header_marker
-                    .prev()
-                    .expect("All blocks other than the first block should have a predecessor."),
-            )?
-            .expect("Block before the header marker must have header in the database.")
-            .block_hash
-        }
-    };
+    let latest_block_hash = get_pending_parent_block_hash(&reader, genesis_parent_hash)?;
     let mut tasks = FuturesUnordered::new();
     tasks.push(
         get_pending_data(
@@ -1,11 +1,22 @@
+#[cfg(test)]
+#[path = "base_layer_test.rs"]
+mod base_layer_test;
+
+use std::sync::Mutex;
+
 use async_trait::async_trait;
 #[cfg(test)]
 use mockall::automock;
-use papyrus_base_layer::ethereum_base_layer_contract::EthereumBaseLayerContract;
+use papyrus_base_layer::ethereum_base_layer_contract::{
+    EthereumBaseLayerConfig,
+    EthereumBaseLayerContract,
+    EthereumBaseLayerError,
+};
 use papyrus_base_layer::BaseLayerContract;
+use papyrus_common::metrics as papyrus_metrics;
 use starknet_api::block::{BlockHash, BlockNumber};
 
-pub type EthereumBaseLayerSource = EthereumBaseLayerContract;
+pub type EthereumBaseLayerSource = BaseLayerSource<EthereumBaseLayerContract>;
 
 #[derive(thiserror::Error, Debug)]
 pub enum BaseLayerSourceError {
@@ -27,17 +38,55 @@ pub trait BaseLayerSourceTrait {
     ) -> Result<Option<(BlockNumber, BlockHash)>, BaseLayerSourceError>;
 }
 
+/// A source of the blocks proved on the base layer, which reports in the metrics how far it lags
+/// behind the base layer.
+pub struct BaseLayerSource<Contract> {
+    contract: Contract,
+    // The latest base layer block that the proved block was read at.
+    last_processed_l1_block: Mutex<Option<u64>>,
+}
+
+impl<Contract> BaseLayerSource<Contract> {
+    fn from_contract(contract: Contract) -> Self {
+        Self { contract, last_processed_l1_block: Mutex::new(None) }
+    }
+}
+
+impl EthereumBaseLayerSource {
+    pub fn new(config: EthereumBaseLayerConfig) -> Result<Self, EthereumBaseLayerError> {
+        Ok(Self::from_contract(EthereumBaseLayerContract::new(config)?))
+    }
+}
+
 #[async_trait]
 impl<
     Error: std::error::Error + 'static + Sync + Send,
-    BaseLayerSource: BaseLayerContract<Error = Error> + Sync + Send,
-> BaseLayerSourceTrait for BaseLayerSource
+    Contract: BaseLayerContract<Error = Error> + Sync + Send,
+> BaseLayerSourceTrait for BaseLayerSource<Contract>
 {
     async fn latest_proved_block(
         &self,
     ) -> Result<Option<(BlockNumber, BlockHash)>, BaseLayerSourceError> {
-        self.latest_proved_block(None)
+        let latest_l1_block = self
+            .contract
+            .latest_l1_block_number()
             .await
-            .map_err(|e| BaseLayerSourceError::BaseLayerContractError(Box::new(e)))
+            .map_err(|e| BaseLayerSourceError::BaseLayerContractError(Box::new(e)))?;
+        let latest_proved_block = self.contract.proved_block_at(latest_l1_block).await;
+        let last_processed_l1_block = {
+            let mut last_processed_l1_block =
+                self.last_processed_l1_block.lock().expect("Poisoned last processed L1 block.");
+            if latest_proved_block.is_ok() {
+                *last_processed_l1_block = Some(latest_l1_block);
+            }
+            *last_processed_l1_block
+        };
+        if let Some(last_processed_l1_block) = last_processed_l1_block {
+            metrics::gauge!(
+                papyrus_metrics::PAPYRUS_BASE_LAYER_SOURCE_LAG,
+                latest_l1_block.saturating_sub(last_processed_l1_block) as f64
+            );
+        }
+        latest_proved_block.map_err(|e| BaseLayerSourceError::BaseLayerContractError(Box::new(e)))
     }
 }
@@ -0,0 +1,74 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use assert_matches::assert_matches;
+use async_trait::async_trait;
+use papyrus_base_layer::BaseLayerContract;
+use papyrus_common::metrics::PAPYRUS_BASE_LAYER_SOURCE_LAG;
+use prometheus_parse::Value::Gauge;
+use starknet_api::block::{BlockHash, BlockNumber};
+use test_utils::prometheus_is_contained;
+
+use crate::sources::base_layer::{BaseLayerSource, BaseLayerSourceError, BaseLayerSourceTrait};
+use crate::test_utils::get_metrics_handle;
+
+const LAST_READABLE_L1_BLOCK: u64 = 5;
+
+// A base layer that advances by a block on each poll, and whose proved block can't be read after
+// LAST_READABLE_L1_BLOCK.
+struct StuckBaseLayerContract {
+    latest_l1_block: AtomicU64,
+}
+
+#[async_trait]
+impl BaseLayerContract for StuckBaseLayerContract {
+    type Error = io::Error;
+
+    async fn latest_l1_block_number(&self) -> Result<u64, Self::Error> {
+        Ok(self.latest_l1_block.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    async fn proved_block_at(
+        &self,
+        l1_block_number: u64,
+    ) -> Result<Option<(BlockNumber, BlockHash)>, Self::Error> {
+        if l1_block_number > LAST_READABLE_L1_BLOCK {
+            return Err(io::Error::other("The base layer node is stuck."));
+        }
+        Ok(Some((BlockNumber(l1_block_number), BlockHash::default())))
+    }
+
+    async fn latest_proved_block(
+        &self,
+        _min_confirmations: Option<u64>,
+    ) -> Result<Option<(BlockNumber, BlockHash)>, Self::Error> {
+        unimplemented!("The base layer source doesn't use it.")
+    }
+}
+
+#[tokio::test]
+async fn source_lag_grows_while_the_proved_block_cant_be_read() {
+    let handle = get_metrics_handle();
+    let get_lag =
+        || match prometheus_is_contained(handle.render(), PAPYRUS_BASE_LAYER_SOURCE_LAG, &[]) {
+            Some(Gauge(value)) => value,
+            value => panic!("Unexpected value {value:?} of {PAPYRUS_BASE_LAYER_SOURCE_LAG}."),
+        };
+    let source = BaseLayerSource::from_contract(StuckBaseLayerContract {
+        latest_l1_block: AtomicU64::new(0),
+    });
+
+    for l1_block in 1..=LAST_READABLE_L1_BLOCK {
+        let latest_proved_block = source.latest_proved_block().await.unwrap();
+        assert_eq!(latest_proved_block, Some((BlockNumber(l1_block), BlockHash::default())));
+        assert_eq!(get_lag(), 0.0);
+    }
+
+    for expected_lag in 1..=5 {
+        assert_matches!(
+            source.latest_proved_block().await,
+            Err(BaseLayerSourceError::BaseLayerContractError(_))
+        );
+        assert_eq!(get_lag(), expected_lag as f64);
+    }
+}
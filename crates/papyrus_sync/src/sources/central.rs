@@ -6,11 +6,13 @@ mod state_update_stream;
 use std::collections::{BTreeMap, HashMap};
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use async_stream::stream;
 use async_trait::async_trait;
 use cairo_lang_starknet::casm_contract_class::CasmContractClass;
 use futures::stream::BoxStream;
+use futures::Future;
 use futures_util::StreamExt;
 use indexmap::IndexMap;
 use itertools::chain;
@@ -18,13 +20,19 @@ use lru::LruCache;
 #[cfg(test)]
 use mockall::automock;
 use papyrus_common::pending_classes::ApiContractClass;
-use papyrus_common::BlockHashAndNumber;
+use papyrus_common::{metrics as papyrus_metrics, BlockHashAndNumber};
 use papyrus_config::converters::{deserialize_optional_map, serialize_optional_map};
-use papyrus_config::dumping::{append_sub_config_name, ser_param, SerializeConfig};
+use papyrus_config::dumping::{
+    append_sub_config_name,
+    ser_optional_param,
+    ser_param,
+    SerializeConfig,
+};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use papyrus_storage::header::StarknetVersion;
 use papyrus_storage::state::StateStorageReader;
 use papyrus_storage::{StorageError, StorageReader};
+use reqwest::header::USER_AGENT;
 use serde::{Deserialize, Serialize};
 use starknet_api::block::{Block, BlockHash, BlockNumber};
 use starknet_api::core::{ClassHash, CompiledClassHash, GlobalRoot};
@@ -33,14 +41,16 @@ use starknet_api::hash::StarkFelt;
 use starknet_api::state::StateDiff;
 use starknet_api::StarknetApiError;
 use starknet_client::reader::{ReaderClientError, StarknetFeederGatewayClient, StarknetReader};
-use starknet_client::{ClientCreationError, RetryConfig};
+use starknet_client::{ClientCreationError, ClientError, RetryConfig};
 use tracing::{debug, trace};
+use validator::Validate;
 
 use self::state_update_stream::{StateUpdateStream, StateUpdateStreamConfig};
 
 type CentralResult<T> = Result<T, CentralError>;
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Validate)]
 pub struct CentralSourceConfig {
+    #[validate(range(min = 1))]
     pub concurrent_requests: usize,
     pub url: String,
     #[serde(deserialize_with = "deserialize_optional_map")]
@@ -50,7 +60,14 @@ pub struct CentralSourceConfig {
     pub max_classes_to_download: usize,
     // TODO(dan): validate that class_cache_size is a positive integer.
     pub class_cache_size: usize,
+    #[validate]
     pub retry_config: RetryConfig,
+    // Overrides the User-Agent header of the requests, e.g. for branded deployments. By default
+    // it's "papyrus/<node version> (<system information>)".
+    pub user_agent: Option<String>,
+    // Caps the body of each response, so that a broken or malicious feeder-gateway can't make us
+    // run out of memory while reading it.
+    pub max_response_bytes: usize,
 }
 
 impl Default for CentralSourceConfig {
@@ -68,6 +85,9 @@ impl Default for CentralSourceConfig {
                 retry_max_delay_millis: 30000,
                 max_retries: 10,
             },
+            user_agent: None,
+            // 256 MiB.
+            max_response_bytes: 1 << 28,
         }
     }
 }
@@ -79,7 +99,7 @@ impl SerializeConfig for CentralSourceConfig {
                 "concurrent_requests",
                 &self.concurrent_requests,
                 "Maximum number of concurrent requests to Starknet feeder-gateway for getting a \
-                 type of data (for example, blocks).",
+                 type of data (for example, blocks). Must be positive.",
                 ParamPrivacyInput::Public,
             ),
             ser_param(
@@ -118,9 +138,40 @@ impl SerializeConfig for CentralSourceConfig {
                 "Size of class cache, must be a positive integer.",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "max_response_bytes",
+                &self.max_response_bytes,
+                "Maximum size in bytes of the body of a response from Starknet feeder-gateway. \
+                 Requests whose response is larger fail.",
+                ParamPrivacyInput::Public,
+            ),
         ]);
-        chain!(self_params_dump, append_sub_config_name(self.retry_config.dump(), "retry_config"))
-            .collect()
+        chain!(
+            self_params_dump,
+            ser_optional_param(
+                &self.user_agent,
+                String::new(),
+                "user_agent",
+                "Overrides the User-Agent header of the requests to Starknet feeder-gateway. If \
+                 not set, it's papyrus/<node version> followed by the system information.",
+                ParamPrivacyInput::Public,
+            ),
+            append_sub_config_name(self.retry_config.dump(), "retry_config")
+        )
+        .collect()
+    }
+}
+
+impl CentralSourceConfig {
+    // The headers to send with each request to Starknet feeder-gateway. A User-Agent header
+    // overrides the one the client sends by default.
+    pub(crate) fn request_headers(&self) -> Option<HashMap<String, String>> {
+        let Some(user_agent) = &self.user_agent else {
+            return self.http_headers.clone();
+        };
+        let mut http_headers = self.http_headers.clone().unwrap_or_default();
+        http_headers.insert(USER_AGENT.to_string(), user_agent.clone());
+        Some(http_headers)
     }
 }
 
@@ -153,6 +204,19 @@ pub enum CentralError {
     StorageError(#[from] StorageError),
     #[error("Wrong type of contract class")]
     BadContractClassType,
+    #[error("The body of a response from central is larger than {max_response_bytes} bytes.")]
+    ResponseTooLarge { max_response_bytes: usize },
+}
+
+impl From<ReaderClientError> for CentralError {
+    fn from(err: ReaderClientError) -> Self {
+        match err {
+            ReaderClientError::ClientError(ClientError::ResponseTooLarge {
+                max_response_bytes,
+            }) => CentralError::ResponseTooLarge { max_response_bytes },
+            err => CentralError::ClientError(Arc::new(err)),
+        }
+    }
 }
 
 #[cfg_attr(test, automock)]
@@ -191,10 +255,8 @@ pub trait CentralSourceTrait {
     ) -> Result<CasmContractClass, CentralError>;
 }
 
-pub(crate) type BlocksStream<'a> = BoxStream<
-    'a,
-    Result<(BlockNumber, Block, CentralBlockSignatureData, StarknetVersion), CentralError>,
->;
+pub(crate) type CentralBlock = (BlockNumber, Block, CentralBlockSignatureData, StarknetVersion);
+pub(crate) type BlocksStream<'a> = BoxStream<'a, CentralResult<CentralBlock>>;
 type CentralStateUpdate =
     (BlockNumber, BlockHash, StateDiff, IndexMap<ClassHash, DeprecatedContractClass>);
 pub(crate) type StateUpdatesStream<'a> = BoxStream<'a, CentralResult<CentralStateUpdate>>;
@@ -207,12 +269,15 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> CentralSourceTrait
 {
     // Returns the block hash and the block number of the latest block from the central source.
     async fn get_latest_block(&self) -> Result<Option<BlockHashAndNumber>, CentralError> {
-        self.starknet_client.latest_block().await.map_err(Arc::new)?.map_or(Ok(None), |block| {
-            Ok(Some(BlockHashAndNumber {
-                block_hash: block.block_hash,
-                block_number: block.block_number,
-            }))
-        })
+        measure_request(CentralEndpoint::GetBlock, self.starknet_client.latest_block())
+            .await
+            .map_err(Arc::new)?
+            .map_or(Ok(None), |block| {
+                Ok(Some(BlockHashAndNumber {
+                    block_hash: block.block_hash,
+                    block_number: block.block_number,
+                }))
+            })
     }
 
     // Returns the current block hash of the given block number from the central source.
@@ -220,8 +285,7 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> CentralSourceTrait
         &self,
         block_number: BlockNumber,
     ) -> Result<Option<BlockHash>, CentralError> {
-        self.starknet_client
-            .block(block_number)
+        measure_request(CentralEndpoint::GetBlock, self.starknet_client.block(block_number))
             .await
             .map_err(Arc::new)?
             .map_or(Ok(None), |block| Ok(Some(block.block_hash)))
@@ -252,13 +316,22 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> CentralSourceTrait
         up_to_block_number: BlockNumber,
     ) -> BlocksStream<'_> {
         stream! {
-            // TODO(dan): add explanation.
+            // Up to concurrent_requests blocks are downloaded at a time. Blocks that finish
+            // downloading before their predecessors are buffered, so that the blocks are yielded in
+            // order. An error is yielded once all the blocks before the failed one were yielded,
+            // and then the stream ends since the following blocks can't be stored without it.
             let mut res =
                 futures_util::stream::iter(initial_block_number.iter_up_to(up_to_block_number))
                     .map(|bn| async move {
                         let block_and_signature = futures_util::try_join!(
-                            self.starknet_client.block(bn),
-                            self.starknet_client.block_signature(bn)
+                            measure_request(
+                                CentralEndpoint::GetBlock,
+                                self.starknet_client.block(bn)
+                            ),
+                            measure_request(
+                                CentralEndpoint::GetSignature,
+                                self.starknet_client.block_signature(bn)
+                            )
                         );
                         (bn, block_and_signature)
                     })
@@ -347,8 +420,12 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> CentralSourceTrait
                 return Ok(class.clone());
             }
         }
-        let client_class =
-            self.starknet_client.class_by_hash(class_hash).await.map_err(Arc::new)?;
+        let client_class = measure_request(
+            CentralEndpoint::GetClassByHash,
+            self.starknet_client.class_by_hash(class_hash),
+        )
+        .await
+        .map_err(Arc::new)?;
         match client_class {
             None => Err(CentralError::ClassNotFound),
             Some(class) => {
@@ -373,7 +450,12 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> CentralSourceTrait
                 return Ok(class.clone());
             }
         }
-        match self.starknet_client.compiled_class_by_hash(class_hash).await {
+        match measure_request(
+            CentralEndpoint::GetCompiledClassByClassHash,
+            self.starknet_client.compiled_class_by_hash(class_hash),
+        )
+        .await
+        {
             Ok(Some(compiled_class)) => {
                 let mut compiled_class_cache =
                     self.compiled_class_cache.lock().expect("Failed to lock class cache.");
@@ -381,11 +463,54 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> CentralSourceTrait
                 Ok(compiled_class)
             }
             Ok(None) => Err(CentralError::CompiledClassNotFound { class_hash }),
-            Err(err) => Err(CentralError::ClientError(Arc::new(err))),
+            Err(err) => Err(err.into()),
         }
     }
 }
 
+/// The endpoints of the central source, by which its requests are measured.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum CentralEndpoint {
+    GetBlock,
+    GetSignature,
+    GetStateUpdate,
+    GetClassByHash,
+    GetCompiledClassByClassHash,
+}
+
+impl CentralEndpoint {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::GetBlock => "get_block",
+            Self::GetSignature => "get_signature",
+            Self::GetStateUpdate => "get_state_update",
+            Self::GetClassByHash => "get_class_by_hash",
+            Self::GetCompiledClassByClassHash => "get_compiled_class_by_class_hash",
+        }
+    }
+}
+
+// Awaits a request to the given endpoint of the central source, and records the request and its
+// latency in the metrics of the endpoint.
+pub(crate) async fn measure_request<Response>(
+    endpoint: CentralEndpoint,
+    request: impl Future<Output = Response>,
+) -> Response {
+    let start_time = Instant::now();
+    let response = request.await;
+    let endpoint = endpoint.name();
+    metrics::increment_counter!(
+        papyrus_metrics::PAPYRUS_CENTRAL_REQUESTS,
+        papyrus_metrics::PAPYRUS_CENTRAL_ENDPOINT_LABEL => endpoint
+    );
+    metrics::histogram!(
+        papyrus_metrics::PAPYRUS_CENTRAL_REQUEST_LATENCY_SECONDS,
+        start_time.elapsed().as_secs_f64(),
+        papyrus_metrics::PAPYRUS_CENTRAL_ENDPOINT_LABEL => endpoint
+    );
+    response
+}
+
 fn client_to_central_block(
     current_block_number: BlockNumber,
     maybe_client_block: Result<
@@ -400,9 +525,8 @@ fn client_to_central_block(
         Ok((Some(block), Some(signature_data))) => {
             debug!("Received new block {current_block_number} with hash {}.", block.block_hash);
             trace!("Block: {block:#?}, signature data: {signature_data:#?}.");
-            let (block, version) = block
-                .to_starknet_api_block_and_version()
-                .map_err(|err| CentralError::ClientError(Arc::new(err)))?;
+            let (block, version) =
+                block.to_starknet_api_block_and_version().map_err(CentralError::from)?;
             Ok((
                 block,
                 CentralBlockSignatureData {
@@ -424,7 +548,7 @@ fn client_to_central_block(
             debug!("Block {current_block_number} not found.");
             Err(CentralError::BlockNotFound { block_number: current_block_number })
         }
-        Err(err) => Err(CentralError::ClientError(Arc::new(err))),
+        Err(err) => Err(err.into()),
     }
 }
 
@@ -438,10 +562,11 @@ impl CentralSource {
     ) -> Result<CentralSource, ClientCreationError> {
         let starknet_client = StarknetFeederGatewayClient::new(
             &config.url,
-            config.http_headers,
+            config.request_headers(),
             node_version,
             config.retry_config,
-        )?;
+        )?
+        .with_max_response_bytes(config.max_response_bytes);
 
         Ok(CentralSource {
             concurrent_requests: config.concurrent_requests,
@@ -16,7 +16,13 @@ use starknet_client::reader::{ReaderClientResult, StarknetReader, StateUpdate};
 use tracing::log::trace;
 use tracing::{debug, instrument};
 
-use super::{ApiContractClass, CentralResult, CentralStateUpdate};
+use super::{
+    measure_request,
+    ApiContractClass,
+    CentralEndpoint,
+    CentralResult,
+    CentralStateUpdate,
+};
 use crate::CentralError;
 
 type TasksQueue<T> = FuturesOrdered<Pin<Box<dyn Future<Output = T> + Send>>>;
@@ -202,7 +208,12 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> StateUpdateStream<
             let starknet_client = self.starknet_client.clone();
             *should_poll_again = true;
             self.download_state_update_tasks.push_back(Box::pin(async move {
-                (current_block_number, starknet_client.state_update(current_block_number).await)
+                let state_update = measure_request(
+                    CentralEndpoint::GetStateUpdate,
+                    starknet_client.state_update(current_block_number),
+                )
+                .await;
+                (current_block_number, state_update)
             }));
             self.initial_block_number = self.initial_block_number.next();
         }
@@ -239,7 +250,7 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> StateUpdateStream<
             // Class was not found.
             Ok(None) => Err(CentralError::ClassNotFound),
             // An error occurred while downloading the class.
-            Err(err) => Err(CentralError::ClientError(err.into())),
+            Err(err) => Err(err.into()),
         }
     }
 }
@@ -377,7 +388,10 @@ async fn download_class_if_necessary<TStarknetClient: StarknetReader>(
 
     // Class not found in storage - download.
     trace!("Downloading class {:?}.", class_hash);
-    let client_class = starknet_client.class_by_hash(class_hash).await.map_err(Arc::new)?;
+    let client_class =
+        measure_request(CentralEndpoint::GetClassByHash, starknet_client.class_by_hash(class_hash))
+            .await
+            .map_err(Arc::new)?;
     match client_class {
         None => Ok(None),
         Some(class) => {
@@ -8,19 +8,22 @@ use cairo_lang_starknet::casm_contract_class::CasmContractClass;
 use futures::StreamExt;
 use indexmap::IndexMap;
 use papyrus_common::pending_classes::{ApiContractClass, PendingClasses};
+use papyrus_common::sync_progress::SyncStatus;
 use papyrus_common::BlockHashAndNumber;
 use papyrus_storage::base_layer::BaseLayerStorageReader;
+use papyrus_storage::body::BodyStorageReader;
 use papyrus_storage::header::{HeaderStorageReader, StarknetVersion};
+use papyrus_storage::prune::PruneStorageReader;
 use papyrus_storage::state::StateStorageReader;
 use papyrus_storage::test_utils::get_test_storage;
 use papyrus_storage::{StorageError, StorageReader, StorageWriter};
 use starknet_api::block::{Block, BlockBody, BlockHash, BlockHeader, BlockNumber};
 use starknet_api::core::ClassHash;
-use starknet_api::hash::StarkFelt;
+use starknet_api::hash::{StarkFelt, GENESIS_HASH};
 use starknet_api::stark_felt;
 use starknet_api::state::StateDiff;
 use starknet_client::reader::PendingData;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
 use tracing::{debug, error};
 
 use super::pending::MockPendingSourceTrait;
@@ -94,19 +97,106 @@ async fn run_sync(
     writer: StorageWriter,
     central: impl CentralSourceTrait + Send + Sync + 'static,
     base_layer: impl BaseLayerSourceTrait + Send + Sync,
+) -> StateSyncResult {
+    run_sync_with_status(
+        reader,
+        writer,
+        central,
+        base_layer,
+        watch::channel(SyncStatus::default()).0,
+    )
+    .await
+}
+
+fn get_test_sync_config() -> SyncConfig {
+    SyncConfig {
+        block_propagation_sleep_duration: SYNC_SLEEP_DURATION,
+        base_layer_propagation_sleep_duration: BASE_LAYER_SLEEP_DURATION,
+        recoverable_error_sleep_duration: SYNC_SLEEP_DURATION,
+        blocks_max_stream_size: STREAM_SIZE,
+        state_updates_max_stream_size: STREAM_SIZE,
+        genesis_parent_hash: BlockHash(stark_felt!(GENESIS_HASH)),
+        num_sync_workers: 1,
+        prune_keep_blocks: None,
+        start_block: None,
+    }
+}
+
+// Mocks a central that has a chain of n_blocks blocks. Mockall evaluates the expectations of a
+// method in the order they were set, so the expectations that were already set on the mock take
+// precedence over these.
+fn mock_central_chain(central_mock: &mut MockCentralSourceTrait, n_blocks: u64) {
+    let latest_block_number = BlockNumber(n_blocks - 1);
+    central_mock.expect_get_latest_block().returning(move || {
+        Ok(Some(BlockHashAndNumber {
+            block_number: latest_block_number,
+            block_hash: create_block_hash(latest_block_number, false),
+        }))
+    });
+    central_mock.expect_stream_new_blocks().returning(move |initial, up_to| {
+        let blocks_stream: BlocksStream<'_> = stream! {
+            for block_number in initial.iter_up_to(up_to) {
+                let header = BlockHeader {
+                    block_number,
+                    block_hash: create_block_hash(block_number, false),
+                    parent_hash: create_block_hash(block_number.prev().unwrap_or_default(), false),
+                    ..BlockHeader::default()
+                };
+                yield Ok((
+                    block_number,
+                    Block { header, body: BlockBody::default() },
+                    CentralBlockSignatureData::default(),
+                    StarknetVersion(STARKNET_VERSION.to_string())
+                ));
+            }
+        }
+        .boxed();
+        blocks_stream
+    });
+    central_mock.expect_stream_state_updates().returning(move |initial, up_to| {
+        let state_stream: StateUpdatesStream<'_> = stream! {
+            for block_number in initial.iter_up_to(up_to) {
+                yield Ok((
+                    block_number,
+                    create_block_hash(block_number, false),
+                    StateDiff::default(),
+                    IndexMap::new(),
+                ));
+            }
+        }
+        .boxed();
+        state_stream
+    });
+    central_mock.expect_get_block_hash().returning(|bn| Ok(Some(create_block_hash(bn, false))));
+}
+
+// Like run_sync, but publishes the sync status to the given sender.
+async fn run_sync_with_status(
+    reader: StorageReader,
+    writer: StorageWriter,
+    central: impl CentralSourceTrait + Send + Sync + 'static,
+    base_layer: impl BaseLayerSourceTrait + Send + Sync,
+    status_sender: watch::Sender<SyncStatus>,
+) -> StateSyncResult {
+    run_sync_with_config(reader, writer, central, base_layer, status_sender, get_test_sync_config())
+        .await
+}
+
+// Like run_sync_with_status, but with the given sync config.
+async fn run_sync_with_config(
+    reader: StorageReader,
+    writer: StorageWriter,
+    central: impl CentralSourceTrait + Send + Sync + 'static,
+    base_layer: impl BaseLayerSourceTrait + Send + Sync,
+    status_sender: watch::Sender<SyncStatus>,
+    config: SyncConfig,
 ) -> StateSyncResult {
     // Mock to the pending source that always returns the default pending data.
     let mut pending_source = MockPendingSourceTrait::new();
     pending_source.expect_get_pending_data().returning(|| Ok(PendingData::default()));
 
     let mut state_sync = GenericStateSync {
-        config: SyncConfig {
-            block_propagation_sleep_duration: SYNC_SLEEP_DURATION,
-            base_layer_propagation_sleep_duration: BASE_LAYER_SLEEP_DURATION,
-            recoverable_error_sleep_duration: SYNC_SLEEP_DURATION,
-            blocks_max_stream_size: STREAM_SIZE,
-            state_updates_max_stream_size: STREAM_SIZE,
-        },
+        config,
         shared_highest_block: Arc::new(RwLock::new(None)),
         pending_data: Arc::new(RwLock::new(PendingData::default())),
         central_source: Arc::new(central),
@@ -115,6 +205,8 @@ async fn run_sync(
         base_layer_source: Arc::new(base_layer),
         reader,
         writer,
+        progress_sender: broadcast::channel(1).0,
+        status_sender,
     };
 
     state_sync.run().await?;
@@ -270,6 +362,166 @@ async fn sync_happy_flow() {
     }
 }
 
+#[tokio::test]
+async fn blocks_outside_the_prune_window_are_pruned() {
+    const N_BLOCKS: u64 = 5;
+    const KEEP_BLOCKS: u64 = 2;
+    const MAX_TIME_TO_SYNC_MS: u64 = 800;
+    let _ = simple_logger::init_with_env();
+
+    let mut central_mock = MockCentralSourceTrait::new();
+    mock_central_chain(&mut central_mock, N_BLOCKS);
+
+    let mut base_layer_mock = MockBaseLayerSourceTrait::new();
+    base_layer_mock.expect_latest_proved_block().returning(|| Ok(None));
+
+    let ((reader, writer), _temp_dir) = get_test_storage();
+    // The minimal number of kept blocks is enforced only when the config is validated.
+    let config = SyncConfig { prune_keep_blocks: Some(KEEP_BLOCKS), ..get_test_sync_config() };
+    let sync_future = run_sync_with_config(
+        reader.clone(),
+        writer,
+        central_mock,
+        base_layer_mock,
+        watch::channel(SyncStatus::default()).0,
+        config,
+    );
+
+    // Check that once the whole chain is synced, only the last KEEP_BLOCKS bodies and state diffs
+    // are left, while all the headers remain.
+    let check_storage_future =
+        check_storage(reader, Duration::from_millis(MAX_TIME_TO_SYNC_MS), |reader| {
+            let txn = reader.begin_ro_txn().unwrap();
+            if txn.get_state_marker().unwrap() < BlockNumber(N_BLOCKS) {
+                return CheckStoragePredicateResult::InProgress;
+            }
+            // The blocks are pruned right after the last state diff is stored.
+            let first_kept_block = BlockNumber(N_BLOCKS - KEEP_BLOCKS);
+            let pruned_marker = txn.get_pruned_marker().unwrap();
+            if pruned_marker < first_kept_block {
+                return CheckStoragePredicateResult::InProgress;
+            }
+            if pruned_marker > first_kept_block {
+                return CheckStoragePredicateResult::Error;
+            }
+            for block_number in (0..N_BLOCKS).map(BlockNumber) {
+                let is_pruned = block_number < first_kept_block;
+                if txn.get_block_header(block_number).unwrap().is_none()
+                    || txn.get_block_transactions(block_number).unwrap().is_some() == is_pruned
+                    || txn.get_state_diff(block_number).unwrap().is_some() == is_pruned
+                {
+                    return CheckStoragePredicateResult::Error;
+                }
+            }
+            CheckStoragePredicateResult::Passed
+        });
+
+    tokio::select! {
+        sync_result = sync_future => sync_result.unwrap(),
+        storage_check_result = check_storage_future => assert!(storage_check_result),
+    }
+}
+
+#[tokio::test]
+async fn sync_from_start_block() {
+    const N_BLOCKS: u64 = 5;
+    const START_BLOCK: BlockNumber = BlockNumber(3);
+    const MAX_TIME_TO_SYNC_MS: u64 = 800;
+    let _ = simple_logger::init_with_env();
+
+    // Only the parent of the start block and the blocks after it are requested.
+    let mut central_mock = MockCentralSourceTrait::new();
+    central_mock
+        .expect_stream_new_blocks()
+        .withf(|initial, _| *initial < START_BLOCK.prev().unwrap())
+        .never();
+    central_mock.expect_stream_state_updates().withf(|initial, _| *initial < START_BLOCK).never();
+    mock_central_chain(&mut central_mock, N_BLOCKS);
+
+    // The base layer proved a block that is before the start block, so it isn't stored.
+    let mut base_layer_mock = MockBaseLayerSourceTrait::new();
+    base_layer_mock
+        .expect_latest_proved_block()
+        .returning(|| Ok(Some((BlockNumber(0), create_block_hash(BlockNumber(0), false)))));
+
+    let ((reader, writer), _temp_dir) = get_test_storage();
+    let config = SyncConfig { start_block: Some(START_BLOCK), ..get_test_sync_config() };
+    let sync_future = run_sync_with_config(
+        reader.clone(),
+        writer,
+        central_mock,
+        base_layer_mock,
+        watch::channel(SyncStatus::default()).0,
+        config,
+    );
+
+    // Check that the blocks from the start block on are synced, on top of the header of its
+    // parent, while the blocks before it are missing.
+    let check_storage_future =
+        check_storage(reader, Duration::from_millis(MAX_TIME_TO_SYNC_MS), |reader| {
+            let txn = reader.begin_ro_txn().unwrap();
+            if txn.get_state_marker().unwrap() < BlockNumber(N_BLOCKS) {
+                return CheckStoragePredicateResult::InProgress;
+            }
+            if txn.get_header_marker().unwrap() != BlockNumber(N_BLOCKS)
+                || txn.get_pruned_marker().unwrap() != START_BLOCK
+            {
+                return CheckStoragePredicateResult::Error;
+            }
+            for block_number in (0..N_BLOCKS).map(BlockNumber) {
+                let is_header_stored = block_number >= START_BLOCK.prev().unwrap();
+                let is_block_stored = block_number >= START_BLOCK;
+                if txn.get_block_header(block_number).unwrap().is_some() != is_header_stored
+                    || txn.get_block_transactions(block_number).unwrap().is_some()
+                        != is_block_stored
+                    || txn.get_state_diff(block_number).unwrap().is_some() != is_block_stored
+                {
+                    return CheckStoragePredicateResult::Error;
+                }
+            }
+            CheckStoragePredicateResult::Passed
+        });
+
+    tokio::select! {
+        sync_result = sync_future => sync_result.unwrap(),
+        storage_check_result = check_storage_future => assert!(storage_check_result),
+    }
+}
+
+#[tokio::test]
+async fn initial_sync_complete_is_signaled() {
+    const N_BLOCKS: u64 = 5;
+    const MAX_TIME_TO_SYNC_MS: u64 = 800;
+    let _ = simple_logger::init_with_env();
+
+    // Mock having N_BLOCKS chain in central.
+    let mut central_mock = MockCentralSourceTrait::new();
+    mock_central_chain(&mut central_mock, N_BLOCKS);
+
+    let mut base_layer_mock = MockBaseLayerSourceTrait::new();
+    base_layer_mock.expect_latest_proved_block().returning(|| Ok(None));
+
+    let ((reader, writer), _temp_dir) = get_test_storage();
+    let (status_sender, mut status_receiver) = watch::channel(SyncStatus::default());
+    let sync_future =
+        run_sync_with_status(reader.clone(), writer, central_mock, base_layer_mock, status_sender);
+    let initial_sync_future = tokio::time::timeout(
+        Duration::from_millis(MAX_TIME_TO_SYNC_MS),
+        status_receiver.wait_for(|status| status.initial_sync_complete),
+    );
+
+    tokio::select! {
+        sync_result = sync_future => sync_result.unwrap(),
+        status = initial_sync_future => {
+            let status = *status.expect("The initial sync should complete in time.").unwrap();
+            assert_eq!(status, SyncStatus { initial_sync_complete: true, blocks_behind: Some(0) });
+        }
+    }
+    // The signal fires only after the latest block is stored.
+    let header_marker = reader.begin_ro_txn().unwrap().get_header_marker().unwrap();
+    assert_eq!(header_marker, BlockNumber(N_BLOCKS));
+}
+
 #[tokio::test]
 async fn sync_with_revert() {
     let _ = simple_logger::init_with_env();
@@ -1,15 +1,25 @@
+use std::collections::HashMap;
 use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use assert_matches::assert_matches;
+use async_trait::async_trait;
 use cairo_lang_starknet::casm_contract_class::CasmContractClass;
 use futures_util::pin_mut;
 use indexmap::{indexmap, IndexMap};
 use lru::LruCache;
+use metrics_exporter_prometheus::PrometheusHandle;
 use mockall::predicate;
+use papyrus_common::metrics::{
+    PAPYRUS_CENTRAL_ENDPOINT_LABEL,
+    PAPYRUS_CENTRAL_REQUEST_LATENCY_SECONDS,
+};
 use papyrus_storage::state::StateStorageWriter;
 use papyrus_storage::test_utils::get_test_storage;
 use pretty_assertions::assert_eq;
+use prometheus_parse::Value::Untyped;
 use reqwest::StatusCode;
 use starknet_api::block::{BlockHash, BlockNumber};
 use starknet_api::core::{
@@ -32,17 +42,28 @@ use starknet_client::reader::{
     DeployedContract,
     GenericContractClass,
     MockStarknetReader,
+    PendingData,
     ReaderClientError,
+    ReaderClientResult,
     ReplacedClass,
+    StarknetReader,
     StateUpdate,
     StorageEntry,
 };
 use starknet_client::ClientError;
+use test_utils::prometheus_is_contained;
 use tokio_stream::StreamExt;
+use validator::Validate;
 
 use super::state_update_stream::StateUpdateStreamConfig;
 use super::ApiContractClass;
-use crate::sources::central::{CentralError, CentralSourceTrait, GenericCentralSource};
+use crate::sources::central::{
+    CentralError,
+    CentralSourceConfig,
+    CentralSourceTrait,
+    GenericCentralSource,
+};
+use crate::test_utils::get_metrics_handle;
 
 const TEST_CONCURRENT_REQUESTS: usize = 300;
 
@@ -253,6 +274,217 @@ async fn stream_block_headers_error() {
     assert_eq!(expected_block_num, BlockNumber(ERROR_BLOCK_NUMBER + 1));
 }
 
+// A client that tracks how many blocks are downloaded concurrently. Later blocks take less time to
+// download, so they complete before the blocks preceding them.
+#[derive(Default)]
+struct ConcurrencyTrackingReader {
+    num_downloading_blocks: AtomicUsize,
+    max_num_downloading_blocks: AtomicUsize,
+}
+
+#[async_trait]
+impl StarknetReader for ConcurrencyTrackingReader {
+    async fn latest_block(&self) -> ReaderClientResult<Option<Block>> {
+        unimplemented!();
+    }
+
+    async fn block(&self, block_number: BlockNumber) -> ReaderClientResult<Option<Block>> {
+        let num_downloading_blocks = self.num_downloading_blocks.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_num_downloading_blocks.fetch_max(num_downloading_blocks, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100 - block_number.0)).await;
+        self.num_downloading_blocks.fetch_sub(1, Ordering::SeqCst);
+        Ok(Some(Block::default()))
+    }
+
+    async fn class_by_hash(
+        &self,
+        _class_hash: ClassHash,
+    ) -> ReaderClientResult<Option<GenericContractClass>> {
+        unimplemented!();
+    }
+
+    async fn compiled_class_by_hash(
+        &self,
+        _class_hash: ClassHash,
+    ) -> ReaderClientResult<Option<CasmContractClass>> {
+        unimplemented!();
+    }
+
+    async fn state_update(
+        &self,
+        _block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<StateUpdate>> {
+        unimplemented!();
+    }
+
+    async fn pending_data(&self) -> ReaderClientResult<Option<PendingData>> {
+        unimplemented!();
+    }
+
+    async fn is_alive(&self) -> bool {
+        unimplemented!();
+    }
+
+    async fn block_signature(
+        &self,
+        block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<BlockSignatureData>> {
+        Ok(Some(BlockSignatureData { block_number, ..Default::default() }))
+    }
+}
+
+#[tokio::test]
+async fn stream_block_headers_concurrently_and_in_order() {
+    const CONCURRENT_REQUESTS: usize = 3;
+    const END_BLOCK_NUMBER: u64 = 10;
+    let starknet_client = Arc::new(ConcurrencyTrackingReader::default());
+    let ((reader, _), _temp_dir) = get_test_storage();
+    let central_source = GenericCentralSource {
+        concurrent_requests: CONCURRENT_REQUESTS,
+        starknet_client: starknet_client.clone(),
+        storage_reader: reader,
+        state_update_stream_config: state_update_stream_config_for_test(),
+        class_cache: get_test_class_cache(),
+        compiled_class_cache: get_test_compiled_class_cache(),
+    };
+
+    let block_numbers = central_source
+        .stream_new_blocks(BlockNumber(0), BlockNumber(END_BLOCK_NUMBER))
+        .map(|block_tuple| block_tuple.unwrap().0)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(block_numbers, (0..END_BLOCK_NUMBER).map(BlockNumber).collect::<Vec<_>>());
+    assert_eq!(
+        starknet_client.max_num_downloading_blocks.load(Ordering::SeqCst),
+        CONCURRENT_REQUESTS
+    );
+}
+
+// A client that takes a while to download the blocks, and downloads their signatures at once.
+struct SlowBlockReader {
+    block_delay: Duration,
+}
+
+#[async_trait]
+impl StarknetReader for SlowBlockReader {
+    async fn latest_block(&self) -> ReaderClientResult<Option<Block>> {
+        unimplemented!();
+    }
+
+    async fn block(&self, _block_number: BlockNumber) -> ReaderClientResult<Option<Block>> {
+        tokio::time::sleep(self.block_delay).await;
+        Ok(Some(Block::default()))
+    }
+
+    async fn class_by_hash(
+        &self,
+        _class_hash: ClassHash,
+    ) -> ReaderClientResult<Option<GenericContractClass>> {
+        unimplemented!();
+    }
+
+    async fn compiled_class_by_hash(
+        &self,
+        _class_hash: ClassHash,
+    ) -> ReaderClientResult<Option<CasmContractClass>> {
+        unimplemented!();
+    }
+
+    async fn state_update(
+        &self,
+        _block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<StateUpdate>> {
+        unimplemented!();
+    }
+
+    async fn pending_data(&self) -> ReaderClientResult<Option<PendingData>> {
+        unimplemented!();
+    }
+
+    async fn is_alive(&self) -> bool {
+        unimplemented!();
+    }
+
+    async fn block_signature(
+        &self,
+        block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<BlockSignatureData>> {
+        Ok(Some(BlockSignatureData { block_number, ..Default::default() }))
+    }
+}
+
+fn get_central_request_latency_sum(handle: &PrometheusHandle, endpoint: &str) -> f64 {
+    let metric_name = format!("{PAPYRUS_CENTRAL_REQUEST_LATENCY_SECONDS}_sum");
+    match prometheus_is_contained(
+        handle.render(),
+        &metric_name,
+        &[(PAPYRUS_CENTRAL_ENDPOINT_LABEL, endpoint)],
+    ) {
+        Some(Untyped(value)) => value,
+        value => panic!("Unexpected value {value:?} of {metric_name} of {endpoint}."),
+    }
+}
+
+// The other tests that use a central source record their requests in the global metrics recorder
+// too, so the latencies are only bounded, since none of the other tests delay the signature
+// requests.
+#[tokio::test]
+async fn request_latency_is_measured_per_endpoint() {
+    const BLOCK_DELAY: Duration = Duration::from_millis(300);
+    const END_BLOCK_NUMBER: u64 = 2;
+    let handle = get_metrics_handle();
+    let ((reader, _), _temp_dir) = get_test_storage();
+    let central_source = GenericCentralSource {
+        concurrent_requests: TEST_CONCURRENT_REQUESTS,
+        starknet_client: Arc::new(SlowBlockReader { block_delay: BLOCK_DELAY }),
+        storage_reader: reader,
+        state_update_stream_config: state_update_stream_config_for_test(),
+        class_cache: get_test_class_cache(),
+        compiled_class_cache: get_test_compiled_class_cache(),
+    };
+
+    let block_numbers = central_source
+        .stream_new_blocks(BlockNumber(0), BlockNumber(END_BLOCK_NUMBER))
+        .map(|block_tuple| block_tuple.unwrap().0)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(block_numbers, (0..END_BLOCK_NUMBER).map(BlockNumber).collect::<Vec<_>>());
+
+    // The blocks are downloaded concurrently, but the latency of each of them is measured.
+    let block_latency_sum = get_central_request_latency_sum(&handle, "get_block");
+    assert!(block_latency_sum >= (BLOCK_DELAY * END_BLOCK_NUMBER as u32).as_secs_f64());
+    let signature_latency_sum = get_central_request_latency_sum(&handle, "get_signature");
+    assert!(signature_latency_sum < BLOCK_DELAY.as_secs_f64());
+}
+
+#[test]
+fn concurrent_requests_must_be_positive() {
+    let config = CentralSourceConfig { concurrent_requests: 0, ..Default::default() };
+    assert!(config.validate().is_err());
+    assert!(CentralSourceConfig::default().validate().is_ok());
+}
+
+#[test]
+fn user_agent_overrides_the_request_headers() {
+    let http_headers = HashMap::from([("k1".to_owned(), "v1".to_owned())]);
+    let config =
+        CentralSourceConfig { http_headers: Some(http_headers.clone()), ..Default::default() };
+    assert_eq!(config.request_headers(), Some(http_headers.clone()));
+
+    let config = CentralSourceConfig {
+        http_headers: Some(http_headers),
+        user_agent: Some("branded/1.0".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        config.request_headers(),
+        Some(HashMap::from([
+            ("k1".to_owned(), "v1".to_owned()),
+            ("user-agent".to_owned(), "branded/1.0".to_owned()),
+        ]))
+    );
+}
+
 #[tokio::test]
 async fn stream_state_updates() {
     const START_BLOCK_NUMBER: u64 = 5;
@@ -59,10 +59,11 @@ impl PendingSource {
     ) -> Result<PendingSource, ClientCreationError> {
         let starknet_client = StarknetFeederGatewayClient::new(
             &config.url,
-            config.http_headers,
+            config.request_headers(),
             node_version,
             config.retry_config,
-        )?;
+        )?
+        .with_max_response_bytes(config.max_response_bytes);
 
         Ok(PendingSource { starknet_client: Arc::new(starknet_client) })
     }
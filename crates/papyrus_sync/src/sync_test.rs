@@ -1,17 +1,23 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use assert_matches::assert_matches;
+use async_stream::stream;
 use cairo_lang_starknet::casm_contract_class::CasmContractClass;
 use futures_util::StreamExt;
 use indexmap::IndexMap;
+use papyrus_common::metrics::{PAPYRUS_BASE_LAYER_LAST_SUCCESSFUL_POLL, PAPYRUS_UNPROVED_BLOCKS};
 use papyrus_common::pending_classes::{ApiContractClass, PendingClasses, PendingClassesTrait};
+use papyrus_common::sync_progress::{SyncProgressEventKind, SyncStatus};
 use papyrus_storage::base_layer::BaseLayerStorageReader;
-use papyrus_storage::header::HeaderStorageWriter;
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter, StarknetVersion};
+use papyrus_storage::state::StateStorageReader;
 use papyrus_storage::test_utils::get_test_storage;
 use papyrus_storage::{StorageReader, StorageWriter};
 use pretty_assertions::assert_eq;
-use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use prometheus_parse::Value::Gauge;
+use starknet_api::block::{Block, BlockBody, BlockHash, BlockHeader, BlockNumber};
 use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce, PatriciaKey};
 use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
 use starknet_api::hash::{StarkFelt, StarkHash, GENESIS_HASH};
@@ -21,13 +27,22 @@ use starknet_client::reader::objects::pending_data::{PendingBlock, PendingStateU
 use starknet_client::reader::objects::state::StateDiff as ClientStateDiff;
 use starknet_client::reader::objects::transaction::Transaction as ClientTransaction;
 use starknet_client::reader::{DeclaredClassHashEntry, PendingData};
-use test_utils::{get_rng, GetTestInstance};
-use tokio::sync::RwLock;
+use test_utils::{get_rng, prometheus_is_contained, GetTestInstance};
+use tokio::sync::{broadcast, watch, RwLock};
+use validator::Validate;
 
 use crate::sources::base_layer::MockBaseLayerSourceTrait;
-use crate::sources::central::MockCentralSourceTrait;
+use crate::sources::central::{
+    BlocksStream,
+    CentralBlock,
+    CentralBlockSignatureData,
+    MockCentralSourceTrait,
+    StateUpdatesStream,
+};
 use crate::sources::pending::MockPendingSourceTrait;
+use crate::test_utils::get_metrics_handle;
 use crate::{
+    download_blocks_with_workers,
     sort_state_diff,
     stream_new_base_layer_block,
     sync_pending_data,
@@ -35,6 +50,8 @@ use crate::{
     StateSyncError,
     SyncConfig,
     SyncEvent,
+    SyncInconsistency,
+    MAX_BUFFERED_BLOCKS_PER_WORKER,
 };
 
 // TODO(anatg): Add a test to check that the sync calls the sort_state_diff function
@@ -165,6 +182,56 @@ async fn stream_new_base_layer_block_no_blocks_on_base_layer() {
     assert_matches!(event, SyncEvent::NewBaseLayerBlock { block_number: BlockNumber(1), .. });
 }
 
+// The other tests that stream base layer blocks record the number of unproved blocks in the global
+// metrics recorder too, so the base layer is polled until the recorded number is the one of this
+// test.
+#[tokio::test]
+async fn unproved_blocks_grow_while_the_base_layer_doesnt_advance() {
+    const MAX_POLLS: usize = 100;
+    let handle = get_metrics_handle();
+    let get_gauge =
+        |metric_name: &str| match prometheus_is_contained(handle.render(), metric_name, &[]) {
+            Some(Gauge(value)) => value,
+            value => panic!("Unexpected value {value:?} of {metric_name}."),
+        };
+    let ((reader, mut writer), _temp_dir) = get_test_storage();
+
+    // The base layer doesn't advance beyond block 2.
+    let mut mock = MockBaseLayerSourceTrait::new();
+    mock.expect_latest_proved_block()
+        .returning(|| Ok(Some((BlockNumber(2), BlockHash::default()))));
+    let mut stream =
+        stream_new_base_layer_block(reader, Arc::new(mock), Duration::from_millis(0)).boxed();
+
+    let mut header_marker = 0;
+    for new_header_marker in [5, 10, 20] {
+        for block_number in (header_marker..new_header_marker).map(BlockNumber) {
+            let header = BlockHeader { block_number, ..BlockHeader::default() };
+            writer
+                .begin_rw_txn()
+                .unwrap()
+                .append_header(block_number, &header)
+                .unwrap()
+                .commit()
+                .unwrap();
+        }
+        header_marker = new_header_marker;
+
+        // The blocks after block 2 aren't proved on the base layer.
+        let expected_unproved_blocks = (header_marker - 3) as f64;
+        let mut unproved_blocks = None;
+        for _ in 0..MAX_POLLS {
+            stream.next().await.unwrap().unwrap();
+            unproved_blocks = Some(get_gauge(PAPYRUS_UNPROVED_BLOCKS));
+            if unproved_blocks == Some(expected_unproved_blocks) {
+                break;
+            }
+        }
+        assert_eq!(unproved_blocks, Some(expected_unproved_blocks));
+    }
+    assert!(get_gauge(PAPYRUS_BASE_LAYER_LAST_SUCCESSFUL_POLL) > 0.0);
+}
+
 #[test]
 fn store_base_layer_block_test() {
     let (reader, mut writer) = get_test_storage().0;
@@ -193,6 +260,8 @@ fn store_base_layer_block_test() {
         base_layer_source: Arc::new(MockBaseLayerSourceTrait::new()),
         reader,
         writer,
+        progress_sender: broadcast::channel(1).0,
+        status_sender: watch::channel(SyncStatus::default()).0,
     };
 
     // Trying to store a block without a header in the storage.
@@ -212,6 +281,317 @@ fn store_base_layer_block_test() {
     assert_eq!(base_layer_marker, BlockNumber(1));
 }
 
+#[test]
+fn status_reflects_initial_sync_and_lag() {
+    let (reader, writer) = get_test_storage().0;
+    let (status_sender, status_receiver) = watch::channel(SyncStatus::default());
+    let gen_state_sync = GenericStateSync {
+        config: SyncConfig::default(),
+        shared_highest_block: Arc::new(RwLock::new(None)),
+        pending_data: Arc::new(RwLock::new(PendingData::default())),
+        central_source: Arc::new(MockCentralSourceTrait::new()),
+        pending_source: Arc::new(MockPendingSourceTrait::new()),
+        pending_classes: Arc::new(RwLock::new(PendingClasses::default())),
+        base_layer_source: Arc::new(MockBaseLayerSourceTrait::new()),
+        reader,
+        writer,
+        progress_sender: broadcast::channel(1).0,
+        status_sender,
+    };
+
+    gen_state_sync.update_status(BlockNumber(3), BlockNumber(5));
+    assert_eq!(
+        *status_receiver.borrow(),
+        SyncStatus { initial_sync_complete: false, blocks_behind: Some(2) }
+    );
+
+    gen_state_sync.update_status(BlockNumber(5), BlockNumber(5));
+    assert_eq!(
+        *status_receiver.borrow(),
+        SyncStatus { initial_sync_complete: true, blocks_behind: Some(0) }
+    );
+
+    // Falling behind again after the initial sync is reflected in the lag only.
+    gen_state_sync.update_status(BlockNumber(6), BlockNumber(9));
+    assert_eq!(
+        *status_receiver.borrow(),
+        SyncStatus { initial_sync_complete: true, blocks_behind: Some(3) }
+    );
+}
+
+#[tokio::test]
+async fn handle_block_reverts_reports_reorg() {
+    let (reader, mut writer) = get_test_storage().0;
+    add_headers(5, &mut writer);
+
+    // Central agrees with the storage up to block 2.
+    let mut central_mock = MockCentralSourceTrait::new();
+    central_mock.expect_get_block_hash().returning(|block_number| {
+        if block_number.0 <= 2 {
+            Ok(Some(BlockHash(block_number.0.into())))
+        } else {
+            Ok(Some(BlockHash(stark_felt!("0x666"))))
+        }
+    });
+    let (progress_sender, mut progress_receiver) = broadcast::channel(1);
+
+    let mut gen_state_sync = GenericStateSync {
+        config: SyncConfig::default(),
+        shared_highest_block: Arc::new(RwLock::new(None)),
+        pending_data: Arc::new(RwLock::new(PendingData::default())),
+        central_source: Arc::new(central_mock),
+        pending_source: Arc::new(MockPendingSourceTrait::new()),
+        pending_classes: Arc::new(RwLock::new(PendingClasses::default())),
+        base_layer_source: Arc::new(MockBaseLayerSourceTrait::new()),
+        reader,
+        writer,
+        progress_sender,
+        status_sender: watch::channel(SyncStatus::default()).0,
+    };
+
+    gen_state_sync.handle_block_reverts().await.unwrap();
+    let event = progress_receiver.try_recv().unwrap();
+    assert_eq!(event.block_number, BlockNumber(3));
+    assert_eq!(
+        event.kind,
+        SyncProgressEventKind::ReorgDetected {
+            old_head_hash: BlockHash(4_u64.into()),
+            new_head_hash: Some(BlockHash(2_u64.into())),
+        }
+    );
+    assert_eq!(
+        gen_state_sync.reader.begin_ro_txn().unwrap().get_header_marker().unwrap(),
+        BlockNumber(3)
+    );
+}
+
+#[tokio::test]
+async fn dry_run_reports_inconsistencies_without_writing() {
+    const N_BLOCKS: u64 = 4;
+    let wrong_parent_hash = BlockHash(stark_felt!("0x666"));
+    let (reader, writer) = get_test_storage().0;
+
+    // Block 2 doesn't point to block 1 as its parent.
+    let block_hash = |block_number: BlockNumber| BlockHash(StarkHash::from(block_number.0 + 1));
+    let mut central_mock = MockCentralSourceTrait::new();
+    central_mock.expect_stream_new_blocks().returning(move |initial, up_to| {
+        let blocks_stream: BlocksStream<'_> = stream! {
+            for block_number in initial.iter_up_to(up_to) {
+                let parent_hash = match block_number.prev() {
+                    None => BlockHash(stark_felt!(GENESIS_HASH)),
+                    Some(_) if block_number == BlockNumber(2) => wrong_parent_hash,
+                    Some(parent_block_number) => block_hash(parent_block_number),
+                };
+                let header = BlockHeader {
+                    block_number,
+                    block_hash: block_hash(block_number),
+                    parent_hash,
+                    ..BlockHeader::default()
+                };
+                yield Ok((
+                    block_number,
+                    Block { header, body: BlockBody::default() },
+                    CentralBlockSignatureData::default(),
+                    StarknetVersion("0.12.3".to_string()),
+                ));
+            }
+        }
+        .boxed();
+        blocks_stream
+    });
+    central_mock.expect_stream_state_updates().returning(move |initial, up_to| {
+        let state_stream: StateUpdatesStream<'_> = stream! {
+            for block_number in initial.iter_up_to(up_to) {
+                yield Ok((
+                    block_number,
+                    block_hash(block_number),
+                    StateDiff::default(),
+                    IndexMap::new(),
+                ));
+            }
+        }
+        .boxed();
+        state_stream
+    });
+    let (progress_sender, _progress_receiver) = broadcast::channel(1);
+
+    let gen_state_sync = GenericStateSync {
+        config: SyncConfig::default(),
+        shared_highest_block: Arc::new(RwLock::new(None)),
+        pending_data: Arc::new(RwLock::new(PendingData::default())),
+        central_source: Arc::new(central_mock),
+        pending_source: Arc::new(MockPendingSourceTrait::new()),
+        pending_classes: Arc::new(RwLock::new(PendingClasses::default())),
+        base_layer_source: Arc::new(MockBaseLayerSourceTrait::new()),
+        reader,
+        writer,
+        progress_sender,
+        status_sender: watch::channel(SyncStatus::default()).0,
+    };
+
+    let inconsistencies =
+        gen_state_sync.dry_run(BlockNumber(0), BlockNumber(N_BLOCKS)).await.unwrap();
+    assert_eq!(
+        inconsistencies,
+        vec![SyncInconsistency::ParentBlockHashMismatch {
+            block_number: BlockNumber(2),
+            parent_hash: wrong_parent_hash,
+            previous_block_hash: block_hash(BlockNumber(1)),
+        }]
+    );
+
+    let txn = gen_state_sync.reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(0));
+    assert_eq!(txn.get_state_marker().unwrap(), BlockNumber(0));
+}
+
+#[tokio::test]
+async fn blocks_are_downloaded_by_the_configured_number_of_workers() {
+    const NUM_WORKERS: usize = 3;
+    const NUM_BLOCKS: u64 = 12;
+    const BLOCK_DELAY: Duration = Duration::from_millis(50);
+
+    // Counts the blocks whose download is in flight, and the maximum of that count.
+    let num_in_flight = Arc::new(AtomicUsize::new(0));
+    let max_num_in_flight = Arc::new(AtomicUsize::new(0));
+    let mut central_mock = MockCentralSourceTrait::new();
+    let (num_in_flight_clone, max_num_in_flight_clone) =
+        (num_in_flight.clone(), max_num_in_flight.clone());
+    central_mock.expect_stream_new_blocks().returning(move |initial, up_to| {
+        let num_in_flight = num_in_flight_clone.clone();
+        let max_num_in_flight = max_num_in_flight_clone.clone();
+        let blocks_stream: BlocksStream<'_> = stream! {
+            for block_number in initial.iter_up_to(up_to) {
+                let current_num_in_flight = num_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_num_in_flight.fetch_max(current_num_in_flight, Ordering::SeqCst);
+                tokio::time::sleep(BLOCK_DELAY).await;
+                num_in_flight.fetch_sub(1, Ordering::SeqCst);
+                let header = BlockHeader { block_number, ..BlockHeader::default() };
+                yield Ok((
+                    block_number,
+                    Block { header, body: BlockBody::default() },
+                    CentralBlockSignatureData::default(),
+                    StarknetVersion::default(),
+                ));
+            }
+        }
+        .boxed();
+        blocks_stream
+    });
+
+    let block_numbers = download_blocks_with_workers(
+        Arc::new(central_mock),
+        BlockNumber(0),
+        BlockNumber(NUM_BLOCKS),
+        NUM_WORKERS,
+    )
+    .map(|maybe_block| maybe_block.unwrap().0)
+    .collect::<Vec<_>>()
+    .await;
+
+    // The blocks are yielded in order even though the workers download them concurrently.
+    assert_eq!(block_numbers, (0..NUM_BLOCKS).map(BlockNumber).collect::<Vec<_>>());
+    assert_eq!(max_num_in_flight.load(Ordering::SeqCst), NUM_WORKERS);
+}
+
+// Returns a block with the given number, as the central source streams it.
+fn central_block(block_number: BlockNumber) -> CentralBlock {
+    let header = BlockHeader { block_number, ..BlockHeader::default() };
+    (
+        block_number,
+        Block { header, body: BlockBody::default() },
+        CentralBlockSignatureData::default(),
+        StarknetVersion::default(),
+    )
+}
+
+#[tokio::test]
+async fn download_workers_wait_for_their_blocks_to_be_consumed() {
+    const NUM_WORKERS: usize = 2;
+    const NUM_BLOCKS: u64 = 10000;
+
+    let num_downloaded = Arc::new(AtomicUsize::new(0));
+    let mut central_mock = MockCentralSourceTrait::new();
+    let num_downloaded_clone = num_downloaded.clone();
+    central_mock.expect_stream_new_blocks().returning(move |initial, up_to| {
+        let num_downloaded = num_downloaded_clone.clone();
+        let blocks_stream: BlocksStream<'_> = stream! {
+            for block_number in initial.iter_up_to(up_to) {
+                num_downloaded.fetch_add(1, Ordering::SeqCst);
+                tokio::task::yield_now().await;
+                yield Ok(central_block(block_number));
+            }
+        }
+        .boxed();
+        blocks_stream
+    });
+
+    let mut block_stream = download_blocks_with_workers(
+        Arc::new(central_mock),
+        BlockNumber(0),
+        BlockNumber(NUM_BLOCKS),
+        NUM_WORKERS,
+    )
+    .boxed();
+    assert_eq!(block_stream.next().await.unwrap().unwrap().0, BlockNumber(0));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Each worker downloads at most its buffer, the block it waits to send and the first block.
+    let max_num_downloaded = NUM_WORKERS * (MAX_BUFFERED_BLOCKS_PER_WORKER + 2);
+    assert!(num_downloaded.load(Ordering::SeqCst) <= max_num_downloaded);
+}
+
+#[tokio::test]
+async fn download_workers_are_aborted_when_their_blocks_are_dropped() {
+    const NUM_WORKERS: usize = 2;
+    const NUM_BLOCKS: u64 = 10;
+
+    // Held by the streams of the workers, so that it's released once they're aborted.
+    let worker_token = Arc::new(());
+    let mut central_mock = MockCentralSourceTrait::new();
+    let worker_token_clone = worker_token.clone();
+    central_mock.expect_stream_new_blocks().returning(move |initial, _up_to| {
+        let worker_token = worker_token_clone.clone();
+        let blocks_stream: BlocksStream<'_> = stream! {
+            let _worker_token = worker_token;
+            yield Ok(central_block(initial));
+            // A download that never completes.
+            std::future::pending::<()>().await;
+        }
+        .boxed();
+        blocks_stream
+    });
+
+    let mut block_stream = download_blocks_with_workers(
+        Arc::new(central_mock),
+        BlockNumber(0),
+        BlockNumber(NUM_BLOCKS),
+        NUM_WORKERS,
+    )
+    .boxed();
+    assert_eq!(block_stream.next().await.unwrap().unwrap().0, BlockNumber(0));
+    drop(block_stream);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Only the test holds the token once the workers and the source they use are dropped.
+    assert_eq!(Arc::strong_count(&worker_token), 1);
+}
+
+#[test]
+fn num_sync_workers_must_be_positive() {
+    let config = SyncConfig { num_sync_workers: 0, ..Default::default() };
+    assert!(config.validate().is_err());
+    assert!(SyncConfig::default().validate().is_ok());
+}
+
+#[test]
+fn prune_keep_blocks_has_a_minimum() {
+    let config = SyncConfig { prune_keep_blocks: Some(127), ..Default::default() };
+    assert!(config.validate().is_err());
+    let config = SyncConfig { prune_keep_blocks: Some(128), ..Default::default() };
+    assert!(config.validate().is_ok());
+}
+
 // Adds to the storage 'headers_num' headers.
 fn add_headers(headers_num: u64, writer: &mut StorageWriter) {
     for i in 0..headers_num {
@@ -273,6 +653,7 @@ async fn test_pending_sync(
         Arc::new(mock_pending_source),
         pending_data_lock.clone(),
         pending_classes_lock.clone(),
+        BlockHash(stark_felt!(GENESIS_HASH)),
         Duration::ZERO,
     )
     .await
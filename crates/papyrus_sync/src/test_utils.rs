@@ -0,0 +1,11 @@
+use std::sync::OnceLock;
+
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+// Returns a handle to the global metrics recorder, which is installed by the first test that
+// calls it. The tests of the crate run in the same process, so they all record their metrics in
+// it, including the tests that don't call this function.
+pub(crate) fn get_metrics_handle() -> PrometheusHandle {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    HANDLE.get_or_init(|| PrometheusBuilder::new().install_recorder().unwrap()).clone()
+}
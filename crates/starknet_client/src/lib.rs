@@ -18,9 +18,10 @@ mod test_utils;
 pub mod writer;
 
 use std::collections::HashMap;
+use std::time::Duration;
 
-use reqwest::header::HeaderMap;
-use reqwest::{Client, RequestBuilder, StatusCode};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use tracing::warn;
 
 use self::retry::Retry;
@@ -35,6 +36,8 @@ struct StarknetClient {
     http_headers: HeaderMap,
     pub internal_client: Client,
     retry_config: RetryConfig,
+    // If set, reading the body of a response fails once it's larger than this many bytes.
+    max_response_bytes: Option<usize>,
 }
 
 /// Errors that might be encountered while creating the client.
@@ -76,6 +79,9 @@ pub enum ClientError {
     /// A client error representing errors returned by the starknet client.
     #[error(transparent)]
     StarknetError(#[from] StarknetError),
+    /// A client error representing responses whose body is larger than the allowed size.
+    #[error("The body of the response is larger than {max_response_bytes} bytes.")]
+    ResponseTooLarge { max_response_bytes: usize },
 }
 
 // A wrapper error for request_with_retry to handle the case that clone failed.
@@ -83,8 +89,12 @@ pub enum ClientError {
 enum RequestWithRetryError {
     #[error("Request is unclonable.")]
     CloneError,
-    #[error(transparent)]
-    ClientError(#[from] ClientError),
+    #[error("{error}")]
+    ClientError {
+        error: ClientError,
+        // The time the server asked to wait before retrying, if it did.
+        retry_after: Option<Duration>,
+    },
 }
 
 impl StarknetClient {
@@ -111,6 +121,7 @@ impl StarknetClient {
             http_headers: header_map,
             internal_client: Client::builder().user_agent(app_user_agent).build()?,
             retry_config,
+            max_response_bytes: None,
         })
     }
 
@@ -151,36 +162,53 @@ impl StarknetClient {
 
     fn should_retry(err: &RequestWithRetryError) -> bool {
         match err {
-            RequestWithRetryError::ClientError(err) => Self::get_retry_error_code(err).is_some(),
+            RequestWithRetryError::ClientError { error, retry_after: _ } => {
+                Self::get_retry_error_code(error).is_some()
+            }
             RequestWithRetryError::CloneError => false,
         }
     }
 
+    fn get_retry_after(err: &RequestWithRetryError) -> Option<Duration> {
+        match err {
+            RequestWithRetryError::ClientError { error: _, retry_after } => *retry_after,
+            RequestWithRetryError::CloneError => None,
+        }
+    }
+
     // If the request_builder is unclonable, the function will not retry the request upon failure.
+    // Retryable failures are retried with a jittered exponential backoff, unless the server said
+    // when to retry in a Retry-After header.
     pub async fn request_with_retry(
         &self,
         request_builder: RequestBuilder,
     ) -> ClientResult<String> {
         let res = Retry::new(&self.retry_config)
-            .start_with_condition(
+            .start_with_condition_and_delay(
                 || async {
                     match request_builder.try_clone() {
                         Some(request_builder) => self
-                            .request(request_builder)
+                            .request_and_get_retry_after(request_builder)
                             .await
-                            .map_err(RequestWithRetryError::ClientError),
+                            .map_err(|(error, retry_after)| RequestWithRetryError::ClientError {
+                                error,
+                                retry_after,
+                            }),
                         None => Err(RequestWithRetryError::CloneError),
                     }
                 },
                 Self::should_retry,
+                Self::get_retry_after,
             )
             .await;
 
         match res {
             Ok(string) => Ok(string),
-            Err(RequestWithRetryError::ClientError(err)) => Err(Self::get_retry_error_code(&err)
-                .map(|code| ClientError::RetryError { code, message: err.to_string() })
-                .unwrap_or(err)),
+            Err(RequestWithRetryError::ClientError { error: err, retry_after: _ }) => {
+                Err(Self::get_retry_error_code(&err)
+                    .map(|code| ClientError::RetryError { code, message: err.to_string() })
+                    .unwrap_or(err))
+            }
             Err(RequestWithRetryError::CloneError) => {
                 warn!("Starknet client got an unclonable request. Can't retry upon failure.");
                 self.request(request_builder).await
@@ -189,12 +217,25 @@ impl StarknetClient {
     }
 
     async fn request(&self, request_builder: RequestBuilder) -> ClientResult<String> {
+        self.request_and_get_retry_after(request_builder).await.map_err(|(err, _)| err)
+    }
+
+    // On failure, also returns the time the server asked to wait before retrying, if it did.
+    async fn request_and_get_retry_after(
+        &self,
+        request_builder: RequestBuilder,
+    ) -> Result<String, (ClientError, Option<Duration>)> {
         let res = request_builder.headers(self.http_headers.clone()).send().await;
-        let (code, message) = match res {
-            Ok(response) => (response.status(), response.text().await?),
+        let (code, message, retry_after) = match res {
+            Ok(response) => {
+                let code = response.status();
+                let retry_after = get_retry_after_header(&response);
+                let message = self.read_body(response).await.map_err(|err| (err, None))?;
+                (code, message, retry_after)
+            }
             Err(err) => {
                 let msg = err.to_string();
-                (err.status().ok_or(err)?, msg)
+                (err.status().ok_or(err).map_err(|err| (err.into(), None))?, msg, None)
             }
         };
         match code {
@@ -203,10 +244,39 @@ impl StarknetClient {
             // now, keeping both options. In the future, remove the '500' (INTERNAL_SERVER_ERROR)
             // option.
             StatusCode::INTERNAL_SERVER_ERROR | StatusCode::BAD_REQUEST => {
-                let starknet_error: StarknetError = serde_json::from_str(&message)?;
-                Err(ClientError::StarknetError(starknet_error))
+                let starknet_error: StarknetError =
+                    serde_json::from_str(&message).map_err(|err| (err.into(), None))?;
+                Err((ClientError::StarknetError(starknet_error), retry_after))
+            }
+            _ => Err((ClientError::BadResponseStatus { code, message }, retry_after)),
+        }
+    }
+
+    // Reads the body of the response as text. The body is read chunk by chunk, so a body that is
+    // larger than max_response_bytes fails once it's read past the limit, without buffering the
+    // rest of it.
+    async fn read_body(&self, mut response: Response) -> ClientResult<String> {
+        let Some(max_response_bytes) = self.max_response_bytes else {
+            return Ok(response.text().await?);
+        };
+        let response_too_large = ClientError::ResponseTooLarge { max_response_bytes };
+        if response.content_length().is_some_and(|length| length > max_response_bytes as u64) {
+            return Err(response_too_large);
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > max_response_bytes {
+                return Err(response_too_large);
             }
-            _ => Err(ClientError::BadResponseStatus { code, message }),
+            body.extend_from_slice(&chunk);
         }
+        Ok(String::from_utf8_lossy(&body).into_owned())
     }
 }
+
+// Returns the time to wait before retrying from the Retry-After header of the response. Only the
+// delay-seconds form of the header is supported.
+fn get_retry_after_header(response: &Response) -> Option<Duration> {
+    let retry_after = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    retry_after.trim().parse().ok().map(Duration::from_secs)
+}
@@ -188,6 +188,13 @@ impl StarknetFeederGatewayClient {
         })
     }
 
+    /// Makes the requests fail with [`ClientError::ResponseTooLarge`] when the body of their
+    /// response is larger than `max_response_bytes`, before the rest of the body is read.
+    pub fn with_max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.client.max_response_bytes = Some(max_response_bytes);
+        self
+    }
+
     async fn request_with_retry_url(&self, url: Url) -> ReaderClientResult<String> {
         self.client
             .request_with_retry(self.client.internal_client.get(url))
@@ -4,18 +4,20 @@ mod retry_test;
 
 use std::collections::BTreeMap;
 use std::fmt::Debug;
-use std::iter::Take;
+use std::iter::{Map, Take};
 use std::time::Duration;
 
 use papyrus_config::dumping::{ser_param, SerializeConfig};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use serde::{Deserialize, Serialize};
-use tokio_retry::strategy::ExponentialBackoff;
-use tokio_retry::{Action, Condition, RetryIf};
+use tokio_retry::strategy::{jitter, ExponentialBackoff};
+use tokio_retry::{Action, Condition};
 use tracing::debug;
+use validator::{Validate, ValidationError};
 
 /// A configuration for the retry mechanism.
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Validate)]
+#[validate(schema(function = "validate_retry_config"))]
 pub struct RetryConfig {
     /// The initial waiting time in milliseconds.
     pub retry_base_millis: u64,
@@ -38,7 +40,7 @@ impl SerializeConfig for RetryConfig {
             ser_param(
                 "retry_max_delay_millis",
                 &self.retry_max_delay_millis,
-                "Max waiting time after a failed request.",
+                "Max waiting time after a failed request. Must be at least retry_base_millis.",
                 ParamPrivacyInput::Public,
             ),
             ser_param(
@@ -51,18 +53,34 @@ impl SerializeConfig for RetryConfig {
     }
 }
 
+fn validate_retry_config(config: &RetryConfig) -> Result<(), ValidationError> {
+    if config.retry_max_delay_millis < config.retry_base_millis {
+        return Err(ValidationError::new(
+            "retry_max_delay_millis should be at least retry_base_millis",
+        ));
+    }
+    Ok(())
+}
+
+type JitteredBackoff = Map<ExponentialBackoff, fn(Duration) -> Duration>;
+
 /// A utility for retrying actions with a configurable backoff and error filter. Uses an
-/// [`ExponentialBackoff`] strategy.
+/// [`ExponentialBackoff`] strategy with a random jitter, so clients that failed together don't
+/// retry together.
 pub struct Retry {
-    strategy: Take<ExponentialBackoff>,
+    strategy: Take<JitteredBackoff>,
+    max_delay: Duration,
 }
 
 impl Retry {
     pub fn new(config: &RetryConfig) -> Self {
+        let max_delay = Duration::from_millis(config.retry_max_delay_millis);
         Retry {
             strategy: ExponentialBackoff::from_millis(config.retry_base_millis)
-                .max_delay(Duration::from_millis(config.retry_max_delay_millis))
+                .max_delay(max_delay)
+                .map(jitter as fn(Duration) -> Duration)
                 .take(config.max_retries),
+            max_delay,
         }
     }
 
@@ -88,18 +106,44 @@ impl Retry {
         self.start_with_condition(action, |_: &_| true).await
     }
 
-    pub async fn start_with_condition<I, E, A, C>(
+    pub async fn start_with_condition<I, E, A, C>(&self, action: A, condition: C) -> Result<I, E>
+    where
+        E: Debug,
+        A: Action<Item = I, Error = E>,
+        C: Condition<E> + Send,
+    {
+        self.start_with_condition_and_delay(action, condition, |_: &_| None).await
+    }
+
+    /// Like [`Retry::start_with_condition`], but an error can set the time to wait before the next
+    /// attempt instead of the backoff, e.g. when the server said when to retry. The time is capped
+    /// by the max delay of the config.
+    pub async fn start_with_condition_and_delay<I, E, A, C, D>(
         &self,
-        action: A,
+        mut action: A,
         mut condition: C,
+        mut delay: D,
     ) -> Result<I, E>
     where
         E: Debug,
         A: Action<Item = I, Error = E>,
         C: Condition<E> + Send,
+        D: FnMut(&E) -> Option<Duration>,
     {
-        let condition: Box<dyn Send + FnMut(&E) -> bool> =
-            Box::new(|err| Self::log_condition(err, &mut condition));
-        RetryIf::spawn(self.strategy.clone(), action, condition).await
+        let mut strategy = self.strategy.clone();
+        loop {
+            let err = match action.run().await {
+                Ok(item) => return Ok(item),
+                Err(err) => err,
+            };
+            if !Self::log_condition(&err, &mut condition) {
+                return Err(err);
+            }
+            let Some(backoff) = strategy.next() else {
+                return Err(err);
+            };
+            let delay = delay(&err).map_or(backoff, |delay| delay.min(self.max_delay));
+            tokio::time::sleep(delay).await;
+        }
     }
 }
@@ -1,10 +1,13 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
 use assert_matches::assert_matches;
-use mockito::mock;
+use mockito::{mock, Matcher};
 use reqwest::StatusCode;
 
 use crate::starknet_error::{KnownStarknetErrorCode, StarknetError, StarknetErrorCode};
 use crate::test_utils::retry::{get_test_config, MAX_RETRIES};
-use crate::{ClientError, RetryErrorCode, StarknetClient};
+use crate::{ClientError, RetryConfig, RetryErrorCode, StarknetClient};
 
 const NODE_VERSION: &str = "NODE VERSION";
 const URL_SUFFIX: &str = "/query";
@@ -184,3 +187,92 @@ fn serialization_precision() {
     let deserialized = serde_json::to_string(&serialized).unwrap();
     assert_eq!(input, deserialized);
 }
+
+#[tokio::test]
+async fn request_with_retry_honors_retry_after() {
+    const BODY: &str = "body";
+    // The backoff is long enough to fail the test if the Retry-After header is ignored.
+    const BACKOFF: Duration = Duration::from_secs(60);
+    let retry_config = RetryConfig {
+        retry_base_millis: BACKOFF.as_millis().try_into().unwrap(),
+        retry_max_delay_millis: BACKOFF.as_millis().try_into().unwrap(),
+        max_retries: MAX_RETRIES,
+    };
+    let starknet_client = StarknetClient::new(None, NODE_VERSION, retry_config).unwrap();
+    let mock_failure = mock("GET", URL_SUFFIX)
+        .with_status(StatusCode::SERVICE_UNAVAILABLE.as_u16().into())
+        .with_header("Retry-After", "0")
+        .expect(1)
+        .create();
+    let mock_success = mock("GET", URL_SUFFIX).with_status(200).with_body(BODY).create();
+    let mut url = mockito::server_url();
+    url.push_str(URL_SUFFIX);
+    let start = Instant::now();
+    let result =
+        starknet_client.request_with_retry(starknet_client.internal_client.get(&url)).await;
+    assert_eq!(result.unwrap(), BODY);
+    assert!(start.elapsed() < BACKOFF);
+    mock_failure.assert();
+    mock_success.assert();
+}
+
+#[tokio::test]
+async fn request_carries_user_agent() {
+    let mut url = mockito::server_url();
+    url.push_str(URL_SUFFIX);
+
+    let starknet_client = StarknetClient::new(None, NODE_VERSION, get_test_config()).unwrap();
+    let default_mock = mock("GET", URL_SUFFIX)
+        .match_header("user-agent", Matcher::Regex(format!(r"^papyrus/{NODE_VERSION} \(.*\)$")))
+        .with_status(200)
+        .create();
+    starknet_client.request_with_retry(starknet_client.internal_client.get(&url)).await.unwrap();
+    default_mock.assert();
+
+    // A User-Agent header in the http headers overrides the default one.
+    let http_headers = HashMap::from([("User-Agent".to_owned(), "branded/1.0".to_owned())]);
+    let starknet_client =
+        StarknetClient::new(Some(http_headers), NODE_VERSION, get_test_config()).unwrap();
+    let override_mock =
+        mock("GET", URL_SUFFIX).match_header("user-agent", "branded/1.0").with_status(200).create();
+    starknet_client.request_with_retry(starknet_client.internal_client.get(&url)).await.unwrap();
+    override_mock.assert();
+}
+
+#[tokio::test]
+async fn response_larger_than_max_response_bytes_fails() {
+    const MAX_RESPONSE_BYTES: usize = 1024;
+    let mut url = mockito::server_url();
+    url.push_str(URL_SUFFIX);
+    let mut starknet_client = StarknetClient::new(None, NODE_VERSION, get_test_config()).unwrap();
+    starknet_client.max_response_bytes = Some(MAX_RESPONSE_BYTES);
+
+    let body = "a".repeat(MAX_RESPONSE_BYTES);
+    let mock_within_limit = mock("GET", URL_SUFFIX).with_status(200).with_body(&body).create();
+    let result =
+        starknet_client.request_with_retry(starknet_client.internal_client.get(&url)).await;
+    assert_eq!(result.unwrap(), body);
+    mock_within_limit.assert();
+
+    // The rest of the body is delayed, so the request fails before the delay only if it doesn't
+    // wait for the whole body to be buffered.
+    const BODY_DELAY: Duration = Duration::from_secs(1);
+    let mock_too_large = mock("GET", URL_SUFFIX)
+        .with_status(200)
+        .with_body_from_fn(|writer| {
+            writer.write_all(&[b'a'; MAX_RESPONSE_BYTES + 1])?;
+            writer.flush()?;
+            std::thread::sleep(BODY_DELAY);
+            writer.write_all(&[b'a'; MAX_RESPONSE_BYTES])
+        })
+        .create();
+    let start = Instant::now();
+    let result =
+        starknet_client.request_with_retry(starknet_client.internal_client.get(&url)).await;
+    assert!(start.elapsed() < BODY_DELAY);
+    assert_matches!(
+        result,
+        Err(ClientError::ResponseTooLarge { max_response_bytes: MAX_RESPONSE_BYTES })
+    );
+    mock_too_large.assert();
+}